@@ -0,0 +1,160 @@
+use crate::backend::Trace;
+use crate::config::TimestampFormat;
+use crate::frontend::TuiTraceInfo;
+use crate::report::ROUND_POLL_INTERVAL;
+use crate::signal::shutdown_requested;
+use itertools::Itertools;
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::io::Write;
+use std::net::IpAddr;
+use std::thread::sleep;
+
+/// The thresholds beyond which the loss or round-trip time of a hop is alerted.
+#[derive(Debug, Clone, Copy)]
+pub struct AlertThresholds {
+    /// The loss (%) above which a hop is alerted.
+    pub loss_pct: f64,
+    /// The average round-trip time (ms) above which a hop is alerted, if any.
+    pub rtt_ms: Option<f64>,
+}
+
+/// The state of a responding hop which is compared round over round.
+#[derive(Debug, Clone)]
+struct HopState {
+    addrs: BTreeSet<IpAddr>,
+    loss_above: bool,
+    rtt_above: bool,
+}
+
+/// Tracks the state of each hop of a trace as of the prior completed round.
+#[derive(Debug, Default)]
+struct AlertTracker {
+    round: Option<usize>,
+    hops: Option<HashMap<u8, HopState>>,
+}
+
+impl AlertTracker {
+    /// The alerts for the changes of each hop since the prior call, if a round has completed since then.
+    ///
+    /// The first completed round establishes the initial state of each hop and is not alerted.
+    fn next_round(&mut self, trace: &Trace, thresholds: AlertThresholds) -> Vec<String> {
+        if trace.hops().is_empty() || self.round == Some(trace.round()) {
+            return vec![];
+        }
+        self.round = Some(trace.round());
+        let current: HashMap<_, _> = trace
+            .hops()
+            .iter()
+            .filter(|hop| hop.total_recv() > 0)
+            .map(|hop| {
+                let state = HopState {
+                    addrs: hop.addrs().copied().collect(),
+                    loss_above: hop.loss_pct() > thresholds.loss_pct,
+                    rtt_above: thresholds
+                        .rtt_ms
+                        .is_some_and(|rtt_ms| hop.avg_ms() > rtt_ms),
+                };
+                (hop.ttl(), state)
+            })
+            .collect();
+        let mut alerts = vec![];
+        if let Some(prior) = &self.hops {
+            for hop in trace.hops() {
+                let ttl = hop.ttl();
+                match (prior.get(&ttl), current.get(&ttl)) {
+                    (None, Some(current)) => {
+                        alerts.push(format!(
+                            "hop {ttl} appeared: {}",
+                            format_addrs(&current.addrs)
+                        ));
+                    }
+                    (Some(prior), Some(current)) => {
+                        if prior.addrs != current.addrs {
+                            alerts.push(format!(
+                                "hop {ttl} address changed: {} (was {})",
+                                format_addrs(&current.addrs),
+                                format_addrs(&prior.addrs)
+                            ));
+                        }
+                        if prior.loss_above != current.loss_above {
+                            alerts.push(format!(
+                                "hop {ttl} loss {} {:.1}%: {:.1}%",
+                                direction(current.loss_above),
+                                thresholds.loss_pct,
+                                hop.loss_pct()
+                            ));
+                        }
+                        if let Some(rtt_ms) = thresholds.rtt_ms {
+                            if prior.rtt_above != current.rtt_above {
+                                alerts.push(format!(
+                                    "hop {ttl} rtt {} {rtt_ms:.1}ms: {:.1}ms",
+                                    direction(current.rtt_above),
+                                    hop.avg_ms()
+                                ));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.hops = Some(current);
+        alerts
+    }
+}
+
+/// Run continuously, printing a timestamped line only when a hop of any trace changes.
+///
+/// A hop is alerted when it first responds, when it responds from a different set of addresses, and when its loss or
+/// average round-trip time rises above, or falls back below, a threshold.  Each line is flushed to stdout immediately
+/// and the alerts end cleanly if stdout is closed.
+pub fn run_alerts(
+    traces: &[TuiTraceInfo],
+    thresholds: AlertThresholds,
+    timestamp_format: &TimestampFormat,
+) -> anyhow::Result<()> {
+    let mut trackers: Vec<_> = traces.iter().map(|_| AlertTracker::default()).collect();
+    let stdout = io::stdout();
+    while !shutdown_requested() {
+        for (info, tracker) in traces.iter().zip(&mut trackers) {
+            let trace = info.data.read().clone();
+            let alerts = tracker.next_round(&trace, thresholds);
+            if alerts.is_empty() {
+                continue;
+            }
+            let timestamp = timestamp_format.format(chrono::Local::now());
+            let mut stdout = stdout.lock();
+            let written = alerts
+                .iter()
+                .try_for_each(|alert| {
+                    writeln!(
+                        stdout,
+                        "{timestamp} {} ({}) {alert}",
+                        info.target_hostname, info.target_addr
+                    )
+                })
+                .and_then(|()| stdout.flush());
+            match written {
+                Err(err) if err.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                written => written?,
+            }
+        }
+        sleep(ROUND_POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+/// Whether a threshold has been crossed upwards or downwards.
+const fn direction(above: bool) -> &'static str {
+    if above {
+        "above"
+    } else {
+        "below"
+    }
+}
+
+/// The sorted addresses of a hop, space delimited.
+fn format_addrs(addrs: &BTreeSet<IpAddr>) -> String {
+    addrs.iter().join(" ")
+}
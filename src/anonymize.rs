@@ -0,0 +1,224 @@
+use crate::config::{AnonymizeScope, AnonymizeStrategy};
+use crate::frontend::TuiTraceInfo;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+use trippy::tracing::Probe;
+
+/// The `IPv4` documentation networks (RFC 5737) from which placeholder addresses are taken, in order.
+const TOKEN_NETWORKS_V4: [[u8; 3]; 3] = [[192, 0, 2], [198, 51, 100], [203, 0, 113]];
+
+/// A cheaply cloneable anonymizer which hides the addresses of hosts before they are recorded by a trace.
+///
+/// As addresses are replaced before they are recorded, and before any reverse DNS, AS or geolocation lookup, every
+/// output sees the same placeholders and no hostname of a hidden address is ever resolved.
+#[derive(Debug, Clone)]
+pub struct Anonymizer {
+    inner: Arc<AnonymizerInner>,
+}
+
+#[derive(Debug)]
+struct AnonymizerInner {
+    scopes: Vec<AnonymizeScope>,
+    hops: u8,
+    strategy: AnonymizeStrategy,
+    tokens: Mutex<HashMap<IpAddr, IpAddr>>,
+}
+
+impl Anonymizer {
+    pub fn new(scopes: Vec<AnonymizeScope>, hops: u8, strategy: AnonymizeStrategy) -> Self {
+        Self {
+            inner: Arc::new(AnonymizerInner {
+                scopes,
+                hops,
+                strategy,
+                tokens: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Hide the target of a trace, replacing its hostname with the placeholder address, if it is in scope.
+    ///
+    /// This must be called for every trace before any host is recorded such that the placeholder of each target is the
+    /// same in every run.
+    pub fn anonymize_target(&self, info: &mut TuiTraceInfo) {
+        let addr = self.anonymize_addr(info.target_addr, None, info.target_addr);
+        if addr != info.target_addr {
+            info.target_hostname = addr.to_string();
+            info.target_addr = addr;
+        }
+    }
+
+    /// Hide the host which responded to `probe` of the trace to `target`, if it is in scope.
+    pub fn anonymize_probe(&self, probe: &Probe, target: IpAddr) -> Probe {
+        Probe {
+            host: probe
+                .host
+                .map(|host| self.anonymize_addr(host, Some(probe.ttl.0), target)),
+            ..*probe
+        }
+    }
+
+    /// Hide `addr`, observed at `ttl` of the trace to `target`, if it is in scope.
+    pub fn anonymize_addr(&self, addr: IpAddr, ttl: Option<u8>, target: IpAddr) -> IpAddr {
+        if self.in_scope(addr, ttl, target) {
+            self.replace(addr)
+        } else {
+            addr
+        }
+    }
+
+    /// Is `addr`, observed at `ttl` of the trace to `target`, hidden by any of the scopes?
+    fn in_scope(&self, addr: IpAddr, ttl: Option<u8>, target: IpAddr) -> bool {
+        self.inner.scopes.iter().any(|scope| match scope {
+            AnonymizeScope::Private => is_private(addr),
+            AnonymizeScope::Hops => ttl.is_some_and(|ttl| ttl <= self.inner.hops),
+            AnonymizeScope::Target => addr == target,
+        })
+    }
+
+    /// The placeholder of `addr` for the strategy.
+    fn replace(&self, addr: IpAddr) -> IpAddr {
+        match self.inner.strategy {
+            AnonymizeStrategy::Mask => mask(addr),
+            AnonymizeStrategy::Token => {
+                let mut tokens = self.inner.tokens.lock();
+                let index = tokens
+                    .keys()
+                    .filter(|token| token.is_ipv4() == addr.is_ipv4())
+                    .count();
+                *tokens.entry(addr).or_insert_with(|| token(addr, index))
+            }
+        }
+    }
+}
+
+/// Is `addr` a private (RFC 1918, RFC 6598 or unique local) or link-local address?
+fn is_private(addr: IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(addr) => {
+            let [a, b, ..] = addr.octets();
+            addr.is_private() || addr.is_link_local() || (a == 100 && b & 0xc0 == 64)
+        }
+        IpAddr::V6(addr) => addr.is_unique_local() || addr.is_unicast_link_local(),
+    }
+}
+
+/// Zero the last octet of an `IPv4` address, or all but the `/48` prefix of an `IPv6` address.
+fn mask(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V4(addr) => {
+            let [a, b, c, _] = addr.octets();
+            IpAddr::V4(Ipv4Addr::new(a, b, c, 0))
+        }
+        IpAddr::V6(addr) => {
+            let [a, b, c, ..] = addr.segments();
+            IpAddr::V6(Ipv6Addr::new(a, b, c, 0, 0, 0, 0, 0))
+        }
+    }
+}
+
+/// The placeholder for the `index`th distinct address of the family of `addr`, from the documentation networks.
+///
+/// The `IPv4` placeholders wrap once all three documentation networks are exhausted.
+fn token(addr: IpAddr, index: usize) -> IpAddr {
+    match addr {
+        IpAddr::V4(_) => {
+            let [a, b, c] = TOKEN_NETWORKS_V4[index / 254 % TOKEN_NETWORKS_V4.len()];
+            IpAddr::V4(Ipv4Addr::new(a, b, c, (index % 254) as u8 + 1))
+        }
+        IpAddr::V6(_) => {
+            let index = index as u32 + 1;
+            IpAddr::V6(Ipv6Addr::new(
+                0x2001,
+                0xdb8,
+                0,
+                0,
+                0,
+                0,
+                (index >> 16) as u16,
+                index as u16,
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_token_allocation() {
+        let anonymizer = Anonymizer::new(vec![AnonymizeScope::Hops], 255, AnonymizeStrategy::Token);
+        let target = addr("8.8.8.8");
+        let replace = |host: &str| anonymizer.anonymize_addr(addr(host), Some(1), target);
+        assert_eq!(addr("192.0.2.1"), replace("10.0.0.1"));
+        assert_eq!(addr("192.0.2.2"), replace("10.0.0.2"));
+        assert_eq!(addr("2001:db8::1"), replace("fd00::1"));
+        assert_eq!(addr("192.0.2.1"), replace("10.0.0.1"));
+        assert_eq!(addr("192.0.2.3"), replace("10.0.0.3"));
+        assert_eq!(addr("2001:db8::2"), replace("fd00::2"));
+        assert_eq!(addr("2001:db8::1"), replace("fd00::1"));
+    }
+
+    #[test]
+    fn test_token_networks() {
+        assert_eq!(addr("192.0.2.254"), token(addr("10.0.0.1"), 253));
+        assert_eq!(addr("198.51.100.1"), token(addr("10.0.0.1"), 254));
+        assert_eq!(addr("203.0.113.254"), token(addr("10.0.0.1"), 761));
+        assert_eq!(addr("192.0.2.1"), token(addr("10.0.0.1"), 762));
+        assert_eq!(addr("2001:db8::1:0"), token(addr("fd00::1"), 65535));
+    }
+
+    #[test]
+    fn test_mask() {
+        assert_eq!(addr("10.1.2.0"), mask(addr("10.1.2.3")));
+        assert_eq!(addr("2001:db8:1::"), mask(addr("2001:db8:1:2::3")));
+    }
+
+    #[test]
+    fn test_scopes() {
+        let target = addr("8.8.8.8");
+        let private = Anonymizer::new(vec![AnonymizeScope::Private], 0, AnonymizeStrategy::Mask);
+        assert_eq!(
+            addr("10.0.0.0"),
+            private.anonymize_addr(addr("10.0.0.1"), Some(5), target)
+        );
+        assert_eq!(
+            addr("100.64.1.0"),
+            private.anonymize_addr(addr("100.64.1.1"), None, target)
+        );
+        assert_eq!(
+            addr("1.1.1.1"),
+            private.anonymize_addr(addr("1.1.1.1"), Some(1), target)
+        );
+        let hops = Anonymizer::new(vec![AnonymizeScope::Hops], 2, AnonymizeStrategy::Mask);
+        assert_eq!(
+            addr("1.1.1.0"),
+            hops.anonymize_addr(addr("1.1.1.1"), Some(2), target)
+        );
+        assert_eq!(
+            addr("1.1.1.1"),
+            hops.anonymize_addr(addr("1.1.1.1"), Some(3), target)
+        );
+        assert_eq!(
+            addr("1.1.1.1"),
+            hops.anonymize_addr(addr("1.1.1.1"), None, target)
+        );
+        let target_scope =
+            Anonymizer::new(vec![AnonymizeScope::Target], 0, AnonymizeStrategy::Mask);
+        assert_eq!(
+            addr("8.8.8.0"),
+            target_scope.anonymize_addr(target, None, target)
+        );
+        assert_eq!(
+            addr("1.1.1.1"),
+            target_scope.anonymize_addr(addr("1.1.1.1"), None, target)
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use anyhow::anyhow;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A cheaply cloneable table of the organization name of each autonomous system (AS), read from a local file.
+#[derive(Clone)]
+pub struct AsnNames {
+    names: Arc<HashMap<String, String>>,
+}
+
+impl AsnNames {
+    /// Load the ASN to organization name table at `path`.
+    ///
+    /// The table is read into memory once and may be in either of the following formats:
+    ///
+    /// - The CAIDA `as-org2info` format, in which `aut|changed|aut_name|org_id|opaque_id|source` lines map each AS to
+    ///   an organization and `org_id|changed|org_name|country|source` lines name each organization
+    /// - One AS per line, as `15169 Google LLC` (such as the RIPE `asn.txt` format), `AS15169 Google LLC` or
+    ///   `15169|Google LLC`
+    ///
+    /// Blank lines and lines starting with `#` are ignored, other than the CAIDA `# format:` headers.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let data = fs::read_to_string(path)
+            .map_err(|err| anyhow!("failed to read ASN names {}: {}", path.display(), err))?;
+        Ok(Self {
+            names: Arc::new(parse_asn_names(&data)),
+        })
+    }
+
+    /// The organization name of the AS with number `asn`, if it is in the table.
+    pub fn name(&self, asn: &str) -> Option<&str> {
+        self.names.get(asn).map(String::as_str)
+    }
+}
+
+impl Debug for AsnNames {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsnNames")
+            .field("len", &self.names.len())
+            .finish()
+    }
+}
+
+/// Format the AS number `asn`, followed by its organization name if it is in the table, such as `AS15169 Google LLC`.
+pub fn format_asn(asn: &str, asn_names: Option<&AsnNames>) -> String {
+    match asn_names.and_then(|names| names.name(asn)) {
+        Some(name) => format!("AS{asn} {name}"),
+        None => format!("AS{asn}"),
+    }
+}
+
+/// A section of a CAIDA `as-org2info` file.
+#[derive(Clone, Copy)]
+enum Section {
+    Other,
+    Aut,
+    Org,
+}
+
+fn parse_asn_names(data: &str) -> HashMap<String, String> {
+    let mut section = Section::Other;
+    let mut names = HashMap::new();
+    let mut aut_orgs = vec![];
+    let mut orgs = HashMap::new();
+    for line in data.lines().map(str::trim) {
+        if let Some(format) = line.strip_prefix("# format:") {
+            section = match format.trim().split('|').next() {
+                Some("aut") => Section::Aut,
+                Some("org_id") => Section::Org,
+                _ => Section::Other,
+            };
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match section {
+            Section::Aut => {
+                let fields: Vec<_> = line.split('|').collect();
+                if let [aut, _, aut_name, org_id, ..] = fields[..] {
+                    names.insert(aut.to_string(), aut_name.to_string());
+                    aut_orgs.push((aut.to_string(), org_id.to_string()));
+                }
+            }
+            Section::Org => {
+                let fields: Vec<_> = line.split('|').collect();
+                if let [org_id, _, org_name, ..] = fields[..] {
+                    orgs.insert(org_id.to_string(), org_name.to_string());
+                }
+            }
+            Section::Other => {
+                let (asn, name) = line
+                    .split_once('|')
+                    .or_else(|| line.split_once(char::is_whitespace))
+                    .unwrap_or((line, ""));
+                let asn = asn.trim();
+                let asn = asn
+                    .strip_prefix("AS")
+                    .or_else(|| asn.strip_prefix("as"))
+                    .unwrap_or(asn);
+                let name = name.trim();
+                if !name.is_empty() && asn.chars().all(|c| c.is_ascii_digit()) {
+                    names.insert(asn.to_string(), name.to_string());
+                }
+            }
+        }
+    }
+    for (aut, org_id) in aut_orgs {
+        if let Some(org_name) = orgs.get(&org_id).filter(|name| !name.is_empty()) {
+            names.insert(aut, org_name.clone());
+        }
+    }
+    names
+}
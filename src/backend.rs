@@ -1,30 +1,208 @@
+use crate::anonymize::Anonymizer;
 use crate::config::MAX_HOPS;
+use itertools::Itertools;
 use parking_lot::RwLock;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use std::net::{IpAddr, Ipv4Addr};
+use std::ops::RangeInclusive;
 use std::sync::Arc;
-use std::time::Duration;
-use trippy::tracing::{Probe, ProbeStatus, Tracer, TracerChannel, TracerConfig, TracerRound};
+use std::time::{Duration, SystemTime};
+use trippy::tracing::{
+    CompletionReason, IcmpTimestamps, MplsLabelStack, Probe, ProbeStatus, TcpOutcome, Tracer,
+    TracerChannel, TracerConfig, TracerRound, UnreachableCode,
+};
+
+/// The minimum increase (ms) in the average round-trip time from the prior hop for a hop to be considered a likely
+/// return path artifact.
+const RETURN_PATH_MIN_JUMP_MS: f64 = 10_f64;
+
+/// The maximum number of address changes recorded for each hop, later changes are only counted.
+const MAX_ADDR_CHANGES: usize = 16;
+
+/// The minimum fraction of the increase in average round-trip time which a downstream hop must recover for a hop to be
+/// considered a likely return path artifact.
+const RETURN_PATH_MIN_RECOVERY: f64 = 0.5;
+
+/// The configuration of a `Trace`.
+#[derive(Debug, Clone)]
+pub struct TraceConfig {
+    /// The maximum number of samples to record per hop.
+    pub max_samples: usize,
+    /// The upper bound of each bucket of the round-trip time histogram of every hop.
+    pub histogram_buckets: Vec<Duration>,
+    /// The percentiles (%) of the round-trip time of every hop which are shown.
+    pub percentiles: Vec<f64>,
+    /// The time after a probe is sent during which it is counted as pending, rather than lost.
+    pub zero_loss_grace: Duration,
+    /// The destination ports of `tcp` probes, indexed by flow id, if configured.
+    pub tcp_dest_ports: Vec<u16>,
+    /// The smoothing factor of the exponentially weighted moving average round-trip time of every hop.
+    pub ewma_alpha: f64,
+    /// The sliding window over which the loss and round-trip time statistics of every hop are computed, if any.
+    pub stats_window: Option<Duration>,
+    /// The address to which the statistics of each pinned hop are restricted, keyed by ttl.
+    pub pinned_hops: BTreeMap<u8, IpAddr>,
+}
 
 /// The state of all hops in a trace.
 #[derive(Debug, Clone)]
 pub struct Trace {
     max_samples: usize,
+    histogram_buckets: Vec<Duration>,
+    percentiles: Vec<f64>,
+    zero_loss_grace: Duration,
+    tcp_dest_ports: Vec<u16>,
+    ewma_alpha: f64,
+    stats_window: Option<Duration>,
+    pinned_hops: BTreeMap<u8, IpAddr>,
     lowest_ttl: u8,
     highest_ttl: u8,
     round: usize,
+    round_offset: usize,
+    backoff: u32,
+    unreachable_rounds: usize,
+    destination_ttl: Option<u8>,
     hops: Vec<Hop>,
+    unpinned_hops: Vec<Hop>,
+    flows: BTreeMap<u16, Vec<Option<IpAddr>>>,
+    edges: BTreeMap<(IpAddr, IpAddr), usize>,
+    proxy: Option<ProxyStats>,
+    target_addr: Option<IpAddr>,
+    target_changes: usize,
+}
+
+/// The accumulated statistics of every hop and flow of a `Trace`, which may be saved and later restored.
+///
+/// The configuration of the trace is not included, nor is the latest transient state of each hop, such as its `MPLS`
+/// labels, `ICMP` timestamps and `tcp` outcomes, which is only known once the restored trace next receives a response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceState {
+    round: usize,
+    lowest_ttl: u8,
+    highest_ttl: u8,
+    hops: Vec<Hop>,
+    flows: BTreeMap<u16, Vec<Option<IpAddr>>>,
 }
 
 impl Trace {
-    pub fn new(max_samples: usize) -> Self {
+    pub fn new(config: TraceConfig) -> Self {
+        let TraceConfig {
+            max_samples,
+            histogram_buckets,
+            percentiles,
+            zero_loss_grace,
+            tcp_dest_ports,
+            ewma_alpha,
+            stats_window,
+            pinned_hops,
+        } = config;
         Self {
             max_samples,
+            histogram_buckets,
+            percentiles,
+            zero_loss_grace,
+            tcp_dest_ports,
+            ewma_alpha,
+            stats_window,
+            pinned_hops,
             lowest_ttl: 0,
             highest_ttl: 0,
             round: 0,
+            round_offset: 0,
+            backoff: 1,
+            unreachable_rounds: 0,
+            destination_ttl: None,
             hops: (0..MAX_HOPS).map(|_| Hop::default()).collect(),
+            unpinned_hops: (0..MAX_HOPS).map(|_| Hop::default()).collect(),
+            flows: BTreeMap::new(),
+            edges: BTreeMap::new(),
+            proxy: None,
+            target_addr: None,
+            target_changes: 0,
+        }
+    }
+
+    /// Create a trace of the hops of a prior trace, such as one read from a report, for display only.
+    ///
+    /// The trace is never updated from a tracer and has no samples, the target is the hop with the highest ttl and
+    /// the destination is reached at `destination_ttl`, if any.
+    pub fn replay(hops: &[HopStats], destination_ttl: Option<u8>) -> Self {
+        let mut trace = Self::new(TraceConfig {
+            max_samples: 0,
+            histogram_buckets: vec![],
+            percentiles: vec![],
+            zero_loss_grace: Duration::ZERO,
+            tcp_dest_ports: vec![],
+            ewma_alpha: 0_f64,
+            stats_window: None,
+            pinned_hops: BTreeMap::new(),
+        });
+        for stats in hops.iter().filter(|stats| stats.ttl > 0) {
+            if trace.lowest_ttl == 0 {
+                trace.lowest_ttl = stats.ttl;
+            } else {
+                trace.lowest_ttl = trace.lowest_ttl.min(stats.ttl);
+            }
+            trace.highest_ttl = trace.highest_ttl.max(stats.ttl);
+            trace.hops[usize::from(stats.ttl) - 1] = Hop::from(stats);
         }
+        trace.round = hops
+            .iter()
+            .map(|stats| stats.sent)
+            .max()
+            .unwrap_or_default()
+            .saturating_sub(1);
+        trace.destination_ttl = destination_ttl;
+        trace
+    }
+
+    /// The accumulated statistics of this trace, to be restored by `restore`.
+    pub fn state(&self) -> TraceState {
+        TraceState {
+            round: self.round,
+            lowest_ttl: self.lowest_ttl,
+            highest_ttl: self.highest_ttl,
+            hops: self.hops.clone(),
+            flows: self.flows.clone(),
+        }
+    }
+
+    /// Replace the statistics of this trace with those of a prior trace, such that the trace continues from them.
+    ///
+    /// The rounds of the tracer, which always starts from the first round, follow the last round of the prior trace.
+    /// Samples beyond the configured maximum are discarded, as are the histograms if the buckets have since changed.
+    pub fn restore(&mut self, state: TraceState) {
+        if state.hops.len() != MAX_HOPS || state.highest_ttl == 0 {
+            return;
+        }
+        self.round = state.round;
+        self.round_offset = state.round + 1;
+        self.lowest_ttl = state.lowest_ttl;
+        self.highest_ttl = state.highest_ttl;
+        self.hops = state.hops;
+        for hop in &mut self.hops {
+            hop.samples.truncate(self.max_samples);
+            hop.sample_times.truncate(self.max_samples);
+            if hop.histogram.len() != self.histogram_buckets.len() + 1 {
+                hop.histogram.clear();
+            }
+        }
+        self.flows = state
+            .flows
+            .into_iter()
+            .filter(|(_, hosts)| hosts.len() == MAX_HOPS)
+            .collect();
+    }
+
+    /// The factor by which the round duration is scaled by adaptive rate probing, 1 if not slowed.
+    pub fn backoff(&self) -> u32 {
+        self.backoff
+    }
+
+    /// The number of consecutive completed rounds, up to and including the latest, in which the target did not respond.
+    pub fn unreachable_rounds(&self) -> usize {
+        self.unreachable_rounds
     }
 
     /// The current round of tracing.
@@ -32,14 +210,117 @@ impl Trace {
         self.round
     }
 
+    /// The time after a probe is sent during which it is counted as pending, rather than lost.
+    pub fn zero_loss_grace(&self) -> Duration {
+        self.zero_loss_grace
+    }
+
+    /// The destination ports of `tcp` probes, indexed by flow id, if configured.
+    pub fn tcp_dest_ports(&self) -> &[u16] {
+        &self.tcp_dest_ports
+    }
+
+    /// The smoothing factor of the exponentially weighted moving average round-trip time of every hop.
+    pub fn ewma_alpha(&self) -> f64 {
+        self.ewma_alpha
+    }
+
+    /// The sliding window over which the loss and round-trip time statistics of every hop are computed, if any.
+    pub fn stats_window(&self) -> Option<Duration> {
+        self.stats_window
+    }
+
+    /// The address to which the statistics of each pinned hop are restricted, keyed by ttl.
+    pub fn pinned_hops(&self) -> &BTreeMap<u8, IpAddr> {
+        &self.pinned_hops
+    }
+
+    /// The target host of the most recent round, if any.
+    pub const fn target_addr(&self) -> Option<IpAddr> {
+        self.target_addr
+    }
+
+    /// The number of times the target host has changed, such as when re-resolved.
+    pub const fn target_changes(&self) -> usize {
+        self.target_changes
+    }
+
+    /// The ttl at which the target host responded in the most recent round, if it did.
+    pub fn destination_ttl(&self) -> Option<u8> {
+        self.destination_ttl
+    }
+
+    /// The upper bound of each bucket of the round-trip time histogram of every hop.
+    pub fn histogram_buckets(&self) -> &[Duration] {
+        &self.histogram_buckets
+    }
+
+    /// The percentiles (%) of the round-trip time of every hop which are shown.
+    pub fn percentiles(&self) -> &[f64] {
+        &self.percentiles
+    }
+
     /// Information about each hop in the trace.
     pub fn hops(&self) -> &[Hop] {
+        &self.hops[self.hop_range()]
+    }
+
+    /// Information about the responses from any other address than the pinned address of the hop at the same index of
+    /// `hops`, which are excluded from the statistics of that hop.
+    pub fn unpinned_hops(&self) -> &[Hop] {
+        &self.unpinned_hops[self.hop_range()]
+    }
+
+    /// The most recent host observed at each hop for each flow, keyed by flow id.
+    pub fn flows(&self) -> impl Iterator<Item = (u16, &[Option<IpAddr>])> {
+        let range = self.hop_range();
+        self.flows
+            .iter()
+            .map(move |(flow_id, hosts)| (*flow_id, &hosts[range.clone()]))
+    }
+
+    /// The number of rounds in which each pair of hosts responded at consecutive responding ttls, keyed by the host at
+    /// the lower ttl and then the host at the higher ttl.
+    pub fn edges(&self) -> impl Iterator<Item = (IpAddr, IpAddr, usize)> + '_ {
+        self.edges
+            .iter()
+            .map(|(&(from, to), &count)| (from, to, count))
+    }
+
+    /// The distinct paths observed, each with the flows which followed it.
+    ///
+    /// Flows which observed the same host at every hop which both have observed are considered to have followed the same
+    /// path, a hop which either flow has not yet observed, such as one which has not responded to it, never separates
+    /// them.  The hosts of such a path are those observed by any of its flows.
+    ///
+    /// Note that each round probes a single flow, the next in turn, and so a flow is only observed once every `max_flows`
+    /// rounds.
+    pub fn paths(&self) -> Vec<FlowPath> {
+        let mut paths: Vec<FlowPath> = vec![];
+        for (flow_id, hosts) in self.flows() {
+            match paths.iter_mut().find(|path| path.matches(hosts)) {
+                Some(path) => {
+                    path.flow_ids.push(flow_id);
+                    for (path_host, host) in path.hosts.iter_mut().zip(hosts) {
+                        if path_host.is_none() {
+                            *path_host = *host;
+                        }
+                    }
+                }
+                None => paths.push(FlowPath {
+                    flow_ids: vec![flow_id],
+                    hosts: hosts.to_vec(),
+                }),
+            }
+        }
+        paths
+    }
+
+    fn hop_range(&self) -> std::ops::Range<usize> {
         if self.lowest_ttl == 0 || self.highest_ttl == 0 {
-            &[]
+            0..0
         } else {
-            let start = (self.lowest_ttl as usize) - 1;
-            let end = self.highest_ttl as usize;
-            &self.hops[start..end]
+            (self.lowest_ttl as usize) - 1..self.highest_ttl as usize
         }
     }
 
@@ -53,6 +334,59 @@ impl Trace {
         self.highest_ttl == hop.ttl
     }
 
+    /// The number of rounds since `hop` last received a response, zero if it did in the current round and `None` if it
+    /// never has.
+    pub fn rounds_since_response(&self, hop: &Hop) -> Option<usize> {
+        hop.last_recv_round
+            .map(|round| self.round.saturating_sub(round))
+    }
+
+    /// Was `hop` probed but has never responded, and is not the target hop?
+    pub fn is_unresponsive(&self, hop: &Hop) -> bool {
+        hop.total_recv == 0 && !hop.is_skipped() && !self.is_target(hop)
+    }
+
+    /// The rows of a table of the hops at `order`, the indices of `hops` in the order in which they are shown.
+    ///
+    /// If `collapse` is set then each run of at least two unresponsive hops, which are at consecutive ttls and are
+    /// adjacent in `order`, is collapsed into a single row of the whole ttl range of the run.  Each pinned hop to which
+    /// any other address responded is followed by a row of the responses from those other addresses.
+    pub fn hop_rows(&self, order: &[usize], collapse: bool) -> Vec<HopRow> {
+        fn flush(run: &mut Vec<usize>, rows: &mut Vec<HopRow>) {
+            match (run.iter().min(), run.iter().max()) {
+                (Some(&first), Some(&last)) if first != last => {
+                    rows.push(HopRow::Unresponsive(first..=last));
+                }
+                (Some(&index), _) => rows.push(HopRow::Hop(index)),
+                _ => {}
+            }
+            run.clear();
+        }
+        let hops = self.hops();
+        let unpinned_hops = self.unpinned_hops();
+        let mut rows = vec![];
+        let mut run = vec![];
+        for &index in order {
+            if collapse && self.is_unresponsive(&hops[index]) {
+                if run
+                    .last()
+                    .is_some_and(|&last: &usize| last.abs_diff(index) != 1)
+                {
+                    flush(&mut run, &mut rows);
+                }
+                run.push(index);
+            } else {
+                flush(&mut run, &mut rows);
+                rows.push(HopRow::Hop(index));
+                if unpinned_hops[index].total_recv > 0 {
+                    rows.push(HopRow::Unpinned(index));
+                }
+            }
+        }
+        flush(&mut run, &mut rows);
+        rows
+    }
+
     /// Return the target `Hop`.
     ///
     /// TODO Do we guarantee there is always a target hop?
@@ -64,70 +398,339 @@ impl Trace {
         }
     }
 
+    /// The statistics of connections made to the target via a proxy, if any.
+    pub fn proxy(&self) -> Option<&ProxyStats> {
+        self.proxy.as_ref()
+    }
+
+    /// Record the result of connecting to `target` via a proxy, `None` if the connection failed.
+    pub fn update_from_proxy(&mut self, target: &str, rtt: Option<Duration>) {
+        let proxy = self.proxy.get_or_insert_with(|| ProxyStats {
+            target: target.to_string(),
+            ..ProxyStats::default()
+        });
+        proxy.total_sent += 1;
+        if let Some(rtt) = rtt {
+            proxy.total_recv += 1;
+            proxy.total_time += rtt;
+            proxy.best = proxy.best.map_or(Some(rtt), |d| Some(d.min(rtt)));
+            proxy.worst = proxy.worst.map_or(Some(rtt), |d| Some(d.max(rtt)));
+        }
+        proxy.last = rtt;
+    }
+
     /// Update the tracing state from a `TracerRound`.
+    ///
+    /// Any probe which was pending a response in the prior round is now considered to be lost, as responses are never
+    /// accepted after the round in which the probe was sent has completed.
+    ///
+    /// If the target host of the round differs from that of the prior round then the statistics of every hop, which
+    /// are those of the path to the prior target, are first discarded.
     pub fn update_from_round(&mut self, round: &TracerRound<'_>) {
+        if self
+            .target_addr
+            .is_some_and(|addr| addr != round.target_addr)
+        {
+            self.clear_hops();
+            self.target_changes += 1;
+        }
+        self.target_addr = Some(round.target_addr);
+        for hop in &mut self.hops {
+            hop.total_pending = 0;
+        }
+        let now = SystemTime::now();
         self.highest_ttl = self.highest_ttl.max(round.largest_ttl.0);
+        self.backoff = round.backoff;
+        self.destination_ttl = match round.reason {
+            CompletionReason::TargetFound => Some(round.largest_ttl.0),
+            CompletionReason::RoundTimeLimitExceeded => None,
+        };
+        self.unreachable_rounds = match round.reason {
+            CompletionReason::TargetFound => 0,
+            CompletionReason::RoundTimeLimitExceeded => self.unreachable_rounds + 1,
+        };
         for probe in round.probes {
-            self.update_from_probe(probe);
+            self.update_from_probe(probe, now);
+        }
+        self.update_edges(round);
+        let range = self.hop_range();
+        for (index, hop) in self
+            .hops
+            .iter_mut()
+            .enumerate()
+            .take(range.end)
+            .skip(range.start)
+        {
+            if hop.is_skipped() {
+                hop.ttl = u8::try_from(index + 1).unwrap_or(u8::MAX);
+            }
+        }
+        if let Some(window) = self.stats_window {
+            self.update_window_stats(now, window);
+        }
+        self.update_return_path_hints();
+    }
+
+    /// Discard the statistics of every hop and flow, continuing from the current round.
+    fn clear_hops(&mut self) {
+        self.lowest_ttl = 0;
+        self.highest_ttl = 0;
+        self.destination_ttl = None;
+        self.hops = (0..MAX_HOPS).map(|_| Hop::default()).collect();
+        self.unpinned_hops = (0..MAX_HOPS).map(|_| Hop::default()).collect();
+        self.flows.clear();
+        self.edges.clear();
+    }
+
+    /// Count the edges between the hosts which responded at consecutive responding ttls, up to the largest ttl, of a
+    /// round.
+    ///
+    /// Where several hosts responded at a ttl, such as to several probes per round, every host is joined to every host
+    /// of the next responding ttl.  A host which responded at consecutive ttls, such as the target host, is not joined
+    /// to itself.
+    fn update_edges(&mut self, round: &TracerRound<'_>) {
+        let mut hosts: BTreeMap<u8, Vec<IpAddr>> = BTreeMap::new();
+        for probe in round
+            .probes
+            .iter()
+            .filter(|probe| probe.status == ProbeStatus::Complete && probe.ttl <= round.largest_ttl)
+        {
+            if let Some(host) = probe.host {
+                let ttl_hosts = hosts.entry(probe.ttl.0).or_default();
+                if !ttl_hosts.contains(&host) {
+                    ttl_hosts.push(host);
+                }
+            }
+        }
+        for (prior, next) in hosts.values().tuple_windows() {
+            for (&from, &to) in prior.iter().cartesian_product(next) {
+                if from != to {
+                    *self.edges.entry((from, to)).or_default() += 1;
+                }
+            }
         }
     }
 
-    fn update_from_probe(&mut self, probe: &Probe) {
+    /// Evict the samples of every hop which are older than the stats `window` and compute the statistics of those which
+    /// remain.
+    fn update_window_stats(&mut self, now: SystemTime, window: Duration) {
+        let range = self.hop_range();
+        for hop in &mut self.hops[range] {
+            let retained = hop
+                .sample_times
+                .iter()
+                .take_while(|&&time| now.duration_since(time).unwrap_or_default() <= window)
+                .count();
+            hop.samples.truncate(retained);
+            hop.sample_times.truncate(retained);
+            hop.window = Some(WindowStats::new(&hop.samples, hop.total_pending));
+        }
+    }
+
+    /// Flag the hops whose round-trip time is likely inflated by the return path, rather than the forward path.
+    ///
+    /// A hop is flagged if its average round-trip time jumps by at least `RETURN_PATH_MIN_JUMP_MS` from the nearest
+    /// prior responding hop and the nearest downstream responding hop recovers at least `RETURN_PATH_MIN_RECOVERY` of
+    /// that jump.  As any delay on the forward path would also be observed by all downstream hops, such a jump is
+    /// most likely caused by congestion on the return path from that hop, or by the hop deprioritizing the generation
+    /// of `ICMP` responses, and not by a forward bottleneck.
+    fn update_return_path_hints(&mut self) {
+        let range = self.hop_range();
+        let responding: Vec<_> = range
+            .clone()
+            .filter(|&i| self.hops[i].total_recv > 0)
+            .collect();
+        for i in range {
+            self.hops[i].return_path_hint = false;
+        }
+        for window in responding.windows(3) {
+            let [prior, hop, next] =
+                [window[0], window[1], window[2]].map(|i| self.hops[i].avg_ms());
+            let jump = hop - prior;
+            if jump >= RETURN_PATH_MIN_JUMP_MS && hop - next >= jump * RETURN_PATH_MIN_RECOVERY {
+                self.hops[window[1]].return_path_hint = true;
+            }
+        }
+    }
+
+    fn update_from_probe(&mut self, probe: &Probe, now: SystemTime) {
         let index = usize::from(probe.ttl.0) - 1;
         if self.lowest_ttl == 0 {
             self.lowest_ttl = probe.ttl.0;
         } else {
             self.lowest_ttl = self.lowest_ttl.min(probe.ttl.0);
         }
-        self.round = self.round.max(probe.round.0);
+        let round = self.round_offset + probe.round.0;
+        self.round = self.round.max(round);
         match probe.status {
             ProbeStatus::Complete => {
-                let hop = &mut self.hops[index];
+                let unpinned = self
+                    .pinned_hops
+                    .get(&probe.ttl.0)
+                    .is_some_and(|&addr| probe.host != Some(addr));
+                let hop = if unpinned {
+                    &mut self.unpinned_hops[index]
+                } else {
+                    &mut self.hops[index]
+                };
                 hop.ttl = probe.ttl.0;
                 hop.total_sent += 1;
                 hop.total_recv += 1;
                 let dur = probe.duration();
                 let dur_ms = dur.as_secs_f64() * 1000_f64;
                 hop.total_time += dur;
+                if let Some(prev) = hop.last {
+                    let delta = dur.abs_diff(prev).as_secs_f64() * 1000_f64;
+                    hop.jitter += (delta - hop.jitter) / 16_f64;
+                }
                 hop.last = Some(dur);
-                hop.samples.insert(0, dur);
+                hop.ewma = Some(
+                    hop.ewma
+                        .map_or(dur_ms, |ewma| self.ewma_alpha.mul_add(dur_ms - ewma, ewma)),
+                );
+                hop.push_sample(dur, probe.sent.unwrap_or(now), self.max_samples);
                 hop.best = hop.best.map_or(Some(dur), |d| Some(d.min(dur)));
                 hop.worst = hop.worst.map_or(Some(dur), |d| Some(d.max(dur)));
                 hop.mean += (dur_ms - hop.mean) / hop.total_recv as f64;
                 hop.m2 += (dur_ms - hop.mean) * (dur_ms - hop.mean);
-                if hop.samples.len() > self.max_samples {
-                    hop.samples.pop();
-                }
+                hop.histogram.resize(self.histogram_buckets.len() + 1, 0);
+                hop.histogram[self
+                    .histogram_buckets
+                    .partition_point(|&bucket| bucket < dur)] += 1;
                 let host = probe.host.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+                if !hop.addrs.contains_key(&host) {
+                    if !hop.addrs.is_empty() {
+                        hop.record_addr_change(host, round);
+                    }
+                    hop.first_seen.push(host);
+                }
                 *hop.addrs.entry(host).or_default() += 1;
+                hop.last_addr = Some(host);
+                hop.last_sequence = Some(probe.sequence.0);
+                hop.last_recv_sequence = Some(probe.sequence.0);
+                hop.last_recv_round = Some(round);
+                if probe.mpls.is_some() {
+                    hop.mpls = probe.mpls;
+                }
+                if probe.icmp_timestamps.is_some() {
+                    hop.icmp_timestamps = probe.icmp_timestamps;
+                }
+                if probe.tcp_outcome.is_some() {
+                    hop.tcp_outcome = probe.tcp_outcome;
+                }
+                if probe.unreachable_code.is_some() {
+                    hop.unreachable_code = probe.unreachable_code;
+                }
+                if let Some(&port) = self.tcp_dest_ports.get(usize::from(probe.flow_id.0)) {
+                    hop.tcp_dest_ports.insert(port);
+                    if let Some(outcome) = probe.tcp_outcome {
+                        hop.tcp_port_outcomes.insert(port, outcome);
+                    }
+                }
+                self.flows
+                    .entry(probe.flow_id.0)
+                    .or_insert_with(|| vec![None; MAX_HOPS])[index] = Some(host);
             }
             ProbeStatus::Awaited => {
+                let pending = probe.sent.is_some_and(|sent| {
+                    now.duration_since(sent).unwrap_or_default() < self.zero_loss_grace
+                });
+                if pending {
+                    self.hops[index].total_pending += 1;
+                }
                 self.hops[index].total_sent += 1;
                 self.hops[index].ttl = probe.ttl.0;
-                self.hops[index].samples.insert(0, Duration::default());
-                if self.hops[index].samples.len() > self.max_samples {
-                    self.hops[index].samples.pop();
-                }
+                self.hops[index].last_sequence = Some(probe.sequence.0);
+                self.hops[index].push_sample(
+                    Duration::default(),
+                    probe.sent.unwrap_or(now),
+                    self.max_samples,
+                );
             }
             ProbeStatus::NotSent => {}
         }
     }
 }
 
-/// Information about a single `Hop` within a `Trace`.
+/// A row of a table of hops, by index of the hops of a `Trace`.
 #[derive(Debug, Clone)]
+pub enum HopRow {
+    /// A single hop.
+    Hop(usize),
+    /// A run of unresponsive hops at consecutive ttls, from the lowest to the highest index, collapsed into one row.
+    Unresponsive(RangeInclusive<usize>),
+    /// The responses from any other address than the pinned address of a hop, by index of the unpinned hops.
+    Unpinned(usize),
+}
+
+/// A distinct path through the network and the flows which followed it.
+#[derive(Debug, Clone)]
+pub struct FlowPath {
+    /// The ids of the flows which followed this path.
+    pub flow_ids: Vec<u16>,
+    /// The host observed at each hop of this path, if any.
+    pub hosts: Vec<Option<IpAddr>>,
+}
+
+impl FlowPath {
+    /// Did a flow which observed `hosts` follow this path, observing the same host at every hop which both observed?
+    fn matches(&self, hosts: &[Option<IpAddr>]) -> bool {
+        self.hosts.iter().zip(hosts).all(|pair| match pair {
+            (Some(path_host), Some(host)) => path_host == host,
+            _ => true,
+        })
+    }
+}
+
+/// Information about a single `Hop` within a `Trace`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Hop {
     ttl: u8,
     addrs: HashMap<IpAddr, usize>,
+    first_seen: Vec<IpAddr>,
     total_sent: usize,
     total_recv: usize,
+    total_pending: usize,
     total_time: Duration,
     last: Option<Duration>,
+    last_addr: Option<IpAddr>,
+    last_sequence: Option<u16>,
+    last_recv_sequence: Option<u16>,
+    last_recv_round: Option<usize>,
     best: Option<Duration>,
     worst: Option<Duration>,
     mean: f64,
     m2: f64,
-    samples: Vec<Duration>,
+    jitter: f64,
+    ewma: Option<f64>,
+    samples: VecDeque<Duration>,
+    sample_times: VecDeque<SystemTime>,
+    #[serde(skip)]
+    window: Option<WindowStats>,
+    histogram: Vec<usize>,
+    #[serde(skip)]
+    mpls: Option<MplsLabelStack>,
+    #[serde(skip)]
+    icmp_timestamps: Option<IcmpTimestamps>,
+    #[serde(skip)]
+    tcp_outcome: Option<TcpOutcome>,
+    #[serde(skip)]
+    tcp_port_outcomes: BTreeMap<u16, TcpOutcome>,
+    #[serde(skip)]
+    unreachable_code: Option<UnreachableCode>,
+    tcp_dest_ports: BTreeSet<u16>,
+    return_path_hint: bool,
+    addr_change_count: usize,
+    addr_changes: Vec<AddrChange>,
+}
+
+/// A new address which responded at a hop after another address had already responded.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct AddrChange {
+    /// The new address.
+    pub addr: IpAddr,
+    /// The round in which the new address first responded.
+    pub round: usize,
 }
 
 impl Hop {
@@ -145,6 +748,11 @@ impl Hop {
         self.addrs.iter()
     }
 
+    /// The position of `addr` in the order in which the addresses of this hop first responded, if it has.
+    pub fn first_seen(&self, addr: &IpAddr) -> Option<usize> {
+        self.first_seen.iter().position(|seen| seen == addr)
+    }
+
     /// The number of unique address observed for this time-to-live.
     pub fn addr_count(&self) -> usize {
         self.addrs.len()
@@ -155,16 +763,35 @@ impl Hop {
         self.total_sent
     }
 
+    /// Was this hop skipped, such that no probe was ever sent to it?
+    ///
+    /// This is the case for the ttls between those probed when a ttl stride greater than one is configured.
+    pub const fn is_skipped(&self) -> bool {
+        self.total_sent == 0
+    }
+
     /// The total number of probes responses received.
     pub fn total_recv(&self) -> usize {
         self.total_recv
     }
 
-    /// The % of packets that are lost.
+    /// The number of probes from the most recent round which have not received a response but were sent within the
+    /// zero loss grace period and so are not yet considered lost.
+    pub fn total_pending(&self) -> usize {
+        self.total_pending
+    }
+
+    /// The % of packets that are lost, excluding those pending a response.
+    ///
+    /// If a stats window is configured this is the % of the packets sent within the window.
     pub fn loss_pct(&self) -> f64 {
-        if self.total_sent > 0 {
-            let lost = self.total_sent - self.total_recv;
-            lost as f64 / self.total_sent as f64 * 100f64
+        if let Some(window) = &self.window {
+            return window.loss_pct;
+        }
+        let settled = self.total_sent - self.total_pending;
+        if settled > 0 {
+            let lost = settled - self.total_recv;
+            lost as f64 / settled as f64 * 100f64
         } else {
             0_f64
         }
@@ -175,38 +802,229 @@ impl Hop {
         self.last.map(|last| last.as_secs_f64() * 1000_f64)
     }
 
-    /// The duration of the best probe observed.
+    /// The address which responded to the most recent probe which received a response.
+    pub fn last_addr(&self) -> Option<IpAddr> {
+        self.last_addr
+    }
+
+    /// The sequence number of the most recent probe sent.
+    pub fn last_sequence(&self) -> Option<u16> {
+        self.last_sequence
+    }
+
+    /// Did the most recent probe sent receive a response?
+    pub fn is_responding(&self) -> bool {
+        self.last_sequence.is_some() && self.last_sequence == self.last_recv_sequence
+    }
+
+    /// The duration of the best probe observed, within the stats window if configured.
     pub fn best_ms(&self) -> Option<f64> {
-        self.best.map(|last| last.as_secs_f64() * 1000_f64)
+        self.window
+            .as_ref()
+            .map_or(self.best, |window| window.best)
+            .map(|last| last.as_secs_f64() * 1000_f64)
     }
 
-    /// The duration of the worst probe observed.
+    /// The duration of the worst probe observed, within the stats window if configured.
     pub fn worst_ms(&self) -> Option<f64> {
-        self.worst.map(|last| last.as_secs_f64() * 1000_f64)
+        self.window
+            .as_ref()
+            .map_or(self.worst, |window| window.worst)
+            .map(|last| last.as_secs_f64() * 1000_f64)
     }
 
-    /// The average duration of all probes.
+    /// The average duration of all probes, within the stats window if configured.
     pub fn avg_ms(&self) -> f64 {
-        if self.total_recv() > 0 {
+        if let Some(window) = &self.window {
+            window.avg_ms
+        } else if self.total_recv() > 0 {
             (self.total_time.as_secs_f64() * 1000_f64) / self.total_recv as f64
         } else {
             0_f64
         }
     }
 
-    /// The standard deviation of all probes.
+    /// The standard deviation of all probes, within the stats window if configured.
     pub fn stddev_ms(&self) -> f64 {
-        if self.total_recv > 1 {
+        if let Some(window) = &self.window {
+            window.stddev_ms
+        } else if self.total_recv > 1 {
             (self.m2 / (self.total_recv - 1) as f64).sqrt()
         } else {
             0_f64
         }
     }
 
-    /// The last N samples.
-    pub fn samples(&self) -> &[Duration] {
+    /// The interarrival jitter of the round-trip times of consecutive responses, as defined in RFC 3550.
+    ///
+    /// This is a moving average of the absolute difference between consecutive round-trip times, with a gain of
+    /// 1/16, and is zero until at least two responses have been received.
+    pub fn jitter_ms(&self) -> f64 {
+        self.jitter
+    }
+
+    /// The exponentially weighted moving average of the round-trip times of all probes.
+    ///
+    /// Each response moves the average towards its round-trip time by the smoothing factor `Trace::ewma_alpha`, such
+    /// that recent changes in latency are reflected sooner than in the average, and is `None` until a response has
+    /// been received.
+    pub fn ewma_ms(&self) -> Option<f64> {
+        self.ewma
+    }
+
+    /// The last N samples, newest first, within the stats window if configured.
+    pub fn samples(&self) -> &VecDeque<Duration> {
         &self.samples
     }
+
+    /// The round-trip time at each of the `percentiles` (%) of the responses within the last N samples, `None` if there
+    /// are none.
+    ///
+    /// Lost probes are excluded.  The percentile is linearly interpolated between the two closest responses such that
+    /// it is exact for small sample counts, i.e. the p50 of two responses is their average and every percentile of a
+    /// single response is that response.
+    pub fn percentiles_ms(&self, percentiles: &[f64]) -> Vec<Option<f64>> {
+        let mut rtts: Vec<_> = self
+            .samples
+            .iter()
+            .filter(|sample| !sample.is_zero())
+            .map(|sample| sample.as_secs_f64() * 1000_f64)
+            .collect();
+        rtts.sort_unstable_by(f64::total_cmp);
+        percentiles
+            .iter()
+            .map(|&percentile| interpolate_percentile(&rtts, percentile))
+            .collect()
+    }
+
+    /// Record the round-trip time `sample` of a probe sent at `time`, or a zero duration if it was lost, discarding the
+    /// oldest sample if there are more than `max_samples`.
+    fn push_sample(&mut self, sample: Duration, time: SystemTime, max_samples: usize) {
+        self.samples.push_front(sample);
+        self.sample_times.push_front(time);
+        if self.samples.len() > max_samples {
+            self.samples.pop_back();
+            self.sample_times.pop_back();
+        }
+    }
+
+    /// The number of responses received in each bucket of the round-trip time histogram.
+    ///
+    /// Each count is for round-trip times less than or equal to the corresponding `Trace::histogram_buckets` and
+    /// greater than the prior bucket, the final count is for round-trip times greater than the largest bucket.  Unlike
+    /// the samples, the histogram is not limited to the last N responses.
+    pub fn histogram(&self) -> &[usize] {
+        &self.histogram
+    }
+
+    /// The `MPLS` label stack most recently returned by this hop, if any.
+    pub fn mpls(&self) -> Option<&MplsLabelStack> {
+        self.mpls.as_ref()
+    }
+
+    /// The destination ports of the `tcp` probes which received a response from this hop, if configured.
+    pub fn tcp_dest_ports(&self) -> impl Iterator<Item = u16> + '_ {
+        self.tcp_dest_ports.iter().copied()
+    }
+
+    /// Is the round-trip time of this hop likely inflated by the return path, rather than the forward path?
+    pub fn return_path_hint(&self) -> bool {
+        self.return_path_hint
+    }
+
+    /// The number of times a new address responded at this hop after the first, such as for a load balanced (ECMP) or
+    /// anycast hop.
+    pub fn addr_change_count(&self) -> usize {
+        self.addr_change_count
+    }
+
+    /// The first `MAX_ADDR_CHANGES` address changes of this hop, oldest first.
+    pub fn addr_changes(&self) -> &[AddrChange] {
+        &self.addr_changes
+    }
+
+    /// Record that `addr` responded for the first time in `round`, after another address had already responded.
+    fn record_addr_change(&mut self, addr: IpAddr, round: usize) {
+        self.addr_change_count += 1;
+        if self.addr_changes.len() < MAX_ADDR_CHANGES {
+            self.addr_changes.push(AddrChange { addr, round });
+        }
+    }
+
+    /// The timestamps of the most recent `ICMP` `TimestampReply` received at this hop, if any.
+    pub fn icmp_timestamps(&self) -> Option<&IcmpTimestamps> {
+        self.icmp_timestamps.as_ref()
+    }
+
+    /// Whether the port of the most recent `TCP` `SYN-ACK` or `RST` received from this hop, the target host, was open.
+    pub const fn tcp_outcome(&self) -> Option<TcpOutcome> {
+        self.tcp_outcome
+    }
+
+    /// The code of the most recent `ICMP` `DestinationUnreachable` received from this hop, if any.
+    pub const fn unreachable_code(&self) -> Option<UnreachableCode> {
+        self.unreachable_code
+    }
+
+    /// Whether each of the configured `tcp` destination ports was most recently found open or closed at this hop.
+    pub fn tcp_port_outcomes(&self) -> impl Iterator<Item = (u16, TcpOutcome)> + '_ {
+        self.tcp_port_outcomes
+            .iter()
+            .map(|(&port, &outcome)| (port, outcome))
+    }
+}
+
+/// The statistics of a hop of a prior trace, from which a `Hop` is replayed.
+///
+/// All times are in milliseconds.
+#[derive(Debug, Clone, Default)]
+pub struct HopStats {
+    pub ttl: u8,
+    pub addrs: Vec<IpAddr>,
+    pub sent: usize,
+    pub recv: usize,
+    pub pending: usize,
+    pub last_ms: f64,
+    pub avg_ms: f64,
+    pub best_ms: f64,
+    pub worst_ms: f64,
+    pub stddev_ms: f64,
+    pub jitter_ms: f64,
+    pub ewma_ms: f64,
+    pub tcp_dest_ports: Vec<u16>,
+    pub return_path_hint: bool,
+}
+
+impl From<&HopStats> for Hop {
+    /// The responses are shared equally between the addresses of the hop, as the count of each is not recorded.
+    fn from(stats: &HopStats) -> Self {
+        let share = (stats.recv / stats.addrs.len().max(1)).max(1);
+        let responded = stats.recv > 0;
+        let duration =
+            |ms: f64| responded.then(|| Duration::from_secs_f64(ms.max(0_f64) / 1000_f64));
+        Self {
+            ttl: stats.ttl,
+            addrs: stats.addrs.iter().map(|addr| (*addr, share)).collect(),
+            first_seen: stats.addrs.clone(),
+            total_sent: stats.sent,
+            total_recv: stats.recv,
+            total_pending: stats.pending,
+            total_time: duration(stats.avg_ms * stats.recv as f64).unwrap_or_default(),
+            last: duration(stats.last_ms).filter(|_| stats.last_ms > 0_f64),
+            last_addr: stats.addrs.first().copied(),
+            last_sequence: (stats.sent > 0).then_some(0),
+            last_recv_sequence: (stats.last_ms > 0_f64).then_some(0),
+            best: duration(stats.best_ms),
+            worst: duration(stats.worst_ms),
+            mean: stats.avg_ms,
+            m2: stats.stddev_ms.powi(2) * stats.recv.saturating_sub(1) as f64,
+            jitter: stats.jitter_ms,
+            ewma: responded.then_some(stats.ewma_ms),
+            tcp_dest_ports: stats.tcp_dest_ports.iter().copied().collect(),
+            return_path_hint: stats.return_path_hint,
+            ..Self::default()
+        }
+    }
 }
 
 impl Default for Hop {
@@ -214,15 +1032,151 @@ impl Default for Hop {
         Self {
             ttl: 0,
             addrs: HashMap::default(),
+            first_seen: Vec::default(),
             total_sent: 0,
             total_recv: 0,
+            total_pending: 0,
             total_time: Duration::default(),
             last: None,
+            last_addr: None,
+            last_sequence: None,
+            last_recv_sequence: None,
+            last_recv_round: None,
             best: None,
             worst: None,
             mean: 0f64,
             m2: 0f64,
-            samples: Vec::default(),
+            jitter: 0f64,
+            ewma: None,
+            samples: VecDeque::default(),
+            sample_times: VecDeque::default(),
+            window: None,
+            histogram: Vec::default(),
+            mpls: None,
+            icmp_timestamps: None,
+            tcp_outcome: None,
+            tcp_port_outcomes: BTreeMap::new(),
+            unreachable_code: None,
+            tcp_dest_ports: BTreeSet::new(),
+            return_path_hint: false,
+            addr_change_count: 0,
+            addr_changes: Vec::default(),
+        }
+    }
+}
+
+/// The loss and round-trip time statistics of the samples of a hop within the stats window.
+#[derive(Debug, Clone, Default)]
+struct WindowStats {
+    loss_pct: f64,
+    avg_ms: f64,
+    stddev_ms: f64,
+    best: Option<Duration>,
+    worst: Option<Duration>,
+}
+
+impl WindowStats {
+    /// Compute the statistics of `samples`, where a zero duration is a probe which was lost or, for the most recent
+    /// `pending` probes, is still awaiting a response.
+    fn new(samples: &VecDeque<Duration>, pending: usize) -> Self {
+        let received: Vec<_> = samples.iter().copied().filter(|s| !s.is_zero()).collect();
+        let settled = samples.len().saturating_sub(pending);
+        let loss_pct = if settled > 0 {
+            settled.saturating_sub(received.len()) as f64 / settled as f64 * 100f64
+        } else {
+            0_f64
+        };
+        let received_ms: Vec<_> = received
+            .iter()
+            .map(|s| s.as_secs_f64() * 1000_f64)
+            .collect();
+        let avg_ms = if received_ms.is_empty() {
+            0_f64
+        } else {
+            received_ms.iter().sum::<f64>() / received_ms.len() as f64
+        };
+        let stddev_ms = if received_ms.len() > 1 {
+            (received_ms
+                .iter()
+                .map(|ms| (ms - avg_ms) * (ms - avg_ms))
+                .sum::<f64>()
+                / (received_ms.len() - 1) as f64)
+                .sqrt()
+        } else {
+            0_f64
+        };
+        Self {
+            loss_pct,
+            avg_ms,
+            stddev_ms,
+            best: received.iter().min().copied(),
+            worst: received.iter().max().copied(),
+        }
+    }
+}
+
+/// Statistics of the connections made to the target via a proxy.
+///
+/// The round-trip time is the time taken for the proxy to connect to the target and so includes the round-trip time to
+/// the proxy itself.
+#[derive(Debug, Clone, Default)]
+pub struct ProxyStats {
+    target: String,
+    total_sent: usize,
+    total_recv: usize,
+    total_time: Duration,
+    last: Option<Duration>,
+    best: Option<Duration>,
+    worst: Option<Duration>,
+}
+
+impl ProxyStats {
+    /// The target, and port, connected to via the proxy.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// The total number of connections attempted.
+    pub fn total_sent(&self) -> usize {
+        self.total_sent
+    }
+
+    /// The total number of connections which succeeded.
+    pub fn total_recv(&self) -> usize {
+        self.total_recv
+    }
+
+    /// The % of connections which failed.
+    pub fn loss_pct(&self) -> f64 {
+        if self.total_sent > 0 {
+            let lost = self.total_sent - self.total_recv;
+            lost as f64 / self.total_sent as f64 * 100f64
+        } else {
+            0_f64
+        }
+    }
+
+    /// The duration of the last connection, if it succeeded.
+    pub fn last_ms(&self) -> Option<f64> {
+        self.last.map(|last| last.as_secs_f64() * 1000_f64)
+    }
+
+    /// The duration of the fastest connection.
+    pub fn best_ms(&self) -> Option<f64> {
+        self.best.map(|best| best.as_secs_f64() * 1000_f64)
+    }
+
+    /// The duration of the slowest connection.
+    pub fn worst_ms(&self) -> Option<f64> {
+        self.worst.map(|worst| worst.as_secs_f64() * 1000_f64)
+    }
+
+    /// The average duration of all successful connections.
+    pub fn avg_ms(&self) -> f64 {
+        if self.total_recv > 0 {
+            (self.total_time.as_secs_f64() * 1000_f64) / self.total_recv as f64
+        } else {
+            0_f64
         }
     }
 }
@@ -234,13 +1188,213 @@ impl Default for Hop {
 ///
 /// Note that currently each `Probe` is published individually at the end of a round and so the lock is taken multiple
 /// times per round.
+///
+/// If an `anonymizer` is given then the hosts, and target, of each round are hidden before the trace is updated.
 pub fn run_backend(
     config: &TracerConfig,
     channel: TracerChannel,
     trace_data: Arc<RwLock<Trace>>,
+    anonymizer: Option<Anonymizer>,
 ) -> anyhow::Result<()> {
-    let tracer = Tracer::new(config, move |round| {
-        trace_data.write().update_from_round(round);
+    let tracer = Tracer::new(config, move |round| match &anonymizer {
+        Some(anonymizer) => {
+            let target = round.target_addr;
+            let probes: Vec<_> = round
+                .probes
+                .iter()
+                .map(|probe| anonymizer.anonymize_probe(probe, target))
+                .collect();
+            trace_data.write().update_from_round(&TracerRound::new(
+                &probes,
+                round.largest_ttl,
+                round.reason,
+                round.backoff,
+                anonymizer.anonymize_addr(target, None, target),
+            ));
+        }
+        None => trace_data.write().update_from_round(round),
     });
     Ok(tracer.trace(channel)?)
 }
+
+/// The `percentile` (%) of the ascending `sorted` values, linearly interpolated between the two closest ranks.
+fn interpolate_percentile(sorted: &[f64], percentile: f64) -> Option<f64> {
+    let last = sorted.len().checked_sub(1)?;
+    let rank = percentile / 100_f64 * last as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    Some((sorted[upper] - sorted[lower]).mul_add(rank - lower as f64, sorted[lower]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MAX_UNBOUNDED_SAMPLES;
+    use crate::test_util::{config, update, update_at, TARGET};
+
+    /// A trace which records up to `max_samples` samples per hop, of a round per each of `rtts_ms` in which the first
+    /// hop responded after that round-trip time.
+    fn trace(max_samples: usize, rtts_ms: impl Iterator<Item = u64>) -> Trace {
+        let mut trace = Trace::new(TraceConfig {
+            max_samples,
+            ..config()
+        });
+        for (round, rtt) in rtts_ms.enumerate() {
+            update(&mut trace, round, &[Some((1, rtt))]);
+        }
+        trace
+    }
+
+    /// Is the `actual` value (ms) within a nanosecond of the `expected` value?
+    fn near(expected: f64, actual: f64) -> bool {
+        (expected - actual).abs() < 1e-6
+    }
+
+    fn samples_ms(hop: &Hop) -> Vec<u128> {
+        hop.samples().iter().map(Duration::as_millis).collect()
+    }
+
+    #[test]
+    fn test_max_samples() {
+        let trace = trace(16, 1..=20);
+        let hop = &trace.hops()[0];
+        assert_eq!(20, hop.total_sent());
+        assert_eq!((5..=20).rev().collect::<Vec<_>>(), samples_ms(hop));
+    }
+
+    #[test]
+    fn test_max_samples_unbounded() {
+        let trace = trace(MAX_UNBOUNDED_SAMPLES, 1..=1000);
+        let hop = &trace.hops()[0];
+        assert_eq!((1..=1000).rev().collect::<Vec<_>>(), samples_ms(hop));
+    }
+
+    #[test]
+    fn test_interpolate_percentile() {
+        assert_eq!(None, interpolate_percentile(&[], 50_f64));
+        for percentile in [0_f64, 50_f64, 100_f64] {
+            assert_eq!(Some(10_f64), interpolate_percentile(&[10_f64], percentile));
+        }
+        let two = [10_f64, 20_f64];
+        assert_eq!(Some(10_f64), interpolate_percentile(&two, 0_f64));
+        assert_eq!(Some(15_f64), interpolate_percentile(&two, 50_f64));
+        assert_eq!(Some(20_f64), interpolate_percentile(&two, 100_f64));
+        let four = [10_f64, 20_f64, 30_f64, 40_f64];
+        assert_eq!(Some(25_f64), interpolate_percentile(&four, 50_f64));
+        assert_eq!(Some(37_f64), interpolate_percentile(&four, 90_f64));
+    }
+
+    #[test]
+    fn test_percentiles_ms() {
+        let mut trace = Trace::new(config());
+        update(&mut trace, 0, &[None]);
+        assert_eq!(
+            vec![None, None],
+            trace.hops()[0].percentiles_ms(&[0_f64, 100_f64])
+        );
+        update(&mut trace, 1, &[Some((1, 30))]);
+        update(&mut trace, 2, &[None]);
+        update(&mut trace, 3, &[Some((1, 10))]);
+        assert_eq!(
+            vec![Some(10_f64), Some(20_f64), Some(30_f64)],
+            trace.hops()[0].percentiles_ms(&[0_f64, 50_f64, 100_f64])
+        );
+    }
+
+    /// The jitter moves by 1/16th of the difference of each consecutive pair of round-trip times from it (RFC 3550).
+    #[test]
+    fn test_jitter() {
+        let mut trace = Trace::new(config());
+        update(&mut trace, 0, &[Some((1, 10))]);
+        assert!(near(0_f64, trace.hops()[0].jitter_ms()));
+        for (round, rtt, jitter) in [
+            (1, 30, 1.25_f64),
+            (2, 20, 1.796_875_f64),
+            (3, 20, 1.684_570_312_5_f64),
+        ] {
+            update(&mut trace, round, &[Some((1, rtt))]);
+            assert!(near(jitter, trace.hops()[0].jitter_ms()));
+        }
+    }
+
+    #[test]
+    fn test_ewma() {
+        let mut trace = Trace::new(TraceConfig {
+            ewma_alpha: 0.25,
+            ..config()
+        });
+        update(&mut trace, 0, &[None]);
+        assert_eq!(None, trace.hops()[0].ewma_ms());
+        for (round, rtt, ewma) in [(1, 100, 100_f64), (2, 200, 125_f64), (3, 25, 100_f64)] {
+            update(&mut trace, round, &[Some((1, rtt))]);
+            assert!(near(ewma, trace.hops()[0].ewma_ms().unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_stats_window() {
+        let mut trace = Trace::new(TraceConfig {
+            stats_window: Some(Duration::from_mins(1)),
+            ..config()
+        });
+        let now = SystemTime::now();
+        let ago = |secs| now - Duration::from_secs(secs);
+        update_at(&mut trace, 0, ago(3600), &[Some((1, 100))]);
+        update_at(&mut trace, 1, ago(3600), &[None]);
+        update_at(&mut trace, 2, ago(10), &[Some((1, 10))]);
+        update_at(&mut trace, 3, ago(5), &[None]);
+        update_at(&mut trace, 4, ago(1), &[Some((1, 30))]);
+        let hop = &trace.hops()[0];
+        assert_eq!(5, hop.total_sent());
+        assert_eq!(vec![30, 0, 10], samples_ms(hop));
+        assert!(near(100_f64 / 3_f64, hop.loss_pct()));
+        assert!(near(20_f64, hop.avg_ms()));
+        assert_eq!(Some(10_f64), hop.best_ms());
+        assert_eq!(Some(30_f64), hop.worst_ms());
+    }
+
+    /// The clock may be stepped back, such that a probe had been sent after the current time, or its response appears
+    /// to have been received before it was sent.
+    #[test]
+    fn test_clock_stepped_back() {
+        let mut trace = Trace::new(TraceConfig {
+            stats_window: Some(Duration::from_mins(1)),
+            ..config()
+        });
+        let now = SystemTime::now();
+        update_at(
+            &mut trace,
+            0,
+            now + Duration::from_hours(1),
+            &[Some((1, 10))],
+        );
+        update_at(
+            &mut trace,
+            1,
+            now - Duration::from_secs(10),
+            &[Some((1, 20))],
+        );
+        let hop = &trace.hops()[0];
+        assert_eq!(vec![20, 10], samples_ms(hop));
+        assert!(near(15_f64, hop.avg_ms()));
+        assert!(near(0_f64, hop.loss_pct()));
+
+        let mut trace = Trace::new(config());
+        let probe = Probe::new(0.into(), 1.into(), 0.into(), 0.into(), now)
+            .with_status(ProbeStatus::Complete)
+            .with_host(TARGET)
+            .with_received(now - Duration::from_millis(10));
+        trace.update_from_round(&TracerRound::new(
+            &[probe],
+            1.into(),
+            CompletionReason::TargetFound,
+            1,
+            TARGET,
+        ));
+        let hop = &trace.hops()[0];
+        assert_eq!(vec![0], samples_ms(hop));
+        assert_eq!(Some(0_f64), hop.last_ms());
+        assert_eq!(Some(0_f64), hop.best_ms());
+        assert!(near(0_f64, hop.avg_ms()));
+    }
+}
@@ -0,0 +1,178 @@
+use crate::dns::{DnsEntry, DnsResolver, Resolved};
+use crate::report::{read_reports, ReportHop};
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::path::Path;
+
+/// How a hop deviates from the route of the baseline.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Deviation {
+    /// The hop responded from an address which did not respond at the same ttl of the baseline.
+    Address,
+    /// The hop responded from an address in an `AS` which did not respond at the same ttl of the baseline.
+    Asn,
+}
+
+/// The expected route of each target, read from a prior json report.
+#[derive(Debug)]
+pub struct Baseline {
+    routes: HashMap<String, BTreeMap<u8, Vec<IpAddr>>>,
+}
+
+impl Baseline {
+    /// Read the route of each target from the JSON report document at `path`.
+    ///
+    /// Only the hops which responded are recorded, a hop which did not respond in the baseline is expected at no
+    /// address.
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let routes = read_reports(path)?
+            .iter()
+            .map(|report| {
+                let route = report
+                    .hops
+                    .iter()
+                    .map(ReportHop::stats)
+                    .filter(|stats| !stats.addrs.is_empty())
+                    .map(|stats| (stats.ttl, stats.addrs))
+                    .collect();
+                (report.info.target.hostname.clone(), route)
+            })
+            .collect();
+        Ok(Self { routes })
+    }
+
+    /// How the hop at `ttl` of the trace to `target`, which responded from `addrs`, deviates from the baseline, if at
+    /// all.
+    ///
+    /// Reports are matched by target hostname, as for `--diff`, and a trace without a baseline never deviates.  A hop
+    /// which responded from an address not in the baseline deviates by `AS` if `lookup_as_info` is set and the `AS` of
+    /// that address is known and not the `AS` of any address of the baseline hop, otherwise it deviates by address.
+    pub fn deviation<'a>(
+        &self,
+        target: &str,
+        ttl: u8,
+        addrs: impl Iterator<Item = &'a IpAddr>,
+        dns: &DnsResolver,
+        lookup_as_info: bool,
+    ) -> Option<Deviation> {
+        let expected = self
+            .routes
+            .get(target)?
+            .get(&ttl)
+            .map_or(&[][..], Vec::as_slice);
+        let unexpected: Vec<_> = addrs.filter(|addr| !expected.contains(addr)).collect();
+        if unexpected.is_empty() {
+            None
+        } else if lookup_as_info {
+            let expected_asns: Vec<_> =
+                expected.iter().filter_map(|&addr| asn(dns, addr)).collect();
+            let asn_changed = unexpected
+                .iter()
+                .filter_map(|&&addr| asn(dns, addr))
+                .any(|asn| !expected_asns.contains(&asn));
+            Some(if asn_changed {
+                Deviation::Asn
+            } else {
+                Deviation::Address
+            })
+        } else {
+            Some(Deviation::Address)
+        }
+    }
+}
+
+/// The `AS` of `addr`, if known.
+fn asn(dns: &DnsResolver, addr: IpAddr) -> Option<String> {
+    match dns.reverse_lookup_with_asinfo(addr) {
+        DnsEntry::Resolved(Resolved::WithAsInfo(_, _, asinfo)) if !asinfo.asn.is_empty() => {
+            Some(asinfo.asn)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{resolver, temp_path};
+    use serde_json::json;
+
+    fn hop(ttl: u8, ips: &[&str]) -> serde_json::Value {
+        let hosts: Vec<_> = ips
+            .iter()
+            .map(|ip| json!({"ip": ip, "hostname": ip}))
+            .collect();
+        json!({
+            "ttl": ttl,
+            "hosts": hosts,
+            "loss_pct": "0.00",
+            "sent": 1,
+            "last": "1.00",
+            "recv": usize::from(!ips.is_empty()),
+            "avg": "1.00",
+            "best": "1.00",
+            "worst": "1.00",
+            "stddev": "0.00",
+            "jitter": "0.00",
+        })
+    }
+
+    /// The baseline of a report of the trace to `example.com`, written to the report file `name`.
+    fn baseline(name: &str) -> Baseline {
+        let path = temp_path(name);
+        let report = json!({
+            "info": {"target": {"ip": "10.0.0.10", "hostname": "example.com"}},
+            "hops": [
+                hop(1, &["10.0.0.1"]),
+                hop(2, &["10.0.0.2", "10.0.0.3"]),
+                hop(3, &[]),
+                hop(4, &["10.0.0.10"]),
+            ],
+            "flows": [],
+        });
+        std::fs::write(&path, report.to_string()).unwrap();
+        let baseline = Baseline::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        baseline
+    }
+
+    fn addrs(ips: &[&str]) -> Vec<IpAddr> {
+        ips.iter().map(|ip| ip.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_deviation() {
+        let baseline = baseline("deviation.json");
+        let dns = resolver();
+        let deviation = |target: &str, ttl: u8, ips: &[&str]| {
+            baseline.deviation(target, ttl, addrs(ips).iter(), &dns, false)
+        };
+        assert_eq!(None, deviation("example.com", 1, &["10.0.0.1"]));
+        assert_eq!(None, deviation("example.com", 2, &["10.0.0.3"]));
+        assert_eq!(None, deviation("example.com", 2, &[]));
+        assert_eq!(None, deviation("example.com", 3, &[]));
+        assert_eq!(
+            Some(Deviation::Address),
+            deviation("example.com", 1, &["10.0.0.1", "10.0.0.4"])
+        );
+        assert_eq!(
+            Some(Deviation::Address),
+            deviation("example.com", 3, &["10.0.0.5"])
+        );
+        assert_eq!(
+            Some(Deviation::Address),
+            deviation("example.com", 5, &["10.0.0.10"])
+        );
+        assert_eq!(None, deviation("other.com", 1, &["10.0.0.4"]));
+    }
+
+    #[test]
+    fn test_deviation_unknown_asn() {
+        let baseline = baseline("unknown-asn.json");
+        let dns = resolver();
+        assert_eq!(
+            Some(Deviation::Address),
+            baseline.deviation("example.com", 1, addrs(&["10.0.0.4"]).iter(), &dns, true)
+        );
+    }
+}
@@ -2,10 +2,13 @@
 
 #[cfg(target_os = "linux")]
 /// Check if `CAP_NET_RAW` is in the permitted set and if so raise it to the effective set.
-pub fn ensure_caps() -> anyhow::Result<()> {
+///
+/// If the capability is not permitted, and is not `required` as tracing may fall back to an unprivileged socket, then
+/// this is not an error.
+pub fn ensure_caps(required: bool) -> anyhow::Result<()> {
     if caps::has_cap(None, caps::CapSet::Permitted, caps::Capability::CAP_NET_RAW)? {
         caps::raise(None, caps::CapSet::Effective, caps::Capability::CAP_NET_RAW)?;
-    } else {
+    } else if required {
         eprintln!("capability CAP_NET_RAW is required, see https://github.com/fujiapple852/trippy#privileges");
         std::process::exit(-1);
     }
@@ -23,9 +26,9 @@ pub fn drop_caps() -> anyhow::Result<()> {
 
 #[cfg(all(unix, not(target_os = "linux")))]
 #[allow(clippy::unnecessary_wraps)]
-/// Ensure the effective user is `root`.
-pub fn ensure_caps() -> anyhow::Result<()> {
-    if !nix::unistd::Uid::effective().is_root() {
+/// Ensure the effective user is `root`, if `required`.
+pub fn ensure_caps(required: bool) -> anyhow::Result<()> {
+    if required && !nix::unistd::Uid::effective().is_root() {
         eprintln!("root user required to use raw sockets, see https://github.com/fujiapple852/trippy#privileges");
         std::process::exit(-1);
     }
@@ -46,7 +49,7 @@ pub fn drop_caps() -> anyhow::Result<()> {
 #[cfg(not(unix))]
 #[allow(clippy::unnecessary_wraps)]
 /// Ensure the effective user is `root`.
-pub fn ensure_caps() -> anyhow::Result<()> {
+pub fn ensure_caps(_required: bool) -> anyhow::Result<()> {
     Ok(())
 }
 
@@ -1,17 +1,37 @@
-use clap::{ArgEnum, Parser};
+use crate::config::file::default_config_path;
+use crate::interfaces::interface_names;
+use crate::theme::{LOSS_HIGH_PCT, LOSS_WARN_PCT};
+use anyhow::anyhow;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, SecondsFormat};
+use clap::{ArgEnum, CommandFactory, FromArgMatches, Parser};
+use itertools::Itertools;
+use std::ffi::OsString;
+use std::fs;
+use std::io::Read;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::time::Duration;
 
+mod file;
+
+pub use file::{default_config_dir, TrippyConfig};
+
 /// The maximum number of hops we allow.
 ///
 /// The IP `ttl` is a u8 (0..255) but since a `ttl` of zero isn't useful we only allow 255 distinct hops.
 pub const MAX_HOPS: usize = u8::MAX as usize;
 
+/// The name of the persistent DNS cache file within the config directory.
+pub const DNS_CACHE_FILE: &str = "dns_cache.json";
+
 /// The minimum TUI refresh rate.
 const TUI_MIN_REFRESH_RATE_MS: Duration = Duration::from_millis(50);
 
 /// The maximum TUI refresh rate.
-const TUI_MAX_REFRESH_RATE_MS: Duration = Duration::from_millis(1000);
+const TUI_MAX_REFRESH_RATE_MS: Duration = Duration::from_secs(1);
 
 /// The minimum socket read timeout.
 const MIN_READ_TIMEOUT_MS: Duration = Duration::from_millis(10);
@@ -23,14 +43,71 @@ const MAX_READ_TIMEOUT_MS: Duration = Duration::from_millis(100);
 const MIN_GRACE_DURATION_MS: Duration = Duration::from_millis(10);
 
 /// The maximum grace duration.
-const MAX_GRACE_DURATION_MS: Duration = Duration::from_millis(1000);
+const MAX_GRACE_DURATION_MS: Duration = Duration::from_secs(1);
+
+/// The minimum interval at which targets are re-resolved.
+const MIN_RERESOLVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The minimum width to which hostnames may be truncated in the Tui, including the ellipsis.
+const MIN_HOSTNAME_WIDTH: usize = 8;
+
+/// The maximum round duration when probing a fixed TTL.
+///
+/// A round which does not reach the target lasts for the maximum round duration and so we cap both the minimum and
+/// maximum round duration to raise the rate at which the single hop is probed.
+pub const FIXED_TTL_MAX_ROUND_DURATION: Duration = Duration::from_millis(250);
+
+/// The default upper bound of each histogram bucket, in milliseconds.
+pub const DEFAULT_HISTOGRAM_BUCKETS_MS: [u64; 13] =
+    [1, 2, 4, 8, 16, 32, 64, 128, 256, 512, 1024, 2048, 4096];
+
+/// The maximum number of percentiles shown in the Tui.
+const MAX_TUI_PERCENTILES: usize = 3;
 
 /// The minimum packet size we allow.
 pub const MIN_PACKET_SIZE: u16 = 28;
 
+/// The minimum packet size we allow for the `paris` multipath strategy.
+pub const MIN_PARIS_PACKET_SIZE: u16 = MIN_PACKET_SIZE + 2;
+
+/// The default destination port for udp probes in the fixed mode.
+pub const DEFAULT_UDP_DEST_PORT_BASE: u16 = 33434;
+
+/// The maximum destination port base we allow for udp probes in the increment mode.
+///
+/// This ensures that there are sufficient ports available for at least one round.
+pub const MAX_UDP_DEST_PORT_BASE: u16 = u16::MAX - MAX_HOPS as u16;
+
+/// The minimum initial sequence number chosen for `random_sequence`, which avoids the well known ports as the
+/// sequence number may be used as the destination port of `udp` and `tcp` probes.
+pub const MIN_RANDOM_SEQUENCE: u16 = 1024;
+
+/// The maximum initial sequence number chosen for `random_sequence`.
+///
+/// This ensures that there are sufficient sequence numbers available for at least one round.
+pub const MAX_RANDOM_SEQUENCE: u16 = u16::MAX - MAX_HOPS as u16 - 1;
+
+/// The sequence number at or beyond which the tracer restarts from the initial sequence number in the next round.
+const MAX_SEQUENCE: u16 = u16::MAX - MAX_HOPS as u16 - 1;
+
+/// The maximum number of flows we allow.
+pub const MAX_FLOWS: u16 = 64;
+
+/// The maximum number of probes per ttl in each round we allow.
+pub const MAX_PROBES_PER_ROUND: u8 = 16;
+
+/// The maximum number of samples per hop we allow to be retained for the whole of a report.
+pub const MAX_UNBOUNDED_SAMPLES: usize = 1_000_000;
+
 /// The maximum packet size we allow.
 pub const MAX_PACKET_SIZE: u16 = 1024;
 
+/// The maximum packet size we allow if probes are fragmented.
+pub const MAX_FRAGMENTED_PACKET_SIZE: u16 = 8192;
+
+/// The size of each fragment of a fragmented probe, probes no larger than this are never fragmented.
+pub const FRAGMENT_SIZE: u16 = 576;
+
 /// The tool mode.
 #[derive(Debug, Copy, Clone, ArgEnum)]
 pub enum Mode {
@@ -46,6 +123,194 @@ pub enum Mode {
     Csv,
     /// Generate a JSON report for N cycles.
     Json,
+    /// Display a continuous stream of JSON objects, one per round.
+    JsonStream,
+    /// Run continuously and print a line only when a hop changes.
+    Alert,
+}
+
+impl Mode {
+    /// The report mode implied by the extension of an output file, if any.
+    fn from_output_path(path: &str) -> Option<Self> {
+        match Path::new(path).extension()?.to_str()? {
+            "json" => Some(Self::Json),
+            "csv" => Some(Self::Csv),
+            "md" => Some(Self::Markdown),
+            _ => None,
+        }
+    }
+}
+
+/// How timestamps are rendered in reports.
+#[derive(Debug, Clone)]
+pub enum TimestampFormat {
+    /// RFC 3339 with millisecond precision and the local timezone offset, i.e. `2022-05-01T12:00:00.000+01:00`.
+    Rfc3339,
+    /// Seconds since the Unix epoch.
+    Unix,
+    /// A custom strftime-style format, i.e. `%Y-%m-%d %H:%M:%S %Z`.
+    Custom(String),
+}
+
+impl TimestampFormat {
+    /// Render `timestamp` in this format.
+    pub fn format(&self, timestamp: DateTime<Local>) -> String {
+        match self {
+            Self::Rfc3339 => timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            Self::Unix => timestamp.timestamp().to_string(),
+            Self::Custom(format) => timestamp.format(format).to_string(),
+        }
+    }
+}
+
+/// Parse a `timestamp_format`, either `rfc3339`, `unix` or a custom strftime-style format.
+///
+/// A custom format must contain at least one `%` specifier, such that an unknown format name is rejected rather than
+/// rendered literally.
+fn parse_timestamp_format(timestamp_format: &str) -> Result<TimestampFormat, String> {
+    match timestamp_format {
+        "rfc3339" => Ok(TimestampFormat::Rfc3339),
+        "unix" => Ok(TimestampFormat::Unix),
+        format if !format.contains('%') => Err(format!(
+            "unknown timestamp format {format}: must be rfc3339, unix or a strftime-style format"
+        )),
+        format if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) => {
+            Err(format!("invalid strftime-style timestamp format {format}"))
+        }
+        format => Ok(TimestampFormat::Custom(format.to_string())),
+    }
+}
+
+/// The unit in which round-trip times are shown.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ArgEnum)]
+pub enum RttUnit {
+    /// Milliseconds.
+    Ms,
+    /// Microseconds.
+    Us,
+}
+
+/// How round-trip times are shown in the Tui and reports.
+///
+/// Round-trip times are always stored at full precision, in milliseconds, and only converted when rendered.
+#[derive(Debug, Copy, Clone)]
+pub struct RttFormat {
+    pub unit: RttUnit,
+    pub precision: usize,
+}
+
+impl RttFormat {
+    pub const fn new(unit: RttUnit, precision: usize) -> Self {
+        Self { unit, precision }
+    }
+
+    /// Render the round-trip time `rtt_ms` in this unit and precision, without a unit suffix.
+    pub fn format(self, rtt_ms: f64) -> String {
+        let rtt = match self.unit {
+            RttUnit::Ms => rtt_ms,
+            RttUnit::Us => rtt_ms * 1000_f64,
+        };
+        format!("{rtt:.*}", self.precision)
+    }
+}
+
+/// How hops which have never responded are shown in the Tui and the table reports.
+#[derive(Debug, Clone)]
+pub struct UnknownHops {
+    /// The text shown in place of the addresses of a hop which has never responded, if not the default.
+    pub placeholder: Option<String>,
+    /// Collapse each run of consecutive hops which have never responded into a single row.
+    pub collapse: bool,
+}
+
+impl UnknownHops {
+    pub const fn new(placeholder: Option<String>, collapse: bool) -> Self {
+        Self {
+            placeholder,
+            collapse,
+        }
+    }
+
+    /// The text shown in place of the addresses of a hop which has never responded, `default` if not configured.
+    pub fn placeholder<'a>(&'a self, default: &'a str) -> &'a str {
+        self.placeholder.as_deref().unwrap_or(default)
+    }
+
+    /// The text shown in place of the addresses of a row of `count` collapsed hops.
+    pub fn collapsed(count: usize) -> String {
+        format!("{count} hops not responding")
+    }
+}
+
+/// The loss (%) thresholds of the status of each hop in the rich markdown report.
+#[derive(Debug, Copy, Clone)]
+pub struct MarkdownRich {
+    /// The loss (%) at or above which a hop with any loss is marked as a warning.
+    pub loss_warn_pct: f64,
+    /// The loss (%) at or above which a hop is marked as failing.
+    pub loss_critical_pct: f64,
+}
+
+impl MarkdownRich {
+    pub const fn new(loss_warn_pct: f64, loss_critical_pct: f64) -> Self {
+        Self {
+            loss_warn_pct,
+            loss_critical_pct,
+        }
+    }
+
+    /// The status indicator of a hop with `loss_pct` loss.
+    pub fn status(&self, loss_pct: f64) -> &'static str {
+        if loss_pct >= self.loss_critical_pct {
+            "❌"
+        } else if loss_pct > 0_f64 && loss_pct >= self.loss_warn_pct {
+            "⚠️"
+        } else {
+            "✅"
+        }
+    }
+}
+
+/// The maximum number of decimal places of round-trip times.
+pub const MAX_RTT_PRECISION: usize = 6;
+
+/// A column of the pretty, markdown and csv reports.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ArgEnum)]
+pub enum ReportColumn {
+    /// The time-to-live of the hop.
+    Hop,
+    /// The reverse DNS hostname of each address of the hop.
+    Host,
+    /// Each address of the hop.
+    Ip,
+    /// The percentage of probes lost.
+    Loss,
+    /// The number of probes sent.
+    Sent,
+    /// The number of probes received.
+    Recv,
+    /// The most recent round-trip time.
+    Last,
+    /// The average round-trip time.
+    Avg,
+    /// The best round-trip time.
+    Best,
+    /// The worst round-trip time.
+    Worst,
+    /// The standard deviation of the round-trip time.
+    Stddev,
+    /// The jitter of the round-trip time.
+    Jitter,
+    /// The exponentially weighted moving average round-trip time.
+    Ewma,
+    /// The round-trip time at each of the `--percentiles`, one column per percentile.
+    Percentiles,
+    /// The autonomous system number of each address of the hop (requires `-z`).
+    Asn,
+    /// The geolocation of each address of the hop (requires `--geoip-mmdb`).
+    Geo,
+    /// The label of each address of the hop (requires `--labels`).
+    Label,
 }
 
 /// The tracing protocol.
@@ -53,12 +318,77 @@ pub enum Mode {
 pub enum TraceProtocol {
     /// Internet Control Message Protocol
     Icmp,
+    /// Internet Control Message Protocol timestamp requests (not implemented by many hosts)
+    IcmpTimestamp,
     /// User Datagram Protocol
     Udp,
     /// Transmission Control Protocol
     Tcp,
 }
 
+/// The kind of socket used for icmp probes.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ArgEnum)]
+pub enum IcmpSocketMode {
+    /// A raw socket, which requires privileges.
+    Raw,
+    /// An unprivileged datagram socket, where the OS permits one.
+    Dgram,
+    /// A raw socket if permitted, otherwise an unprivileged datagram socket.
+    Auto,
+}
+
+/// The strategy used to handle multiple paths through load-balanced networks.
+#[derive(Debug, Copy, Clone, ArgEnum)]
+pub enum MultipathStrategy {
+    /// Vary the flow identifier for every probe.
+    Classic,
+    /// Keep the flow identifier constant for all probes.
+    Paris,
+}
+
+/// How the probes of each round are scheduled.
+#[derive(Debug, Copy, Clone, ArgEnum)]
+pub enum ScheduleStrategy {
+    /// Send the probes of a round in a burst, as fast as the maximum in-flight allows.
+    Sync,
+    /// Space the probes of a round evenly across the minimum round duration.
+    RoundRobin,
+}
+
+/// A flag set in the header of tcp probes.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ArgEnum)]
+pub enum TcpFlag {
+    /// Synchronize, to open a connection.
+    Syn,
+    /// Acknowledge, as if for an established connection.
+    Ack,
+    /// Finish, to close a connection.
+    Fin,
+    /// Push.
+    Psh,
+    /// Urgent.
+    Urg,
+}
+
+/// How the destination port of udp probes is chosen.
+#[derive(Debug, Copy, Clone, ArgEnum)]
+pub enum UdpDestPortMode {
+    /// Increment the destination port for every probe.
+    Increment,
+    /// Use the same destination port for every probe.
+    Fixed,
+}
+
+impl UdpDestPortMode {
+    /// The default mode for the `multipath_strategy`.
+    pub const fn default_for(multipath_strategy: MultipathStrategy) -> Self {
+        match multipath_strategy {
+            MultipathStrategy::Classic => Self::Increment,
+            MultipathStrategy::Paris => Self::Fixed,
+        }
+    }
+}
+
 /// How to render the addresses.
 #[derive(Debug, Copy, Clone, ArgEnum)]
 pub enum AddressMode {
@@ -70,6 +400,37 @@ pub enum AddressMode {
     Both,
 }
 
+/// Which addresses to hide from all output.
+#[derive(Debug, Copy, Clone, ArgEnum)]
+pub enum AnonymizeScope {
+    /// Private (RFC 1918, RFC 6598 and unique local) and link-local addresses.
+    Private,
+    /// The addresses of the first hops (see `--anonymize-hops`).
+    Hops,
+    /// The address of the target.
+    Target,
+}
+
+/// How hidden addresses are replaced.
+#[derive(Debug, Copy, Clone, ArgEnum)]
+pub enum AnonymizeStrategy {
+    /// Zero the last octet of IPv4 addresses and all but the /48 prefix of IPv6 addresses.
+    Mask,
+    /// Replace each distinct address with a distinct placeholder address from the documentation ranges.
+    Token,
+}
+
+/// How to order the addresses of a hop.
+#[derive(Debug, Copy, Clone, ArgEnum)]
+pub enum AddressOrder {
+    /// The order in which each address first responded.
+    FirstSeen,
+    /// Numerically by IP address.
+    Ip,
+    /// By descending frequency of response, then numerically by IP address.
+    Frequency,
+}
+
 /// How DNS queries wil be resolved.
 #[derive(Debug, Copy, Clone, ArgEnum)]
 pub enum DnsResolveMethod {
@@ -81,20 +442,74 @@ pub enum DnsResolveMethod {
     Google,
     /// Resolve using the Cloudflare `1.1.1.1` DNS service.
     Cloudflare,
+    /// Resolve using DNS-over-HTTPS to the `--dns-endpoint`, Cloudflare by default.
+    Doh,
+    /// Resolve using DNS-over-TLS to the `--dns-endpoint`, Cloudflare by default.
+    Dot,
+}
+
+/// The built-in color theme of the Tui.
+#[derive(Debug, Copy, Clone, ArgEnum)]
+pub enum TuiTheme {
+    /// The default colors.
+    Default,
+    /// No colors, using only the default colors of the terminal.
+    Mono,
+    /// Bright colors, with round-trip time and loss highlighted.
+    HighContrast,
 }
 
 /// Trace a route to a host and record statistics
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Args {
     /// A space delimited list of hostnames and IPs to trace
-    #[clap(required = true)]
+    #[clap(required_unless_present_any = &["targets-file", "print-json-schema", "diff", "replay", "list-interfaces"])]
     pub targets: Vec<String>,
 
+    /// Read additional targets from a file, one per line, or from stdin if `-`
+    #[clap(long)]
+    pub targets_file: Option<String>,
+
+    /// Re-resolve the hostname of each target at this interval and trace to the new address, discarding the
+    /// statistics of every hop, if it changes
+    #[clap(long)]
+    pub reresolve: Option<String>,
+
     /// Tracing protocol.
     #[clap(arg_enum, short = 'p', long, default_value = "icmp")]
     pub protocol: TraceProtocol,
 
+    /// Trace the target over icmp, udp and tcp at the same time, rather than over the protocol, and compare the hops of
+    /// each side by side in the Tui
+    #[clap(long)]
+    pub compare_protocols: bool,
+
+    /// The multipath strategy.
+    #[clap(arg_enum, long, default_value = "classic")]
+    pub multipath_strategy: MultipathStrategy,
+
+    /// How the destination port of udp probes is chosen [default: increment for classic, fixed for paris]
+    #[clap(arg_enum, long)]
+    pub udp_dest_port_mode: Option<UdpDestPortMode>,
+
+    /// The destination port of the first udp probe [default: the initial sequence for increment, 33434 for fixed]
+    #[clap(long)]
+    pub udp_dest_port_base: Option<u16>,
+
+    /// The destination ports of tcp probes, comma delimited, each traced in turn [default: the sequence number]
+    #[clap(long, use_value_delimiter = true)]
+    pub tcp_dest_ports: Vec<u16>,
+
+    /// The flags to set in tcp probes, comma delimited, such as ack to pass stateless firewalls
+    #[clap(arg_enum, long, use_value_delimiter = true, default_value = "syn")]
+    pub tcp_flags: Vec<TcpFlag>,
+
+    /// The number of distinct flows to trace, one per round in turn (paris multipath strategy only)
+    #[clap(long, default_value_t = 1)]
+    pub max_flows: u16,
+
     /// The TTL to start from
     #[clap(long, default_value_t = 1)]
     pub first_ttl: u8,
@@ -103,6 +518,18 @@ pub struct Args {
     #[clap(short = 't', long, default_value_t = 64)]
     pub max_ttl: u8,
 
+    /// Probe every nth TTL from first-ttl, up to max-ttl
+    #[clap(long, default_value_t = 1, conflicts_with = "fixed-ttl")]
+    pub ttl_stride: u8,
+
+    /// Stop increasing the TTL once a response is received from the target host, up to max-ttl
+    #[clap(long, conflicts_with = "fixed-ttl")]
+    pub auto_ttl: bool,
+
+    /// Probe a single hop at this TTL, at a higher rate
+    #[clap(long, conflicts_with_all = &["first-ttl", "max-ttl"])]
+    pub fixed_ttl: Option<u8>,
+
     /// The minimum duration of every round
     #[clap(short = 'i', long, default_value = "1s")]
     pub min_round_duration: String,
@@ -115,157 +542,1465 @@ pub struct Args {
     #[clap(short = 'g', long, default_value = "100ms")]
     pub grace_duration: String,
 
+    /// Adapt the grace period of each round to the slowest response of the prior round, up to this maximum, and end
+    /// the round as soon as every hop before the target has responded
+    #[clap(long)]
+    pub adaptive_grace: Option<String>,
+
+    /// The time after a probe is sent during which it is counted as pending, rather than lost [default: grace-duration]
+    #[clap(long)]
+    pub zero_loss_grace: Option<String>,
+
+    /// The maximum number of probes to send per second, across all hops and targets
+    #[clap(long)]
+    pub max_pps: Option<u32>,
+
+    /// Slow probing while the loss of any hop is high, trading measurement resolution for gentleness
+    #[clap(long)]
+    pub adaptive_rate: bool,
+
+    /// The loss (%) of any hop above which adaptive rate probing slows down
+    #[clap(long, default_value_t = 20_f64, requires = "adaptive-rate")]
+    pub adaptive_loss_threshold: f64,
+
+    /// The loss (%) of every hop below which adaptive rate probing speeds up again
+    #[clap(long, default_value_t = 5_f64, requires = "adaptive-rate")]
+    pub adaptive_recover_threshold: f64,
+
     /// The maximum number of in-flight ICMP echo requests
     #[clap(short = 'U', long, default_value_t = 24)]
     pub max_inflight: u8,
 
+    /// The number of probes sent for each ttl in every round
+    #[clap(long, default_value_t = 1)]
+    pub probes_per_round: u8,
+
+    /// How the probes of each round are scheduled, in a burst or spaced across the minimum round duration
+    #[clap(arg_enum, long, default_value = "sync")]
+    pub schedule: ScheduleStrategy,
+
     /// The initial sequence number
     #[clap(long, default_value_t = 33000)]
     pub initial_sequence: u16,
 
+    /// Choose a random initial sequence number for every run, to avoid collisions between concurrent instances
+    #[clap(long, conflicts_with = "initial-sequence")]
+    pub random_sequence: bool,
+
     /// The socket read timeout
     #[clap(long, default_value = "10ms")]
     pub read_timeout: String,
 
+    /// How long to await the response to a probe before it is considered lost [default: the round duration]
+    #[clap(long)]
+    pub probe_timeout: Option<String>,
+
     /// The size of IP packet to send (IP header + ICMP header + payload)
     #[clap(long, default_value_t = 84)]
     pub packet_size: u16,
 
+    /// Send probes as IP fragments of up to 576 bytes, without the don't-fragment bit, and allow a packet size of up to
+    /// 8192 (not icmp-timestamp)
+    #[clap(long)]
+    pub fragment: bool,
+
+    /// Set (true) or clear (false) the don't-fragment bit of every probe, rather than leaving it to the OS
+    #[clap(long)]
+    pub dont_fragment: Option<bool>,
+
     /// The repeating pattern in the payload of the ICMP packet
     #[clap(long, default_value_t = 0)]
     pub payload_pattern: u8,
 
-    /// The source port (TCP & UDP only)
+    /// The payload of every probe as hex bytes, i.e. 0x74726970, truncated or zero padded to fit the packet size
+    #[clap(long, conflicts_with = "payload-pattern")]
+    pub payload_hex: Option<String>,
+
+    /// The source port, or a range of source ports such as 40000:40015 to rotate across one per round such that
+    /// probes spread across flows of routers which rate limit responses per flow (TCP & UDP only)
+    #[clap(long, parse(try_from_str = parse_source_port))]
+    pub source_port: Option<RangeInclusive<u16>>,
+
+    /// The identifier of icmp probes, incremented for each additional target (ICMP only)
+    /// [default: the process id modulo 65535]
+    #[clap(long)]
+    pub icmp_id: Option<u16>,
+
+    /// The type-of-service (TOS) byte to set on all probes, i.e. 0xb8 for DSCP EF [default: unset]
+    #[clap(long, parse(try_from_str = parse_tos))]
+    pub tos: Option<u8>,
+
+    /// The network interface to bind to [default: OS chooses]
+    #[clap(long)]
+    pub interface: Option<String>,
+
+    /// List the network interfaces, with their status and addresses, and exit
+    #[clap(long)]
+    pub list_interfaces: bool,
+
+    /// The source IP address to send probes from [default: OS chooses]
     #[clap(long)]
-    pub source_port: Option<u16>,
+    pub source_address: Option<IpAddr>,
+
+    /// The socket used for icmp probes, dgram needs no privileges where the OS permits it and auto uses dgram only if
+    /// raw is not permitted (ICMP only)
+    #[clap(arg_enum, long, default_value = "auto")]
+    pub icmp_socket_mode: IcmpSocketMode,
 
     /// The maximum time to wait to perform DNS queries.
     #[clap(long, default_value = "5s")]
     pub dns_timeout: String,
 
+    /// Persist reverse DNS lookups to a cache in the config directory, expiring entries after this duration
+    #[clap(long)]
+    pub dns_cache_ttl: Option<String>,
+
     /// How to perform DNS queries.
     #[clap(arg_enum, short = 'r', long, default_value = "system")]
     pub dns_resolve_method: DnsResolveMethod,
 
+    /// The server of the doh and dot resolvers, one of cloudflare, google or quad9 or a url such as
+    /// `https://dns.example.com/dns-query` or `tls://dns.example.com:853` [default: cloudflare]
+    #[clap(long)]
+    pub dns_endpoint: Option<String>,
+
+    /// Lookup the location of hosts in a local `MaxMind` database file
+    #[clap(long)]
+    pub geoip_mmdb: Option<String>,
+
+    /// Lookup the organization name of each AS in a local ASN to name table file, rather than querying it
+    #[clap(long)]
+    pub asn_names: Option<String>,
+
+    /// Show a label alongside hosts from a local file of key=label lines, keyed by address, network or AS
+    #[clap(long)]
+    pub labels: Option<String>,
+
+    /// Pin the hop at a ttl to an address, comma delimited as ttl=addr, such that the responses from any other address
+    /// are shown as a separate hop
+    #[clap(long, use_value_delimiter = true, parse(try_from_str = parse_pin_hop))]
+    pub pin_hop: Vec<(u8, IpAddr)>,
+
+    /// Hide private addresses, the first hops and/or the target from all output
+    #[clap(arg_enum, long, use_value_delimiter = true)]
+    pub anonymize: Vec<AnonymizeScope>,
+
+    /// The number of first hops hidden by `--anonymize hops`
+    #[clap(long, default_value_t = 1)]
+    pub anonymize_hops: u8,
+
+    /// How hidden addresses are replaced in the output
+    #[clap(arg_enum, long, default_value = "mask")]
+    pub anonymize_strategy: AnonymizeStrategy,
+
     /// Lookup autonomous system (AS) information during DNS queries.
     #[clap(long, short = 'z')]
     pub dns_lookup_as_info: bool,
 
+    /// Show every reverse DNS hostname of each address, rather than only the first
+    #[clap(long)]
+    pub dns_resolve_all: bool,
+
+    /// Disable DNS entirely, showing only IP addresses and never performing reverse or AS lookups
+    #[clap(long)]
+    pub no_dns: bool,
+
     /// The maximum number of samples to record per hop.
     #[clap(long, short = 's', default_value_t = 256)]
     pub tui_max_samples: usize,
 
+    /// Retain every sample of each hop for the whole report, rather than up to the maximum number of samples, such that
+    /// the percentiles are computed over every round (report modes only)
+    #[clap(long)]
+    pub max_samples_unbounded: bool,
+
+    /// The smoothing factor of the exponentially weighted moving average round-trip time of each hop, from 0 to 1
+    #[clap(long, default_value_t = 0.1)]
+    pub ewma_alpha: f64,
+
+    /// Show the exponentially weighted moving average round-trip time of each hop in the Tui
+    #[clap(long)]
+    pub tui_show_ewma: bool,
+
+    /// Show the apparent offset of the clock of each hop from the icmp-timestamp replies in the Tui
+    #[clap(long)]
+    pub show_clock_skew: bool,
+
+    /// Show the round-trip time percentiles of each hop in the Tui
+    #[clap(long)]
+    pub tui_show_percentiles: bool,
+
+    /// Show a column of the loss and round-trip time of the most recent probes of each hop in the Tui, this many
+    /// probes wide
+    #[clap(long)]
+    pub tui_history_width: Option<usize>,
+
+    /// Dim the hops in the Tui which responded previously but not in this many most recent rounds
+    #[clap(long, default_value_t = 3)]
+    pub tui_stale_rounds: usize,
+
+    /// The text shown in place of the addresses of a hop which has never responded [default: No response in the Tui,
+    /// ??? in reports]
+    #[clap(long)]
+    pub unknown_hop: Option<String>,
+
+    /// Collapse each run of consecutive hops which have never responded into a single row in the Tui, expanded with
+    /// the x key, and in the pretty and markdown reports
+    #[clap(long)]
+    pub collapse_unresponsive: bool,
+
+    /// Compute the loss and round-trip time statistics of each hop in the Tui over this sliding window, i.e. 5m,
+    /// rather than since tracing started
+    #[clap(long)]
+    pub stats_window: Option<String>,
+
     /// Preserve the screen on exit
     #[clap(long)]
     pub tui_preserve_screen: bool,
 
+    /// Exit the Tui after this many rounds of tracing
+    #[clap(long = "round-count-exit")]
+    pub tui_max_rounds: Option<usize>,
+
+    /// Show only the hops in this ttl window, i.e. 5:12, which may be scrolled with the page up & down keys
+    #[clap(long, parse(try_from_str = parse_hop_window))]
+    pub tui_hop_window: Option<RangeInclusive<u8>>,
+
     /// The TUI refresh rate
     #[clap(long, default_value = "100ms")]
     pub tui_refresh_rate: String,
 
-    /// How to render addresses.
-    #[clap(arg_enum, short = 'a', long, default_value = "host")]
-    pub tui_address_mode: AddressMode,
+    /// The color theme of the Tui
+    #[clap(arg_enum, long, default_value = "default")]
+    pub tui_theme: TuiTheme,
+
+    /// Override the colors of the Tui theme with those defined in this toml (or yaml) file
+    #[clap(long)]
+    pub tui_theme_file: Option<String>,
+
+    /// The loss (%) at or above which a hop with any loss is shown with the loss-low color of the Tui theme
+    #[clap(long, default_value_t = LOSS_WARN_PCT)]
+    pub loss_warn_threshold: f64,
+
+    /// The loss (%) at or above which a hop is shown with the loss-high color of the Tui theme
+    #[clap(long, default_value_t = LOSS_HIGH_PCT)]
+    pub loss_critical_threshold: f64,
+
+    /// How to render addresses [default: host]
+    #[clap(arg_enum, short = 'a', long)]
+    pub tui_address_mode: Option<AddressMode>,
 
     /// The maximum number of addresses to show per hop
     #[clap(long)]
     pub tui_max_addresses_per_hop: Option<u8>,
 
+    /// The maximum width of each hostname in the Tui, longer hostnames are truncated with an ellipsis other than for the
+    /// selected hop
+    #[clap(long)]
+    pub max_hostname_width: Option<usize>,
+
+    /// Show the addresses of each hop in a stable order in the Tui, first-seen if no order is given
+    #[clap(
+        arg_enum,
+        long,
+        value_name = "ORDER",
+        min_values = 0,
+        max_values = 1,
+        require_equals = true,
+        default_missing_value = "first-seen"
+    )]
+    pub preserve_order: Option<AddressOrder>,
+
     /// Output mode
     #[clap(arg_enum, short = 'm', long, default_value = "tui")]
     pub mode: Mode,
 
+    /// Write the report to this file instead of stdout, the mode is inferred from a .json, .csv or .md extension
+    #[clap(long)]
+    pub output: Option<String>,
+
     /// The number of report cycles to run
     #[clap(short = 'c', long, default_value_t = 10)]
     pub report_cycles: usize,
-}
 
-/// We only allow multiple targets to be specified for the Tui and for `Icmp` tracing.
-pub fn validate_multi(mode: Mode, protocol: TraceProtocol, targets: &[String]) {
-    match (mode, protocol) {
-        (Mode::Stream | Mode::Pretty | Mode::Markdown | Mode::Csv | Mode::Json, _)
-            if targets.len() > 1 =>
-        {
-            eprintln!("only a single target may be specified for this mode");
-            exit(-1);
-        }
-        (_, TraceProtocol::Tcp | TraceProtocol::Udp) if targets.len() > 1 => {
-            eprintln!("only a single target may be specified for TCP and UDP tracing");
-            exit(-1);
-        }
-        _ => {}
-    }
-}
+    /// Show the progress of the report cycles on stderr
+    #[clap(long)]
+    pub progress: bool,
 
-/// Validate `report_cycles`
-pub fn validate_report_cycles(report_cycles: usize) {
-    if report_cycles == 0 {
-        eprintln!(
-            "report_cycles ({}) must be greater than zero",
-            report_cycles
-        );
-        exit(-1);
-    }
-}
+    /// Exit with code 2 if the target did not respond in this many consecutive rounds at the end of the report
+    #[clap(long)]
+    pub require_reachable: Option<usize>,
 
-/// Validate `tui_refresh_rate`
-pub fn validate_tui_refresh_rate(tui_refresh_rate: Duration) {
-    if tui_refresh_rate < TUI_MIN_REFRESH_RATE_MS || tui_refresh_rate > TUI_MAX_REFRESH_RATE_MS {
-        eprintln!(
-            "tui_refresh_rate ({:?}) must be between {:?} and {:?} inclusive",
-            tui_refresh_rate, TUI_MIN_REFRESH_RATE_MS, TUI_MAX_REFRESH_RATE_MS
-        );
-        exit(-1);
-    }
-}
+    /// How to render timestamps in reports, either rfc3339, unix or a strftime-style format such as '%H:%M:%S'
+    #[clap(long, default_value = "rfc3339", parse(try_from_str = parse_timestamp_format))]
+    pub timestamp_format: TimestampFormat,
 
-/// Validate `grace_duration`
-pub fn validate_grace_duration(grace_duration: Duration) {
-    if grace_duration < MIN_GRACE_DURATION_MS || grace_duration > MAX_GRACE_DURATION_MS {
-        eprintln!(
-            "grace_duration ({:?}) must be between {:?} and {:?} inclusive",
-            grace_duration, MIN_GRACE_DURATION_MS, MAX_GRACE_DURATION_MS
-        );
-        exit(-1);
-    }
-}
+    /// The unit of round-trip times in the Tui and the pretty, markdown, csv and stream reports
+    #[clap(arg_enum, long, default_value = "ms")]
+    pub rtt_unit: RttUnit,
 
-/// Validate `packet_size`
-pub fn validate_packet_size(packet_size: u16) {
-    if !(MIN_PACKET_SIZE..=MAX_PACKET_SIZE).contains(&packet_size) {
-        eprintln!(
-            "packet_size ({}) must be between {} and {} inclusive",
-            packet_size, MIN_PACKET_SIZE, MAX_PACKET_SIZE
-        );
-        exit(-1);
-    }
-}
+    /// The number of decimal places of round-trip times in the Tui and the pretty, markdown, csv and stream reports
+    #[clap(long, default_value_t = 1)]
+    pub rtt_precision: usize,
 
-/// Validate `source_port`
-pub fn validate_source_port(source_port: u16) {
-    if source_port < 1024 {
-        eprintln!("source_port ({}) must be >= 1024", source_port);
-        exit(-1);
-    }
-}
+    /// The columns, in order, of the pretty, markdown and csv reports, comma delimited
+    #[clap(
+        arg_enum,
+        long,
+        use_value_delimiter = true,
+        default_value = "hop,host,loss,sent,recv,last,avg,best,worst,stddev,jitter"
+    )]
+    pub report_columns: Vec<ReportColumn>,
 
-/// Validate `min_round_duration` and `max_round_duration`
-pub fn validate_round_duration(min_round_duration: Duration, max_round_duration: Duration) {
-    if min_round_duration > max_round_duration {
-        eprintln!(
-            "max_round_duration ({:?}) must not be less than min_round_duration ({:?})",
-            max_round_duration, min_round_duration
-        );
-        exit(-1);
-    }
-}
+    /// Render the pretty and markdown reports with this template file rather than as a table
+    #[clap(long)]
+    pub report_template: Option<String>,
 
-/// Validate `read_timeout`
-pub fn validate_read_timeout(read_timeout: Duration) {
+    /// Mark each hop of the markdown report with its status by the loss thresholds, and bold the target hop
+    #[clap(long, conflicts_with = "report-template")]
+    pub markdown_rich: bool,
+
+    /// A free-text description of the trace, embedded in the Tui header and the pretty, markdown and json reports
+    #[clap(long)]
+    pub trace_description: Option<String>,
+
+    /// Publish each round as JSON to clients of a TCP address or Unix socket path (i.e. 127.0.0.1:4000)
+    #[clap(long)]
+    pub publish: Option<String>,
+
+    /// Also write each round as JSON to stderr, or to this file (i.e. --columns-as-json=/dev/fd/3), while tracing
+    #[clap(
+        long,
+        value_name = "PATH",
+        min_values = 0,
+        max_values = 1,
+        require_equals = true,
+        default_missing_value = "-"
+    )]
+    pub columns_as_json: Option<String>,
+
+    /// Serve Prometheus metrics over HTTP on this address, or on this port of the loopback interface if only a port is
+    /// given (i.e. 9100 or 0.0.0.0:9100)
+    #[clap(long, parse(try_from_str = parse_metrics_addr))]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Push the round-trip time and loss of each hop to a statsd endpoint over UDP every round (i.e. 127.0.0.1:8125)
+    #[clap(long)]
+    pub statsd: Option<String>,
+
+    /// Write the round-trip time, loss and jitter of each hop as influx line protocol every round to a udp://, http://
+    /// or file: address
+    #[clap(long)]
+    pub influx: Option<String>,
+
+    /// Push each round as JSON to WebSocket clients on this port, on all interfaces
+    #[clap(long)]
+    pub ws_port: Option<u16>,
+
+    /// Connect to the target via a SOCKS5 or HTTP CONNECT proxy, tracing only up to the proxy (TCP only)
+    #[clap(long)]
+    pub proxy: Option<String>,
+
+    /// Export a histogram of the round-trip times of each hop to a JSON file
+    #[clap(long)]
+    pub export_histogram: Option<String>,
+
+    /// Export the topology of the hops, and the paths between them, to a Graphviz DOT file
+    #[clap(long)]
+    pub export_dot: Option<String>,
+
+    /// Save the statistics of each hop to a file every round and restore them from it on startup (Tui only)
+    #[clap(long)]
+    pub state_file: Option<String>,
+
+    /// The round-trip time percentiles (%) of each hop to compute from the recent samples, comma delimited
+    #[clap(long, use_value_delimiter = true, default_value = "50,95,99")]
+    pub percentiles: Vec<f64>,
+
+    /// The upper bound of each histogram bucket, comma delimited [default: 1ms,2ms,4ms,..,4096ms]
+    #[clap(long, use_value_delimiter = true)]
+    pub histogram_buckets: Vec<String>,
+
+    /// Write all probes sent and responses received to a pcap file
+    #[clap(long)]
+    pub write_pcap: Option<String>,
+
+    /// Discover the MTU of the path to each target, and the hop which limits it, print it and exit
+    #[clap(long)]
+    pub discover_mtu: bool,
+
+    /// Compare a baseline and a current json report, print the hops which changed and exit
+    #[clap(long, number_of_values = 2, value_names = &["BASELINE", "CURRENT"])]
+    pub diff: Vec<String>,
+
+    /// The loss % of a hop above, or back below, which the alert mode prints an alert
+    #[clap(long, default_value_t = 10.0)]
+    pub alert_loss_threshold: f64,
+
+    /// The average round-trip time of a hop above, or back below, which the alert mode prints an alert, if any
+    #[clap(long)]
+    pub alert_rtt_threshold: Option<String>,
+
+    /// The minimum change in the average round-trip time of a hop reported by --diff
+    #[clap(long, default_value = "10ms")]
+    pub diff_rtt_threshold: String,
+
+    /// The minimum change in the loss % of a hop reported by --diff
+    #[clap(long, default_value_t = 5.0)]
+    pub diff_loss_threshold: f64,
+
+    /// Show a json report in the Tui, read-only and without tracing
+    #[clap(long, conflicts_with_all = &["targets", "targets-file", "diff"])]
+    pub replay: Option<String>,
+
+    /// Highlight the hops of the Tui which deviate from the route of a prior json report, by address or AS
+    #[clap(long, conflicts_with = "diff")]
+    pub baseline: Option<String>,
+
+    /// Resolve the targets, print the address, family and source of each trace and the merged configuration, and exit
+    /// without tracing
+    #[clap(long, conflicts_with_all = &["replay", "diff"])]
+    pub dry_run: bool,
+
+    /// Print the JSON schema of the json report and exit
+    #[clap(long, hide = true)]
+    pub print_json_schema: bool,
+
+    /// Write a text snapshot of the Tui table of hops to a file on exit, or when `s` is pressed
+    #[clap(long)]
+    pub snapshot: Option<String>,
+
+    /// Read configuration from a toml or yaml file [default: ~/.config/trippy/config.toml]
+    #[clap(long)]
+    pub config_file: Option<String>,
+
+    /// The configuration merged from the command line and the config file, keyed as for the config file.
+    #[clap(skip)]
+    pub merged_config: TrippyConfig,
+}
+
+impl Args {
+    /// Parse the command line arguments and merge them with the config file, if any.
+    ///
+    /// Arguments explicitly passed on the command line always take precedence over those in the config file.  If no
+    /// config file is specified then the default config file is used, if it exists.
+    pub fn load() -> anyhow::Result<Self> {
+        let cli_args: Vec<OsString> = std::env::args_os().collect();
+        let command = Self::command();
+        let matches = command
+            .clone()
+            .ignore_errors(true)
+            .get_matches_from(&cli_args);
+        let config_file = match matches.value_of("config-file") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => default_config_path().filter(|path| path.exists()),
+        };
+        let args_list = match config_file {
+            Some(path) => TrippyConfig::read(&path)?.merge(&command, &matches, &cli_args),
+            None => cli_args,
+        };
+        let matches = command.clone().get_matches_from(args_list);
+        let mut args = Self::from_arg_matches(&matches)?;
+        if let Some(path) = &args.targets_file {
+            args.targets.extend(read_targets(path)?);
+        }
+        args.targets = args.targets.into_iter().unique().collect();
+        args.merged_config = TrippyConfig::from_matches(&command, &matches, &args.targets);
+        if matches.occurrences_of("mode") == 0 {
+            if let Some(mode) = args.output.as_deref().and_then(Mode::from_output_path) {
+                args.mode = mode;
+                if let Some(value) = mode.to_possible_value() {
+                    args.merged_config.insert("mode", value.get_name());
+                }
+            }
+        }
+        if args.targets.is_empty()
+            && !args.print_json_schema
+            && args.diff.is_empty()
+            && args.replay.is_none()
+            && !args.list_interfaces
+        {
+            return Err(anyhow!("no targets specified"));
+        }
+        Ok(args)
+    }
+}
+
+/// Read a list of targets from the file at `path`, or from stdin if `path` is `-`.
+///
+/// Targets are listed one per line, blank lines and lines starting with `#` are ignored.
+fn read_targets(path: &str) -> anyhow::Result<Vec<String>> {
+    let data = if path == "-" {
+        let mut data = String::new();
+        std::io::stdin()
+            .read_to_string(&mut data)
+            .map_err(|err| anyhow!("failed to read targets from stdin: {}", err))?;
+        data
+    } else {
+        fs::read_to_string(path)
+            .map_err(|err| anyhow!("failed to read targets file {}: {}", path, err))?
+    };
+    Ok(data
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// We only allow multiple targets to be specified for the Tui and report modes and for `Icmp` tracing.
+pub fn validate_multi(mode: Mode, protocol: TraceProtocol, targets: &[String]) {
+    match (mode, protocol) {
+        (Mode::Stream, _) if targets.len() > 1 => {
+            eprintln!("only a single target may be specified for this mode");
+            exit(-1);
+        }
+        (_, TraceProtocol::Tcp | TraceProtocol::Udp) if targets.len() > 1 => {
+            eprintln!("only a single target may be specified for TCP and UDP tracing");
+            exit(-1);
+        }
+        _ => {}
+    }
+}
+
+/// Validate `compare_protocols`
+///
+/// The protocols are compared in the Tui, for a single target, and `udp` and `tcp` probes require raw sockets.
+pub fn validate_compare_protocols(
+    compare_protocols: bool,
+    mode: Mode,
+    targets: &[String],
+    icmp_socket_mode: IcmpSocketMode,
+    proxy: Option<&str>,
+) {
+    if !compare_protocols {
+        return;
+    }
+    if !matches!(mode, Mode::Tui) {
+        eprintln!("compare_protocols may only be used with the tui mode");
+        exit(-1);
+    }
+    if targets.len() > 1 {
+        eprintln!("only a single target may be specified for compare_protocols");
+        exit(-1);
+    }
+    if icmp_socket_mode == IcmpSocketMode::Dgram {
+        eprintln!("compare_protocols may not be used with icmp_socket_mode dgram, as udp and tcp probes require raw sockets");
+        exit(-1);
+    }
+    if proxy.is_some() {
+        eprintln!("compare_protocols may not be used with proxy");
+        exit(-1);
+    }
+}
+
+/// Validate `rtt_precision`
+pub fn validate_rtt_precision(rtt_precision: usize) {
+    if rtt_precision > MAX_RTT_PRECISION {
+        eprintln!("rtt_precision ({rtt_precision}) must not be greater than {MAX_RTT_PRECISION}");
+        exit(-1);
+    }
+}
+
+/// Validate `progress`
+pub fn validate_progress(progress: bool, mode: Mode) {
+    if progress && !matches!(mode, Mode::Pretty | Mode::Markdown | Mode::Csv | Mode::Json) {
+        eprintln!("warning: progress is only shown for the pretty, markdown, csv and json modes");
+    }
+}
+
+/// Validate `report_cycles`
+pub fn validate_report_cycles(report_cycles: usize) {
+    if report_cycles == 0 {
+        eprintln!("report_cycles ({report_cycles}) must be greater than zero");
+        exit(-1);
+    }
+}
+
+/// Validate `require_reachable`
+///
+/// Reachability is only checked when a report completes, over at most the `report_cycles` rounds of the report.
+pub fn validate_require_reachable(
+    require_reachable: Option<usize>,
+    mode: Mode,
+    report_cycles: usize,
+) {
+    if let Some(rounds) = require_reachable {
+        if !matches!(mode, Mode::Pretty | Mode::Markdown | Mode::Csv | Mode::Json) {
+            eprintln!(
+                "require_reachable may only be used with the pretty, markdown, csv and json modes"
+            );
+            exit(-1);
+        }
+        if rounds == 0 || rounds > report_cycles {
+            eprintln!(
+                "require_reachable ({rounds}) must be greater than zero and no more than report_cycles ({report_cycles})"
+            );
+            exit(-1);
+        }
+    }
+}
+
+/// Validate `unknown_hop` and `collapse_unresponsive`
+///
+/// The placeholder must fit on a single line of the table of hops, and hops are only collapsed in the Tui and in the
+/// pretty and markdown reports.
+pub fn validate_unknown_hops(unknown_hop: Option<&str>, collapse_unresponsive: bool, mode: Mode) {
+    if let Some(unknown_hop) = unknown_hop {
+        if unknown_hop.trim().is_empty() || unknown_hop.contains('\n') {
+            eprintln!("unknown_hop ({unknown_hop:?}) must not be empty and must be a single line");
+            exit(-1);
+        }
+        if matches!(
+            mode,
+            Mode::Stream | Mode::Json | Mode::JsonStream | Mode::Alert
+        ) {
+            eprintln!("unknown_hop may only be used with the tui, pretty, markdown and csv modes");
+            exit(-1);
+        }
+    }
+    if collapse_unresponsive && !matches!(mode, Mode::Tui | Mode::Pretty | Mode::Markdown) {
+        eprintln!("collapse_unresponsive may only be used with the tui, pretty and markdown modes");
+        exit(-1);
+    }
+}
+
+/// Validate `report_columns`
+///
+/// The `asn` column requires AS information to be looked up, the `geo` column requires a geolocation database and the
+/// `label` column requires a table of labels.
+pub fn validate_report_columns(
+    report_columns: &[ReportColumn],
+    lookup_as_info: bool,
+    geoip: bool,
+    labels: bool,
+) {
+    if report_columns.contains(&ReportColumn::Asn) && !lookup_as_info {
+        eprintln!("the asn report column requires AS information lookup (-z)");
+        exit(-1);
+    }
+    if report_columns.contains(&ReportColumn::Geo) && !geoip {
+        eprintln!("the geo report column requires a geolocation database (--geoip-mmdb)");
+        exit(-1);
+    }
+    if report_columns.contains(&ReportColumn::Label) && !labels {
+        eprintln!("the label report column requires a table of labels (--labels)");
+        exit(-1);
+    }
+}
+
+/// Validate `report_template`
+pub fn validate_report_template(report_template: Option<&str>, mode: Mode) {
+    if report_template.is_some() && !matches!(mode, Mode::Pretty | Mode::Markdown) {
+        eprintln!("report_template may only be used with the pretty or markdown modes");
+        exit(-1);
+    }
+}
+
+/// Validate `trace_description`
+///
+/// The description is shown on a single line of the Tui header and report headers, and is only embedded in the Tui
+/// and in the pretty, markdown and json reports.
+pub fn validate_trace_description(trace_description: Option<&str>, mode: Mode) {
+    if let Some(trace_description) = trace_description {
+        if trace_description.trim().is_empty() || trace_description.contains('\n') {
+            eprintln!(
+                "trace_description ({trace_description:?}) must not be empty and must be a single line"
+            );
+            exit(-1);
+        }
+        if !matches!(mode, Mode::Tui | Mode::Pretty | Mode::Markdown | Mode::Json) {
+            eprintln!(
+                "trace_description may only be used with the tui, pretty, markdown and json modes"
+            );
+            exit(-1);
+        }
+    }
+}
+
+/// Validate `output`
+///
+/// Only the pretty, markdown, csv and json reports may be written to a file.
+pub fn validate_output(mode: Mode, output: Option<&str>) {
+    if output.is_some() && !matches!(mode, Mode::Pretty | Mode::Markdown | Mode::Csv | Mode::Json) {
+        eprintln!("output may only be used with the pretty, markdown, csv and json modes");
+        exit(-1);
+    }
+}
+
+/// The path MTU is discovered with `icmp` probes sent directly to each target.
+pub fn validate_discover_mtu(discover_mtu: bool, protocol: TraceProtocol, proxy: Option<&str>) {
+    if discover_mtu && !matches!(protocol, TraceProtocol::Icmp) {
+        eprintln!("discover_mtu may only be used with icmp tracing");
+        exit(-1);
+    }
+    if discover_mtu && proxy.is_some() {
+        eprintln!("discover_mtu may not be used with proxy");
+        exit(-1);
+    }
+}
+
+/// A report may only be replayed in the Tui mode.
+pub fn validate_replay(mode: Mode) {
+    if !matches!(mode, Mode::Tui) {
+        eprintln!("replay may only be used with the tui mode");
+        exit(-1);
+    }
+}
+
+/// A baseline route may only be highlighted in the Tui mode.
+pub fn validate_baseline(baseline: Option<&str>, mode: Mode) {
+    if baseline.is_some() && !matches!(mode, Mode::Tui) {
+        eprintln!("baseline may only be used with the tui mode");
+        exit(-1);
+    }
+}
+
+/// A state file may only be used in the Tui mode.
+pub fn validate_state_file(state_file: Option<&str>, mode: Mode) {
+    if state_file.is_some() && !matches!(mode, Mode::Tui) {
+        eprintln!("state_file may only be used with the tui mode");
+        exit(-1);
+    }
+}
+
+/// Validate `alert_loss_threshold`
+pub fn validate_alert_loss_threshold(alert_loss_threshold: f64) {
+    if !(0_f64..100_f64).contains(&alert_loss_threshold) {
+        eprintln!(
+            "alert_loss_threshold ({alert_loss_threshold}) must be at least 0 and less than 100"
+        );
+        exit(-1);
+    }
+}
+
+/// Validate `asn_names`
+///
+/// The table of AS names is only used when AS information is looked up.
+pub fn validate_asn_names(asn_names: Option<&str>, lookup_as_info: bool) {
+    if asn_names.is_some() && !lookup_as_info {
+        eprintln!("asn_names requires AS information lookup (-z)");
+        exit(-1);
+    }
+}
+
+/// Validate `anonymize`
+///
+/// The path MTU discovery prints the real addresses of the target and limiting hop, and a replay shows the addresses
+/// recorded in the report, so neither can be anonymized.
+pub fn validate_anonymize(anonymize: &[AnonymizeScope], discover_mtu: bool, replay: bool) {
+    if !anonymize.is_empty() && discover_mtu {
+        eprintln!("anonymize may not be used with discover_mtu");
+        exit(-1);
+    }
+    if !anonymize.is_empty() && replay {
+        eprintln!("anonymize may not be used with replay");
+        exit(-1);
+    }
+}
+
+/// Validate `reresolve`
+///
+/// A trace via a proxy is to the proxy, whose address is never re-resolved.
+pub fn validate_reresolve(reresolve: Option<Duration>, proxy: Option<&str>) {
+    if let Some(reresolve) = reresolve {
+        if reresolve < MIN_RERESOLVE_INTERVAL {
+            eprintln!("reresolve ({reresolve:?}) must be at least {MIN_RERESOLVE_INTERVAL:?}");
+            exit(-1);
+        }
+        if proxy.is_some() {
+            eprintln!("reresolve may not be used with proxy");
+            exit(-1);
+        }
+    }
+}
+
+/// Validate `proxy`
+///
+/// Connecting via a proxy is only meaningful for connection oriented `Tcp` tracing.
+pub fn validate_proxy(proxy: Option<&str>, protocol: TraceProtocol) {
+    if proxy.is_some() && !matches!(protocol, TraceProtocol::Tcp) {
+        eprintln!("proxy may only be used with the tcp protocol");
+        exit(-1);
+    }
+}
+
+/// The clock offset is derived from the replies to `ICMP` timestamp requests and is only shown in the Tui.
+pub fn validate_show_clock_skew(show_clock_skew: bool, protocol: TraceProtocol, mode: Mode) {
+    if !show_clock_skew {
+        return;
+    }
+    if !matches!(protocol, TraceProtocol::IcmpTimestamp) {
+        eprintln!("show_clock_skew may only be used with the icmp-timestamp protocol");
+        exit(-1);
+    }
+    if !matches!(mode, Mode::Tui) {
+        eprintln!("show_clock_skew may only be used with the tui mode");
+        exit(-1);
+    }
+}
+
+/// Validate `icmp_id`
+///
+/// Each target is traced with a distinct identifier, starting from `icmp_id`, which must not exceed the maximum.
+pub fn validate_icmp_id(icmp_id: Option<u16>, protocol: TraceProtocol, targets: &[String]) {
+    let Some(icmp_id) = icmp_id else {
+        return;
+    };
+    if !matches!(protocol, TraceProtocol::Icmp | TraceProtocol::IcmpTimestamp) {
+        eprintln!("icmp_id may only be used with the icmp and icmp-timestamp protocols");
+        exit(-1);
+    }
+    if usize::from(icmp_id) + targets.len().saturating_sub(1) > usize::from(u16::MAX) {
+        eprintln!(
+            "icmp_id ({icmp_id}) plus the number of additional targets ({}) must not exceed {}",
+            targets.len() - 1,
+            u16::MAX
+        );
+        exit(-1);
+    }
+}
+
+/// Validate `icmp_socket_mode`
+pub fn validate_icmp_socket_mode(
+    icmp_socket_mode: IcmpSocketMode,
+    protocol: TraceProtocol,
+    discover_mtu: bool,
+) {
+    if icmp_socket_mode != IcmpSocketMode::Dgram {
+        return;
+    }
+    if !matches!(protocol, TraceProtocol::Icmp) {
+        eprintln!("icmp_socket_mode dgram may only be used with the icmp protocol");
+        exit(-1);
+    }
+    if discover_mtu {
+        eprintln!(
+            "icmp_socket_mode dgram may not be used with discover_mtu, which requires a raw socket"
+        );
+        exit(-1);
+    }
+}
+
+/// Validate `percentiles`
+///
+/// At most `MAX_TUI_PERCENTILES` percentiles fit in the Tui table of hops.
+pub fn validate_percentiles(percentiles: &[f64], tui_show_percentiles: bool) {
+    if let Some(percentile) = percentiles
+        .iter()
+        .find(|&&percentile| !(percentile > 0_f64 && percentile <= 100_f64))
+    {
+        eprintln!("percentiles ({percentile}) must be greater than 0 and no more than 100");
+        exit(-1);
+    }
+    if tui_show_percentiles && percentiles.len() > MAX_TUI_PERCENTILES {
+        eprintln!(
+            "tui_show_percentiles requires no more than {MAX_TUI_PERCENTILES} percentiles ({})",
+            percentiles.len()
+        );
+        exit(-1);
+    }
+}
+
+/// Validate `histogram_buckets`
+pub fn validate_histogram_buckets(histogram_buckets: &[Duration]) {
+    if histogram_buckets.windows(2).any(|pair| pair[0] >= pair[1]) {
+        eprintln!("histogram_buckets ({histogram_buckets:?}) must be strictly increasing");
+        exit(-1);
+    }
+}
+
+/// Validate `max_pps`
+pub fn validate_max_pps(max_pps: Option<u32>) {
+    if max_pps == Some(0) {
+        eprintln!("max_pps (0) must be greater than zero");
+        exit(-1);
+    }
+}
+
+/// Validate `adaptive_loss_threshold` and `adaptive_recover_threshold`
+pub fn validate_adaptive_rate(adaptive_loss_threshold: f64, adaptive_recover_threshold: f64) {
+    if !(0_f64..=100_f64).contains(&adaptive_loss_threshold)
+        || !(0_f64..=100_f64).contains(&adaptive_recover_threshold)
+    {
+        eprintln!(
+            "adaptive_loss_threshold ({adaptive_loss_threshold}) and adaptive_recover_threshold ({adaptive_recover_threshold}) must be between 0 and 100"
+        );
+        exit(-1);
+    }
+    if adaptive_recover_threshold >= adaptive_loss_threshold {
+        eprintln!(
+            "adaptive_recover_threshold ({adaptive_recover_threshold}) must be less than adaptive_loss_threshold ({adaptive_loss_threshold})"
+        );
+        exit(-1);
+    }
+}
+
+/// Parse a `tui_hop_window` such as `5:12` into an inclusive range of ttl.
+fn parse_hop_window(hop_window: &str) -> Result<RangeInclusive<u8>, String> {
+    let (start, end) = hop_window
+        .split_once(':')
+        .ok_or_else(|| format!("invalid hop window {hop_window}: must be start:end"))?;
+    let start: u8 = start
+        .parse()
+        .map_err(|err| format!("invalid hop window start {start}: {err}"))?;
+    let end: u8 = end
+        .parse()
+        .map_err(|err| format!("invalid hop window end {end}: {err}"))?;
+    if start == 0 || start > end {
+        return Err(format!(
+            "invalid hop window {hop_window}: start must be greater than zero and not greater than end"
+        ));
+    }
+    Ok(start..=end)
+}
+
+/// Validate `tui_max_rounds`
+pub fn validate_tui_max_rounds(tui_max_rounds: Option<usize>) {
+    if tui_max_rounds == Some(0) {
+        eprintln!("tui_max_rounds (0) must be greater than zero");
+        exit(-1);
+    }
+}
+
+/// Validate `max_hostname_width`
+pub fn validate_max_hostname_width(max_hostname_width: Option<usize>) {
+    match max_hostname_width {
+        Some(width) if width < MIN_HOSTNAME_WIDTH => {
+            eprintln!("max_hostname_width ({width}) must be at least {MIN_HOSTNAME_WIDTH}");
+            exit(-1);
+        }
+        _ => {}
+    }
+}
+
+/// Validate `tui_history_width`
+///
+/// The history is drawn from the samples of each hop, and so cannot be wider than `tui_max_samples`.
+pub fn validate_tui_history_width(tui_history_width: Option<usize>, tui_max_samples: usize) {
+    match tui_history_width {
+        Some(0) => {
+            eprintln!("tui_history_width (0) must be greater than zero");
+            exit(-1);
+        }
+        Some(width) if width > tui_max_samples => {
+            eprintln!(
+                "warning: tui_history_width ({width}) is greater than tui_max_samples ({tui_max_samples}), only the most recent {tui_max_samples} probes will be shown"
+            );
+        }
+        _ => {}
+    }
+}
+
+/// Validate `tui_stale_rounds`
+pub fn validate_tui_stale_rounds(tui_stale_rounds: usize) {
+    if tui_stale_rounds == 0 {
+        eprintln!("tui_stale_rounds ({tui_stale_rounds}) must be greater than zero");
+        exit(-1);
+    }
+}
+
+/// Validate `loss_warn_threshold` and `loss_critical_threshold`
+pub fn validate_loss_thresholds(loss_warn_threshold: f64, loss_critical_threshold: f64) {
+    if !(0_f64..loss_critical_threshold).contains(&loss_warn_threshold)
+        || loss_critical_threshold > 100_f64
+    {
+        eprintln!(
+            "loss_warn_threshold ({loss_warn_threshold}) and loss_critical_threshold ({loss_critical_threshold}) must satisfy 0 <= warn < critical <= 100"
+        );
+        exit(-1);
+    }
+}
+
+/// Validate `tui_refresh_rate`
+pub fn validate_tui_refresh_rate(tui_refresh_rate: Duration) {
+    if tui_refresh_rate < TUI_MIN_REFRESH_RATE_MS || tui_refresh_rate > TUI_MAX_REFRESH_RATE_MS {
+        eprintln!(
+            "tui_refresh_rate ({tui_refresh_rate:?}) must be between {TUI_MIN_REFRESH_RATE_MS:?} and {TUI_MAX_REFRESH_RATE_MS:?} inclusive"
+        );
+        exit(-1);
+    }
+}
+
+/// Validate `grace_duration`
+pub fn validate_grace_duration(grace_duration: Duration) {
+    if grace_duration < MIN_GRACE_DURATION_MS || grace_duration > MAX_GRACE_DURATION_MS {
+        eprintln!(
+            "grace_duration ({grace_duration:?}) must be between {MIN_GRACE_DURATION_MS:?} and {MAX_GRACE_DURATION_MS:?} inclusive"
+        );
+        exit(-1);
+    }
+}
+
+/// Validate `adaptive_grace`
+pub fn validate_adaptive_grace(adaptive_grace: Option<Duration>, grace_duration: Duration) {
+    if let Some(adaptive_grace) = adaptive_grace {
+        if adaptive_grace < grace_duration || adaptive_grace > MAX_GRACE_DURATION_MS {
+            eprintln!(
+                "adaptive_grace ({adaptive_grace:?}) must be between grace_duration ({grace_duration:?}) and {MAX_GRACE_DURATION_MS:?} inclusive"
+            );
+            exit(-1);
+        }
+    }
+}
+
+/// Validate `ewma_alpha`
+///
+/// An alpha of zero would never move the average from the first sample.
+pub fn validate_ewma_alpha(ewma_alpha: f64) {
+    if !(ewma_alpha > 0_f64 && ewma_alpha <= 1_f64) {
+        eprintln!("ewma_alpha ({ewma_alpha}) must be greater than 0 and less than or equal to 1");
+        exit(-1);
+    }
+}
+
+/// Validate `packet_size`, which may be larger if probes are fragmented.
+pub fn validate_packet_size(packet_size: u16, fragment: bool) {
+    let max_packet_size = if fragment {
+        MAX_FRAGMENTED_PACKET_SIZE
+    } else {
+        MAX_PACKET_SIZE
+    };
+    if !(MIN_PACKET_SIZE..=max_packet_size).contains(&packet_size) {
+        eprintln!(
+            "packet_size ({packet_size}) must be between {MIN_PACKET_SIZE} and {max_packet_size} inclusive"
+        );
+        exit(-1);
+    }
+}
+
+/// Validate `fragment`
+///
+/// Fragments are sent on a raw socket, and `icmp-timestamp` probes have a fixed size and so are never fragmented.
+pub fn validate_fragment(
+    fragment: bool,
+    packet_size: u16,
+    protocol: TraceProtocol,
+    icmp_socket_mode: IcmpSocketMode,
+) {
+    if !fragment {
+        return;
+    }
+    if matches!(protocol, TraceProtocol::IcmpTimestamp) {
+        eprintln!("fragment may not be used with the icmp-timestamp protocol");
+        exit(-1);
+    }
+    if icmp_socket_mode == IcmpSocketMode::Dgram {
+        eprintln!("fragment may not be used with icmp_socket_mode dgram, which does not permit fragmenting probes");
+        exit(-1);
+    }
+    if packet_size <= FRAGMENT_SIZE {
+        eprintln!(
+            "warning: probes of packet_size ({packet_size}) are not fragmented, use a packet_size greater than {FRAGMENT_SIZE}"
+        );
+    }
+}
+
+/// Validate `dont_fragment`
+///
+/// The don't-fragment bit is set by building the IP header on a raw socket, and fragmented probes never have it set.
+pub fn validate_dont_fragment(
+    dont_fragment: Option<bool>,
+    fragment: bool,
+    icmp_socket_mode: IcmpSocketMode,
+) {
+    if dont_fragment == Some(true) && fragment {
+        eprintln!("dont_fragment may not be set with fragment");
+        exit(-1);
+    }
+    if dont_fragment.is_some() && icmp_socket_mode == IcmpSocketMode::Dgram {
+        eprintln!("dont_fragment may not be used with icmp_socket_mode dgram, which does not permit building the IP header");
+        exit(-1);
+    }
+}
+
+/// Parse a `payload_hex` such as `0x74726970` or `74726970` into bytes.
+pub fn parse_payload_hex(payload_hex: &str) -> anyhow::Result<Vec<u8>> {
+    let hex = payload_hex
+        .strip_prefix("0x")
+        .or_else(|| payload_hex.strip_prefix("0X"))
+        .unwrap_or(payload_hex);
+    if hex.is_empty() || !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return Err(anyhow!(
+            "invalid payload_hex {}: must be a non-empty even number of hex digits",
+            payload_hex
+        ));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|err| anyhow!("invalid payload_hex {}: {}", payload_hex, err))
+        })
+        .collect()
+}
+
+/// Validate `payload_hex`
+///
+/// The payload is truncated or padded to fit the `packet_size` and so may not be longer than the largest payload
+/// of a packet of `MAX_PACKET_SIZE`.
+pub fn validate_payload_hex(payload_hex: Option<&[u8]>) {
+    if let Some(payload) = payload_hex {
+        let max_payload_size = usize::from(MAX_PACKET_SIZE - MIN_PACKET_SIZE);
+        if payload.len() > max_payload_size {
+            eprintln!(
+                "payload_hex ({} bytes) must be at most {max_payload_size} bytes",
+                payload.len()
+            );
+            exit(-1);
+        }
+    }
+}
+
+/// Validate `multipath_strategy` against the `packet_size`.
+///
+/// The `paris` strategy requires space in the payload to adjust the checksum for `icmp` and `udp` and is not supported
+/// for `icmp-timestamp`, which has no payload.
+pub fn validate_multipath_strategy(
+    multipath_strategy: MultipathStrategy,
+    protocol: TraceProtocol,
+    packet_size: u16,
+) {
+    match (multipath_strategy, protocol) {
+        (MultipathStrategy::Paris, TraceProtocol::IcmpTimestamp) => {
+            eprintln!(
+                "the paris multipath strategy is not supported for the icmp-timestamp protocol"
+            );
+            exit(-1);
+        }
+        (MultipathStrategy::Paris, TraceProtocol::Icmp | TraceProtocol::Udp)
+            if packet_size < MIN_PARIS_PACKET_SIZE =>
+        {
+            eprintln!(
+                "packet_size ({packet_size}) must be at least {MIN_PARIS_PACKET_SIZE} for the paris multipath strategy"
+            );
+            exit(-1);
+        }
+        _ => {}
+    }
+}
+
+/// Validate `udp_dest_port_mode` and `udp_dest_port_base`.
+///
+/// The `paris` strategy keeps the destination port fixed.  The `fixed` mode encodes the sequence in the checksum and so
+/// requires space in the payload to adjust it.
+pub fn validate_udp_dest_port(
+    mode: Option<UdpDestPortMode>,
+    base: Option<u16>,
+    protocol: TraceProtocol,
+    multipath_strategy: MultipathStrategy,
+    max_flows: u16,
+    packet_size: u16,
+) {
+    if !matches!(protocol, TraceProtocol::Udp) {
+        if mode.is_some() || base.is_some() {
+            eprintln!(
+                "udp_dest_port_mode and udp_dest_port_base may only be used with the udp protocol"
+            );
+            exit(-1);
+        }
+        return;
+    }
+    match mode.unwrap_or_else(|| UdpDestPortMode::default_for(multipath_strategy)) {
+        UdpDestPortMode::Increment => {
+            if matches!(multipath_strategy, MultipathStrategy::Paris) {
+                eprintln!("udp_dest_port_mode (increment) may not be used with the paris multipath strategy");
+                exit(-1);
+            }
+            if let Some(base) = base.filter(|&base| base > MAX_UDP_DEST_PORT_BASE) {
+                eprintln!("udp_dest_port_base ({base}) must be at most {MAX_UDP_DEST_PORT_BASE} for the increment mode");
+                exit(-1);
+            }
+        }
+        UdpDestPortMode::Fixed => {
+            let base = base.unwrap_or(DEFAULT_UDP_DEST_PORT_BASE);
+            let max_base = u16::MAX - (max_flows.max(1) - 1);
+            if base == 0 || base > max_base {
+                eprintln!("udp_dest_port_base ({base}) must be between 1 and {max_base} for the fixed mode with {max_flows} flows");
+                exit(-1);
+            }
+            if packet_size < MIN_PARIS_PACKET_SIZE {
+                eprintln!("packet_size ({packet_size}) must be at least {MIN_PARIS_PACKET_SIZE} for the fixed udp_dest_port_mode");
+                exit(-1);
+            }
+        }
+    }
+}
+
+/// Validate `tcp_dest_ports`
+///
+/// Each destination port is traced in turn as a distinct flow and so the `paris` strategy, which also uses flows, is
+/// not supported.
+pub fn validate_tcp_dest_ports(
+    tcp_dest_ports: &[u16],
+    protocol: TraceProtocol,
+    multipath_strategy: MultipathStrategy,
+) {
+    if tcp_dest_ports.is_empty() {
+        return;
+    }
+    if !matches!(protocol, TraceProtocol::Tcp) {
+        eprintln!("tcp_dest_ports may only be used with the tcp protocol");
+        exit(-1);
+    }
+    if matches!(multipath_strategy, MultipathStrategy::Paris) {
+        eprintln!("tcp_dest_ports may not be used with the paris multipath strategy");
+        exit(-1);
+    }
+    if tcp_dest_ports.contains(&0) || !tcp_dest_ports.iter().all_unique() {
+        eprintln!("tcp_dest_ports ({tcp_dest_ports:?}) must be distinct and greater than zero");
+        exit(-1);
+    }
+    if tcp_dest_ports.len() > usize::from(MAX_FLOWS) {
+        eprintln!(
+            "tcp_dest_ports ({} ports) must be at most {MAX_FLOWS} ports",
+            tcp_dest_ports.len()
+        );
+        exit(-1);
+    }
+}
+
+/// Validate `tcp_flags`
+///
+/// Probes must set one of `syn`, `ack` or `fin` for the target to respond to them, and `syn` and `fin` may not be set
+/// together.
+pub fn validate_tcp_flags(tcp_flags: &[TcpFlag], protocol: TraceProtocol) {
+    if tcp_flags != [TcpFlag::Syn] && !matches!(protocol, TraceProtocol::Tcp) {
+        eprintln!("tcp_flags may only be used with the tcp protocol");
+        exit(-1);
+    }
+    if !tcp_flags
+        .iter()
+        .any(|flag| matches!(flag, TcpFlag::Syn | TcpFlag::Ack | TcpFlag::Fin))
+    {
+        eprintln!("tcp_flags ({tcp_flags:?}) must include one of syn, ack or fin");
+        exit(-1);
+    }
+    if tcp_flags.contains(&TcpFlag::Syn) && tcp_flags.contains(&TcpFlag::Fin) {
+        eprintln!("tcp_flags ({tcp_flags:?}) may not include both syn and fin");
+        exit(-1);
+    }
+}
+
+/// Validate `max_flows`
+pub fn validate_max_flows(max_flows: u16, multipath_strategy: MultipathStrategy) {
+    if !(1..=MAX_FLOWS).contains(&max_flows) {
+        eprintln!("max_flows ({max_flows}) must be between 1 and {MAX_FLOWS} inclusive");
+        exit(-1);
+    }
+    if max_flows > 1 && !matches!(multipath_strategy, MultipathStrategy::Paris) {
+        eprintln!("max_flows ({max_flows}) may only be used with the paris multipath strategy");
+        exit(-1);
+    }
+}
+
+/// Parse a `tos` value, either in decimal or in hex with a `0x` prefix.
+fn parse_tos(tos: &str) -> Result<u8, std::num::ParseIntError> {
+    match tos.strip_prefix("0x").or_else(|| tos.strip_prefix("0X")) {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => tos.parse(),
+    }
+}
+
+/// Parse the `metrics_addr`, a socket address or a port which is bound on the loopback interface.
+fn parse_metrics_addr(metrics_addr: &str) -> Result<SocketAddr, String> {
+    match metrics_addr.parse::<u16>() {
+        Ok(port) => Ok(SocketAddr::from((Ipv4Addr::LOCALHOST, port))),
+        Err(_) => metrics_addr
+            .parse()
+            .map_err(|err| format!("invalid metrics address {metrics_addr}: {err}")),
+    }
+}
+
+/// Validate `tos`
+///
+/// The two least significant bits of the type-of-service byte are the ECN field, which is managed by the kernel.
+pub fn validate_tos(tos: Option<u8>) {
+    if let Some(tos) = tos {
+        if tos & 0b11 != 0 {
+            eprintln!("tos ({tos:#04x}) must not set the two least significant (ECN) bits");
+            exit(-1);
+        }
+    }
+}
+
+/// Validate `source_address`
+///
+/// The address must be assigned to a local interface and be of the same family as all targets.
+pub fn validate_source_address(source_address: Option<IpAddr>, target_addrs: &[IpAddr]) {
+    if let Some(source_address) = source_address {
+        if !pnet::datalink::interfaces()
+            .iter()
+            .flat_map(|e| &e.ips)
+            .any(|ip| ip.ip() == source_address)
+        {
+            eprintln!("source_address ({source_address}) is not a local address");
+            exit(-1);
+        }
+        if let Some(addr) = target_addrs
+            .iter()
+            .find(|addr| addr.is_ipv4() != source_address.is_ipv4())
+        {
+            eprintln!("source_address ({source_address}) may not be used with target ({addr}) of a different family");
+            exit(-1);
+        }
+    }
+}
+
+/// Validate `source_port`
+///
+/// A range of source ports must be within the ephemeral ports, above the well-known ports, and only rotates across
+/// flows for the classic multipath strategy, as each round of a `paris` trace must keep to a single flow.
+pub fn validate_source_port(
+    source_port: &RangeInclusive<u16>,
+    protocol: TraceProtocol,
+    multipath_strategy: MultipathStrategy,
+) {
+    if *source_port.start() < 1024 {
+        eprintln!("source_port ({}) must be >= 1024", source_port.start());
+        exit(-1);
+    }
+    if source_port.start() == source_port.end() {
+        return;
+    }
+    if !matches!(protocol, TraceProtocol::Udp | TraceProtocol::Tcp) {
+        eprintln!("a source_port range may only be used with the udp and tcp protocols");
+        exit(-1);
+    }
+    if matches!(multipath_strategy, MultipathStrategy::Paris) {
+        eprintln!("a source_port range may not be used with the paris multipath strategy");
+        exit(-1);
+    }
+}
+
+/// Parse a `source_port` such as `40000`, or a range of source ports such as `40000:40015`.
+fn parse_source_port(source_port: &str) -> Result<RangeInclusive<u16>, String> {
+    let (start, end) = source_port
+        .split_once(':')
+        .unwrap_or((source_port, source_port));
+    let start: u16 = start
+        .parse()
+        .map_err(|err| format!("invalid source port {start}: {err}"))?;
+    let end: u16 = end
+        .parse()
+        .map_err(|err| format!("invalid source port {end}: {err}"))?;
+    if start > end {
+        return Err(format!(
+            "invalid source port range {source_port}: start must not be greater than end"
+        ));
+    }
+    Ok(start..=end)
+}
+
+/// Parse a `pin_hop` such as `3=10.0.0.1`.
+fn parse_pin_hop(pin_hop: &str) -> Result<(u8, IpAddr), String> {
+    let (ttl, addr) = pin_hop
+        .split_once('=')
+        .ok_or_else(|| format!("invalid pinned hop {pin_hop}: expected ttl=addr"))?;
+    let ttl: u8 = ttl
+        .parse()
+        .map_err(|err| format!("invalid pinned hop ttl {ttl}: {err}"))?;
+    if ttl == 0 {
+        return Err(format!(
+            "invalid pinned hop {pin_hop}: ttl must be at least 1"
+        ));
+    }
+    let addr: IpAddr = addr
+        .parse()
+        .map_err(|err| format!("invalid pinned hop address {addr}: {err}"))?;
+    Ok((ttl, addr))
+}
+
+/// Validate `pin_hop`
+pub fn validate_pin_hop(pin_hop: &[(u8, IpAddr)]) {
+    if let Some((ttl, _)) = pin_hop.iter().duplicates_by(|(ttl, _)| ttl).next() {
+        eprintln!("pin_hop may only pin the hop at ttl {ttl} once");
+        exit(-1);
+    }
+}
+
+/// Validate `min_round_duration` and `max_round_duration`
+pub fn validate_round_duration(min_round_duration: Duration, max_round_duration: Duration) {
+    if min_round_duration > max_round_duration {
+        eprintln!(
+            "max_round_duration ({max_round_duration:?}) must not be less than min_round_duration ({min_round_duration:?})"
+        );
+        exit(-1);
+    }
+}
+
+/// Validate `probe_timeout`
+///
+/// A round which reaches the target ends soon after `min_round_duration` and so a longer probe timeout would never
+/// take effect.
+pub fn validate_probe_timeout(probe_timeout: Option<Duration>, min_round_duration: Duration) {
+    if let Some(probe_timeout) = probe_timeout {
+        if probe_timeout.is_zero() || probe_timeout > min_round_duration {
+            eprintln!(
+                "probe_timeout ({probe_timeout:?}) must be greater than zero and not greater than min_round_duration ({min_round_duration:?})"
+            );
+            exit(-1);
+        }
+    }
+}
+
+/// Validate `markdown_rich`
+pub fn validate_markdown_rich(markdown_rich: bool, mode: Mode) {
+    if markdown_rich && !matches!(mode, Mode::Markdown) {
+        eprintln!("markdown_rich may only be used with the markdown mode");
+        exit(-1);
+    }
+}
+
+/// Validate `max_samples_unbounded`
+///
+/// Every sample of a report is retained in memory, `report_cycles` multiplied by `probes_per_round` samples for each hop,
+/// and so this is limited to `MAX_UNBOUNDED_SAMPLES`.
+pub fn validate_max_samples_unbounded(
+    max_samples_unbounded: bool,
+    mode: Mode,
+    report_cycles: usize,
+    probes_per_round: u8,
+) {
+    if !max_samples_unbounded {
+        return;
+    }
+    if !matches!(mode, Mode::Pretty | Mode::Markdown | Mode::Csv | Mode::Json) {
+        eprintln!(
+            "max_samples_unbounded may only be used with the pretty, markdown, csv and json modes"
+        );
+        exit(-1);
+    }
+    let samples = report_cycles.saturating_mul(usize::from(probes_per_round));
+    if samples > MAX_UNBOUNDED_SAMPLES {
+        eprintln!(
+            "max_samples_unbounded may only be used for at most {MAX_UNBOUNDED_SAMPLES} samples per hop, but report_cycles ({report_cycles}) and probes_per_round ({probes_per_round}) would retain {samples}"
+        );
+        exit(-1);
+    }
+}
+
+/// Validate `stats_window`
+///
+/// The stats window only applies to the Tui and alert modes, reports are always computed over all rounds traced.
+pub fn validate_stats_window(stats_window: Option<Duration>, mode: Mode) {
+    if let Some(stats_window) = stats_window {
+        if stats_window.is_zero() {
+            eprintln!("stats_window ({stats_window:?}) must be greater than zero");
+            exit(-1);
+        }
+        if !matches!(mode, Mode::Tui | Mode::Alert) {
+            eprintln!("stats_window may only be used with the tui and alert modes");
+            exit(-1);
+        }
+    }
+}
+
+/// Validate `read_timeout`
+pub fn validate_read_timeout(read_timeout: Duration) {
     if read_timeout < MIN_READ_TIMEOUT_MS || read_timeout > MAX_READ_TIMEOUT_MS {
         eprintln!(
-            "read_timeout ({:?}) must be between {:?} and {:?} inclusive",
-            read_timeout, MIN_READ_TIMEOUT_MS, MAX_READ_TIMEOUT_MS
+            "read_timeout ({read_timeout:?}) must be between {MIN_READ_TIMEOUT_MS:?} and {MAX_READ_TIMEOUT_MS:?} inclusive"
         );
         exit(-1);
     }
@@ -274,7 +2009,7 @@ pub fn validate_read_timeout(read_timeout: Duration) {
 /// Validate `max_inflight`
 pub fn validate_max_inflight(max_inflight: u8) {
     if max_inflight == 0 {
-        eprintln!("max_inflight ({}) must be greater than zero", max_inflight);
+        eprintln!("max_inflight ({max_inflight}) must be greater than zero");
         exit(-1);
     }
 }
@@ -295,6 +2030,59 @@ pub fn validate_ttl(first_ttl: u8, max_ttl: u8) {
     }
 }
 
+/// Validate `ttl_stride`
+///
+/// A stride which would step beyond `max_ttl` from `first_ttl` probes only a single hop.
+pub fn validate_ttl_stride(ttl_stride: u8, first_ttl: u8, max_ttl: u8) {
+    if ttl_stride == 0 {
+        eprintln!("ttl_stride ({ttl_stride}) must be greater than 0");
+        exit(-1);
+    }
+    if ttl_stride > 1 && u16::from(first_ttl) + u16::from(ttl_stride) > u16::from(max_ttl) {
+        eprintln!("ttl_stride ({ttl_stride}) must be less than or equal to max_ttl ({max_ttl}) minus first_ttl ({first_ttl})");
+        exit(-1);
+    }
+}
+
+/// Validate `initial_sequence`
+///
+/// If the sequence numbers of a single round, `probes_per_round` per ttl, extend beyond `MAX_SEQUENCE` then the tracer
+/// restarts from the initial sequence number every round, such that a late response to a probe from a prior round may
+/// be mismatched with the probe of the current round which has the same sequence number.
+pub fn validate_initial_sequence(
+    initial_sequence: u16,
+    first_ttl: u8,
+    max_ttl: u8,
+    probes_per_round: u8,
+) {
+    if initial_sequence > MAX_SEQUENCE {
+        eprintln!("initial_sequence ({initial_sequence}) must not be greater than {MAX_SEQUENCE}");
+        exit(-1);
+    }
+    let round_probes =
+        (u32::from(max_ttl.saturating_sub(first_ttl)) + 1) * u32::from(probes_per_round);
+    if u32::from(initial_sequence) + round_probes > u32::from(MAX_SEQUENCE) {
+        eprintln!(
+            "warning: initial_sequence ({initial_sequence}) plus the {round_probes} probes per round exceeds {MAX_SEQUENCE}, the same sequence numbers will be reused every round and late responses may be mismatched"
+        );
+    }
+}
+
+/// Validate `probes_per_round`
+///
+/// Every ttl is sent `probes_per_round` probes in each round, which multiplies the probe volume, and the time needed to
+/// send them all, by `probes_per_round`.
+pub fn validate_probes_per_round(probes_per_round: u8, discover_mtu: bool) {
+    if !(1..=MAX_PROBES_PER_ROUND).contains(&probes_per_round) {
+        eprintln!("probes_per_round ({probes_per_round}) must be between 1 and {MAX_PROBES_PER_ROUND} inclusive");
+        exit(-1);
+    }
+    if probes_per_round > 1 && discover_mtu {
+        eprintln!("probes_per_round ({probes_per_round}) may not be used with discover_mtu");
+        exit(-1);
+    }
+}
+
 /// Validate `dns_resolve_method` and `dns_lookup_as_info`
 pub fn validate_dns(dns_resolve_method: DnsResolveMethod, dns_lookup_as_info: bool) {
     match dns_resolve_method {
@@ -305,3 +2093,82 @@ pub fn validate_dns(dns_resolve_method: DnsResolveMethod, dns_lookup_as_info: bo
         _ => {}
     }
 }
+
+/// Validate `dns_endpoint`
+///
+/// A custom endpoint must be an `https://` url for the `doh` resolver and a `tls://` url for the `dot` resolver.
+pub fn validate_dns_endpoint(dns_resolve_method: DnsResolveMethod, dns_endpoint: Option<&str>) {
+    let Some(dns_endpoint) = dns_endpoint else {
+        return;
+    };
+    match dns_resolve_method {
+        DnsResolveMethod::Doh if dns_endpoint.starts_with("tls://") => {
+            eprintln!("dns_endpoint ({dns_endpoint}) must be an https:// url for the doh resolver");
+            exit(-1);
+        }
+        DnsResolveMethod::Dot if dns_endpoint.starts_with("https://") => {
+            eprintln!("dns_endpoint ({dns_endpoint}) must be a tls:// url for the dot resolver");
+            exit(-1);
+        }
+        DnsResolveMethod::Doh | DnsResolveMethod::Dot => {}
+        _ => {
+            eprintln!("dns_endpoint may only be used with the doh and dot resolvers");
+            exit(-1);
+        }
+    }
+}
+
+/// Validate `dns_resolve_all`
+///
+/// The `system` resolver only returns a single hostname for each address, so a warning is shown if every hostname
+/// was requested.
+pub fn validate_dns_resolve_all(dns_resolve_method: DnsResolveMethod, dns_resolve_all: bool) {
+    if dns_resolve_all && matches!(dns_resolve_method, DnsResolveMethod::System) {
+        eprintln!("warning: the system resolver returns a single hostname for each address (use '-r' to choose another resolver)");
+    }
+}
+
+/// Validate `no_dns`
+///
+/// Without DNS every target must be an IP address, and addresses are always shown as IP without AS information, so a
+/// warning is shown if another `tui_address_mode` or `dns_lookup_as_info` was requested.
+pub fn validate_no_dns(
+    no_dns: bool,
+    targets: &[String],
+    tui_address_mode: Option<AddressMode>,
+    dns_lookup_as_info: bool,
+) {
+    if !no_dns {
+        return;
+    }
+    if let Some(target) = targets
+        .iter()
+        .find(|target| target.parse::<IpAddr>().is_err())
+    {
+        eprintln!("target ({target}) must be an IP address when DNS is disabled (--no-dns)");
+        exit(-1);
+    }
+    if matches!(
+        tui_address_mode,
+        Some(AddressMode::Host | AddressMode::Both)
+    ) {
+        eprintln!("warning: DNS is disabled (--no-dns), showing addresses as IP");
+    }
+    if dns_lookup_as_info {
+        eprintln!("warning: DNS is disabled (--no-dns), AS information will not be looked up");
+    }
+}
+
+/// Validate `interface`
+pub fn validate_interface(interface: Option<&str>) {
+    if let Some(interface) = interface {
+        let names = interface_names();
+        if !names.iter().any(|name| name == interface) {
+            eprintln!(
+                "interface ({interface}) does not exist, available interfaces are: {}",
+                names.join(", ")
+            );
+            exit(-1);
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use anyhow::anyhow;
+use clap::{ArgMatches, Command};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The name of the positional argument which holds the list of targets.
+const TARGETS_ARG: &str = "targets";
+
+/// The name of the argument which specifies the config file.
+const CONFIG_FILE_ARG: &str = "config-file";
+
+/// The names of the arguments which are not recorded in the merged configuration, the config file itself, the dry run
+/// which prints it and the help and version of the command.
+const UNRECORDED_ARGS: [&str; 4] = [CONFIG_FILE_ARG, "dry-run", "help", "version"];
+
+/// The configuration read from a `toml` or `yaml` config file.
+///
+/// The keys of the config file mirror the long-form names of the command line flags (i.e. `first-ttl`) and the list of
+/// targets may be supplied with the `targets` key.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct TrippyConfig {
+    #[serde(flatten)]
+    entries: BTreeMap<String, ConfigValue>,
+}
+
+/// A single value in the config file.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ConfigValue {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    List(Vec<Self>),
+}
+
+impl TrippyConfig {
+    /// Read and parse the config file at `path`.
+    ///
+    /// Files with a `yaml` or `yml` extension are parsed as `yaml`, all others are parsed as `toml`.
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let data = fs::read_to_string(path)
+            .map_err(|err| anyhow!("failed to read config file {}: {}", path.display(), err))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml" | "yml") => serde_yaml::from_str(&data)
+                .map_err(|err| anyhow!("failed to parse config file {}: {}", path.display(), err)),
+            _ => toml::from_str(&data)
+                .map_err(|err| anyhow!("failed to parse config file {}: {}", path.display(), err)),
+        }
+    }
+
+    /// The configuration of the parsed arguments `matches` of `command` and the `targets`, keyed as for the config file.
+    ///
+    /// Only the arguments which were passed, on the command line or in the config file, are recorded and not the default
+    /// values of the others, such that the configuration may itself be used as a config file.
+    pub fn from_matches(command: &Command<'_>, matches: &ArgMatches, targets: &[String]) -> Self {
+        let mut entries = BTreeMap::new();
+        if !targets.is_empty() {
+            let targets = targets.iter().cloned().map(ConfigValue::String).collect();
+            entries.insert(String::from(TARGETS_ARG), ConfigValue::List(targets));
+        }
+        for arg in command.get_arguments() {
+            let key = match arg.get_long() {
+                Some(key)
+                    if !UNRECORDED_ARGS.contains(&key)
+                        && matches.occurrences_of(arg.get_id()) > 0 =>
+                {
+                    key
+                }
+                _ => continue,
+            };
+            let value = if !arg.is_takes_value_set() {
+                ConfigValue::Bool(true)
+            } else if let Some(values) = matches.values_of(arg.get_id()) {
+                let values: Vec<_> = values.collect();
+                if arg.is_use_value_delimiter_set() {
+                    ConfigValue::from_arg_value(&values.join(","))
+                } else if arg.is_multiple_occurrences_set() {
+                    ConfigValue::List(
+                        values
+                            .into_iter()
+                            .map(ConfigValue::from_arg_value)
+                            .collect(),
+                    )
+                } else {
+                    ConfigValue::from_arg_value(values[0])
+                }
+            } else {
+                continue;
+            };
+            entries.insert(key.to_string(), value);
+        }
+        Self { entries }
+    }
+
+    /// Record `value` as the value of the argument `key`, replacing any prior value.
+    pub fn insert(&mut self, key: &str, value: &str) {
+        self.entries
+            .insert(key.to_string(), ConfigValue::from_arg_value(value));
+    }
+
+    /// Merge the config file with the command line arguments.
+    ///
+    /// Each entry of the config file is converted to the equivalent command line argument and placed ahead of the
+    /// command line arguments such that all values are parsed and validated in exactly the same way.  Entries for
+    /// arguments which were explicitly passed on the command line are skipped so that the command line always wins.
+    ///
+    /// Unknown keys are reported as a warning and are otherwise ignored.
+    pub fn merge(
+        &self,
+        command: &Command<'_>,
+        matches: &ArgMatches,
+        cli_args: &[OsString],
+    ) -> Vec<OsString> {
+        let (args, warnings) = self.merge_args(command, matches, cli_args);
+        for warning in warnings {
+            eprintln!("warning: {warning}");
+        }
+        args
+    }
+
+    /// Merge the config file with the command line arguments, returning the merged arguments and the warnings.
+    ///
+    /// A list value of an argument which may occur more than once but which takes a single value per occurrence is
+    /// passed as one occurrence per element.
+    fn merge_args(
+        &self,
+        command: &Command<'_>,
+        matches: &ArgMatches,
+        cli_args: &[OsString],
+    ) -> (Vec<OsString>, Vec<String>) {
+        let mut warnings = vec![];
+        let mut file_args = vec![];
+        let mut file_targets = vec![];
+        for (key, value) in &self.entries {
+            if key == TARGETS_ARG {
+                if matches.occurrences_of(TARGETS_ARG) == 0 {
+                    value.push_values(&mut file_targets);
+                }
+                continue;
+            }
+            match command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(key.as_str()))
+            {
+                Some(arg) if arg.get_id() == CONFIG_FILE_ARG => {
+                    warnings.push(format!("ignoring config file key `{key}`"));
+                }
+                Some(arg) if matches.occurrences_of(arg.get_id()) == 0 => {
+                    let flag = OsString::from(format!("--{key}"));
+                    match value {
+                        ConfigValue::List(values)
+                            if arg.is_takes_value_set() && !arg.is_multiple_values_set() =>
+                        {
+                            for value in values {
+                                file_args.push(flag.clone());
+                                value.push_values(&mut file_args);
+                            }
+                        }
+                        value if arg.is_takes_value_set() => {
+                            file_args.push(flag);
+                            value.push_values(&mut file_args);
+                        }
+                        ConfigValue::Bool(true) => file_args.push(flag),
+                        _ => {}
+                    }
+                }
+                Some(_) => {}
+                None => warnings.push(format!("unknown config file key `{key}`")),
+            }
+        }
+        let (bin, rest) = cli_args
+            .split_first()
+            .map_or((None, &[][..]), |(bin, rest)| (Some(bin), rest));
+        let args = bin
+            .cloned()
+            .into_iter()
+            .chain(file_args)
+            .chain(rest.iter().cloned())
+            .chain(file_targets)
+            .collect();
+        (args, warnings)
+    }
+}
+
+impl ConfigValue {
+    /// The value of a single command line argument value, as an integer or float if it is one and otherwise as a string.
+    fn from_arg_value(value: &str) -> Self {
+        if let Ok(i) = value.parse() {
+            Self::Integer(i)
+        } else if value
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '.' || c == '-')
+        {
+            value
+                .parse()
+                .map_or_else(|_| Self::String(value.to_string()), Self::Float)
+        } else {
+            Self::String(value.to_string())
+        }
+    }
+
+    /// Append this value to a list of command line arguments.
+    fn push_values(&self, args: &mut Vec<OsString>) {
+        match self {
+            Self::Bool(b) => args.push(OsString::from(b.to_string())),
+            Self::Integer(i) => args.push(OsString::from(i.to_string())),
+            Self::Float(f) => args.push(OsString::from(f.to_string())),
+            Self::String(s) => args.push(OsString::from(s)),
+            Self::List(values) => {
+                for value in values {
+                    value.push_values(args);
+                }
+            }
+        }
+    }
+}
+
+/// The default location of the config file, `$XDG_CONFIG_HOME/trippy/config.toml` or `~/.config/trippy/config.toml`.
+pub fn default_config_path() -> Option<PathBuf> {
+    default_config_dir().map(|config_dir| config_dir.join("config.toml"))
+}
+
+/// The default config directory, `$XDG_CONFIG_HOME/trippy` or `~/.config/trippy`.
+pub fn default_config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|config_dir| config_dir.join("trippy"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Args;
+    use clap::CommandFactory;
+
+    /// Merge the `toml` config file with the command line arguments `cli`, returning the merged arguments and warnings.
+    fn merge(config: &str, cli: &[&str]) -> (Vec<String>, Vec<String>) {
+        let config: TrippyConfig = toml::from_str(config).unwrap();
+        let cli_args: Vec<OsString> = std::iter::once("trip")
+            .chain(cli.iter().copied())
+            .map(OsString::from)
+            .collect();
+        let command = Args::command();
+        let matches = command
+            .clone()
+            .ignore_errors(true)
+            .get_matches_from(&cli_args);
+        let (args, warnings) = config.merge_args(&command, &matches, &cli_args);
+        let args = args
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect();
+        (args, warnings)
+    }
+
+    #[test]
+    fn test_merge_file_only() {
+        let (args, warnings) = merge("first-ttl = 3\nmode = \"json\"", &["example.com"]);
+        assert_eq!(
+            vec!["trip", "--first-ttl", "3", "--mode", "json", "example.com"],
+            args
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_merge_cli_takes_precedence() {
+        let (args, _) = merge(
+            "first-ttl = 3\nmax-ttl = 10",
+            &["--first-ttl", "5", "example.com"],
+        );
+        assert_eq!(
+            vec!["trip", "--max-ttl", "10", "--first-ttl", "5", "example.com"],
+            args
+        );
+        let merged = Args::command().get_matches_from(&args);
+        assert_eq!(Some("5"), merged.value_of("first-ttl"));
+        assert_eq!(Some("10"), merged.value_of("max-ttl"));
+    }
+
+    #[test]
+    fn test_merge_targets() {
+        let (args, _) = merge("targets = [\"a.com\", \"b.com\"]", &[]);
+        assert_eq!(vec!["trip", "a.com", "b.com"], args);
+        let (args, _) = merge("targets = [\"a.com\", \"b.com\"]", &["c.com"]);
+        assert_eq!(vec!["trip", "c.com"], args);
+    }
+
+    #[test]
+    fn test_merge_unknown_key() {
+        let (args, warnings) = merge("no-such-flag = 1\nfirst-ttl = 2", &["example.com"]);
+        assert_eq!(vec!["trip", "--first-ttl", "2", "example.com"], args);
+        assert_eq!(vec!["unknown config file key `no-such-flag`"], warnings);
+    }
+
+    #[test]
+    fn test_merge_config_file_key() {
+        let (args, warnings) = merge("config-file = \"other.toml\"", &["example.com"]);
+        assert_eq!(vec!["trip", "example.com"], args);
+        assert_eq!(vec!["ignoring config file key `config-file`"], warnings);
+    }
+
+    #[test]
+    fn test_merge_value_types() {
+        let (args, warnings) = merge(
+            r#"
+            tui-preserve-screen = true
+            no-dns = false
+            max-inflight = 8
+            ewma-alpha = 0.5
+            dns-timeout = "2s"
+            percentiles = "50,99"
+            pin-hop = ["1=10.0.0.1", "2=10.0.0.2"]
+            diff = ["old.json", "new.json"]
+            "#,
+            &[],
+        );
+        assert_eq!(
+            vec![
+                "trip",
+                "--diff",
+                "old.json",
+                "new.json",
+                "--dns-timeout",
+                "2s",
+                "--ewma-alpha",
+                "0.5",
+                "--max-inflight",
+                "8",
+                "--percentiles",
+                "50,99",
+                "--pin-hop",
+                "1=10.0.0.1",
+                "--pin-hop",
+                "2=10.0.0.2",
+                "--tui-preserve-screen",
+            ],
+            args
+        );
+        assert!(warnings.is_empty());
+        let merged = Args::command().try_get_matches_from(&args).unwrap();
+        assert_eq!(2, merged.occurrences_of("pin-hop"));
+        assert!(merged.is_present("tui-preserve-screen"));
+        assert!(!merged.is_present("no-dns"));
+    }
+
+    #[test]
+    fn test_from_matches_round_trip() {
+        let cli_args = [
+            "trip",
+            "--tui-preserve-screen",
+            "--first-ttl",
+            "2",
+            "--pin-hop",
+            "1=10.0.0.1",
+            "--pin-hop",
+            "2=10.0.0.2",
+            "example.com",
+        ];
+        let command = Args::command();
+        let matches = command.clone().get_matches_from(cli_args);
+        let config = TrippyConfig::from_matches(&command, &matches, &[String::from("example.com")]);
+        let toml = toml::to_string(&config).unwrap();
+        assert_eq!(
+            "first-ttl = 2\npin-hop = \"1=10.0.0.1,2=10.0.0.2\"\ntargets = [\"example.com\"]\ntui-preserve-screen = true\n",
+            toml
+        );
+        let (args, _) = merge(&toml, &[]);
+        let merged = Args::command().get_matches_from(&args);
+        assert_eq!(Some("2"), merged.value_of("first-ttl"));
+        assert_eq!(
+            vec!["1=10.0.0.1", "2=10.0.0.2"],
+            merged.values_of("pin-hop").unwrap().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["example.com"],
+            merged.values_of("targets").unwrap().collect::<Vec<_>>()
+        );
+    }
+}
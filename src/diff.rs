@@ -0,0 +1,325 @@
+use crate::report::{read_reports, Report, ReportHop};
+use comfy_table::presets::UTF8_FULL;
+use comfy_table::{ContentArrangement, Table};
+use itertools::Itertools;
+use serde::Serialize;
+use std::path::Path;
+
+/// The thresholds beyond which a change in the round-trip time or loss of a hop is reported.
+#[derive(Debug, Clone, Copy)]
+pub struct DiffThresholds {
+    /// The minimum change in the average round-trip time (ms).
+    pub rtt_ms: f64,
+    /// The minimum change in the loss (%).
+    pub loss_pct: f64,
+}
+
+/// How a hop changed between the baseline and current report.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HopChange {
+    /// The hop responded in the current report but not in the baseline.
+    Appeared,
+    /// The hop responded in the baseline but not in the current report.
+    Disappeared,
+    /// The hop responded from a different set of addresses.
+    AddressChanged,
+    /// The average round-trip time of the hop changed beyond the threshold.
+    RttChanged,
+    /// The loss of the hop changed beyond the threshold.
+    LossChanged,
+}
+
+impl HopChange {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Appeared => "appeared",
+            Self::Disappeared => "disappeared",
+            Self::AddressChanged => "address changed",
+            Self::RttChanged => "rtt changed",
+            Self::LossChanged => "loss changed",
+        }
+    }
+}
+
+/// A single change of a hop between the baseline and current report.
+#[derive(Debug, Serialize)]
+pub struct HopDiff {
+    target: String,
+    ttl: u8,
+    change: HopChange,
+    baseline: String,
+    current: String,
+}
+
+/// Compare the JSON reports at `baseline` and `current` and print the hops which changed, as a table or as JSON.
+///
+/// Reports are matched by target hostname, a target which is present in only one of the reports is compared against
+/// an empty report such that all of its hops are shown to have appeared or disappeared.  The description of each
+/// report, if any, is shown above the table.
+pub fn run_diff(
+    baseline: &Path,
+    current: &Path,
+    thresholds: DiffThresholds,
+    json: bool,
+) -> anyhow::Result<()> {
+    let baseline = read_reports(baseline)?;
+    let current = read_reports(current)?;
+    let targets = baseline
+        .iter()
+        .chain(&current)
+        .map(|report| report.info.target.hostname.as_str())
+        .unique();
+    let diffs: Vec<_> = targets
+        .flat_map(|target| {
+            diff_hops(
+                target,
+                &target_hops(&baseline, target),
+                &target_hops(&current, target),
+                thresholds,
+            )
+        })
+        .collect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diffs)?);
+        return Ok(());
+    }
+    for (name, reports) in [("Baseline", &baseline), ("Current", &current)] {
+        if let Some(description) = description(reports) {
+            println!("{name}: {description}");
+        }
+    }
+    if diffs.is_empty() {
+        println!("No changes");
+    } else {
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(vec!["Target", "Hop", "Change", "Baseline", "Current"]);
+        for diff in &diffs {
+            table.add_row(vec![
+                diff.target.clone(),
+                diff.ttl.to_string(),
+                diff.change.name().to_string(),
+                diff.baseline.clone(),
+                diff.current.clone(),
+            ]);
+        }
+        println!("{table}");
+    }
+    Ok(())
+}
+
+/// The description of the first report of `reports` to have one, if any.
+fn description(reports: &[Report]) -> Option<&str> {
+    reports
+        .iter()
+        .find_map(|report| report.info.description.as_deref())
+}
+
+/// The hops of the report for `target`, if any.
+fn target_hops<'a>(reports: &'a [Report], target: &str) -> Vec<&'a ReportHop> {
+    reports
+        .iter()
+        .find(|report| report.info.target.hostname == target)
+        .map(|report| report.hops.iter().collect())
+        .unwrap_or_default()
+}
+
+/// The hop at `ttl`, if it responded.
+fn responding_hop<'a>(hops: &[&'a ReportHop], ttl: u8) -> Option<&'a ReportHop> {
+    hops.iter()
+        .find(|hop| hop.ttl == ttl && !hop.hosts.is_empty())
+        .copied()
+}
+
+/// The changes of each hop, by ttl, of a single target.
+///
+/// Hops which did not respond are treated as absent such that a hop which stops responding is shown to have
+/// disappeared, the address, round-trip time and loss are only compared for hops which responded in both reports.
+fn diff_hops(
+    target: &str,
+    baseline: &[&ReportHop],
+    current: &[&ReportHop],
+    thresholds: DiffThresholds,
+) -> Vec<HopDiff> {
+    let ttls = baseline
+        .iter()
+        .chain(current)
+        .map(|hop| hop.ttl)
+        .sorted_unstable()
+        .dedup();
+    let mut diffs = vec![];
+    let mut push = |ttl, change, baseline, current| {
+        diffs.push(HopDiff {
+            target: target.to_string(),
+            ttl,
+            change,
+            baseline,
+            current,
+        });
+    };
+    for ttl in ttls {
+        match (responding_hop(baseline, ttl), responding_hop(current, ttl)) {
+            (None, Some(cur)) => push(ttl, HopChange::Appeared, String::new(), format_addrs(cur)),
+            (Some(base), None) => {
+                push(
+                    ttl,
+                    HopChange::Disappeared,
+                    format_addrs(base),
+                    String::new(),
+                );
+            }
+            (Some(base), Some(cur)) => {
+                let (base_addrs, cur_addrs) = (format_addrs(base), format_addrs(cur));
+                if base_addrs != cur_addrs {
+                    push(ttl, HopChange::AddressChanged, base_addrs, cur_addrs);
+                }
+                if (cur.avg - base.avg).abs() > thresholds.rtt_ms {
+                    push(
+                        ttl,
+                        HopChange::RttChanged,
+                        format!("{:.2}ms", base.avg),
+                        format!("{:.2}ms", cur.avg),
+                    );
+                }
+                if (cur.loss_pct - base.loss_pct).abs() > thresholds.loss_pct {
+                    push(
+                        ttl,
+                        HopChange::LossChanged,
+                        format!("{:.2}%", base.loss_pct),
+                        format!("{:.2}%", cur.loss_pct),
+                    );
+                }
+            }
+            (None, None) => {}
+        }
+    }
+    diffs
+}
+
+/// The sorted addresses of a hop, space delimited.
+fn format_addrs(hop: &ReportHop) -> String {
+    hop.hosts
+        .iter()
+        .map(|host| host.ip.as_str())
+        .sorted()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const THRESHOLDS: DiffThresholds = DiffThresholds {
+        rtt_ms: 5_f64,
+        loss_pct: 10_f64,
+    };
+
+    fn hop(ttl: u8, ips: &[&str], loss_pct: f64, avg: f64) -> ReportHop {
+        let hosts: Vec<_> = ips
+            .iter()
+            .map(|ip| json!({"ip": ip, "hostname": ip}))
+            .collect();
+        serde_json::from_value(json!({
+            "ttl": ttl,
+            "hosts": hosts,
+            "loss_pct": format!("{loss_pct:.2}"),
+            "sent": 10,
+            "last": format!("{avg:.2}"),
+            "recv": 10,
+            "avg": format!("{avg:.2}"),
+            "best": format!("{avg:.2}"),
+            "worst": format!("{avg:.2}"),
+            "stddev": "0.00",
+            "jitter": "0.00",
+        }))
+        .unwrap()
+    }
+
+    fn diff(
+        baseline: &[ReportHop],
+        current: &[ReportHop],
+    ) -> Vec<(u8, &'static str, String, String)> {
+        diff_hops(
+            "example.com",
+            &baseline.iter().collect::<Vec<_>>(),
+            &current.iter().collect::<Vec<_>>(),
+            THRESHOLDS,
+        )
+        .into_iter()
+        .map(|diff| (diff.ttl, diff.change.name(), diff.baseline, diff.current))
+        .collect()
+    }
+
+    #[test]
+    fn test_no_changes() {
+        let hops = [
+            hop(1, &["10.0.0.1"], 0_f64, 1_f64),
+            hop(2, &[], 100_f64, 0_f64),
+        ];
+        let current = [
+            hop(1, &["10.0.0.1"], 5_f64, 4_f64),
+            hop(2, &[], 100_f64, 0_f64),
+        ];
+        assert!(diff(&hops, &current).is_empty());
+    }
+
+    #[test]
+    fn test_appeared_and_disappeared() {
+        let baseline = [
+            hop(1, &["10.0.0.1"], 0_f64, 1_f64),
+            hop(2, &[], 100_f64, 0_f64),
+        ];
+        let current = [
+            hop(1, &[], 100_f64, 0_f64),
+            hop(2, &["10.0.0.2"], 0_f64, 2_f64),
+            hop(3, &["10.0.0.3"], 0_f64, 3_f64),
+        ];
+        assert_eq!(
+            vec![
+                (1, "disappeared", String::from("10.0.0.1"), String::new()),
+                (2, "appeared", String::new(), String::from("10.0.0.2")),
+                (3, "appeared", String::new(), String::from("10.0.0.3")),
+            ],
+            diff(&baseline, &current)
+        );
+    }
+
+    #[test]
+    fn test_changed() {
+        let baseline = [
+            hop(1, &["10.0.0.2", "10.0.0.1"], 0_f64, 1_f64),
+            hop(2, &["10.0.0.3"], 0_f64, 1_f64),
+        ];
+        let current = [
+            hop(1, &["10.0.0.1", "10.0.0.2"], 0_f64, 1_f64),
+            hop(2, &["10.0.0.4"], 20_f64, 12.5),
+        ];
+        assert_eq!(
+            vec![
+                (
+                    2,
+                    "address changed",
+                    String::from("10.0.0.3"),
+                    String::from("10.0.0.4")
+                ),
+                (
+                    2,
+                    "rtt changed",
+                    String::from("1.00ms"),
+                    String::from("12.50ms")
+                ),
+                (
+                    2,
+                    "loss changed",
+                    String::from("0.00%"),
+                    String::from("20.00%")
+                ),
+            ],
+            diff(&baseline, &current)
+        );
+    }
+}
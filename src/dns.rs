@@ -1,8 +1,14 @@
+use crate::asn::AsnNames;
 use crate::config::DnsResolveMethod;
 use crate::dns::inner::DnsResolverInner;
+use crate::labels::HostLabels;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
-use std::net::IpAddr;
-use std::rc::Rc;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 
 /// The state of reverse DNS resolution.
@@ -23,9 +29,9 @@ pub enum DnsEntry {
 /// Information about a resolved `IpAddr`.
 #[derive(Debug, Clone)]
 pub enum Resolved {
-    /// Resolved without AsInfo.
+    /// Resolved without `AsInfo`.
     Normal(IpAddr, Vec<String>),
-    /// Resolved with AsInfo.
+    /// Resolved with `AsInfo`.
     WithAsInfo(IpAddr, Vec<String>, AsInfo),
 }
 
@@ -33,20 +39,20 @@ impl Display for DnsEntry {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         #[allow(clippy::match_same_arms)]
         match self {
-            DnsEntry::Resolved(Resolved::Normal(_, hosts)) => write!(f, "{}", hosts.join(" ")),
-            DnsEntry::Resolved(Resolved::WithAsInfo(_, hosts, asinfo)) => {
+            Self::Resolved(Resolved::Normal(_, hosts)) => write!(f, "{}", hosts.join(" ")),
+            Self::Resolved(Resolved::WithAsInfo(_, hosts, asinfo)) => {
                 write!(f, "AS{} {}", asinfo.asn, hosts.join(" "))
             }
-            DnsEntry::Pending(ip) => write!(f, "{}", ip),
-            DnsEntry::NotFound(ip) => write!(f, "{}", ip),
-            DnsEntry::Failed(ip) => write!(f, "Failed: {}", ip),
-            DnsEntry::Timeout(ip) => write!(f, "Timeout: {}", ip),
+            Self::Pending(ip) => write!(f, "{ip}"),
+            Self::NotFound(ip) => write!(f, "{ip}"),
+            Self::Failed(ip) => write!(f, "Failed: {ip}"),
+            Self::Timeout(ip) => write!(f, "Timeout: {ip}"),
         }
     }
 }
 
 /// Autonomous System (AS) information.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AsInfo {
     pub asn: String,
     pub prefix: String,
@@ -57,31 +63,136 @@ pub struct AsInfo {
 }
 
 /// Configuration for the `DnsResolver`.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct DnsResolverConfig {
     pub resolve_method: DnsResolveMethod,
     pub timeout: Duration,
+    pub cache: Option<DnsCacheConfig>,
+    /// Disable all DNS queries, such that no resolver thread is started and every address is reported as not found.
+    pub disabled: bool,
+    /// The offline table of `AS` organization names, if any, used instead of querying the name of each `AS`.
+    pub asn_names: Option<AsnNames>,
+    /// The table of labels of hosts, if any, shown alongside their addresses.
+    pub labels: Option<HostLabels>,
+    /// The server of the `doh` and `dot` resolve methods, Cloudflare if not set.
+    pub endpoint: Option<DnsEndpoint>,
+    /// Return every hostname of a resolved address, rather than only the first.
+    pub resolve_all: bool,
 }
 
-impl DnsResolverConfig {
-    pub fn new(resolve_method: DnsResolveMethod, timeout: Duration) -> Self {
-        Self {
-            resolve_method,
-            timeout,
+/// Configuration for the persistent reverse DNS cache.
+#[derive(Debug, Clone)]
+pub struct DnsCacheConfig {
+    /// The file the cache is loaded from and saved to.
+    pub path: PathBuf,
+    /// The maximum age of a resolved entry.
+    ///
+    /// Entries for addresses which could not be resolved are kept for the shorter of this and
+    /// `DNS_CACHE_NEGATIVE_TTL`.
+    pub ttl: Duration,
+}
+
+impl DnsCacheConfig {
+    pub fn new(path: PathBuf, ttl: Duration) -> Self {
+        Self { path, ttl }
+    }
+}
+
+/// The server of an encrypted (`DNS-over-HTTPS` or `DNS-over-TLS`) resolver.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DnsEndpoint {
+    /// The Cloudflare `1.1.1.1` service, as `cloudflare-dns.com`.
+    Cloudflare,
+    /// The Google `8.8.8.8` service, as `dns.google`.
+    Google,
+    /// The Quad9 `9.9.9.9` service, as `dns.quad9.net`.
+    Quad9,
+    /// A custom server, named `host` in its certificate, at `addrs` and `port`.
+    Custom {
+        host: String,
+        addrs: Vec<IpAddr>,
+        port: u16,
+    },
+}
+
+impl FromStr for DnsEndpoint {
+    type Err = anyhow::Error;
+
+    /// Parse and resolve a DNS endpoint such as `google`, `https://dns.example.com/dns-query` or
+    /// `tls://dns.example.com:853`.
+    ///
+    /// The port defaults to 443 for `https://` and 853 for `tls://` urls, the path of an `https://` url must be
+    /// `/dns-query`, if given.  The host of a custom url is resolved once, using the system resolver, and must be the
+    /// name of the server in its certificate.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cloudflare" => return Ok(Self::Cloudflare),
+            "google" => return Ok(Self::Google),
+            "quad9" => return Ok(Self::Quad9),
+            _ => {}
+        }
+        let (host_port, default_port) = if let Some(rest) = s.strip_prefix("https://") {
+            let (host_port, path) = rest.split_once('/').unwrap_or((rest, "dns-query"));
+            if !matches!(path, "dns-query" | "dns-query/" | "") {
+                return Err(anyhow!(
+                    "invalid dns endpoint {}: the path must be /dns-query",
+                    s
+                ));
+            }
+            (host_port, 443)
+        } else if let Some(rest) = s.strip_prefix("tls://") {
+            (rest.trim_end_matches('/'), 853)
+        } else {
+            return Err(anyhow!(
+                "invalid dns endpoint {}: must be cloudflare, google, quad9 or start with https:// or tls://",
+                s
+            ));
+        };
+        let (host, port) = match host_port.rsplit_once(':') {
+            Some((host, port)) if !port.ends_with(']') => (
+                host,
+                port.parse()
+                    .map_err(|_| anyhow!("invalid dns endpoint {}: invalid port {}", s, port))?,
+            ),
+            _ => (host_port, default_port),
+        };
+        let host = host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host);
+        if host.is_empty() || host.contains('/') {
+            return Err(anyhow!("invalid dns endpoint {}: missing host", s));
         }
+        let addrs: Vec<_> = (host, port)
+            .to_socket_addrs()
+            .map_err(|err| anyhow!("invalid dns endpoint {}: {}", s, err))?
+            .map(|addr| addr.ip())
+            .collect();
+        if addrs.is_empty() {
+            return Err(anyhow!("invalid dns endpoint {}: no addresses found", s));
+        }
+        Ok(Self::Custom {
+            host: host.to_string(),
+            addrs,
+            port,
+        })
     }
 }
 
 /// A cheaply cloneable, non-blocking, caching, forward and reverse DNS resolver.
-#[derive(Clone)]
+///
+/// Clones share a single cache and may be sent to other threads.  The persistent cache, if any, is saved when the
+/// resolver returned by `start` is dropped, such that clones held by threads which outlive it never prevent the save.
 pub struct DnsResolver {
-    inner: Rc<DnsResolverInner>,
+    inner: Arc<DnsResolverInner>,
+    primary: bool,
 }
 
 impl DnsResolver {
     pub fn start(config: DnsResolverConfig) -> anyhow::Result<Self> {
         Ok(Self {
-            inner: Rc::new(DnsResolverInner::start(config)?),
+            inner: Arc::new(DnsResolverInner::start(config)?),
+            primary: true,
         })
     }
 
@@ -100,15 +211,47 @@ impl DnsResolver {
     /// If the entry exists but is `DnsEntry::Timeout` then it is changed to be `DnsEntry::Pending` and enqueued.
     ///
     /// If enqueuing times out then the entry is changed to be `DnsEntry::Timeout` and returned.
+    ///
+    /// Only the first hostname of a `DnsEntry::Resolved` is returned, unless `DnsResolverConfig::resolve_all` is set.
     pub fn reverse_lookup(&self, addr: IpAddr) -> DnsEntry {
-        self.inner.reverse_lookup(addr, false)
+        self.hostnames(self.inner.reverse_lookup(addr, false))
     }
 
     /// Perform a non-blocking reverse DNS lookup of `IpAddr` and return a `DnsEntry` with `AS` information.
     ///
     /// See [`DnsResolver::reverse_lookup`]
     pub fn reverse_lookup_with_asinfo(&self, addr: IpAddr) -> DnsEntry {
-        self.inner.reverse_lookup(addr, true)
+        self.hostnames(self.inner.reverse_lookup(addr, true))
+    }
+
+    /// Keep only the first hostname of a resolved `DnsEntry`, unless every hostname should be returned.
+    fn hostnames(&self, mut dns_entry: DnsEntry) -> DnsEntry {
+        if !self.config().resolve_all {
+            if let DnsEntry::Resolved(
+                Resolved::Normal(_, hostnames) | Resolved::WithAsInfo(_, hostnames, _),
+            ) = &mut dns_entry
+            {
+                hostnames.truncate(1);
+            }
+        }
+        dns_entry
+    }
+
+    /// The label of `addr` from the table of labels, if any.
+    ///
+    /// The address is matched by address or network and otherwise by its `AS`, if the `AS` information of the address
+    /// has already been resolved.  No lookup is enqueued.
+    pub fn label(&self, addr: IpAddr) -> Option<String> {
+        let labels = self.config().labels.as_ref()?;
+        labels
+            .addr_label(addr)
+            .or_else(|| match self.inner.cached(addr) {
+                Some(DnsEntry::Resolved(Resolved::WithAsInfo(_, _, asinfo))) => {
+                    labels.asn_label(&asinfo.asn)
+                }
+                _ => None,
+            })
+            .map(String::from)
     }
 
     /// Get the `DnsResolverConfig`.
@@ -119,22 +262,52 @@ impl DnsResolver {
     pub fn flush(&self) {
         self.inner.flush();
     }
+
+    /// Record the `hostnames` of `addr` as resolved, such as those of a replayed report.
+    ///
+    /// A seeded entry is returned by a reverse DNS lookup even if DNS is disabled.
+    pub fn seed(&self, addr: IpAddr, hostnames: Vec<String>) {
+        self.inner.seed(addr, hostnames);
+    }
+}
+
+impl Clone for DnsResolver {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            primary: false,
+        }
+    }
+}
+
+impl Drop for DnsResolver {
+    fn drop(&mut self) {
+        if self.primary {
+            self.inner.save();
+        }
+    }
 }
 
 /// Private impl of resolver.
 mod inner {
-    use crate::dns::{AsInfo, DnsEntry, DnsResolveMethod, DnsResolverConfig, Resolved};
+    use crate::asn::AsnNames;
+    use crate::dns::{
+        AsInfo, DnsCacheConfig, DnsEndpoint, DnsEntry, DnsResolveMethod, DnsResolverConfig,
+        Resolved,
+    };
     use anyhow::anyhow;
     use crossbeam::channel::{bounded, Receiver, Sender};
     use itertools::Itertools;
     use parking_lot::RwLock;
+    use serde::{Deserialize, Serialize};
     use std::collections::HashMap;
-    use std::net::{IpAddr, Ipv4Addr};
+    use std::fs;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
     use std::str::FromStr;
     use std::sync::Arc;
     use std::thread;
-    use std::time::Duration;
-    use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use trust_dns_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
     use trust_dns_resolver::error::ResolveErrorKind;
     use trust_dns_resolver::proto::rr::RecordType;
     use trust_dns_resolver::{Name, Resolver};
@@ -145,13 +318,31 @@ mod inner {
     /// The duration wait to enqueue a `DnsEntry::Pending` to the resolver before returning `DnsEntry::Timeout`.
     const RESOLVER_QUEUE_TIMEOUT: Duration = Duration::from_millis(10);
 
+    /// The maximum age of a persisted entry for an address which could not be resolved.
+    const DNS_CACHE_NEGATIVE_TTL: Duration = Duration::from_mins(5);
+
+    /// The addresses of the Google `8.8.8.8` service.
+    const GOOGLE_IPS: [IpAddr; 4] = [
+        IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)),
+        IpAddr::V4(Ipv4Addr::new(8, 8, 4, 4)),
+        IpAddr::V6(Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888)),
+        IpAddr::V6(Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8844)),
+    ];
+
+    /// The name of the Google `8.8.8.8` service in its certificate.
+    const GOOGLE_TLS_DNS_NAME: &str = "dns.google";
+
     /// Alias for a cache of reverse DNS lookup entries.
     type Cache = Arc<RwLock<HashMap<IpAddr, DnsEntry>>>;
 
+    /// Alias for the time at which each reverse DNS lookup entry was resolved.
+    type ResolvedAt = Arc<RwLock<HashMap<IpAddr, SystemTime>>>;
+
     #[derive(Clone)]
     enum DnsProvider {
         TrustDns(Arc<Resolver>),
         DnsLookup,
+        Disabled,
     }
 
     #[derive(Debug, Clone)]
@@ -166,14 +357,52 @@ mod inner {
         provider: DnsProvider,
         tx: Sender<DnsResolveRequest>,
         addr_cache: Cache,
+        resolved_at: ResolvedAt,
+    }
+
+    /// The resolver configuration of a `DNS-over-HTTPS` (if `https`) or `DNS-over-TLS` resolver to `endpoint`.
+    fn encrypted_config(endpoint: Option<&DnsEndpoint>, https: bool) -> ResolverConfig {
+        let name_servers = match endpoint.unwrap_or(&DnsEndpoint::Cloudflare) {
+            DnsEndpoint::Cloudflare if https => return ResolverConfig::cloudflare_https(),
+            DnsEndpoint::Cloudflare => return ResolverConfig::cloudflare_tls(),
+            DnsEndpoint::Quad9 if https => return ResolverConfig::quad9_https(),
+            DnsEndpoint::Quad9 => return ResolverConfig::quad9_tls(),
+            DnsEndpoint::Google if https => NameServerConfigGroup::from_ips_https(
+                &GOOGLE_IPS,
+                443,
+                GOOGLE_TLS_DNS_NAME.to_string(),
+                true,
+            ),
+            DnsEndpoint::Google => NameServerConfigGroup::from_ips_tls(
+                &GOOGLE_IPS,
+                853,
+                GOOGLE_TLS_DNS_NAME.to_string(),
+                true,
+            ),
+            DnsEndpoint::Custom { host, addrs, port } if https => {
+                NameServerConfigGroup::from_ips_https(addrs, *port, host.clone(), true)
+            }
+            DnsEndpoint::Custom { host, addrs, port } => {
+                NameServerConfigGroup::from_ips_tls(addrs, *port, host.clone(), true)
+            }
+        };
+        ResolverConfig::from_parts(None, vec![], name_servers)
     }
 
     impl DnsResolverInner {
         pub fn start(config: DnsResolverConfig) -> anyhow::Result<Self> {
             let (tx, rx) = bounded(RESOLVER_MAX_QUEUE_SIZE);
-            let addr_cache = Arc::new(RwLock::new(HashMap::new()));
+            // the persistent cache is neither loaded nor saved if DNS is disabled
+            let (entries, times) = match &config.cache {
+                Some(cache) if !config.disabled => load_cache(cache),
+                _ => (HashMap::new(), HashMap::new()),
+            };
+            let addr_cache = Arc::new(RwLock::new(entries));
+            let resolved_at = Arc::new(RwLock::new(times));
 
-            let provider = if let DnsResolveMethod::System = config.resolve_method {
+            let provider = if config.disabled {
+                DnsProvider::Disabled
+            } else if matches!(config.resolve_method, DnsResolveMethod::System) {
                 DnsProvider::DnsLookup
             } else {
                 let mut options = ResolverOpts::default();
@@ -184,23 +413,40 @@ mod inner {
                     DnsResolveMethod::Cloudflare => {
                         Resolver::new(ResolverConfig::cloudflare(), options)
                     }
+                    DnsResolveMethod::Doh => {
+                        Resolver::new(encrypted_config(config.endpoint.as_ref(), true), options)
+                    }
+                    DnsResolveMethod::Dot => {
+                        Resolver::new(encrypted_config(config.endpoint.as_ref(), false), options)
+                    }
                     DnsResolveMethod::System => unreachable!(),
                 }?;
                 let resolver = Arc::new(res);
                 DnsProvider::TrustDns(resolver)
             };
 
-            // spawn a thread to process the resolve queue
-            {
+            // spawn a thread to process the resolve queue, unless DNS is disabled
+            if !config.disabled {
                 let cache = addr_cache.clone();
+                let resolved_at = resolved_at.clone();
                 let provider = provider.clone();
-                thread::spawn(move || resolver_queue_processor(rx, &provider, &cache));
+                let asn_names = config.asn_names.clone();
+                thread::spawn(move || {
+                    resolver_queue_processor(
+                        rx,
+                        &provider,
+                        asn_names.as_ref(),
+                        &cache,
+                        &resolved_at,
+                    );
+                });
             }
             Ok(Self {
                 config,
                 provider,
                 tx,
                 addr_cache,
+                resolved_at,
             })
         }
 
@@ -214,10 +460,20 @@ mod inner {
                     Ok(resolver.lookup_ip(hostname)?.iter().collect::<Vec<_>>())
                 }
                 DnsProvider::DnsLookup => Ok(dns_lookup::lookup_host(hostname)?),
+                DnsProvider::Disabled => Ok(vec![IpAddr::from_str(hostname)
+                    .map_err(|_| anyhow!("cannot resolve {} as DNS is disabled", hostname))?]),
             }
         }
 
         pub fn reverse_lookup(&self, addr: IpAddr, with_asinfo: bool) -> DnsEntry {
+            if self.config.disabled {
+                return self
+                    .addr_cache
+                    .read()
+                    .get(&addr)
+                    .cloned()
+                    .unwrap_or(DnsEntry::NotFound(addr));
+            }
             let mut enqueue = false;
 
             // Check if we have already attempted to resolve this `IpAddr` and return the current `DnsEntry` if so,
@@ -269,9 +525,120 @@ mod inner {
             }
         }
 
+        /// The current `DnsEntry` of `addr`, if it has been looked up, without enqueuing it.
+        pub fn cached(&self, addr: IpAddr) -> Option<DnsEntry> {
+            self.addr_cache.read().get(&addr).cloned()
+        }
+
         pub fn flush(&self) {
             self.addr_cache.write().clear();
         }
+
+        pub fn seed(&self, addr: IpAddr, hostnames: Vec<String>) {
+            self.addr_cache
+                .write()
+                .insert(addr, DnsEntry::Resolved(Resolved::Normal(addr, hostnames)));
+        }
+
+        /// Save the persistent cache, if any.
+        pub fn save(&self) {
+            if let Some(cache) = self.config.cache.as_ref().filter(|_| !self.config.disabled) {
+                if let Err(err) = save_cache(cache, &self.addr_cache, &self.resolved_at) {
+                    eprintln!("failed to save DNS cache {}: {}", cache.path.display(), err);
+                }
+            }
+        }
+    }
+
+    /// A reverse DNS lookup entry as persisted in the cache file.
+    ///
+    /// The `hostnames` are `None` if the address could not be resolved.
+    #[derive(Serialize, Deserialize)]
+    struct CacheFileEntry {
+        addr: IpAddr,
+        resolved_at: u64,
+        hostnames: Option<Vec<String>>,
+        as_info: Option<AsInfo>,
+    }
+
+    /// Load all unexpired entries from the persistent cache.
+    ///
+    /// A missing or unreadable cache file is treated as empty such that a corrupt cache never prevents tracing.
+    fn load_cache(
+        cache: &DnsCacheConfig,
+    ) -> (HashMap<IpAddr, DnsEntry>, HashMap<IpAddr, SystemTime>) {
+        let now = SystemTime::now();
+        let entries: Vec<CacheFileEntry> = fs::read(&cache.path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        let mut addr_cache = HashMap::new();
+        let mut resolved_at = HashMap::new();
+        for entry in entries {
+            let time = UNIX_EPOCH + Duration::from_secs(entry.resolved_at);
+            let ttl = match entry.hostnames {
+                Some(_) => cache.ttl,
+                None => cache.ttl.min(DNS_CACHE_NEGATIVE_TTL),
+            };
+            if now.duration_since(time).map_or(true, |age| age > ttl) {
+                continue;
+            }
+            let addr = entry.addr;
+            let dns_entry = match (entry.hostnames, entry.as_info) {
+                (Some(hostnames), Some(as_info)) => {
+                    DnsEntry::Resolved(Resolved::WithAsInfo(addr, hostnames, as_info))
+                }
+                (Some(hostnames), None) => DnsEntry::Resolved(Resolved::Normal(addr, hostnames)),
+                (None, _) => DnsEntry::NotFound(addr),
+            };
+            addr_cache.insert(addr, dns_entry);
+            resolved_at.insert(addr, time);
+        }
+        (addr_cache, resolved_at)
+    }
+
+    /// Save all resolved, and not found, entries to the persistent cache.
+    ///
+    /// The cache is written to a temporary file which is then renamed such that a concurrent reader never sees a
+    /// partially written cache.
+    fn save_cache(
+        cache: &DnsCacheConfig,
+        addr_cache: &Cache,
+        resolved_at: &ResolvedAt,
+    ) -> anyhow::Result<()> {
+        let resolved_at = resolved_at.read();
+        let entries: Vec<_> = addr_cache
+            .read()
+            .values()
+            .filter_map(|entry| {
+                let (addr, hostnames, as_info) = match entry {
+                    DnsEntry::Resolved(Resolved::Normal(addr, hostnames)) => {
+                        (*addr, Some(hostnames.clone()), None)
+                    }
+                    DnsEntry::Resolved(Resolved::WithAsInfo(addr, hostnames, as_info)) => {
+                        (*addr, Some(hostnames.clone()), Some(as_info.clone()))
+                    }
+                    DnsEntry::NotFound(addr) => (*addr, None, None),
+                    DnsEntry::Pending(_) | DnsEntry::Failed(_) | DnsEntry::Timeout(_) => {
+                        return None
+                    }
+                };
+                let time = resolved_at.get(&addr)?;
+                Some(CacheFileEntry {
+                    addr,
+                    resolved_at: time.duration_since(UNIX_EPOCH).ok()?.as_secs(),
+                    hostnames,
+                    as_info,
+                })
+            })
+            .collect();
+        if let Some(dir) = cache.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp_path = cache.path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec(&entries)?)?;
+        fs::rename(&tmp_path, &cache.path)?;
+        Ok(())
     }
 
     /// Process each `IpAddr` from the resolver queue and perform the reverse DNS lookup.
@@ -281,10 +648,13 @@ mod inner {
     fn resolver_queue_processor(
         rx: Receiver<DnsResolveRequest>,
         provider: &DnsProvider,
+        asn_names: Option<&AsnNames>,
         cache: &Cache,
+        resolved_at: &ResolvedAt,
     ) {
         for DnsResolveRequest { addr, with_asinfo } in rx {
             let entry = match &provider {
+                DnsProvider::Disabled => DnsEntry::NotFound(addr),
                 DnsProvider::DnsLookup => {
                     // we can't distinguish between a failed lookup or a genuine error and so we just assume all
                     // failures are `DnsEntry::NotFound`.
@@ -297,7 +667,8 @@ mod inner {
                     Ok(name) => {
                         let hostnames = name.iter().map(Name::to_string).collect();
                         if with_asinfo {
-                            let as_info = lookup_asinfo(resolver, addr).unwrap_or_default();
+                            let as_info =
+                                lookup_asinfo(resolver, addr, asn_names).unwrap_or_default();
                             DnsEntry::Resolved(Resolved::WithAsInfo(addr, hostnames, as_info))
                         } else {
                             DnsEntry::Resolved(Resolved::Normal(addr, hostnames))
@@ -311,23 +682,37 @@ mod inner {
                 },
             };
             cache.write().insert(addr, entry);
+            resolved_at.write().insert(addr, SystemTime::now());
         }
     }
 
     /// Lookup up `AsInfo` for an `IpAddr` address.
-    fn lookup_asinfo(resolver: &Arc<Resolver>, addr: IpAddr) -> anyhow::Result<AsInfo> {
+    fn lookup_asinfo(
+        resolver: &Arc<Resolver>,
+        addr: IpAddr,
+        asn_names: Option<&AsnNames>,
+    ) -> anyhow::Result<AsInfo> {
         match addr {
-            IpAddr::V4(addr) => lookup_asinfo_ipv4(resolver, addr),
+            IpAddr::V4(addr) => lookup_asinfo_ipv4(resolver, addr, asn_names),
             IpAddr::V6(_) => Ok(AsInfo::default()),
         }
     }
 
     /// Lookup up `AsInfo` for an `Ipv4Addr` address.
-    fn lookup_asinfo_ipv4(resolver: &Arc<Resolver>, addr: Ipv4Addr) -> anyhow::Result<AsInfo> {
+    ///
+    /// If an offline table of `AS` names is configured then the name is taken from the table, and is empty if the `AS`
+    /// is absent, rather than queried.
+    fn lookup_asinfo_ipv4(
+        resolver: &Arc<Resolver>,
+        addr: Ipv4Addr,
+        asn_names: Option<&AsnNames>,
+    ) -> anyhow::Result<AsInfo> {
         let origin_query_txt = query_asn_ipv4(resolver, addr)?;
         let asinfo = parse_origin_query_txt(&origin_query_txt)?;
-        let asn_query_txt = query_asn_name_ipv4(resolver, &asinfo.asn)?;
-        let as_name = parse_asn_query_txt(&asn_query_txt)?;
+        let as_name = match asn_names {
+            Some(asn_names) => asn_names.name(&asinfo.asn).unwrap_or_default().to_string(),
+            None => parse_asn_query_txt(&query_asn_name_ipv4(resolver, &asinfo.asn)?)?,
+        };
         Ok(AsInfo {
             asn: asinfo.asn,
             prefix: asinfo.prefix,
@@ -356,7 +741,7 @@ mod inner {
 
     /// Perform the `asn` query.
     fn query_asn_name_ipv4(resolver: &Arc<Resolver>, asn: &str) -> anyhow::Result<String> {
-        let query = format!("AS{}.asn.cymru.com.", asn);
+        let query = format!("AS{asn}.asn.cymru.com.");
         let name = Name::from_str(query.as_str())?;
         let response = resolver.lookup(name, RecordType::TXT)?;
         let data = response.iter().next().ok_or_else(|| anyhow!("asn query"))?;
@@ -0,0 +1,183 @@
+use crate::dns::{DnsEntry, DnsResolver};
+use crate::frontend::TuiTraceInfo;
+use crate::report::ROUND_POLL_INTERVAL;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::thread::sleep;
+
+/// The maximum width of the edge between two hosts, that of the most frequently observed edge.
+const MAX_PENWIDTH: f64 = 5_f64;
+
+/// Export the topology of all traces as a Graphviz DOT graph to `path` every time any trace completes a round.
+///
+/// The hosts are labelled by the `resolver`, such that their hostnames are filled in by later exports as they are
+/// resolved.
+pub fn run_dot_export(
+    path: PathBuf,
+    traces: Vec<TuiTraceInfo>,
+    resolver: DnsResolver,
+    lookup_as_info: bool,
+) -> anyhow::Result<()> {
+    thread::Builder::new()
+        .name(String::from("dot"))
+        .spawn(move || {
+            let mut rounds: Vec<Option<usize>> = vec![None; traces.len()];
+            loop {
+                let latest: Vec<_> = traces
+                    .iter()
+                    .map(|info| Some(info.data.read().round()))
+                    .collect();
+                if latest != rounds {
+                    if let Err(err) = write_dot(&path, &traces, &resolver, lookup_as_info) {
+                        eprintln!("dot export failed: {err}");
+                    }
+                    rounds = latest;
+                }
+                sleep(ROUND_POLL_INTERVAL);
+            }
+        })?;
+    Ok(())
+}
+
+/// Write the topology of all traces to `path` as a Graphviz DOT graph.
+///
+/// Each host observed by any trace is a node, labelled with its hostname, and `AS` if `lookup_as_info` is set, and each
+/// target host is drawn with a double border.  An edge joins two hosts which responded at consecutive responding ttls
+/// of the same round, labelled and weighted by the number of rounds in which it was observed over all traces, so the
+/// branches of load-balanced paths are drawn side by side.  A routing loop is drawn as a cycle.
+///
+/// The graph is first written to a temporary file which then replaces `path` so that readers never observe a partially
+/// written file.
+pub fn write_dot(
+    path: &Path,
+    traces: &[TuiTraceInfo],
+    resolver: &DnsResolver,
+    lookup_as_info: bool,
+) -> anyhow::Result<()> {
+    let mut edges: BTreeMap<(IpAddr, IpAddr), usize> = BTreeMap::new();
+    let mut targets = BTreeSet::new();
+    for info in traces {
+        let trace = info.data.read();
+        for (from, to, count) in trace.edges() {
+            *edges.entry((from, to)).or_default() += count;
+        }
+        targets.insert(trace.target_addr().unwrap_or(info.target_addr));
+    }
+    let hosts: BTreeSet<_> = edges
+        .keys()
+        .flat_map(|&edge| <[IpAddr; 2]>::from(edge))
+        .collect();
+    let max_count = edges.values().copied().max().unwrap_or(1);
+    let mut dot = String::from("digraph trippy {\n    rankdir=LR;\n    node [shape=box];\n");
+    for &host in hosts.union(&targets) {
+        let label = escape(&host_label(resolver, host, lookup_as_info));
+        let border = if targets.contains(&host) {
+            ", peripheries=2"
+        } else {
+            ""
+        };
+        writeln!(dot, "    \"{host}\" [label=\"{label}\"{border}];")?;
+    }
+    for (&(from, to), &count) in &edges {
+        let penwidth = 1_f64 + (MAX_PENWIDTH - 1_f64) * count as f64 / max_count as f64;
+        writeln!(
+            dot,
+            "    \"{from}\" -> \"{to}\" [label=\"{count}\", weight={count}, penwidth={penwidth:.1}];"
+        )?;
+    }
+    dot.push_str("}\n");
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, dot)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// The label of the node of `addr`, its label from the table of labels and hostname, if any, followed by the address.
+fn host_label(resolver: &DnsResolver, addr: IpAddr, lookup_as_info: bool) -> String {
+    let entry = if lookup_as_info {
+        resolver.reverse_lookup_with_asinfo(addr)
+    } else {
+        resolver.reverse_lookup(addr)
+    };
+    let mut lines: Vec<_> = resolver.label(addr).into_iter().collect();
+    let addr = addr.to_string();
+    if matches!(entry, DnsEntry::Resolved(_)) && entry.to_string() != addr {
+        lines.push(entry.to_string());
+    }
+    lines.push(addr);
+    lines.join("\n")
+}
+
+/// Escape `label` for a quoted DOT string, in which a newline is a line break.
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{resolver, temp_path, trace, Response, TARGET};
+    use std::net::Ipv4Addr;
+
+    /// A trace of a round per route, in which each host of the route responded at consecutive ttls.
+    fn trace_info(routes: &[&[u8]]) -> TuiTraceInfo {
+        let rounds: Vec<Vec<Response>> = routes
+            .iter()
+            .map(|route| route.iter().map(|&host| Some((host, 1))).collect())
+            .collect();
+        let rounds: Vec<_> = rounds.iter().map(Vec::as_slice).collect();
+        crate::test_util::trace_info(&TARGET.to_string(), trace(&rounds))
+    }
+
+    #[test]
+    fn test_write_dot() {
+        let path = temp_path("test.dot");
+        let resolver = resolver();
+        resolver.seed(
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            vec![String::from("gw \"home\"")],
+        );
+        let traces = [trace_info(&[&[1, 2, 10], &[1, 3, 10], &[1, 2, 10]])];
+        write_dot(&path, &traces, &resolver, false).unwrap();
+        assert_eq!(
+            "digraph trippy {\n    rankdir=LR;\n    node [shape=box];\n\
+             \x20   \"10.0.0.1\" [label=\"gw \\\"home\\\"\\n10.0.0.1\"];\n\
+             \x20   \"10.0.0.2\" [label=\"10.0.0.2\"];\n\
+             \x20   \"10.0.0.3\" [label=\"10.0.0.3\"];\n\
+             \x20   \"10.0.0.10\" [label=\"10.0.0.10\", peripheries=2];\n\
+             \x20   \"10.0.0.1\" -> \"10.0.0.2\" [label=\"2\", weight=2, penwidth=5.0];\n\
+             \x20   \"10.0.0.1\" -> \"10.0.0.3\" [label=\"1\", weight=1, penwidth=3.0];\n\
+             \x20   \"10.0.0.2\" -> \"10.0.0.10\" [label=\"2\", weight=2, penwidth=5.0];\n\
+             \x20   \"10.0.0.3\" -> \"10.0.0.10\" [label=\"1\", weight=1, penwidth=3.0];\n\
+             }\n",
+            fs::read_to_string(&path).unwrap()
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_write_dot_unresponsive_target() {
+        let path = temp_path("empty.dot");
+        write_dot(&path, &[trace_info(&[])], &resolver(), false).unwrap();
+        assert_eq!(
+            "digraph trippy {\n    rankdir=LR;\n    node [shape=box];\n\
+             \x20   \"10.0.0.10\" [label=\"10.0.0.10\", peripheries=2];\n\
+             }\n",
+            fs::read_to_string(&path).unwrap()
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!("a\\\\b\\\"c\\nd", escape("a\\b\"c\nd"));
+    }
+}
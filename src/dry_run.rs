@@ -0,0 +1,82 @@
+use crate::config::{Args, TrippyConfig};
+use crate::frontend::TuiTraceInfo;
+use comfy_table::presets::UTF8_FULL;
+use comfy_table::{ContentArrangement, Table};
+use serde::Serialize;
+use std::net::IpAddr;
+use trippy::tracing::discover_src_addr;
+
+/// The resolved target, protocol and source of a single trace, as printed by `--dry-run`.
+#[derive(Debug, Serialize)]
+struct DryRunTrace {
+    target: String,
+    addr: IpAddr,
+    family: &'static str,
+    protocol: String,
+    source_addr: IpAddr,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interface: Option<String>,
+}
+
+/// The traces and the merged configuration, as printed by `--dry-run` as JSON.
+#[derive(Debug, Serialize)]
+struct DryRun<'a> {
+    traces: Vec<DryRunTrace>,
+    config: &'a TrippyConfig,
+}
+
+/// Print the resolved target and source of each trace, and the merged configuration, without sending any probes.
+///
+/// The configuration is printed with the keys of the config file, as `toml` or, as JSON, alongside the traces, such that
+/// it may be saved as a config file.
+pub fn run_dry_run(traces: &[TuiTraceInfo], args: &Args, json: bool) -> anyhow::Result<()> {
+    let results = traces
+        .iter()
+        .map(|info| {
+            Ok(DryRunTrace {
+                target: info.target_hostname.clone(),
+                addr: info.target_addr,
+                family: if info.target_addr.is_ipv4() {
+                    "IPv4"
+                } else {
+                    "IPv6"
+                },
+                protocol: info.protocol.clone(),
+                source_addr: discover_src_addr(
+                    info.target_addr,
+                    args.interface.as_deref(),
+                    args.source_address,
+                )?,
+                interface: args.interface.clone(),
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if json {
+        let dry_run = DryRun {
+            traces: results,
+            config: &args.merged_config,
+        };
+        println!("{}", serde_json::to_string_pretty(&dry_run)?);
+    } else {
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(vec!["Target", "Family", "Protocol", "Source", "Interface"]);
+        for result in &results {
+            table.add_row(vec![
+                format!("{} ({})", result.target, result.addr),
+                result.family.to_string(),
+                result.protocol.clone(),
+                result.source_addr.to_string(),
+                result
+                    .interface
+                    .clone()
+                    .unwrap_or_else(|| String::from("OS chooses")),
+            ]);
+        }
+        println!("{table}");
+        print!("{}", toml::to_string(&args.merged_config)?);
+    }
+    Ok(())
+}
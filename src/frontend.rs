@@ -1,6 +1,11 @@
-use crate::backend::Hop;
-use crate::config::{AddressMode, DnsResolveMethod};
+use crate::asn::format_asn;
+use crate::backend::{FlowPath, Hop, HopRow, TraceConfig};
+use crate::baseline::{Baseline, Deviation};
+use crate::config::{AddressMode, AddressOrder, DnsResolveMethod, RttFormat, RttUnit, UnknownHops};
 use crate::dns::{DnsEntry, Resolved};
+use crate::geoip::GeoIpLookup;
+use crate::signal::shutdown_requested;
+use crate::theme::Theme;
 use crate::{DnsResolver, Trace};
 use chrono::SecondsFormat;
 use crossterm::event::KeyModifiers;
@@ -11,27 +16,33 @@ use crossterm::{
 };
 use itertools::Itertools;
 use parking_lot::RwLock;
-use std::collections::BTreeMap;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
 use std::io;
 use std::net::IpAddr;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
+use trippy::tracing::{PauseHandle, TcpOutcome, UnreachableReason};
 use tui::layout::{Alignment, Direction, Rect};
 use tui::text::{Span, Spans};
 use tui::widgets::{BarChart, BorderType, Clear, Paragraph, Sparkline, TableState, Tabs};
 use tui::{
-    backend::{Backend, CrosstermBackend},
+    backend::{Backend, CrosstermBackend, TestBackend},
     layout::{Constraint, Layout},
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     widgets::{Block, Borders, Cell, Row, Table},
     Frame, Terminal,
 };
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-const TABLE_HEADER: [&str; 11] = [
-    "#", "Host", "Loss%", "Snt", "Recv", "Last", "Avg", "Best", "Wrst", "StDev", "Sts",
+const TABLE_HEADER: [&str; 12] = [
+    "#", "Host", "Loss%", "Snt", "Recv", "Last", "Avg", "Best", "Wrst", "StDev", "Jttr", "Sts",
 ];
 
-const TABLE_WIDTH: [Constraint; 11] = [
+const TABLE_WIDTH: [Constraint; 12] = [
     Constraint::Percentage(3),
     Constraint::Percentage(42),
     Constraint::Percentage(5),
@@ -43,8 +54,27 @@ const TABLE_WIDTH: [Constraint; 11] = [
     Constraint::Percentage(5),
     Constraint::Percentage(5),
     Constraint::Percentage(5),
+    Constraint::Percentage(5),
 ];
 
+/// The index of the `Ewma` column, after the `Avg` column, when shown.
+const EWMA_COLUMN: usize = 7;
+
+/// The width (%) of the `Ewma` column, taken from the `Host` column, when shown.
+const EWMA_WIDTH: u16 = 5;
+
+/// The width (%) of each percentile column, taken from the `Host` column, when shown.
+const PERCENTILE_WIDTH: u16 = 5;
+
+/// The maximum width (%) of the `History` column, taken from the `Host` column, when shown.
+const MAX_HISTORY_WIDTH: u16 = 20;
+
+/// The symbols of the `History` column for the round-trip time of a probe, from lowest to highest.
+const HISTORY_SYMBOLS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The symbol of the `History` column for a probe which was lost.
+const HISTORY_LOST_SYMBOL: char = '×';
+
 const LAYOUT_WITHOUT_TABS: [Constraint; 3] = [
     Constraint::Length(5),
     Constraint::Min(10),
@@ -58,19 +88,32 @@ const LAYOUT_WITH_TABS: [Constraint; 4] = [
     Constraint::Length(6),
 ];
 
-const HELP_LINES: [&str; 14] = [
+/// The average round-trip time (ms) above which a hop with no loss is considered to have a problem.
+const PROBLEM_AVG_MS: f64 = 100_f64;
+
+const HELP_LINES: [&str; 24] = [
     "[up] & [down]    - select hop",
     "[left] & [right] - select trace",
+    "[tab]            - cycle the selected trace",
     "[esc]            - clear selection",
     "f                - toggle freeze display",
+    "[space]          - pause & resume tracing",
+    "s                - write snapshot (if configured)",
     "Ctrl+r           - reset statistics",
     "Ctrl+k           - flush DNS cache",
     "i                - show IP only",
     "n                - show hostname only",
     "b                - show both IP and hostname",
+    "a                - cycle IP, hostname and both",
+    "o                - cycle the sort column",
+    "r                - reverse the sort order",
+    "p                - toggle showing problem hops only",
+    "x                - collapse & expand unresponsive hops",
+    "[pgup] & [pgdn]  - scroll the hop window (if configured)",
     "[ & ]            - expand & collapse hosts",
     "{ & }            - expand & collapse hosts to max and min",
     "z                - toggle AS information (if available)",
+    "v                - toggle the protocol comparison (if configured)",
     "h                - toggle help",
     "q                - quit",
 ];
@@ -84,72 +127,137 @@ pub struct TuiTraceInfo {
     pub protocol: String,
     pub first_ttl: u8,
     pub max_ttl: u8,
+    pub ttl_stride: u8,
     pub grace_duration: Duration,
     pub min_round_duration: Duration,
+    pub tos: Option<u8>,
+    pub dont_fragment: Option<bool>,
+    pub description: Option<String>,
 }
 
 impl TuiTraceInfo {
-    #[allow(clippy::too_many_arguments)]
-    pub fn new(
-        data: Arc<RwLock<Trace>>,
-        target_hostname: String,
-        target_addr: IpAddr,
-        protocol: String,
-        first_ttl: u8,
-        max_ttl: u8,
-        grace_duration: Duration,
-        min_round_duration: Duration,
-    ) -> Self {
-        Self {
-            data,
-            target_hostname,
-            target_addr,
-            protocol,
-            first_ttl,
-            max_ttl,
-            grace_duration,
-            min_round_duration,
-        }
+    /// The target host of the most recent round, which differs from the initial target if it has been re-resolved.
+    pub fn current_target_addr(&self) -> IpAddr {
+        self.data.read().target_addr().unwrap_or(self.target_addr)
     }
 }
 
 /// Tui configuration.
 #[derive(Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct TuiConfig {
     /// Refresh rate.
-    refresh_rate: Duration,
+    pub refresh_rate: Duration,
     /// Preserve screen on exit.
-    preserve_screen: bool,
+    pub preserve_screen: bool,
     /// How to render addresses.
-    address_mode: AddressMode,
+    pub address_mode: AddressMode,
     /// Lookup `AS` information.
-    lookup_as_info: bool,
+    pub lookup_as_info: bool,
     /// The maximum number of addresses to show per hop.
-    max_addrs: Option<u8>,
+    pub max_addrs: Option<u8>,
+    /// The maximum width of each hostname, if truncated.
+    pub max_hostname_width: Option<usize>,
     /// The maximum number of samples to record per hop.
-    max_samples: usize,
-}
-
-impl TuiConfig {
-    pub fn new(
-        refresh_rate: Duration,
-        preserve_screen: bool,
-        address_mode: AddressMode,
-        lookup_as_info: bool,
-        max_addrs: Option<u8>,
-        max_samples: usize,
-    ) -> Self {
-        Self {
-            refresh_rate,
-            preserve_screen,
-            address_mode,
-            lookup_as_info,
-            max_addrs,
-            max_samples,
+    pub max_samples: usize,
+    /// The number of rounds after which to exit, if any.
+    pub max_rounds: Option<usize>,
+    /// The ttl window of hops to show, if any.
+    pub hop_window: Option<RangeInclusive<u8>>,
+    /// How to order the addresses of each hop, if in a stable order.
+    pub address_order: Option<AddressOrder>,
+    /// Show the exponentially weighted moving average round-trip time column.
+    pub show_ewma: bool,
+    /// Show the apparent clock offset of each hop which replied to an `ICMP` timestamp request.
+    pub show_clock_skew: bool,
+    /// The round-trip time percentiles to show a column for, if any.
+    pub percentiles: Vec<f64>,
+    /// How to show round-trip times.
+    pub rtt_format: RttFormat,
+    /// The number of probes to show in the history column, if shown.
+    pub history_width: Option<usize>,
+    /// The number of rounds without a response after which a hop which previously responded is considered stale.
+    pub stale_rounds: usize,
+    /// How hops which have never responded are shown.
+    pub unknown_hops: UnknownHops,
+    /// Compare the hops of the traces of each protocol side by side.
+    pub compare_protocols: bool,
+    /// The colors of the Tui.
+    pub theme: Theme,
+    /// The `GeoIP` database to lookup locations, if any.
+    pub geoip: Option<GeoIpLookup>,
+    /// The handle used to pause and resume all tracers.
+    pub pause: PauseHandle,
+    /// The file to write a text snapshot of the table of hops to, if any.
+    pub snapshot: Option<PathBuf>,
+    /// The report being replayed, read-only, if any.
+    pub replay: Option<String>,
+    /// The route to highlight the deviations of each hop from, if any.
+    pub baseline: Option<Baseline>,
+}
+
+/// The column by which the table of hops is sorted.
+///
+/// Hops are sorted by ascending `Ttl`, and by descending value for all other columns, unless the order is reversed.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum SortColumn {
+    Ttl,
+    LossPct,
+    Last,
+    Avg,
+    Worst,
+    StdDev,
+    Jitter,
+}
+
+impl SortColumn {
+    /// The next column in the cycle.
+    const fn next(self) -> Self {
+        match self {
+            Self::Ttl => Self::LossPct,
+            Self::LossPct => Self::Last,
+            Self::Last => Self::Avg,
+            Self::Avg => Self::Worst,
+            Self::Worst => Self::StdDev,
+            Self::StdDev => Self::Jitter,
+            Self::Jitter => Self::Ttl,
+        }
+    }
+
+    /// The name of the column as shown in the table header.
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Ttl => "#",
+            Self::LossPct => "Loss%",
+            Self::Last => "Last",
+            Self::Avg => "Avg",
+            Self::Worst => "Wrst",
+            Self::StdDev => "StDev",
+            Self::Jitter => "Jttr",
+        }
+    }
+
+    /// Compare two hops by this column, in the natural order of the column.
+    fn compare(self, a: &Hop, b: &Hop) -> Ordering {
+        match self {
+            Self::Ttl => a.ttl().cmp(&b.ttl()),
+            Self::LossPct => b.loss_pct().total_cmp(&a.loss_pct()),
+            Self::Last => b
+                .last_ms()
+                .unwrap_or_default()
+                .total_cmp(&a.last_ms().unwrap_or_default()),
+            Self::Avg => b.avg_ms().total_cmp(&a.avg_ms()),
+            Self::Worst => b
+                .worst_ms()
+                .unwrap_or_default()
+                .total_cmp(&a.worst_ms().unwrap_or_default()),
+            Self::StdDev => b.stddev_ms().total_cmp(&a.stddev_ms()),
+            Self::Jitter => b.jitter_ms().total_cmp(&a.jitter_ms()),
         }
     }
 }
 
+#[allow(clippy::struct_excessive_bools)]
 struct TuiApp {
     selected_tracer_data: Trace,
     trace_info: Vec<TuiTraceInfo>,
@@ -159,12 +267,24 @@ struct TuiApp {
     resolver: DnsResolver,
     show_help: bool,
     frozen_start: Option<SystemTime>,
+    paused_start: Option<SystemTime>,
+    sort_column: SortColumn,
+    sort_reversed: bool,
+    problems_only: bool,
+    collapse_unresponsive: bool,
+    hop_window: Option<RangeInclusive<u8>>,
+    show_compare: bool,
+    compare_data: Vec<Trace>,
 }
 
 impl TuiApp {
     fn new(tui_config: TuiConfig, resolver: DnsResolver, trace_info: Vec<TuiTraceInfo>) -> Self {
+        let selected_tracer_data = trace_info[0].data.read().clone();
+        let hop_window = tui_config.hop_window.clone();
+        let show_compare = tui_config.compare_protocols;
+        let collapse_unresponsive = tui_config.unknown_hops.collapse;
         Self {
-            selected_tracer_data: Trace::new(tui_config.max_samples),
+            selected_tracer_data,
             trace_info,
             tui_config,
             table_state: TableState::default(),
@@ -172,6 +292,14 @@ impl TuiApp {
             resolver,
             show_help: false,
             frozen_start: None,
+            paused_start: None,
+            sort_column: SortColumn::Ttl,
+            sort_reversed: false,
+            problems_only: false,
+            collapse_unresponsive,
+            hop_window,
+            show_compare,
+            compare_data: vec![],
         }
     }
 
@@ -179,25 +307,166 @@ impl TuiApp {
         &self.selected_tracer_data
     }
 
+    /// Whether a report is being replayed, in which case the traces are never updated, paused or cleared.
+    const fn is_replay(&self) -> bool {
+        self.tui_config.replay.is_some()
+    }
+
     fn snapshot_trace_data(&mut self) {
         self.selected_tracer_data = self.trace_info[self.trace_selected].data.read().clone();
+        if self.show_compare {
+            self.compare_data = self
+                .trace_info
+                .iter()
+                .map(|info| info.data.read().clone())
+                .collect();
+        }
+    }
+
+    /// Have all traces completed the maximum number of rounds, if set?
+    fn all_rounds_complete(&self) -> bool {
+        self.tui_config.max_rounds.is_some_and(|max_rounds| {
+            self.trace_info
+                .iter()
+                .all(|info| info.data.read().round() >= max_rounds - 1)
+        })
     }
 
-    fn clear_trace_data(&mut self) {
-        *self.trace_info[self.trace_selected].data.write() =
-            Trace::new(self.tui_config.max_samples);
+    fn clear_trace_data(&self) {
+        let mut trace = self.trace_info[self.trace_selected].data.write();
+        *trace = Trace::new(TraceConfig {
+            max_samples: self.tui_config.max_samples,
+            histogram_buckets: trace.histogram_buckets().to_vec(),
+            percentiles: trace.percentiles().to_vec(),
+            zero_loss_grace: trace.zero_loss_grace(),
+            tcp_dest_ports: trace.tcp_dest_ports().to_vec(),
+            ewma_alpha: trace.ewma_alpha(),
+            stats_window: trace.stats_window(),
+            pinned_hops: trace.pinned_hops().clone(),
+        });
     }
 
     fn tracer_config(&self) -> &TuiTraceInfo {
         &self.trace_info[self.trace_selected]
     }
 
+    /// The indices of the hops of the selected trace in the order in which they are displayed.
+    ///
+    /// The index of each row of the table of hops maps to the hop at the corresponding index here.  Hops with equal
+    /// values in the sort column retain their ttl order, whether or not the order is reversed.  Hops outside the hop
+    /// window, if any, are not displayed but are still traced.
+    fn display_order(&self) -> Vec<usize> {
+        let hops = self.tracer_data().hops();
+        let mut order: Vec<_> = (0..hops.len())
+            .filter(|&i| {
+                self.hop_window
+                    .as_ref()
+                    .is_none_or(|window| window.contains(&hops[i].ttl()))
+            })
+            .filter(|&i| !self.problems_only || is_problem(&hops[i]))
+            .collect();
+        order.sort_by(|&a, &b| {
+            let ordering = self.sort_column.compare(&hops[a], &hops[b]);
+            if self.sort_reversed {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+        order
+    }
+
+    /// The rows of the table of hops of the selected trace in the order in which they are displayed.
+    ///
+    /// The index of each row of the table maps to the row at the corresponding index here, each run of unresponsive
+    /// hops is collapsed into a single row if collapsing is enabled.
+    fn display_rows(&self) -> Vec<HopRow> {
+        self.tracer_data()
+            .hop_rows(&self.display_order(), self.collapse_unresponsive)
+    }
+
+    /// The selected hop, or the target hop if no hop is selected.
+    ///
+    /// The first hop of a selected row of collapsed hops is selected.
+    fn selected_hop(&self) -> &Hop {
+        self.table_state
+            .selected()
+            .and_then(|s| self.display_rows().get(s).cloned())
+            .map_or_else(
+                || self.tracer_data().target_hop(),
+                |row| match row {
+                    HopRow::Hop(i) => &self.tracer_data().hops()[i],
+                    HopRow::Unresponsive(range) => &self.tracer_data().hops()[*range.start()],
+                    HopRow::Unpinned(i) => &self.tracer_data().unpinned_hops()[i],
+                },
+            )
+    }
+
+    fn cycle_sort_column(&mut self) {
+        self.sort_column = self.sort_column.next();
+        self.clear();
+    }
+
+    fn reverse_sort(&mut self) {
+        self.sort_reversed = !self.sort_reversed;
+        self.clear();
+    }
+
+    /// Toggle between the comparison of the hops of each protocol and the table of hops of the selected trace.
+    fn toggle_compare(&mut self) {
+        if self.tui_config.compare_protocols {
+            self.show_compare = !self.show_compare;
+            self.snapshot_trace_data();
+        }
+    }
+
+    fn toggle_problems_only(&mut self) {
+        self.problems_only = !self.problems_only;
+        self.clear();
+    }
+
+    fn toggle_collapse_unresponsive(&mut self) {
+        self.collapse_unresponsive = !self.collapse_unresponsive;
+        self.clear();
+    }
+
+    /// The number of hops which are hidden above and below the hop window.
+    fn hidden_hops(&self) -> (usize, usize) {
+        self.hop_window.as_ref().map_or((0, 0), |window| {
+            let hops = self.tracer_data().hops();
+            let above = hops.iter().filter(|hop| hop.ttl() < *window.start());
+            let below = hops.iter().filter(|hop| hop.ttl() > *window.end());
+            (above.count(), below.count())
+        })
+    }
+
+    /// Scroll the hop window, if any, one hop towards the target.
+    fn scroll_window_down(&mut self) {
+        let max_ttl = self.tracer_config().max_ttl;
+        if let Some(window) = &self.hop_window {
+            if *window.end() < max_ttl {
+                self.hop_window = Some(window.start() + 1..=window.end() + 1);
+                self.clear();
+            }
+        }
+    }
+
+    /// Scroll the hop window, if any, one hop away from the target.
+    fn scroll_window_up(&mut self) {
+        if let Some(window) = &self.hop_window {
+            if *window.start() > 1 {
+                self.hop_window = Some(window.start() - 1..=window.end() - 1);
+                self.clear();
+            }
+        }
+    }
+
     fn next_hop(&mut self) {
-        let hop_count = self.tracer_data().hops().len();
+        let hop_count = self.display_rows().len();
         if hop_count == 0 {
             return;
         }
-        let max_index = 0.max(hop_count.saturating_sub(1));
+        let max_index = hop_count.saturating_sub(1);
         let i = match self.table_state.selected() {
             Some(i) => {
                 if i < max_index {
@@ -212,7 +481,7 @@ impl TuiApp {
     }
 
     fn previous_hop(&mut self) {
-        let hop_count = self.tracer_data().hops().len();
+        let hop_count = self.display_rows().len();
         if hop_count == 0 {
             return;
         }
@@ -224,7 +493,7 @@ impl TuiApp {
                     i
                 }
             }
-            None => 0.max(hop_count.saturating_sub(1)),
+            None => hop_count.saturating_sub(1),
         };
         self.table_state.select(Some(i));
     }
@@ -238,6 +507,17 @@ impl TuiApp {
     fn previous_trace(&mut self) {
         if self.trace_selected > 0 {
             self.trace_selected -= 1;
+        }
+    }
+
+    /// Select the next trace, wrapping around to the first; or the prior trace, wrapping around to the last, if
+    /// `reverse`.
+    fn cycle_trace(&mut self, reverse: bool) {
+        let count = self.trace_info.len();
+        self.trace_selected = if reverse {
+            (self.trace_selected + count - 1) % count
+        } else {
+            (self.trace_selected + 1) % count
         };
     }
 
@@ -256,6 +536,72 @@ impl TuiApp {
         };
     }
 
+    /// Pause or resume all tracers.
+    ///
+    /// The display is not updated while paused, tracers complete the round in progress before pausing.
+    fn toggle_pause(&mut self) {
+        self.tui_config.pause.toggle();
+        self.paused_start = match self.paused_start {
+            None => Some(SystemTime::now()),
+            Some(_) => None,
+        };
+    }
+
+    /// Cycle the address mode from IP only, to hostname only, to both IP and hostname.
+    ///
+    /// Hostnames are resolved when first rendered and so no lookups are performed until a mode which shows them.
+    fn cycle_address_mode(&mut self) {
+        self.tui_config.address_mode = match self.tui_config.address_mode {
+            AddressMode::IP => AddressMode::Host,
+            AddressMode::Host => AddressMode::Both,
+            AddressMode::Both => AddressMode::IP,
+        };
+    }
+
+    /// Write a text snapshot of the table of hops, as currently displayed, to the snapshot file, if configured.
+    ///
+    /// The table is rendered `width` columns wide and tall enough to include every hop, regardless of the size of the
+    /// terminal.
+    fn write_snapshot(&mut self, width: u16) -> io::Result<()> {
+        let Some(path) = self.tui_config.snapshot.clone() else {
+            return Ok(());
+        };
+        let max_addrs = self.tui_config.max_addrs;
+        let show_clock_skew = self.tui_config.show_clock_skew;
+        let hops = self.tracer_data().hops();
+        let unpinned_hops = self.tracer_data().unpinned_hops();
+        let rows_height: u16 = self
+            .display_rows()
+            .into_iter()
+            .map(|row| match row {
+                HopRow::Hop(i) => hop_row_height(&hops[i], max_addrs, show_clock_skew),
+                HopRow::Unresponsive(_) => 1,
+                HopRow::Unpinned(i) => {
+                    hop_row_height(&unpinned_hops[i], max_addrs, show_clock_skew)
+                }
+            })
+            .sum();
+        let height = rows_height + 3;
+        let mut terminal = Terminal::new(TestBackend::new(width, height))?;
+        terminal.draw(|f| render_table(f, self, f.size()))?;
+        let buffer = terminal.backend().buffer();
+        let mut text = String::new();
+        for y in 0..height {
+            let mut line = String::new();
+            let mut skip = 0;
+            for x in 0..width {
+                let symbol = &buffer.get(x, y).symbol;
+                if skip == 0 {
+                    line.push_str(symbol);
+                }
+                skip = skip.max(symbol.width()).saturating_sub(1);
+            }
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        fs::write(path, text)
+    }
+
     fn toggle_asinfo(&mut self) {
         self.tui_config.lookup_as_info = !self.tui_config.lookup_as_info;
     }
@@ -314,7 +660,7 @@ pub fn run_frontend(
     }
     terminal.show_cursor()?;
     if let Err(err) = res {
-        println!("{:?}", err);
+        println!("{err:?}");
     }
     Ok(())
 }
@@ -327,28 +673,45 @@ fn run_app<B: Backend>(
 ) -> io::Result<()> {
     let mut app = TuiApp::new(tui_config, resolver, trace_info);
     loop {
-        if app.frozen_start == None {
+        if app.frozen_start.is_none() && app.paused_start.is_none() {
             app.snapshot_trace_data();
-        };
+        }
         terminal.draw(|f| render_app(f, &mut app))?;
+        if app.all_rounds_complete() || shutdown_requested() {
+            return app.write_snapshot(terminal.size()?.width);
+        }
         if event::poll(app.tui_config.refresh_rate)? {
             if let Event::Key(key) = event::read()? {
                 match (key.code, key.modifiers) {
-                    (KeyCode::Char('q'), _) if !app.show_help => return Ok(()),
-                    (KeyCode::Char('q'), _) if app.show_help => app.toggle_help(),
+                    (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        return app.write_snapshot(terminal.size()?.width);
+                    }
+                    (KeyCode::Char('q'), _) if !app.show_help => {
+                        return app.write_snapshot(terminal.size()?.width);
+                    }
+                    (KeyCode::Char('q') | KeyCode::Esc, _) if app.show_help => app.toggle_help(),
                     (KeyCode::Char('h'), _) => app.toggle_help(),
                     (KeyCode::Char('f'), _) if !app.show_help => app.toggle_freeze(),
-                    (KeyCode::Char('r'), KeyModifiers::CONTROL) if !app.show_help => {
+                    (KeyCode::Char(' '), _) if !app.show_help && !app.is_replay() => {
+                        app.toggle_pause();
+                    }
+                    (KeyCode::Char('s'), _) if !app.show_help => {
+                        app.write_snapshot(terminal.size()?.width)?;
+                    }
+                    (KeyCode::Char('r'), KeyModifiers::CONTROL)
+                        if !app.show_help && !app.is_replay() =>
+                    {
                         app.clear();
                         app.clear_trace_data();
                     }
-                    (KeyCode::Char('k'), KeyModifiers::CONTROL) if !app.show_help => {
+                    (KeyCode::Char('k'), KeyModifiers::CONTROL)
+                        if !app.show_help && !app.is_replay() =>
+                    {
                         app.resolver.flush();
                     }
                     (KeyCode::Down, _) if !app.show_help => app.next_hop(),
                     (KeyCode::Up, _) if !app.show_help => app.previous_hop(),
                     (KeyCode::Esc, _) if !app.show_help => app.clear(),
-                    (KeyCode::Esc, _) if app.show_help => app.toggle_help(),
                     (KeyCode::Left, _) if !app.show_help => {
                         app.previous_trace();
                         app.clear();
@@ -357,6 +720,14 @@ fn run_app<B: Backend>(
                         app.next_trace();
                         app.clear();
                     }
+                    (KeyCode::Tab, _) if !app.show_help => {
+                        app.cycle_trace(false);
+                        app.clear();
+                    }
+                    (KeyCode::BackTab, _) if !app.show_help => {
+                        app.cycle_trace(true);
+                        app.clear();
+                    }
                     (KeyCode::Char('i'), _) if !app.show_help => {
                         app.tui_config.address_mode = AddressMode::IP;
                     }
@@ -366,11 +737,23 @@ fn run_app<B: Backend>(
                     (KeyCode::Char('b'), _) if !app.show_help => {
                         app.tui_config.address_mode = AddressMode::Both;
                     }
+                    (KeyCode::Char('a'), _) if !app.show_help => app.cycle_address_mode(),
+                    (KeyCode::Char('o'), _) if !app.show_help => app.cycle_sort_column(),
+                    (KeyCode::Char('r'), _) if !app.show_help => app.reverse_sort(),
+                    (KeyCode::Char('p'), _) if !app.show_help => app.toggle_problems_only(),
+                    (KeyCode::Char('x'), _) if !app.show_help => {
+                        app.toggle_collapse_unresponsive();
+                    }
+                    (KeyCode::Char('v'), _) if !app.show_help => app.toggle_compare(),
+                    (KeyCode::PageDown, _) if !app.show_help => app.scroll_window_down(),
+                    (KeyCode::PageUp, _) if !app.show_help => app.scroll_window_up(),
                     (KeyCode::Char('z'), _) if !app.show_help => {
                         match app.resolver.config().resolve_method {
                             DnsResolveMethod::Resolv
                             | DnsResolveMethod::Google
-                            | DnsResolveMethod::Cloudflare => {
+                            | DnsResolveMethod::Cloudflare
+                            | DnsResolveMethod::Doh
+                            | DnsResolveMethod::Dot => {
                                 app.toggle_asinfo();
                                 app.resolver.flush();
                             }
@@ -438,12 +821,14 @@ fn render_app<B: Backend>(f: &mut Frame<'_, B>, app: &mut TuiApp) {
 }
 
 /// Render the title, config, target, clock and keyboard controls.
-fn render_header<B: Backend>(f: &mut Frame<'_, B>, app: &mut TuiApp, rect: Rect) {
+#[allow(clippy::too_many_lines)]
+fn render_header<B: Backend>(f: &mut Frame<'_, B>, app: &TuiApp, rect: Rect) {
     let header_block = Block::default()
         .title(format!(" Trippy v{} ", clap::crate_version!()))
         .title_alignment(Alignment::Center)
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(app.tui_config.theme.border))
         .style(Style::default());
     let now = chrono::Local::now().to_rfc3339_opts(SecondsFormat::Secs, true);
     let clock_span = Spans::from(Span::raw(now));
@@ -460,53 +845,93 @@ fn render_header<B: Backend>(f: &mut Frame<'_, B>, app: &mut TuiApp, rect: Rect)
         .alignment(Alignment::Right);
     let protocol = &app.tracer_config().protocol;
     let dns = format_dns_method(app.resolver.config().resolve_method);
-    let as_info = match app.resolver.config().resolve_method {
-        DnsResolveMethod::System => String::from("n/a"),
-        DnsResolveMethod::Resolv | DnsResolveMethod::Google | DnsResolveMethod::Cloudflare => {
-            if app.tui_config.lookup_as_info {
-                String::from("on")
-            } else {
-                String::from("off")
-            }
-        }
+    let as_info = if matches!(
+        app.resolver.config().resolve_method,
+        DnsResolveMethod::System
+    ) {
+        String::from("n/a")
+    } else if app.tui_config.lookup_as_info {
+        String::from("on")
+    } else {
+        String::from("off")
     };
     let interval = humantime::format_duration(app.tracer_config().min_round_duration);
+    let tos = app
+        .tracer_config()
+        .tos
+        .map(|tos| format!(" tos={tos:#04x}"))
+        .unwrap_or_default();
+    let dont_fragment = match app.tracer_config().dont_fragment {
+        Some(true) => " df=on",
+        Some(false) => " df=off",
+        None => "",
+    };
     let grace = humantime::format_duration(app.tracer_config().grace_duration);
     let first_ttl = app.tracer_config().first_ttl;
     let max_ttl = app.tracer_config().max_ttl;
+    let ttl_stride = match app.tracer_config().ttl_stride {
+        1 => String::new(),
+        ttl_stride => format!(" ttl-stride={ttl_stride}"),
+    };
+    let rtt_unit = match app.tui_config.rtt_format.unit {
+        RttUnit::Ms => String::new(),
+        RttUnit::Us => String::from(" rtt-unit=us"),
+    };
     let max_hosts = app
         .tui_config
         .max_addrs
         .map_or_else(|| String::from("auto"), |m| m.to_string());
+    let trace_count = if app.trace_info.len() > 1 {
+        format!(" [{} of {}]", app.trace_selected + 1, app.trace_info.len())
+    } else {
+        String::new()
+    };
     let left_spans = vec![
         Spans::from(vec![
             Span::styled("Target: ", Style::default().add_modifier(Modifier::BOLD)),
             Span::raw(format!(
-                "{} ({})",
+                "{} ({}{}){trace_count}",
                 app.tracer_config().target_hostname,
-                app.tracer_config().target_addr
+                app.tracer_config().current_target_addr(),
+                format_target_changes(app.tracer_config().data.read().target_changes())
             )),
+            Span::raw(
+                app.tracer_config()
+                    .description
+                    .as_ref()
+                    .map_or_else(String::new, |description| format!(" - {description}")),
+            ),
         ]),
         Spans::from(vec![
             Span::styled("Config: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(format!("protocol={} dns={} as-info={} interval={} grace={} start-ttl={} max-ttl={} max-hosts={}",
-                              protocol, dns, as_info, interval, grace, first_ttl, max_ttl, max_hosts))]),
+            Span::raw(match &app.tui_config.replay {
+                Some(replay) => format!("replay={replay} start-ttl={first_ttl} max-ttl={max_ttl}{ttl_stride} max-hosts={max_hosts}{tos}{rtt_unit}"),
+                None => format!("protocol={protocol} dns={dns} as-info={as_info} interval={interval} grace={grace} start-ttl={first_ttl} max-ttl={max_ttl}{ttl_stride} max-hosts={max_hosts}{tos}{dont_fragment}{rtt_unit}"),
+            })]),
         Spans::from(vec![
             Span::styled("Status: ", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(if let Some(start) = app.frozen_start {
-                format!(
-                    "Frozen ({})",
-                    humantime::format_duration(Duration::from_secs(
-                        start.elapsed().unwrap_or_default().as_secs()
-                    ))
-                )
-            } else {
-                String::from("Running")
-            }),
+            Span::raw(format_status(app)),
             Span::raw(format!(
                 ", discovered {} hops",
                 app.tracer_data().hops().len()
             )),
+            Span::raw(
+                app.tracer_data()
+                    .destination_ttl()
+                    .map_or_else(String::new, |ttl| {
+                        format!(", destination reached at hop {ttl}")
+                    }),
+            ),
+            Span::raw(app.tracer_data().proxy().map_or_else(String::new, |proxy| {
+                format!(
+                    ", proxy connect to {} last={} loss={:.1}%",
+                    proxy.target(),
+                    proxy
+                        .last_ms()
+                        .map_or_else(|| String::from("???"), |last| format!("{last:.1}ms")),
+                    proxy.loss_pct()
+                )
+            })),
         ]),
     ];
 
@@ -518,6 +943,28 @@ fn render_header<B: Backend>(f: &mut Frame<'_, B>, app: &mut TuiApp, rect: Rect)
     f.render_widget(left, rect);
 }
 
+/// Format the number of times the target has changed, if any, i.e. `, re-resolved 2 times`.
+fn format_target_changes(changes: usize) -> String {
+    match changes {
+        0 => String::new(),
+        1 => String::from(", re-resolved once"),
+        changes => format!(", re-resolved {changes} times"),
+    }
+}
+
+/// Format the state of the tracers.
+fn format_status(app: &TuiApp) -> String {
+    match (app.paused_start, app.frozen_start) {
+        (Some(start), _) => format!("PAUSED ({})", format_elapsed(start)),
+        (None, Some(start)) => format!("Frozen ({})", format_elapsed(start)),
+        (None, None) if app.is_replay() => String::from("REPLAY (read-only)"),
+        (None, None) => match app.tracer_data().backoff() {
+            1 => String::from("Running"),
+            backoff => format!("Running (rate slowed {backoff}x)"),
+        },
+    }
+}
+
 /// Format the `DnsResolveMethod`.
 fn format_dns_method(resolve_method: DnsResolveMethod) -> String {
     match resolve_method {
@@ -525,24 +972,36 @@ fn format_dns_method(resolve_method: DnsResolveMethod) -> String {
         DnsResolveMethod::Resolv => String::from("resolv"),
         DnsResolveMethod::Google => String::from("google"),
         DnsResolveMethod::Cloudflare => String::from("cloudflare"),
+        DnsResolveMethod::Doh => String::from("doh"),
+        DnsResolveMethod::Dot => String::from("dot"),
     }
 }
 
 /// Render the tabs, one per trace.
-fn render_tabs<B: Backend>(f: &mut Frame<'_, B>, app: &mut TuiApp, rect: Rect) {
+fn render_tabs<B: Backend>(f: &mut Frame<'_, B>, app: &TuiApp, rect: Rect) {
     let tabs_block = Block::default()
-        .title("Traces")
+        .title(format!(
+            "Traces ({} of {})",
+            app.trace_selected + 1,
+            app.trace_info.len()
+        ))
         .title_alignment(Alignment::Left)
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(app.tui_config.theme.border))
         .style(Style::default());
     let titles: Vec<_> = app
         .trace_info
         .iter()
         .map(|trace| {
+            let title = if app.tui_config.compare_protocols {
+                format!("{} {}", trace.target_hostname, trace.protocol)
+            } else {
+                trace.target_hostname.clone()
+            };
             Spans::from(Span::styled(
-                &trace.target_hostname,
-                Style::default().fg(Color::Green),
+                title,
+                Style::default().fg(app.tui_config.theme.tab_fg),
             ))
         })
         .collect();
@@ -553,7 +1012,7 @@ fn render_tabs<B: Backend>(f: &mut Frame<'_, B>, app: &mut TuiApp, rect: Rect) {
         .highlight_style(
             Style::default()
                 .add_modifier(Modifier::BOLD)
-                .bg(Color::Black),
+                .bg(app.tui_config.theme.tab_selected_bg),
         );
     f.render_widget(tabs, rect);
 }
@@ -561,18 +1020,190 @@ fn render_tabs<B: Backend>(f: &mut Frame<'_, B>, app: &mut TuiApp, rect: Rect) {
 /// Render the body.
 ///
 /// This is the table of hop data or, if there is no data, the spash screen.
+///
+/// If more than one distinct path has been discovered then the paths are shown alongside the table of hop data.  If
+/// the protocols are being compared then the hops of every protocol are shown side by side instead.
 fn render_body<B: Backend>(f: &mut Frame<'_, B>, rec: Rect, app: &mut TuiApp) {
     if app.tracer_data().hops().is_empty() {
-        render_splash(f, rec);
+        render_splash(f, app, rec);
+    } else if app.show_compare {
+        render_compare(f, app, rec);
+    } else {
+        let paths = app.tracer_data().paths();
+        if paths.len() > 1 {
+            let chunks = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(70), Constraint::Percentage(30)].as_ref())
+                .split(rec);
+            render_table(f, app, chunks[0]);
+            render_paths(f, app, &paths, chunks[1]);
+        } else {
+            render_table(f, app, rec);
+        }
+    }
+}
+
+/// Render the distinct paths discovered by tracing multiple flows.
+///
+/// Each column is a distinct path, headed by the ids of the flows which followed it, and each row is a hop.
+fn render_paths<B: Backend>(f: &mut Frame<'_, B>, app: &TuiApp, paths: &[FlowPath], rect: Rect) {
+    let header_cells = paths.iter().map(|path| {
+        let flows = path.flow_ids.iter().join(",");
+        Cell::from(format!("flows {flows}"))
+            .style(Style::default().fg(app.tui_config.theme.header_fg))
+    });
+    let header = Row::new(header_cells)
+        .style(Style::default().bg(app.tui_config.theme.header_bg))
+        .height(1)
+        .bottom_margin(0);
+    let hop_count = paths
+        .iter()
+        .map(|path| path.hosts.len())
+        .max()
+        .unwrap_or_default();
+    let rows = (0..hop_count).map(|i| {
+        let cells = paths
+            .iter()
+            .map(|path| match path.hosts.get(i).copied().flatten() {
+                Some(addr) => Cell::from(format_flow_host(app, addr)),
+                None => Cell::from("???"),
+            });
+        Row::new(cells).height(1).bottom_margin(0)
+    });
+    let widths: Vec<_> = paths
+        .iter()
+        .map(|_| Constraint::Ratio(1, paths.len() as u32))
+        .collect();
+    let table = Table::new(rows)
+        .header(header)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(Style::default().fg(app.tui_config.theme.border))
+                .title("Paths"),
+        )
+        .widths(&widths);
+    f.render_widget(table, rect);
+}
+
+/// Render the hops of the trace of each protocol side by side, one row per ttl.
+///
+/// Each cell is the host which most recently responded at that ttl, with its loss and average round-trip time.  Rows at
+/// which the protocols do not all reach the same host, such as where a hop responds to only some of the protocols, are
+/// highlighted.
+fn render_compare<B: Backend>(f: &mut Frame<'_, B>, app: &TuiApp, rect: Rect) {
+    let theme = &app.tui_config.theme;
+    let header_cells = std::iter::once(Cell::from("#"))
+        .chain(
+            app.trace_info
+                .iter()
+                .map(|info| Cell::from(info.protocol.clone())),
+        )
+        .map(|cell| cell.style(Style::default().fg(theme.header_fg)));
+    let header = Row::new(header_cells)
+        .style(Style::default().bg(theme.header_bg))
+        .height(1)
+        .bottom_margin(0);
+    let max_ttl = app
+        .compare_data
+        .iter()
+        .filter_map(|trace| trace.hops().last().map(Hop::ttl))
+        .max()
+        .unwrap_or_default();
+    let rows = (1..=max_ttl).map(|ttl| {
+        let hops: Vec<_> = app
+            .compare_data
+            .iter()
+            .map(|trace| trace.hops().iter().find(|hop| hop.ttl() == ttl))
+            .collect();
+        let style = if hops
+            .iter()
+            .map(|hop| hop.and_then(Hop::last_addr))
+            .all_equal()
+        {
+            Style::default()
+        } else {
+            Style::default()
+                .fg(theme.rtt_warn)
+                .add_modifier(Modifier::BOLD)
+        };
+        let cells =
+            std::iter::once(Cell::from(ttl.to_string())).chain(hops.into_iter().map(|hop| {
+                Cell::from(hop.map_or_else(String::new, |hop| format_compare_hop(app, hop)))
+            }));
+        Row::new(cells).style(style).height(1).bottom_margin(0)
+    });
+    let widths: Vec<_> = std::iter::once(Constraint::Length(4))
+        .chain(
+            app.trace_info
+                .iter()
+                .map(|_| Constraint::Ratio(1, app.trace_info.len() as u32)),
+        )
+        .collect();
+    let table = Table::new(rows)
+        .header(header)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(Style::default().fg(theme.border))
+                .title("Protocols"),
+        )
+        .widths(&widths);
+    f.render_widget(table, rect);
+}
+
+/// Format a hop of the protocol comparison as its most recent host, loss and average round-trip time.
+fn format_compare_hop(app: &TuiApp, hop: &Hop) -> String {
+    let host = hop.last_addr().map_or_else(
+        || String::from(app.tui_config.unknown_hops.placeholder("No response")),
+        |addr| format_flow_host(app, addr),
+    );
+    if hop.total_recv() > 0 {
+        format!(
+            "{host} {:.1}% {}",
+            hop.loss_pct(),
+            app.tui_config.rtt_format.format(hop.avg_ms())
+        )
     } else {
-        render_table(f, app, rec);
+        format!("{host} {:.1}%", hop.loss_pct())
+    }
+}
+
+/// Format a host in a path according to the `AddressMode`.
+fn format_flow_host(app: &TuiApp, addr: IpAddr) -> String {
+    match app.tui_config.address_mode {
+        AddressMode::IP => addr.to_string(),
+        AddressMode::Host | AddressMode::Both => truncate_hostname(
+            &app.resolver.reverse_lookup(addr).to_string(),
+            app.tui_config.max_hostname_width,
+        ),
+    }
+}
+
+/// Truncate `hostname` to `max_width` columns, ending with an ellipsis, if it is wider.
+fn truncate_hostname(hostname: &str, max_width: Option<usize>) -> String {
+    match max_width {
+        Some(max_width) if hostname.width() > max_width => {
+            let mut truncated = String::new();
+            for c in hostname.chars() {
+                if truncated.width() + c.width().unwrap_or(0) >= max_width {
+                    break;
+                }
+                truncated.push(c);
+            }
+            truncated.push('…');
+            truncated
+        }
+        _ => hostname.to_string(),
     }
 }
 
 /// Render the splash screen.
 ///
 /// This is shown on startup whilst we await the first round of data to be available.
-fn render_splash<B: Backend>(f: &mut Frame<'_, B>, rect: Rect) {
+fn render_splash<B: Backend>(f: &mut Frame<'_, B>, app: &TuiApp, rect: Rect) {
     let chunks = Layout::default()
         .constraints([Constraint::Percentage(35), Constraint::Percentage(65)].as_ref())
         .split(rect);
@@ -580,13 +1211,14 @@ fn render_splash<B: Backend>(f: &mut Frame<'_, B>, rect: Rect) {
         .title("Hops")
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(app.tui_config.theme.border))
         .style(Style::default());
     let splash = vec![
-        r#" _____    _                "#,
-        r#"|_   _| _(_)_ __ _ __ _  _ "#,
-        r#"  | || '_| | '_ \ '_ \ || |"#,
-        r#"  |_||_| |_| .__/ .__/\_, |"#,
-        r#"           |_|  |_|   |__/ "#,
+        r" _____    _                ",
+        r"|_   _| _(_)_ __ _ __ _  _ ",
+        r"  | || '_| | '_ \ '_ \ || |",
+        r"  |_||_| |_| .__/ .__/\_, |",
+        r"           |_|  |_|   |__/ ",
         "",
         "Awaiting data...",
     ];
@@ -605,73 +1237,253 @@ fn render_splash<B: Backend>(f: &mut Frame<'_, B>, rect: Rect) {
 ///
 /// - The time-to-live (indexed from 1) at this hop (`#`)
 /// - The host(s) reported at this hop (`Host`)
-/// - The packet loss % for all probes at this hop (`Loss%`)
+/// - The packet loss % for all probes at this hop, and the number of probes pending a response, if any (`Loss%`)
 /// - The number of requests sent for all probes at this hop (`Snt`)
 /// - The number of replies received for all probes at this hop (`Recv`)
 /// - The round-trip time of the most recent probe at this hop (`Last`)
 /// - The average round-trip time for all probes at this hop (`Avg`)
+/// - The exponentially weighted moving average round-trip time for all probes at this hop, if shown (`Ewma`)
 /// - The best round-trip time for all probes at this hop (`Best`)
 /// - The worst round-trip time for all probes at this hop (`Wrst`)
 /// - The standard deviation round-trip time for all probes at this hop (`StDev`)
-/// - The status of this hop (`Sts`)
+/// - The interarrival jitter of the round-trip time for all probes at this hop (`Jttr`)
+/// - The round-trip time at each percentile of the recent probes at this hop, if shown (i.e. `P95`)
+/// - The status of this hop, marked with `↩` if the round-trip time is likely a return path artifact, with `⇄` and
+///   the number of address changes if new addresses responded after the first and with `⏱` and the number of rounds
+///   since the hop last responded if it is stale (`Sts`)
+///
+/// A hop which previously responded but has not in the most recent `stale_rounds` rounds is stale and is dimmed, such
+/// that it is clear its statistics are not current.
+/// The hops are shown in the display order which may be sorted by any of the numeric columns and may be filtered to
+/// show only those hops which have a problem or to show only those hops within the hop window.
 fn render_table<B: Backend>(f: &mut Frame<'_, B>, app: &mut TuiApp, rect: Rect) {
-    let header = render_table_header();
+    let show_ewma = app.tui_config.show_ewma;
+    let percentiles = app.tui_config.percentiles.clone();
+    let theme = app.tui_config.theme;
+    let history_width = history_width(app.tui_config.history_width, rect);
+    let header = render_table_header(show_ewma, &percentiles, history_width, &theme);
     let selected_style = Style::default().add_modifier(Modifier::REVERSED);
-    let rows = app.tracer_data().hops().iter().map(|hop| {
-        render_table_row(
-            hop,
-            &app.resolver,
-            app.tracer_data().is_target(hop),
-            app.tui_config.address_mode,
-            app.tui_config.lookup_as_info,
-            app.tui_config.max_addrs,
-        )
+    let mut title = vec![Span::raw(render_table_title(app))];
+    title.extend(render_baseline_legend(app));
+    let hops = app.tracer_data().hops();
+    let unpinned_hops = app.tracer_data().unpinned_hops();
+    let selected = app.table_state.selected();
+    let rows = app.display_rows().into_iter().enumerate().map(|(i, row)| {
+        let hop = match row {
+            HopRow::Hop(i) => &hops[i],
+            HopRow::Unpinned(i) => &unpinned_hops[i],
+            HopRow::Unresponsive(range) => {
+                return render_collapsed_row(
+                    &hops[range],
+                    show_ewma,
+                    percentiles.len(),
+                    history_width,
+                    &theme,
+                );
+            }
+        };
+        render_table_row(app, hop, selected == Some(i), history_width)
     });
+    let widths = table_widths(show_ewma, percentiles.len(), history_width, rect);
     let table = Table::new(rows)
         .header(header)
         .block(
             Block::default()
                 .borders(Borders::ALL)
                 .border_type(BorderType::Rounded)
-                .title("Hops"),
+                .border_style(Style::default().fg(app.tui_config.theme.border))
+                .title(Spans::from(title)),
         )
         .highlight_style(selected_style)
-        .widths(&TABLE_WIDTH);
+        .widths(&widths);
     f.render_stateful_widget(table, rect, &mut app.table_state);
 }
 
-/// Render the table header.
-fn render_table_header() -> Row<'static> {
-    let header_cells = TABLE_HEADER
+/// Render the table title, including the sort order, filter and hop window if not the default.
+fn render_table_title(app: &TuiApp) -> String {
+    let sort = if app.sort_column == SortColumn::Ttl && !app.sort_reversed {
+        String::new()
+    } else {
+        let direction = if (app.sort_column == SortColumn::Ttl) == app.sort_reversed {
+            "desc"
+        } else {
+            "asc"
+        };
+        format!(" (sorted by {} {direction})", app.sort_column.name())
+    };
+    let filter = if app.problems_only {
+        " (problems only)"
+    } else {
+        ""
+    };
+    let collapsed = app
+        .display_rows()
         .iter()
-        .map(|h| Cell::from(*h).style(Style::default().fg(Color::Black)));
+        .filter(|row| matches!(row, HopRow::Unresponsive(_)))
+        .count();
+    let collapsed = match collapsed {
+        0 => String::new(),
+        1 => String::from(" (1 run of unresponsive hops collapsed)"),
+        runs => format!(" ({runs} runs of unresponsive hops collapsed)"),
+    };
+    let window = app.hop_window.as_ref().map_or_else(String::new, |window| {
+        let (above, below) = app.hidden_hops();
+        format!(
+            " (ttl {}-{}, {above} hidden above, {below} hidden below)",
+            window.start(),
+            window.end()
+        )
+    });
+    let pinned = match app.tracer_data().pinned_hops().len() {
+        0 => String::new(),
+        1 => String::from(" (1 hop pinned)"),
+        hops => format!(" ({hops} hops pinned)"),
+    };
+    format!("Hops{sort}{filter}{collapsed}{pinned}{window}")
+}
+
+/// Render the legend of the colors of the hops which deviate from the baseline route, if any.
+///
+/// The `AS` deviation is only shown if `AS` information is shown, as otherwise every deviation is by address.
+fn render_baseline_legend(app: &TuiApp) -> Vec<Span<'static>> {
+    if app.tui_config.baseline.is_none() {
+        return vec![];
+    }
+    let theme = &app.tui_config.theme;
+    let mut legend = vec![
+        Span::raw(" (baseline: "),
+        Span::styled(
+            "address changed",
+            deviation_style(Some(Deviation::Address), theme),
+        ),
+    ];
+    if app.tui_config.lookup_as_info {
+        legend.push(Span::raw(", "));
+        legend.push(Span::styled(
+            "AS changed",
+            deviation_style(Some(Deviation::Asn), theme),
+        ));
+    }
+    legend.push(Span::raw(")"));
+    legend
+}
+
+/// The style of the host of a hop which deviates from the baseline route by `deviation`, if at all.
+fn deviation_style(deviation: Option<Deviation>, theme: &Theme) -> Style {
+    match deviation {
+        Some(Deviation::Address) => Style::default()
+            .fg(theme.baseline_address)
+            .add_modifier(Modifier::BOLD),
+        Some(Deviation::Asn) => Style::default()
+            .fg(theme.baseline_asn)
+            .add_modifier(Modifier::BOLD),
+        None => Style::default(),
+    }
+}
+
+/// Is the hop considered to have a problem?
+///
+/// A hop has a problem if any probes were lost or if the average round-trip time exceeds `PROBLEM_AVG_MS`.
+fn is_problem(hop: &Hop) -> bool {
+    hop.loss_pct() > 0_f64 || hop.avg_ms() > PROBLEM_AVG_MS
+}
+
+/// The widths of the table columns, with the `Ewma` column and `percentiles` columns if shown.
+fn table_widths(
+    show_ewma: bool,
+    percentiles: usize,
+    history_width: usize,
+    rect: Rect,
+) -> Vec<Constraint> {
+    let mut widths = TABLE_WIDTH.to_vec();
+    let mut host_width = 42;
+    if show_ewma {
+        host_width -= EWMA_WIDTH;
+        widths.insert(EWMA_COLUMN, Constraint::Percentage(EWMA_WIDTH));
+    }
+    for _ in 0..percentiles {
+        host_width -= PERCENTILE_WIDTH;
+        widths.insert(widths.len() - 1, Constraint::Percentage(PERCENTILE_WIDTH));
+    }
+    if history_width > 0 {
+        let inner_width = rect.width.saturating_sub(2).max(1);
+        host_width -= (history_width as u16 * 100).div_ceil(inner_width);
+        widths.push(Constraint::Length(history_width as u16));
+    }
+    widths[1] = Constraint::Percentage(host_width);
+    widths
+}
+
+/// The number of probes shown in the `History` column, or zero if it is not shown.
+///
+/// The column is narrowed, or hidden entirely, such that it takes no more than `MAX_HISTORY_WIDTH` of the table.
+fn history_width(history_width: Option<usize>, rect: Rect) -> usize {
+    let available = usize::from(rect.width.saturating_sub(2) * MAX_HISTORY_WIDTH / 100);
+    history_width.map_or(0, |width| width.min(available))
+}
+
+/// Render the table header.
+fn render_table_header(
+    show_ewma: bool,
+    percentiles: &[f64],
+    history_width: usize,
+    theme: &Theme,
+) -> Row<'static> {
+    let mut headers: Vec<_> = TABLE_HEADER.iter().map(ToString::to_string).collect();
+    if show_ewma {
+        headers.insert(EWMA_COLUMN, String::from("Ewma"));
+    }
+    for percentile in percentiles {
+        headers.insert(headers.len() - 1, format!("P{percentile}"));
+    }
+    if history_width > 0 {
+        headers.push(String::from("History"));
+    }
+    let header_cells = headers
+        .into_iter()
+        .map(|h| Cell::from(h).style(Style::default().fg(theme.header_fg)));
     Row::new(header_cells)
-        .style(Style::default().bg(Color::White))
+        .style(Style::default().bg(theme.header_bg))
         .height(1)
         .bottom_margin(0)
 }
 
 /// Render a single row in the table of hops.
-fn render_table_row(
-    hop: &Hop,
-    dns: &DnsResolver,
-    is_target: bool,
-    address_mode: AddressMode,
-    lookup_as_info: bool,
-    max_addr: Option<u8>,
-) -> Row<'static> {
+///
+/// The hostnames of the `selected` row are never truncated.
+fn render_table_row(app: &TuiApp, hop: &Hop, selected: bool, history_width: usize) -> Row<'static> {
+    let config = &app.tui_config;
+    let theme = &config.theme;
+    let rtt_format = config.rtt_format;
+    let is_target = app.tracer_data().is_target(hop);
+    let stale_rounds = app
+        .tracer_data()
+        .rounds_since_response(hop)
+        .filter(|&rounds| rounds >= config.stale_rounds);
+    let deviation = config.baseline.as_ref().and_then(|baseline| {
+        baseline.deviation(
+            &app.tracer_config().target_hostname,
+            hop.ttl(),
+            hop.addrs(),
+            &app.resolver,
+            config.lookup_as_info,
+        )
+    });
+    let max_hostname_width = config.max_hostname_width.filter(|_| !selected);
     let ttl_cell = render_ttl_cell(hop);
-    let hostname_cell = render_hostname_cell(hop, dns, address_mode, lookup_as_info, max_addr);
-    let loss_pct_cell = render_loss_pct_cell(hop);
+    let hostname_cell = render_hostname_cell(hop, &app.resolver, config, max_hostname_width)
+        .style(deviation_style(deviation, theme));
+    let loss_pct_cell = render_loss_pct_cell(hop, theme);
     let total_sent_cell = render_total_sent_cell(hop);
     let total_recv_cell = render_total_recv_cell(hop);
-    let last_cell = render_last_cell(hop);
-    let avg_cell = render_avg_cell(hop);
-    let best_cell = render_best_cell(hop);
-    let worst_cell = render_worst_cell(hop);
-    let stddev_cell = render_stddev_cell(hop);
-    let status_cell = render_status_cell(hop, is_target);
-    let cells = [
+    let last_cell = render_last_cell(hop, rtt_format);
+    let avg_cell = render_avg_cell(hop, rtt_format, theme);
+    let best_cell = render_best_cell(hop, rtt_format);
+    let worst_cell = render_worst_cell(hop, rtt_format);
+    let stddev_cell = render_stddev_cell(hop, rtt_format);
+    let jitter_cell = render_jitter_cell(hop, rtt_format);
+    let status_cell = render_status_cell(hop, is_target, stale_rounds);
+    let mut cells = vec![
         ttl_cell,
         hostname_cell,
         loss_pct_cell,
@@ -682,21 +1494,130 @@ fn render_table_row(
         best_cell,
         worst_cell,
         stddev_cell,
+        jitter_cell,
         status_cell,
     ];
-    let row_height = hop
-        .addr_count()
+    if config.show_ewma {
+        cells.insert(EWMA_COLUMN, render_ewma_cell(hop, rtt_format));
+    }
+    for percentile in hop.percentiles_ms(&config.percentiles) {
+        cells.insert(
+            cells.len() - 1,
+            render_percentile_cell(percentile, rtt_format),
+        );
+    }
+    if history_width > 0 {
+        cells.push(render_history_cell(hop, history_width, theme));
+    }
+    let style = if stale_rounds.is_some() {
+        Style::default().add_modifier(Modifier::DIM)
+    } else {
+        Style::default()
+    };
+    Row::new(cells)
+        .style(style)
+        .height(hop_row_height(
+            hop,
+            config.max_addrs,
+            config.show_clock_skew,
+        ))
+        .bottom_margin(0)
+}
+
+/// Render a single row in the table of hops for a run of unresponsive `hops` at consecutive ttls.
+///
+/// The row shows the ttl range of the run, the highest loss of any hop of the run and the total probes sent to them,
+/// the round-trip time columns are empty as no hop of the run has responded.
+fn render_collapsed_row(
+    hops: &[Hop],
+    show_ewma: bool,
+    percentiles: usize,
+    history_width: usize,
+    theme: &Theme,
+) -> Row<'static> {
+    let ttls = match (hops.first(), hops.last()) {
+        (Some(first), Some(last)) => format!("{}-{}", first.ttl(), last.ttl()),
+        _ => String::new(),
+    };
+    let loss_pct = hops.iter().map(Hop::loss_pct).fold(0_f64, f64::max);
+    let total_sent: usize = hops.iter().map(Hop::total_sent).sum();
+    let mut cells = vec![
+        Cell::from(ttls),
+        Cell::from(UnknownHops::collapsed(hops.len())),
+        Cell::from(format!("{loss_pct:.1}%")).style(Style::default().fg(theme.loss(loss_pct))),
+        Cell::from(total_sent.to_string()),
+        Cell::from("0"),
+    ];
+    let empty = 6 + usize::from(show_ewma) + percentiles;
+    cells.extend((0..empty).map(|_| Cell::from("")));
+    cells.push(Cell::from("🟤"));
+    if history_width > 0 {
+        cells.push(Cell::from(""));
+    }
+    Row::new(cells).height(1).bottom_margin(0)
+}
+
+/// The height of the row for a hop, one line for each address shown, for each `MPLS` label, for the `tcp` ports and
+/// their outcome, for the reason the hop was unreachable and for the clock offset, if shown.
+fn hop_row_height(hop: &Hop, max_addr: Option<u8>, show_clock_skew: bool) -> u16 {
+    let mpls_count = hop.mpls().map_or(0, |mpls| mpls.labels().len());
+    let ports_count = usize::from(hop.tcp_dest_ports().next().is_some());
+    let outcome_count = usize::from(hop.tcp_outcome().is_some());
+    let unreachable_count = usize::from(hop.unreachable_code().is_some());
+    let clock_skew_count = usize::from(show_clock_skew && hop.icmp_timestamps().is_some());
+    (hop.addr_count()
         .min(max_addr.unwrap_or(u8::MAX) as usize)
-        .max(1) as u16;
-    Row::new(cells).height(row_height).bottom_margin(0)
+        .max(1)
+        + mpls_count
+        + ports_count
+        + outcome_count
+        + unreachable_count
+        + clock_skew_count) as u16
+}
+
+/// Render the most recent `width` probes of a hop, newest first as in the samples chart, as a symbol for the round-trip time of each response,
+/// relative to the slowest response shown, or `HISTORY_LOST_SYMBOL` for each lost probe.
+///
+/// Probes which are still awaiting a response are not shown.
+fn render_history_cell(hop: &Hop, width: usize, theme: &Theme) -> Cell<'static> {
+    let samples: Vec<_> = hop
+        .samples()
+        .iter()
+        .skip(hop.total_pending())
+        .take(width)
+        .collect();
+    let slowest = samples
+        .iter()
+        .map(|sample| sample.as_secs_f64())
+        .fold(0_f64, f64::max);
+    let symbols = samples.iter().map(|sample| {
+        if sample.is_zero() {
+            Span::styled(
+                HISTORY_LOST_SYMBOL.to_string(),
+                Style::default().fg(theme.loss_high),
+            )
+        } else {
+            let level =
+                (sample.as_secs_f64() / slowest * HISTORY_SYMBOLS.len() as f64).ceil() as usize;
+            Span::styled(
+                HISTORY_SYMBOLS[level.clamp(1, HISTORY_SYMBOLS.len()) - 1].to_string(),
+                Style::default().fg(theme.rtt(sample.as_secs_f64() * 1000_f64)),
+            )
+        }
+    });
+    Cell::from(Spans::from(symbols.collect::<Vec<_>>()))
 }
 
 fn render_ttl_cell(hop: &Hop) -> Cell<'static> {
     Cell::from(format!("{}", hop.ttl()))
 }
 
-fn render_loss_pct_cell(hop: &Hop) -> Cell<'static> {
-    Cell::from(format!("{:.1}%", hop.loss_pct()))
+fn render_loss_pct_cell(hop: &Hop, theme: &Theme) -> Cell<'static> {
+    let cell = match hop.total_pending() {
+        0 => Cell::from(format!("{:.1}%", hop.loss_pct())),
+        pending => Cell::from(format!("{:.1}% +{pending}", hop.loss_pct())),
+    };
+    cell.style(Style::default().fg(theme.loss(hop.loss_pct())))
 }
 
 fn render_total_sent_cell(hop: &Hop) -> Cell<'static> {
@@ -707,150 +1628,337 @@ fn render_total_recv_cell(hop: &Hop) -> Cell<'static> {
     Cell::from(format!("{}", hop.total_recv()))
 }
 
-fn render_avg_cell(hop: &Hop) -> Cell<'static> {
-    Cell::from(if hop.total_recv() > 0 {
-        format!("{:.1}", hop.avg_ms())
+fn render_avg_cell(hop: &Hop, rtt_format: RttFormat, theme: &Theme) -> Cell<'static> {
+    if hop.total_recv() > 0 {
+        Cell::from(rtt_format.format(hop.avg_ms()))
+            .style(Style::default().fg(theme.rtt(hop.avg_ms())))
     } else {
-        String::default()
-    })
+        Cell::from(String::default())
+    }
 }
 
-fn render_hostname_cell(
-    hop: &Hop,
-    dns: &DnsResolver,
-    address_mode: AddressMode,
+/// Format a `DnsEntry` with or without `AS` information (if available), showing at most `max_addr` hostnames.
+fn format_dns_entry(
+    dns_entry: DnsEntry,
     lookup_as_info: bool,
+    dns: &DnsResolver,
     max_addr: Option<u8>,
-) -> Cell<'static> {
-    /// Format a `DnsEntry` with or without `AS` information (if available)
-    fn format_dns_entry(dns_entry: DnsEntry, lookup_as_info: bool) -> String {
-        match dns_entry {
-            DnsEntry::Resolved(Resolved::Normal(_, hosts)) => hosts.join(" "),
-            DnsEntry::Resolved(Resolved::WithAsInfo(_, hosts, asinfo)) => {
-                if lookup_as_info {
-                    format!("AS{} {}", asinfo.asn, hosts.join(" "))
-                } else {
-                    hosts.join(" ")
-                }
+    max_hostname_width: Option<usize>,
+) -> String {
+    let join = |hosts: &[String]| {
+        hosts
+            .iter()
+            .take(max_addr.map_or(usize::MAX, usize::from))
+            .map(|host| truncate_hostname(host, max_hostname_width))
+            .join(" ")
+    };
+    match dns_entry {
+        DnsEntry::Resolved(Resolved::Normal(_, hosts)) => join(&hosts),
+        DnsEntry::Resolved(Resolved::WithAsInfo(_, hosts, asinfo)) => {
+            if lookup_as_info {
+                let asn = format_asn(&asinfo.asn, dns.config().asn_names.as_ref());
+                format!("{asn} {}", join(&hosts))
+            } else {
+                join(&hosts)
             }
-            DnsEntry::Pending(ip) | DnsEntry::NotFound(ip) => format!("{}", ip),
-            DnsEntry::Failed(ip) => format!("Failed: {}", ip),
-            DnsEntry::Timeout(ip) => format!("Timeout: {}", ip),
         }
+        DnsEntry::Pending(ip) | DnsEntry::NotFound(ip) => format!("{ip}"),
+        DnsEntry::Failed(ip) => format!("Failed: {ip}"),
+        DnsEntry::Timeout(ip) => format!("Timeout: {ip}"),
     }
-    /// Perform a reverse DNS lookup for an address and format the result.
-    fn format_address(
-        addr: &IpAddr,
-        freq: usize,
-        hop: &Hop,
-        dns: &DnsResolver,
-        address_mode: AddressMode,
-        lookup_as_info: bool,
-    ) -> String {
-        let addr_fmt = match address_mode {
-            AddressMode::IP => addr.to_string(),
-            AddressMode::Host => {
-                if lookup_as_info {
-                    let entry = dns.reverse_lookup_with_asinfo(*addr);
-                    format_dns_entry(entry, true)
-                } else {
-                    let entry = dns.reverse_lookup(*addr);
-                    format_dns_entry(entry, false)
-                }
-            }
-            AddressMode::Both => {
-                let hostname = if lookup_as_info {
-                    let entry = dns.reverse_lookup_with_asinfo(*addr);
-                    format_dns_entry(entry, true)
-                } else {
-                    let entry = dns.reverse_lookup(*addr);
-                    format_dns_entry(entry, false)
-                };
-                format!("{} ({})", hostname, addr)
-            }
-        };
+}
 
-        if hop.addr_count() > 1 {
-            format!(
-                "{} [{:.1}%]",
-                addr_fmt,
-                (freq as f64 / hop.total_recv() as f64) * 100_f64
-            )
-        } else {
-            addr_fmt
+/// Perform a reverse DNS lookup for an address and format the result.
+fn format_address(
+    addr: &IpAddr,
+    freq: usize,
+    hop: &Hop,
+    dns: &DnsResolver,
+    config: &TuiConfig,
+    max_hostname_width: Option<usize>,
+) -> String {
+    let lookup_as_info = config.lookup_as_info;
+    let max_addr = config.max_addrs;
+    let addr_fmt = match config.address_mode {
+        AddressMode::IP => addr.to_string(),
+        AddressMode::Host => {
+            if lookup_as_info {
+                let entry = dns.reverse_lookup_with_asinfo(*addr);
+                format_dns_entry(entry, true, dns, max_addr, max_hostname_width)
+            } else {
+                let entry = dns.reverse_lookup(*addr);
+                format_dns_entry(entry, false, dns, max_addr, max_hostname_width)
+            }
         }
+        AddressMode::Both => {
+            let hostname = if lookup_as_info {
+                let entry = dns.reverse_lookup_with_asinfo(*addr);
+                format_dns_entry(entry, true, dns, max_addr, max_hostname_width)
+            } else {
+                let entry = dns.reverse_lookup(*addr);
+                format_dns_entry(entry, false, dns, max_addr, max_hostname_width)
+            };
+            format!("{hostname} ({addr})")
+        }
+    };
+    let addr_fmt = match dns.label(*addr) {
+        Some(label) => format!("{addr_fmt} [{label}]"),
+        None => addr_fmt,
+    };
+    let addr_fmt = match config.geoip.as_ref().and_then(|geoip| geoip.lookup(*addr)) {
+        Some(geo) => format!("{addr_fmt} [{geo}]"),
+        None => addr_fmt,
+    };
+
+    if hop.addr_count() > 1 {
+        format!(
+            "{} [{:.1}%]",
+            addr_fmt,
+            (freq as f64 / hop.total_recv() as f64) * 100_f64
+        )
+    } else {
+        addr_fmt
     }
+}
 
+fn render_hostname_cell(
+    hop: &Hop,
+    dns: &DnsResolver,
+    config: &TuiConfig,
+    max_hostname_width: Option<usize>,
+) -> Cell<'static> {
     Cell::from(if hop.total_recv() > 0 {
-        match max_addr {
-            None => hop
-                .addrs_with_counts()
-                .map(|(addr, &freq)| {
-                    format_address(addr, freq, hop, dns, address_mode, lookup_as_info)
-                })
-                .join("\n"),
-            Some(max_addr) => hop
-                .addrs_with_counts()
-                .sorted_unstable_by_key(|(_, &cnt)| cnt)
-                .rev()
-                .take(max_addr as usize)
-                .map(|(addr, &freq)| {
-                    format_address(addr, freq, hop, dns, address_mode, lookup_as_info)
-                })
-                .join("\n"),
+        let addrs = ordered_addrs(hop, config.max_addrs, config.address_order)
+            .into_iter()
+            .map(|(addr, freq)| format_address(addr, freq, hop, dns, config, max_hostname_width))
+            .join("\n");
+        let addrs = match format_mpls(hop) {
+            mpls if mpls.is_empty() => addrs,
+            mpls => format!("{addrs}\n{mpls}"),
+        };
+        let addrs = match format_tcp_dest_ports(hop) {
+            ports if ports.is_empty() => addrs,
+            ports => format!("{addrs}\n{ports}"),
+        };
+        let addrs = match format_tcp_outcome(hop) {
+            outcome if outcome.is_empty() => addrs,
+            outcome => format!("{addrs}\n{outcome}"),
+        };
+        let addrs = match format_unreachable(hop) {
+            reason if reason.is_empty() => addrs,
+            reason => format!("{addrs}\n{reason}"),
+        };
+        match format_clock_skew(hop) {
+            skew if skew.is_empty() || !config.show_clock_skew => addrs,
+            skew => format!("{addrs}\n{skew}"),
         }
+    } else if hop.is_skipped() {
+        String::from("Skipped (ttl stride)")
     } else {
-        String::from("No response")
+        String::from(config.unknown_hops.placeholder("No response"))
     })
 }
 
-fn render_last_cell(hop: &Hop) -> Cell<'static> {
+/// The addresses of a hop, and the number of responses from each, to show in the order to show them.
+///
+/// If `max_addr` is given then only the most frequent addresses are shown.  Without an `address_order` the addresses
+/// are shown in an arbitrary order, which may change between rounds, and otherwise the selection of the most frequent
+/// addresses is deterministic and the addresses are shown in that order.
+fn ordered_addrs(
+    hop: &Hop,
+    max_addr: Option<u8>,
+    address_order: Option<AddressOrder>,
+) -> Vec<(&IpAddr, usize)> {
+    let mut addrs: Vec<_> = hop
+        .addrs_with_counts()
+        .map(|(addr, &freq)| (addr, freq))
+        .collect();
+    let by_frequency =
+        |a: &(&IpAddr, usize), b: &(&IpAddr, usize)| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0));
+    if let Some(max_addr) = max_addr {
+        if address_order.is_some() {
+            addrs.sort_unstable_by(by_frequency);
+        } else {
+            addrs.sort_unstable_by_key(|&(_, freq)| Reverse(freq));
+        }
+        addrs.truncate(usize::from(max_addr));
+    }
+    match address_order {
+        None => {}
+        Some(AddressOrder::FirstSeen) => {
+            addrs.sort_unstable_by_key(|(addr, _)| hop.first_seen(addr));
+        }
+        Some(AddressOrder::Ip) => addrs.sort_unstable_by_key(|&(addr, _)| addr),
+        Some(AddressOrder::Frequency) => addrs.sort_unstable_by(by_frequency),
+    }
+    addrs
+}
+
+/// Format the destination ports of the `tcp` probes which reached the hop, if configured.
+fn format_tcp_dest_ports(hop: &Hop) -> String {
+    let ports = hop.tcp_dest_ports().join(" ");
+    if ports.is_empty() {
+        ports
+    } else {
+        format!("[ports {ports}]")
+    }
+}
+
+/// Format whether the port of the `tcp` probes which reached the target host was open, closed or unfiltered, per destination
+/// port if configured.
+fn format_tcp_outcome(hop: &Hop) -> String {
+    let name = |outcome| match outcome {
+        TcpOutcome::Open => "open",
+        TcpOutcome::Closed => "closed",
+        TcpOutcome::Unfiltered => "unfiltered",
+    };
+    let ports = hop
+        .tcp_port_outcomes()
+        .map(|(port, outcome)| format!("[port {port} {}]", name(outcome)))
+        .join(" ");
+    match hop.tcp_outcome() {
+        Some(_) if !ports.is_empty() => ports,
+        Some(outcome) => format!("[port {}]", name(outcome)),
+        None => String::new(),
+    }
+}
+
+/// Format the reason given by the most recent `ICMP` `DestinationUnreachable` from the hop, if any.
+///
+/// A closed port is the response of the target host to a `udp` probe which reached it and so is shown as the
+/// destination having been reached, and an administratively prohibited destination is marked as a firewall.
+fn format_unreachable(hop: &Hop) -> String {
+    hop.unreachable_code()
+        .map_or_else(String::new, |code| match code.reason() {
+            UnreachableReason::Net => String::from("[net unreachable]"),
+            UnreachableReason::Host => String::from("[host unreachable]"),
+            UnreachableReason::Protocol => String::from("[protocol unreachable]"),
+            UnreachableReason::Port => String::from("[destination reached]"),
+            UnreachableReason::FragmentationNeeded => String::from("[fragmentation needed]"),
+            UnreachableReason::AdminProhibited => String::from("[firewall: admin prohibited]"),
+            UnreachableReason::Other => format!("[unreachable code {}]", code.0),
+        })
+}
+
+/// Format the apparent clock offset of the hop from the timestamps of its most recent `ICMP` `TimestampReply`, if any.
+fn format_clock_skew(hop: &Hop) -> String {
+    hop.icmp_timestamps()
+        .map_or_else(String::new, |timestamps| {
+            timestamps.clock_offset_ms().map_or_else(
+                || String::from("[clock skew: bogus timestamps]"),
+                |offset| format!("[clock skew: {offset:+}ms]"),
+            )
+        })
+}
+
+/// Format the `MPLS` label stack, one entry per line.
+fn format_mpls(hop: &Hop) -> String {
+    hop.mpls()
+        .map(|mpls| {
+            mpls.labels()
+                .iter()
+                .map(|label| {
+                    format!(
+                        "[MPLS label={} tc={} s={} ttl={}]",
+                        label.label,
+                        label.tc,
+                        u8::from(label.bottom_of_stack),
+                        label.ttl
+                    )
+                })
+                .join("\n")
+        })
+        .unwrap_or_default()
+}
+
+fn render_last_cell(hop: &Hop, rtt_format: RttFormat) -> Cell<'static> {
     Cell::from(
         hop.last_ms()
-            .map(|last| format!("{:.1}", last))
+            .map(|last| rtt_format.format(last))
             .unwrap_or_default(),
     )
 }
 
-fn render_best_cell(hop: &Hop) -> Cell<'static> {
+fn render_best_cell(hop: &Hop, rtt_format: RttFormat) -> Cell<'static> {
     Cell::from(
         hop.best_ms()
-            .map(|best| format!("{:.1}", best))
+            .map(|best| rtt_format.format(best))
             .unwrap_or_default(),
     )
 }
 
-fn render_worst_cell(hop: &Hop) -> Cell<'static> {
+fn render_worst_cell(hop: &Hop, rtt_format: RttFormat) -> Cell<'static> {
     Cell::from(
         hop.worst_ms()
-            .map(|worst| format!("{:.1}", worst))
+            .map(|worst| rtt_format.format(worst))
             .unwrap_or_default(),
     )
 }
 
-fn render_stddev_cell(hop: &Hop) -> Cell<'static> {
+fn render_stddev_cell(hop: &Hop, rtt_format: RttFormat) -> Cell<'static> {
+    Cell::from(if hop.total_recv() > 1 {
+        rtt_format.format(hop.stddev_ms())
+    } else {
+        String::default()
+    })
+}
+
+fn render_jitter_cell(hop: &Hop, rtt_format: RttFormat) -> Cell<'static> {
     Cell::from(if hop.total_recv() > 1 {
-        format!("{:.1}", hop.stddev_ms())
+        rtt_format.format(hop.jitter_ms())
     } else {
         String::default()
     })
 }
 
-fn render_status_cell(hop: &Hop, is_target: bool) -> Cell<'static> {
+fn render_ewma_cell(hop: &Hop, rtt_format: RttFormat) -> Cell<'static> {
+    Cell::from(
+        hop.ewma_ms()
+            .map(|ewma| rtt_format.format(ewma))
+            .unwrap_or_default(),
+    )
+}
+
+fn render_percentile_cell(percentile: Option<f64>, rtt_format: RttFormat) -> Cell<'static> {
+    Cell::from(
+        percentile
+            .map(|percentile| rtt_format.format(percentile))
+            .unwrap_or_default(),
+    )
+}
+
+/// Format the time elapsed since `start` to the nearest second.
+fn format_elapsed(start: SystemTime) -> String {
+    humantime::format_duration(Duration::from_secs(
+        start.elapsed().unwrap_or_default().as_secs(),
+    ))
+    .to_string()
+}
+
+/// Render the status of the hop, followed by a marker if the round-trip time is likely a return path artifact, if the
+/// address changed and if the hop is stale, with the number of rounds since it last responded.
+fn render_status_cell(hop: &Hop, is_target: bool, stale_rounds: Option<usize>) -> Cell<'static> {
     let lost = hop.total_sent() - hop.total_recv();
-    Cell::from(match (lost, is_target) {
+    let status = match (lost, is_target) {
         (lost, target) if target && lost == hop.total_sent() => "🔴",
         (lost, target) if target && lost > 0 => "🟡",
         (lost, target) if !target && lost == hop.total_sent() => "🟤",
         (lost, target) if !target && lost > 0 => "🔵",
         _ => "🟢",
-    })
+    };
+    let return_path = if hop.return_path_hint() { " ↩" } else { "" };
+    let addr_changes = match hop.addr_change_count() {
+        0 => String::new(),
+        count => format!(" ⇄{count}"),
+    };
+    let stale = stale_rounds.map_or_else(String::new, |rounds| format!(" ⏱{rounds}"));
+    Cell::from(format!("{status}{return_path}{addr_changes}{stale}"))
 }
 
 /// Render the footer.
 ///
 /// This contains the history and frequency charts.
-fn render_footer<B: Backend>(f: &mut Frame<'_, B>, rec: Rect, app: &mut TuiApp) {
+fn render_footer<B: Backend>(f: &mut Frame<'_, B>, rec: Rect, app: &TuiApp) {
     let bottom_chunks = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Percentage(75), Constraint::Percentage(25)].as_ref())
@@ -858,16 +1966,25 @@ fn render_footer<B: Backend>(f: &mut Frame<'_, B>, rec: Rect, app: &mut TuiApp)
     render_history(f, app, bottom_chunks[0]);
     render_ping_frequency(f, app, bottom_chunks[1]);
     if app.show_help {
-        render_help(f);
+        render_help(f, &app.tui_config.theme);
+    }
+}
+
+/// Render the title of the ping history, including the stats window if configured.
+fn render_history_title(app: &TuiApp, hop: &Hop) -> String {
+    match app.tracer_data().stats_window() {
+        Some(window) => format!(
+            "Samples #{} (window {})",
+            hop.ttl(),
+            humantime::format_duration(window)
+        ),
+        None => format!("Samples #{}", hop.ttl()),
     }
 }
 
 /// Render the ping history for the final hop which is typically the target.
-fn render_history<B: Backend>(f: &mut Frame<'_, B>, app: &mut TuiApp, rect: Rect) {
-    let target_hop = app.table_state.selected().map_or_else(
-        || app.tracer_data().target_hop(),
-        |s| &app.tracer_data().hops()[s],
-    );
+fn render_history<B: Backend>(f: &mut Frame<'_, B>, app: &TuiApp, rect: Rect) {
+    let target_hop = app.selected_hop();
     let data = target_hop
         .samples()
         .iter()
@@ -877,21 +1994,19 @@ fn render_history<B: Backend>(f: &mut Frame<'_, B>, app: &mut TuiApp, rect: Rect
     let history = Sparkline::default()
         .block(
             Block::default()
-                .title(format!("Samples #{}", target_hop.ttl()))
+                .title(render_history_title(app, target_hop))
                 .borders(Borders::ALL)
-                .border_type(BorderType::Rounded),
+                .border_type(BorderType::Rounded)
+                .border_style(Style::default().fg(app.tui_config.theme.border)),
         )
         .data(&data)
-        .style(Style::default().fg(Color::Yellow));
+        .style(Style::default().fg(app.tui_config.theme.samples));
     f.render_widget(history, rect);
 }
 
 /// Render a histogram of ping frequencies.
-fn render_ping_frequency<B: Backend>(f: &mut Frame<'_, B>, app: &mut TuiApp, rect: Rect) {
-    let target_hop = app.table_state.selected().map_or_else(
-        || app.tracer_data().target_hop(),
-        |s| &app.tracer_data().hops()[s],
-    );
+fn render_ping_frequency<B: Backend>(f: &mut Frame<'_, B>, app: &TuiApp, rect: Rect) {
+    let target_hop = app.selected_hop();
     let freq_data = sample_frequency(target_hop.samples());
     let freq_data_ref: Vec<_> = freq_data.iter().map(|(b, c)| (b.as_str(), *c)).collect();
     let barchart = BarChart::default()
@@ -899,28 +2014,30 @@ fn render_ping_frequency<B: Backend>(f: &mut Frame<'_, B>, app: &mut TuiApp, rec
             Block::default()
                 .title(format!("Frequency #{}", target_hop.ttl()))
                 .borders(Borders::ALL)
-                .border_type(BorderType::Rounded),
+                .border_type(BorderType::Rounded)
+                .border_style(Style::default().fg(app.tui_config.theme.border)),
         )
         .data(freq_data_ref.as_slice())
         .bar_width(4)
         .bar_gap(1)
-        .bar_style(Style::default().fg(Color::Green))
+        .bar_style(Style::default().fg(app.tui_config.theme.frequency))
         .value_style(
             Style::default()
-                .bg(Color::Green)
+                .bg(app.tui_config.theme.frequency)
                 .add_modifier(Modifier::BOLD),
         );
     f.render_widget(barchart, rect);
 }
 
 /// Render help
-fn render_help<B: Backend>(f: &mut Frame<'_, B>) {
+fn render_help<B: Backend>(f: &mut Frame<'_, B>, theme: &Theme) {
     let block = Block::default()
         .title(" Controls ")
         .title_alignment(Alignment::Center)
         .borders(Borders::ALL)
-        .style(Style::default().bg(Color::Blue))
-        .border_type(BorderType::Double);
+        .style(Style::default().bg(theme.help_bg))
+        .border_type(BorderType::Double)
+        .border_style(Style::default().fg(theme.border));
     let control_spans: Vec<_> = HELP_LINES.iter().map(|&line| Spans::from(line)).collect();
     let control = Paragraph::new(control_spans)
         .style(Style::default())
@@ -959,7 +2076,7 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
 }
 
 /// Return the frequency % grouped by sample duration.
-fn sample_frequency(samples: &[Duration]) -> Vec<(String, u64)> {
+fn sample_frequency(samples: &VecDeque<Duration>) -> Vec<(String, u64)> {
     let sample_count = samples.len();
     let mut count_by_duration: BTreeMap<u128, u64> = BTreeMap::new();
     for sample in samples {
@@ -970,7 +2087,7 @@ fn sample_frequency(samples: &[Duration]) -> Vec<(String, u64)> {
     count_by_duration
         .iter()
         .map(|(ping, count)| {
-            let ping = format!("{}", ping);
+            let ping = format!("{ping}");
             let freq_pct = ((*count as f64 / sample_count as f64) * 100_f64) as u64;
             (ping, freq_pct)
         })
@@ -0,0 +1,74 @@
+use anyhow::anyhow;
+use maxminddb::geoip2::City;
+use maxminddb::Reader;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A cheaply cloneable, caching, `GeoIP` lookup backed by a local `MaxMind` `mmdb` database.
+#[derive(Clone)]
+pub struct GeoIpLookup {
+    inner: Arc<GeoIpLookupInner>,
+}
+
+struct GeoIpLookupInner {
+    reader: Reader<Vec<u8>>,
+    cache: Mutex<HashMap<IpAddr, Option<String>>>,
+}
+
+impl GeoIpLookup {
+    /// Load the `MaxMind` database at `path`.
+    ///
+    /// The database is read into memory once, an error is returned if the file is missing or is not a valid database.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let reader = Reader::open_readfile(path)
+            .map_err(|err| anyhow!("failed to load GeoIP database {}: {}", path.display(), err))?;
+        Ok(Self {
+            inner: Arc::new(GeoIpLookupInner {
+                reader,
+                cache: Mutex::new(HashMap::new()),
+            }),
+        })
+    }
+
+    /// Lookup the location of an `IpAddr`, such as `Paris, FR`.
+    ///
+    /// Returns `None` if the address is not in the database.  The result of every lookup is cached.
+    pub fn lookup(&self, addr: IpAddr) -> Option<String> {
+        self.inner
+            .cache
+            .lock()
+            .entry(addr)
+            .or_insert_with(|| self.inner.location(addr))
+            .clone()
+    }
+}
+
+impl Debug for GeoIpLookup {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GeoIpLookup").finish_non_exhaustive()
+    }
+}
+
+impl GeoIpLookupInner {
+    fn location(&self, addr: IpAddr) -> Option<String> {
+        let city: City<'_> = self.reader.lookup(addr).ok()?;
+        let city_name = city
+            .city
+            .and_then(|city| city.names)
+            .and_then(|names| names.get("en").copied());
+        let country_name = city.country.and_then(|country| {
+            country
+                .iso_code
+                .or_else(|| country.names.and_then(|names| names.get("en").copied()))
+        });
+        match (city_name, country_name) {
+            (Some(city), Some(country)) => Some(format!("{city}, {country}")),
+            (Some(name), None) | (None, Some(name)) => Some(name.to_string()),
+            (None, None) => None,
+        }
+    }
+}
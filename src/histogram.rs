@@ -0,0 +1,156 @@
+use crate::backend::Trace;
+use crate::frontend::TuiTraceInfo;
+use crate::report::ROUND_POLL_INTERVAL;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::thread::sleep;
+
+/// The round-trip time histograms of every hop of all traces.
+#[derive(Serialize)]
+pub struct HistogramExport {
+    pub targets: Vec<TargetHistogram>,
+}
+
+/// The round-trip time histograms of every hop of a single trace.
+#[derive(Serialize)]
+pub struct TargetHistogram {
+    pub ip: String,
+    pub hostname: String,
+    pub hops: Vec<HopHistogram>,
+}
+
+/// The round-trip time histogram of a single hop.
+#[derive(Serialize)]
+pub struct HopHistogram {
+    pub ttl: u8,
+    pub buckets: Vec<HistogramBucket>,
+}
+
+/// The number of responses with a round-trip time less than or equal to `le_ms`.
+///
+/// The count of each bucket excludes the responses counted by all smaller buckets.  The final bucket has no upper bound
+/// and so `le_ms` is `null`.
+#[derive(Serialize)]
+pub struct HistogramBucket {
+    pub le_ms: Option<f64>,
+    pub count: usize,
+}
+
+/// Export the histograms to `path` every time any trace completes a round.
+pub fn run_histogram_export(path: PathBuf, traces: Vec<TuiTraceInfo>) -> anyhow::Result<()> {
+    thread::Builder::new()
+        .name(String::from("histogram"))
+        .spawn(move || {
+            let mut rounds: Vec<Option<usize>> = vec![None; traces.len()];
+            loop {
+                let latest: Vec<_> = traces
+                    .iter()
+                    .map(|info| Some(info.data.read().round()))
+                    .collect();
+                if latest != rounds {
+                    if let Err(err) = write_histograms(&path, &traces) {
+                        eprintln!("histogram export failed: {err}");
+                    }
+                    rounds = latest;
+                }
+                sleep(ROUND_POLL_INTERVAL);
+            }
+        })?;
+    Ok(())
+}
+
+/// Write the histograms of all traces to `path` as JSON.
+///
+/// The histograms are first written to a temporary file which then replaces `path` so that readers never observe a
+/// partially written file.
+pub fn write_histograms(path: &Path, traces: &[TuiTraceInfo]) -> anyhow::Result<()> {
+    let export = HistogramExport {
+        targets: traces
+            .iter()
+            .map(|info| TargetHistogram {
+                ip: info.target_addr.to_string(),
+                hostname: info.target_hostname.clone(),
+                hops: hop_histograms(&info.data.read()),
+            })
+            .collect(),
+    };
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(&export)?)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn hop_histograms(trace: &Trace) -> Vec<HopHistogram> {
+    trace
+        .hops()
+        .iter()
+        .map(|hop| {
+            let counts = hop.histogram().iter().copied().chain(std::iter::repeat(0));
+            let bounds = trace
+                .histogram_buckets()
+                .iter()
+                .map(|bucket| Some(bucket.as_secs_f64() * 1000_f64))
+                .chain(std::iter::once(None));
+            HopHistogram {
+                ttl: hop.ttl(),
+                buckets: bounds
+                    .zip(counts)
+                    .map(|(le_ms, count)| HistogramBucket { le_ms, count })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{trace_with_buckets, update, Response};
+
+    /// A trace with `buckets` of a single hop which responded after each of `rtts_ms`, one per round.
+    fn trace(buckets: &[u64], rtts_ms: &[u64]) -> Trace {
+        let rounds: Vec<Vec<Response>> = rtts_ms.iter().map(|&rtt| vec![Some((1, rtt))]).collect();
+        let rounds: Vec<_> = rounds.iter().map(Vec::as_slice).collect();
+        trace_with_buckets(buckets, &rounds)
+    }
+
+    fn buckets(histogram: &HopHistogram) -> Vec<(Option<f64>, usize)> {
+        histogram
+            .buckets
+            .iter()
+            .map(|bucket| (bucket.le_ms, bucket.count))
+            .collect()
+    }
+
+    #[test]
+    fn test_bucket_bounds() {
+        let histograms = hop_histograms(&trace(&[10, 20], &[5, 10, 11, 20, 21, 500]));
+        assert_eq!(1, histograms.len());
+        assert_eq!(1, histograms[0].ttl);
+        assert_eq!(
+            vec![(Some(10_f64), 2), (Some(20_f64), 2), (None, 2)],
+            buckets(&histograms[0])
+        );
+    }
+
+    #[test]
+    fn test_no_buckets() {
+        let histograms = hop_histograms(&trace(&[], &[5, 500]));
+        assert_eq!(vec![(None, 2)], buckets(&histograms[0]));
+    }
+
+    #[test]
+    fn test_no_responses() {
+        let mut trace = trace(&[10, 20], &[5]);
+        update(&mut trace, 1, &[None, None]);
+        let histograms = hop_histograms(&trace);
+        assert_eq!(2, histograms.len());
+        assert_eq!(
+            vec![(Some(10_f64), 0), (Some(20_f64), 0), (None, 0)],
+            buckets(&histograms[1])
+        );
+    }
+}
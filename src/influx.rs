@@ -0,0 +1,332 @@
+use crate::backend::{Hop, Trace};
+use crate::frontend::TuiTraceInfo;
+use crate::report::ROUND_POLL_INTERVAL;
+use anyhow::anyhow;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+use std::thread::sleep;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The name of the measurement of every point.
+const MEASUREMENT: &str = "trippy_hop";
+
+/// The maximum size of a datagram of points, such that it does not fragment on a typical path.
+const MAX_DATAGRAM: usize = 1432;
+
+/// The maximum time to wait to connect to, write to or read from an `InfluxDB` `HTTP` endpoint.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The address to write `InfluxDB` line protocol to.
+#[derive(Debug, Clone)]
+pub enum InfluxAddr {
+    /// Send datagrams to a `UDP` listener, i.e. `udp://127.0.0.1:8089`.
+    Udp(String),
+    /// Post to a `HTTP` write endpoint, i.e. `http://127.0.0.1:8086/write?db=trippy`.
+    Http { authority: String, path: String },
+    /// Append to a file, i.e. `file:points.lp`.
+    File(PathBuf),
+}
+
+impl FromStr for InfluxAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(addr) = s.strip_prefix("udp://") {
+            Ok(Self::Udp(addr.trim_end_matches('/').to_string()))
+        } else if let Some(rest) = s.strip_prefix("http://") {
+            let (authority, path) = rest
+                .find('/')
+                .map_or((rest, "/write"), |i| rest.split_at(i));
+            if authority.is_empty() {
+                return Err(anyhow!("invalid influx address {}: missing host", s));
+            }
+            Ok(Self::Http {
+                authority: authority.to_string(),
+                path: path.to_string(),
+            })
+        } else if let Some(path) = s.strip_prefix("file:").filter(|path| !path.is_empty()) {
+            Ok(Self::File(PathBuf::from(path)))
+        } else {
+            Err(anyhow!(
+                "invalid influx address {}: must start with udp://, http:// or file:",
+                s
+            ))
+        }
+    }
+}
+
+/// A sink which writes `InfluxDB` line protocol to an `InfluxAddr`.
+pub enum Influx {
+    Udp(UdpSocket, SocketAddr),
+    Http {
+        addr: SocketAddr,
+        authority: String,
+        path: String,
+    },
+    File(File),
+}
+
+impl Influx {
+    /// Resolve, bind or open the `addr`.
+    ///
+    /// The `UDP` socket never blocks, and a `HTTP` endpoint is connected to afresh each time points are written.
+    pub fn connect(addr: &InfluxAddr) -> anyhow::Result<Self> {
+        Ok(match addr {
+            InfluxAddr::Udp(addr) => {
+                let addr = resolve(addr)?;
+                let local = match addr {
+                    SocketAddr::V4(_) => SocketAddr::from(([0, 0, 0, 0], 0)),
+                    SocketAddr::V6(_) => SocketAddr::from(([0_u16; 8], 0)),
+                };
+                let socket = UdpSocket::bind(local)?;
+                socket.set_nonblocking(true)?;
+                Self::Udp(socket, addr)
+            }
+            InfluxAddr::Http { authority, path } => Self::Http {
+                addr: resolve(authority)?,
+                authority: authority.clone(),
+                path: path.clone(),
+            },
+            InfluxAddr::File(path) => Self::File(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|err| anyhow!("failed to open {}: {}", path.display(), err))?,
+            ),
+        })
+    }
+
+    /// Write the points, as few datagrams or a single request or write as possible.
+    fn send(&mut self, points: &[String]) -> anyhow::Result<()> {
+        match self {
+            Self::Udp(socket, addr) => {
+                let mut datagram = String::new();
+                for point in points {
+                    if !datagram.is_empty() && datagram.len() + point.len() + 1 > MAX_DATAGRAM {
+                        socket.send_to(datagram.as_bytes(), *addr)?;
+                        datagram.clear();
+                    }
+                    datagram.push_str(point);
+                    datagram.push('\n');
+                }
+                if !datagram.is_empty() {
+                    socket.send_to(datagram.as_bytes(), *addr)?;
+                }
+                Ok(())
+            }
+            Self::Http {
+                addr,
+                authority,
+                path,
+            } => http_write(*addr, authority, path, &lines(points)),
+            Self::File(file) => Ok(file.write_all(lines(points).as_bytes())?),
+        }
+    }
+}
+
+/// The points as lines of line protocol, each terminated with a newline.
+fn lines(points: &[String]) -> String {
+    let mut lines = points.join("\n");
+    lines.push('\n');
+    lines
+}
+
+/// Write the round-trip time, loss and jitter of every hop as `InfluxDB` line protocol every time a trace completes a
+/// round.
+///
+/// Points are written from a dedicated thread such that a slow or absent endpoint never delays the trace.  A failure to
+/// write a round is not retried, if `log` is set then the first failure, and the recovery from it, is reported on
+/// stderr.
+pub fn run_influx(mut influx: Influx, traces: Vec<TuiTraceInfo>, log: bool) -> anyhow::Result<()> {
+    thread::Builder::new()
+        .name(String::from("influx"))
+        .spawn(move || {
+            let mut rounds: Vec<Option<usize>> = vec![None; traces.len()];
+            let mut failing = false;
+            loop {
+                for (info, round) in traces.iter().zip(&mut rounds) {
+                    let trace = info.data.read().clone();
+                    if trace.hops().is_empty() || *round == Some(trace.round()) {
+                        continue;
+                    }
+                    *round = Some(trace.round());
+                    let timestamp = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_nanos();
+                    match influx.send(&render_points(&info.target_hostname, &trace, timestamp)) {
+                        Ok(()) if failing => {
+                            failing = false;
+                            if log {
+                                eprintln!("influx write recovered");
+                            }
+                        }
+                        Err(err) if !failing => {
+                            failing = true;
+                            if log {
+                                eprintln!("influx write failed: {err}");
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                sleep(ROUND_POLL_INTERVAL);
+            }
+        })?;
+    Ok(())
+}
+
+/// Resolve the `host:port` of an `InfluxDB` endpoint.
+fn resolve(addr: &str) -> anyhow::Result<SocketAddr> {
+    addr.to_socket_addrs()
+        .map_err(|err| anyhow!("invalid influx address {}: {}", addr, err))?
+        .next()
+        .ok_or_else(|| anyhow!("invalid influx address {}: no address found", addr))
+}
+
+/// Render a point of every hop of a trace at `timestamp` (ns).
+///
+/// Each point is tagged with the `target`, `ttl` and `host`, the address of the most recent response from the hop or
+/// `unknown` if none, and has the fields `loss_pct` and the integers `sent` and `recv` and, for hops which have
+/// responded, `avg_ms`, `jitter_ms` and `rtt_ms`.  The `rtt_ms` field is only present for hops for which the most recent probe
+/// received a response, as for the Prometheus metrics.
+fn render_points(target: &str, trace: &Trace, timestamp: u128) -> Vec<String> {
+    let target = escape_tag(target);
+    trace
+        .hops()
+        .iter()
+        .map(|hop| {
+            let mut fields = vec![
+                format!("loss_pct={:.3}", hop.loss_pct()),
+                format!("sent={}i", hop.total_sent()),
+                format!("recv={}i", hop.total_recv()),
+            ];
+            if hop.total_recv() > 0 {
+                fields.push(format!("avg_ms={:.3}", hop.avg_ms()));
+                fields.push(format!("jitter_ms={:.3}", hop.jitter_ms()));
+            }
+            if let Some(last) = hop.last_ms().filter(|_| hop.is_responding()) {
+                fields.push(format!("rtt_ms={last:.3}"));
+            }
+            format!(
+                "{MEASUREMENT},target={target},ttl={},host={} {} {timestamp}",
+                hop.ttl(),
+                hop_host(hop),
+                fields.join(",")
+            )
+        })
+        .collect()
+}
+
+/// The address of the most recent response from the hop as a tag value, `unknown` if none.
+fn hop_host(hop: &Hop) -> String {
+    hop.last_addr().map_or_else(
+        || String::from("unknown"),
+        |addr| escape_tag(&addr.to_string()),
+    )
+}
+
+/// Escape the characters which are not permitted unescaped within a tag value of line protocol.
+fn escape_tag(value: &str) -> String {
+    value
+        .chars()
+        .flat_map(|c| match c {
+            ',' | '=' | ' ' | '\\' => vec!['\\', c],
+            c => vec![c],
+        })
+        .collect()
+}
+
+/// Post `body` to the `HTTP` write endpoint at `path` of `authority`, which resolved to `addr`.
+///
+/// Any status other than `2xx` is an error.
+fn http_write(addr: SocketAddr, authority: &str, path: &str, body: &str) -> anyhow::Result<()> {
+    let mut stream = TcpStream::connect_timeout(&addr, HTTP_TIMEOUT)?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    write!(
+        stream,
+        "POST {path} HTTP/1.1\r\nHost: {authority}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        _ => Err(anyhow!("http write failed: {}", status_line.trim_end())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// A trace of a round in which the first hop responded after 10ms and the probe of the second hop was lost.
+    fn trace() -> Trace {
+        crate::test_util::trace(&[&[Some((1, 10)), None]])
+    }
+
+    #[test]
+    fn test_render_points() {
+        assert_eq!(
+            vec![
+                "trippy_hop,target=example.com,ttl=1,host=10.0.0.1 loss_pct=0.000,sent=1i,recv=1i,avg_ms=10.000,jitter_ms=0.000,rtt_ms=10.000 1000",
+                "trippy_hop,target=example.com,ttl=2,host=unknown loss_pct=100.000,sent=1i,recv=0i 1000",
+            ],
+            render_points("example.com", &trace(), 1000)
+        );
+    }
+
+    #[test]
+    fn test_render_points_escaped_target() {
+        let points = render_points("a b,c=d\\e", &trace(), 1000);
+        assert!(points[0].starts_with("trippy_hop,target=a\\ b\\,c\\=d\\\\e,ttl=1,"));
+    }
+
+    #[test]
+    fn test_escape_tag() {
+        assert_eq!("example.com", escape_tag("example.com"));
+        assert_eq!("fe80::1", escape_tag("fe80::1"));
+        assert_eq!("a\\ b\\,c\\=d\\\\e", escape_tag("a b,c=d\\e"));
+    }
+
+    #[test]
+    fn test_parse_udp_addr() {
+        let addr = InfluxAddr::from_str("udp://127.0.0.1:8089/").unwrap();
+        assert!(matches!(addr, InfluxAddr::Udp(addr) if addr == "127.0.0.1:8089"));
+    }
+
+    #[test]
+    fn test_parse_http_addr() {
+        let addr = InfluxAddr::from_str("http://127.0.0.1:8086/write?db=trippy").unwrap();
+        assert!(matches!(
+            addr,
+            InfluxAddr::Http { authority, path } if authority == "127.0.0.1:8086" && path == "/write?db=trippy"
+        ));
+        let addr = InfluxAddr::from_str("http://localhost:8086").unwrap();
+        assert!(matches!(
+            addr,
+            InfluxAddr::Http { authority, path } if authority == "localhost:8086" && path == "/write"
+        ));
+    }
+
+    #[test]
+    fn test_parse_file_addr() {
+        let addr = InfluxAddr::from_str("file:points.lp").unwrap();
+        assert!(matches!(addr, InfluxAddr::File(path) if path == Path::new("points.lp")));
+    }
+
+    #[test]
+    fn test_parse_invalid_addr() {
+        assert!(InfluxAddr::from_str("http:///write").is_err());
+        assert!(InfluxAddr::from_str("file:").is_err());
+        assert!(InfluxAddr::from_str("tcp://127.0.0.1:8089").is_err());
+        assert!(InfluxAddr::from_str("127.0.0.1:8089").is_err());
+    }
+}
@@ -0,0 +1,82 @@
+use comfy_table::presets::UTF8_FULL;
+use comfy_table::{ContentArrangement, Table};
+use pnet::datalink::NetworkInterface;
+use serde::Serialize;
+
+/// A network interface of the local host, as listed by `--list-interfaces`.
+#[derive(Debug, Serialize)]
+struct Interface {
+    name: String,
+    index: u32,
+    status: &'static str,
+    loopback: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mac: Option<String>,
+    addrs: Vec<String>,
+}
+
+impl From<&NetworkInterface> for Interface {
+    fn from(interface: &NetworkInterface) -> Self {
+        let status = if !interface.is_up() {
+            "down"
+        } else if interface.is_running() {
+            "up"
+        } else {
+            "no-carrier"
+        };
+        Self {
+            name: interface.name.clone(),
+            index: interface.index,
+            status,
+            loopback: interface.is_loopback(),
+            mac: interface
+                .mac
+                .filter(|mac| !mac.is_zero())
+                .map(|mac| mac.to_string()),
+            addrs: interface.ips.iter().map(ToString::to_string).collect(),
+        }
+    }
+}
+
+/// The names of the network interfaces of the local host, which may be given to `--interface`.
+pub fn interface_names() -> Vec<String> {
+    pnet::datalink::interfaces()
+        .into_iter()
+        .map(|interface| interface.name)
+        .collect()
+}
+
+/// Print every network interface of the local host with its status and addresses, as a table or as JSON.
+///
+/// An interface is `up` if it is administratively up and running, `no-carrier` if it is up but not running, such as a
+/// cable is unplugged, and otherwise `down`.
+pub fn run_list_interfaces(json: bool) -> anyhow::Result<()> {
+    let interfaces: Vec<_> = pnet::datalink::interfaces()
+        .iter()
+        .map(Interface::from)
+        .collect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&interfaces)?);
+    } else {
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(vec!["Interface", "Status", "MAC", "Addresses"]);
+        for interface in &interfaces {
+            let status = if interface.loopback {
+                format!("{} (loopback)", interface.status)
+            } else {
+                interface.status.to_string()
+            };
+            table.add_row(vec![
+                interface.name.clone(),
+                status,
+                interface.mac.clone().unwrap_or_default(),
+                interface.addrs.join("\n"),
+            ]);
+        }
+        println!("{table}");
+    }
+    Ok(())
+}
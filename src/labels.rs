@@ -0,0 +1,154 @@
+use anyhow::anyhow;
+use pnet::ipnetwork::IpNetwork;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A cheaply cloneable table of human readable labels for hosts, by address, network or AS, read from a local file.
+#[derive(Clone)]
+pub struct HostLabels {
+    inner: Arc<HostLabelsInner>,
+}
+
+struct HostLabelsInner {
+    addrs: HashMap<IpAddr, String>,
+    networks: Vec<(IpNetwork, String)>,
+    asns: HashMap<String, String>,
+}
+
+impl HostLabels {
+    /// Load the table of labels at `path`.
+    ///
+    /// The table is read into memory once and holds one `key=label` line per label, where the key is an address
+    /// (`10.0.0.1`), a network (`10.0.0.0/8`) or an AS (`AS15169`).  Blank lines and lines starting with `#` are ignored,
+    /// any other line which is not a valid label is an error.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let data = fs::read_to_string(path)
+            .map_err(|err| anyhow!("failed to read labels {}: {}", path.display(), err))?;
+        let inner = parse_labels(&data)
+            .map_err(|err| anyhow!("invalid labels {}: {}", path.display(), err))?;
+        Ok(Self {
+            inner: Arc::new(inner),
+        })
+    }
+
+    /// The label of `addr`, matched by address and otherwise by the most specific network which contains it.
+    pub fn addr_label(&self, addr: IpAddr) -> Option<&str> {
+        self.inner
+            .addrs
+            .get(&addr)
+            .or_else(|| {
+                self.inner
+                    .networks
+                    .iter()
+                    .filter(|(network, _)| network.contains(addr))
+                    .max_by_key(|(network, _)| network.prefix())
+                    .map(|(_, label)| label)
+            })
+            .map(String::as_str)
+    }
+
+    /// The label of the AS with number `asn`, if it is in the table.
+    pub fn asn_label(&self, asn: &str) -> Option<&str> {
+        self.inner.asns.get(asn).map(String::as_str)
+    }
+}
+
+impl Debug for HostLabels {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HostLabels")
+            .field("addrs", &self.inner.addrs.len())
+            .field("networks", &self.inner.networks.len())
+            .field("asns", &self.inner.asns.len())
+            .finish()
+    }
+}
+
+fn parse_labels(data: &str) -> anyhow::Result<HostLabelsInner> {
+    let mut labels = HostLabelsInner {
+        addrs: HashMap::new(),
+        networks: vec![],
+        asns: HashMap::new(),
+    };
+    for (i, line) in data.lines().map(str::trim).enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line_number = i + 1;
+        let (key, label) = line
+            .split_once('=')
+            .map(|(key, label)| (key.trim(), label.trim()))
+            .filter(|(_, label)| !label.is_empty())
+            .ok_or_else(|| anyhow!("line {} is not key=label: {}", line_number, line))?;
+        let label = label.to_string();
+        if let Some(asn) = key.strip_prefix("AS").or_else(|| key.strip_prefix("as")) {
+            if asn.is_empty() || !asn.chars().all(|c| c.is_ascii_digit()) {
+                return Err(anyhow!("line {} has an invalid AS: {}", line_number, key));
+            }
+            labels.asns.insert(asn.to_string(), label);
+        } else if let Ok(addr) = key.parse::<IpAddr>() {
+            labels.addrs.insert(addr, label);
+        } else if let Ok(network) = key.parse::<IpNetwork>() {
+            labels.networks.push((network, label));
+        } else {
+            return Err(anyhow!(
+                "line {} has an invalid address, network or AS: {}",
+                line_number,
+                key
+            ));
+        }
+    }
+    Ok(labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(data: &str) -> HostLabels {
+        HostLabels {
+            inner: Arc::new(parse_labels(data).unwrap()),
+        }
+    }
+
+    fn addr(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_addr_label() {
+        let labels = labels(
+            "# office\n\n10.0.0.0/8 = corp\n10.1.0.0/16=lab\n10.1.2.3 = router\nfd00::/8 = ula\n",
+        );
+        assert_eq!(Some("router"), labels.addr_label(addr("10.1.2.3")));
+        assert_eq!(Some("lab"), labels.addr_label(addr("10.1.2.4")));
+        assert_eq!(Some("corp"), labels.addr_label(addr("10.2.0.1")));
+        assert_eq!(Some("ula"), labels.addr_label(addr("fd00::1")));
+        assert_eq!(None, labels.addr_label(addr("192.168.0.1")));
+    }
+
+    #[test]
+    fn test_asn_label() {
+        let labels = labels("AS15169 = google\nas13335=cloudflare\n");
+        assert_eq!(Some("google"), labels.asn_label("15169"));
+        assert_eq!(Some("cloudflare"), labels.asn_label("13335"));
+        assert_eq!(None, labels.asn_label("AS15169"));
+        assert_eq!(None, labels.asn_label("1"));
+    }
+
+    #[test]
+    fn test_invalid_labels() {
+        let error = |data: &str| parse_labels(data).err().unwrap().to_string();
+        assert_eq!("line 2 is not key=label: 10.0.0.1", error("\n10.0.0.1\n"));
+        assert_eq!("line 1 is not key=label: 10.0.0.1 =", error("10.0.0.1 ="));
+        assert_eq!("line 1 has an invalid AS: ASx1", error("ASx1 = x"));
+        assert_eq!("line 1 has an invalid AS: AS", error("AS = x"));
+        assert_eq!(
+            "line 1 has an invalid address, network or AS: example.com",
+            error("example.com = x")
+        );
+    }
+}
@@ -10,164 +10,1021 @@
 )]
 #![forbid(unsafe_code)]
 
-use crate::backend::Trace;
+use crate::alert::{run_alerts, AlertThresholds};
+use crate::anonymize::Anonymizer;
+use crate::asn::AsnNames;
+use crate::backend::{Trace, TraceConfig};
+use crate::baseline::Baseline;
 use crate::caps::{drop_caps, ensure_caps};
 use crate::config::{
-    validate_dns, validate_grace_duration, validate_max_inflight, validate_multi,
-    validate_packet_size, validate_read_timeout, validate_report_cycles, validate_round_duration,
-    validate_source_port, validate_ttl, validate_tui_refresh_rate, Mode, TraceProtocol,
+    default_config_dir, parse_payload_hex, validate_adaptive_grace, validate_adaptive_rate,
+    validate_alert_loss_threshold, validate_anonymize, validate_asn_names, validate_baseline,
+    validate_compare_protocols, validate_discover_mtu, validate_dns, validate_dns_endpoint,
+    validate_dns_resolve_all, validate_dont_fragment, validate_ewma_alpha, validate_fragment,
+    validate_grace_duration, validate_histogram_buckets, validate_icmp_id,
+    validate_icmp_socket_mode, validate_initial_sequence, validate_interface,
+    validate_loss_thresholds, validate_markdown_rich, validate_max_flows,
+    validate_max_hostname_width, validate_max_inflight, validate_max_pps,
+    validate_max_samples_unbounded, validate_multi, validate_multipath_strategy, validate_no_dns,
+    validate_output, validate_packet_size, validate_payload_hex, validate_percentiles,
+    validate_pin_hop, validate_probe_timeout, validate_probes_per_round, validate_progress,
+    validate_proxy, validate_read_timeout, validate_replay, validate_report_columns,
+    validate_report_cycles, validate_report_template, validate_require_reachable,
+    validate_reresolve, validate_round_duration, validate_rtt_precision, validate_show_clock_skew,
+    validate_source_address, validate_source_port, validate_state_file, validate_stats_window,
+    validate_tcp_dest_ports, validate_tcp_flags, validate_tos, validate_trace_description,
+    validate_ttl, validate_ttl_stride, validate_tui_history_width, validate_tui_max_rounds,
+    validate_tui_refresh_rate, validate_tui_stale_rounds, validate_udp_dest_port,
+    validate_unknown_hops, AddressMode, IcmpSocketMode, MarkdownRich, Mode, MultipathStrategy,
+    RttFormat, ScheduleStrategy, TcpFlag, TraceProtocol, UdpDestPortMode, UnknownHops,
+    DEFAULT_HISTOGRAM_BUCKETS_MS, DEFAULT_UDP_DEST_PORT_BASE, DNS_CACHE_FILE,
+    FIXED_TTL_MAX_ROUND_DURATION, MAX_RANDOM_SEQUENCE, MIN_RANDOM_SEQUENCE,
 };
-use crate::dns::{DnsResolver, DnsResolverConfig};
+use crate::diff::{run_diff, DiffThresholds};
+use crate::dns::{DnsCacheConfig, DnsEndpoint, DnsResolver, DnsResolverConfig};
+use crate::dot::{run_dot_export, write_dot};
+use crate::dry_run::run_dry_run;
 use crate::frontend::{TuiConfig, TuiTraceInfo};
+use crate::geoip::GeoIpLookup;
+use crate::histogram::{run_histogram_export, write_histograms};
+use crate::influx::{run_influx, Influx, InfluxAddr};
+use crate::interfaces::run_list_interfaces;
+use crate::labels::HostLabels;
+use crate::metrics::{bind_metrics, run_metrics};
+use crate::mtu::{run_discover_mtu, MtuOptions};
+use crate::progress::Progress;
+use crate::proxy::{run_proxy_probe, ProxyAddr};
+use crate::publish::{run_publisher, run_round_log, PublishAddr, Publisher, RoundLogTarget};
+use crate::replay::replay_traces;
 use crate::report::{
-    run_report_csv, run_report_json, run_report_stream, run_report_table_markdown,
-    run_report_table_pretty,
+    print_json_schema, report_json, run_report_csv, run_report_csv_header, run_report_json,
+    run_report_json_stream, run_report_stream, run_report_table_markdown, run_report_table_pretty,
+    wait_for_round, write_report_file, ReportOptions,
 };
-use clap::Parser;
+use crate::reresolve::run_reresolve;
+use crate::signal::{install_shutdown_handler, shutdown_requested};
+use crate::state::{load_state, run_state_export, write_state};
+use crate::statsd::{run_statsd, Statsd};
+use crate::template::ReportTemplate;
+use crate::theme::Theme;
+use crate::websocket::{bind_websocket, run_websocket};
+use anyhow::anyhow;
 use config::Args;
 use parking_lot::RwLock;
+use rand::Rng;
+use std::io;
+use std::io::Write;
 use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::sync::Arc;
 use std::thread;
-use trippy::tracing::{TracerChannel, TracerConfig};
+use std::time::Duration;
+use trippy::tracing::{
+    AdaptiveRate, PauseHandle, PcapWriter, RateLimiter, TargetHandle, TracerChannel, TracerConfig,
+    TracerConfigParams, UdpDestPort,
+};
 
+mod alert;
+mod anonymize;
+mod asn;
 mod backend;
+mod baseline;
 mod caps;
 mod config;
+mod diff;
 mod dns;
+mod dot;
+mod dry_run;
 mod frontend;
+mod geoip;
+mod histogram;
+mod influx;
+mod interfaces;
+mod labels;
+mod metrics;
+mod mtu;
+mod progress;
+mod proxy;
+mod publish;
+mod replay;
 mod report;
+mod reresolve;
+mod signal;
+mod state;
+mod statsd;
+mod template;
+#[cfg(test)]
+mod test_util;
+mod theme;
+mod websocket;
+
+/// The exit code when `--require-reachable` is given and a target did not respond in the required number of rounds.
+///
+/// Other exit codes are 0 on success, 1 on a runtime error and 255 (-1) on invalid configuration.
+const EXIT_UNREACHABLE: i32 = 2;
 
 #[allow(clippy::too_many_lines)]
 fn main() -> anyhow::Result<()> {
     let pid = u16::try_from(std::process::id() % u32::from(u16::MAX))?;
-    let args = Args::parse();
-    let targets = args.targets;
+    let args = Args::load()?;
+    if args.print_json_schema {
+        print_json_schema();
+        return Ok(());
+    }
+    if args.list_interfaces {
+        return run_list_interfaces(matches!(args.mode, Mode::Json));
+    }
+    if let [baseline, current] = args.diff.as_slice() {
+        let thresholds = DiffThresholds {
+            rtt_ms: humantime::parse_duration(&args.diff_rtt_threshold)?.as_secs_f64() * 1000_f64,
+            loss_pct: args.diff_loss_threshold,
+        };
+        let json = matches!(args.mode, Mode::Json);
+        return run_diff(Path::new(baseline), Path::new(current), thresholds, json);
+    }
+    let targets = args.targets.clone();
+    let lookup_as_info = args.dns_lookup_as_info && !args.no_dns;
+    let address_mode = if args.no_dns {
+        AddressMode::IP
+    } else {
+        args.tui_address_mode.unwrap_or(AddressMode::Host)
+    };
     let protocol = match args.protocol {
         TraceProtocol::Icmp => trippy::tracing::TracerProtocol::Icmp,
+        TraceProtocol::IcmpTimestamp => trippy::tracing::TracerProtocol::IcmpTimestamp,
         TraceProtocol::Udp => trippy::tracing::TracerProtocol::Udp,
         TraceProtocol::Tcp => trippy::tracing::TracerProtocol::Tcp,
     };
+    let protocols = if args.compare_protocols {
+        vec![
+            trippy::tracing::TracerProtocol::Icmp,
+            trippy::tracing::TracerProtocol::Udp,
+            trippy::tracing::TracerProtocol::Tcp,
+        ]
+    } else {
+        vec![protocol]
+    };
+    let icmp_socket_mode = match args.icmp_socket_mode {
+        IcmpSocketMode::Raw => trippy::tracing::IcmpSocketMode::Raw,
+        IcmpSocketMode::Dgram => trippy::tracing::IcmpSocketMode::Dgram,
+        IcmpSocketMode::Auto => trippy::tracing::IcmpSocketMode::Auto,
+    };
+    let multipath_strategy = match args.multipath_strategy {
+        MultipathStrategy::Classic => trippy::tracing::MultipathStrategy::Classic,
+        MultipathStrategy::Paris => trippy::tracing::MultipathStrategy::Paris,
+    };
+    let schedule_strategy = match args.schedule {
+        ScheduleStrategy::Sync => trippy::tracing::ScheduleStrategy::Sync,
+        ScheduleStrategy::RoundRobin => trippy::tracing::ScheduleStrategy::RoundRobin,
+    };
+    let tcp_flags: Vec<_> = args
+        .tcp_flags
+        .iter()
+        .map(|flag| match flag {
+            TcpFlag::Syn => trippy::tracing::TcpFlag::Syn,
+            TcpFlag::Ack => trippy::tracing::TcpFlag::Ack,
+            TcpFlag::Fin => trippy::tracing::TcpFlag::Fin,
+            TcpFlag::Psh => trippy::tracing::TcpFlag::Psh,
+            TcpFlag::Urg => trippy::tracing::TcpFlag::Urg,
+        })
+        .collect();
+    let initial_sequence = if args.random_sequence {
+        rand::thread_rng().gen_range(MIN_RANDOM_SEQUENCE..=MAX_RANDOM_SEQUENCE)
+    } else {
+        args.initial_sequence
+    };
+    let (initial_sequence, udp_dest_port) = match args
+        .udp_dest_port_mode
+        .unwrap_or_else(|| UdpDestPortMode::default_for(args.multipath_strategy))
+    {
+        UdpDestPortMode::Increment => (
+            args.udp_dest_port_base.unwrap_or(initial_sequence),
+            UdpDestPort::Sequence,
+        ),
+        UdpDestPortMode::Fixed => (
+            initial_sequence,
+            UdpDestPort::Fixed(
+                args.udp_dest_port_base
+                    .unwrap_or(DEFAULT_UDP_DEST_PORT_BASE),
+            ),
+        ),
+    };
     let read_timeout = humantime::parse_duration(&args.read_timeout)?;
-    let min_round_duration = humantime::parse_duration(&args.min_round_duration)?;
-    let max_round_duration = humantime::parse_duration(&args.max_round_duration)?;
+    let probe_timeout = args
+        .probe_timeout
+        .as_deref()
+        .map(humantime::parse_duration)
+        .transpose()?;
+    let (first_ttl, max_ttl) = args
+        .fixed_ttl
+        .map_or((args.first_ttl, args.max_ttl), |ttl| (ttl, ttl));
+    let mut min_round_duration = humantime::parse_duration(&args.min_round_duration)?;
+    let mut max_round_duration = humantime::parse_duration(&args.max_round_duration)?;
+    if args.fixed_ttl.is_some() {
+        min_round_duration = min_round_duration.min(FIXED_TTL_MAX_ROUND_DURATION);
+        max_round_duration = max_round_duration.min(FIXED_TTL_MAX_ROUND_DURATION);
+    }
     let grace_duration = humantime::parse_duration(&args.grace_duration)?;
-    let source_port = args.source_port.unwrap_or_else(|| pid.max(1024));
+    let reresolve = args
+        .reresolve
+        .as_deref()
+        .map(humantime::parse_duration)
+        .transpose()?;
+    let adaptive_grace = args
+        .adaptive_grace
+        .as_deref()
+        .map(humantime::parse_duration)
+        .transpose()?;
+    let trace_identifier = args.icmp_id.unwrap_or(pid);
+    let zero_loss_grace = args
+        .zero_loss_grace
+        .as_deref()
+        .map(humantime::parse_duration)
+        .transpose()?
+        .unwrap_or(grace_duration);
+    let payload_hex = args
+        .payload_hex
+        .as_deref()
+        .map(parse_payload_hex)
+        .transpose()?;
+    let source_port = args.source_port.clone().unwrap_or_else(|| {
+        let port = pid.max(1024);
+        port..=port
+    });
     let tui_refresh_rate = humantime::parse_duration(&args.tui_refresh_rate)?;
+    let alert_thresholds = AlertThresholds {
+        loss_pct: args.alert_loss_threshold,
+        rtt_ms: args
+            .alert_rtt_threshold
+            .as_deref()
+            .map(humantime::parse_duration)
+            .transpose()?
+            .map(|rtt| rtt.as_secs_f64() * 1000_f64),
+    };
+    let stats_window = args
+        .stats_window
+        .as_deref()
+        .map(humantime::parse_duration)
+        .transpose()?;
     let report_cycles = args.report_cycles;
+    let max_samples = if args.max_samples_unbounded {
+        report_cycles * usize::from(args.probes_per_round)
+    } else {
+        args.tui_max_samples
+    };
     let dns_timeout = humantime::parse_duration(&args.dns_timeout)?;
+    let dns_cache = match args.dns_cache_ttl.as_deref() {
+        Some(ttl) if !args.no_dns => {
+            let ttl = humantime::parse_duration(ttl)?;
+            let dir = default_config_dir().ok_or_else(|| {
+                anyhow!("unable to determine the config directory for the DNS cache")
+            })?;
+            Some(DnsCacheConfig::new(dir.join(DNS_CACHE_FILE), ttl))
+        }
+        _ => None,
+    };
+    let histogram_buckets = if args.histogram_buckets.is_empty() {
+        DEFAULT_HISTOGRAM_BUCKETS_MS
+            .iter()
+            .map(|&ms| Duration::from_millis(ms))
+            .collect()
+    } else {
+        args.histogram_buckets
+            .iter()
+            .map(|bucket| {
+                humantime::parse_duration(bucket)
+                    .map_err(|err| anyhow!("invalid histogram bucket {}: {}", bucket, err))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+    };
     let max_rounds = match args.mode {
-        Mode::Stream | Mode::Tui => None,
+        Mode::Tui => args.tui_max_rounds,
+        Mode::Stream | Mode::JsonStream | Mode::Alert => None,
         Mode::Pretty | Mode::Markdown | Mode::Csv | Mode::Json => Some(report_cycles),
     };
     validate_multi(args.mode, args.protocol, &targets);
-    validate_ttl(args.first_ttl, args.max_ttl);
+    validate_compare_protocols(
+        args.compare_protocols,
+        args.mode,
+        &targets,
+        args.icmp_socket_mode,
+        args.proxy.as_deref(),
+    );
+    validate_output(args.mode, args.output.as_deref());
+    validate_ttl(first_ttl, max_ttl);
+    validate_ttl_stride(args.ttl_stride, first_ttl, max_ttl);
+    if !args.random_sequence {
+        validate_initial_sequence(initial_sequence, first_ttl, max_ttl, args.probes_per_round);
+    }
     validate_max_inflight(args.max_inflight);
+    validate_probes_per_round(args.probes_per_round, args.discover_mtu);
+    validate_max_pps(args.max_pps);
+    validate_adaptive_rate(
+        args.adaptive_loss_threshold,
+        args.adaptive_recover_threshold,
+    );
     validate_read_timeout(read_timeout);
     validate_round_duration(min_round_duration, max_round_duration);
+    validate_probe_timeout(probe_timeout, min_round_duration);
     validate_grace_duration(grace_duration);
-    validate_packet_size(args.packet_size);
-    validate_source_port(source_port);
+    validate_adaptive_grace(adaptive_grace, grace_duration);
+    validate_ewma_alpha(args.ewma_alpha);
+    validate_alert_loss_threshold(args.alert_loss_threshold);
+    validate_stats_window(stats_window, args.mode);
+    validate_max_samples_unbounded(
+        args.max_samples_unbounded,
+        args.mode,
+        report_cycles,
+        args.probes_per_round,
+    );
+    validate_state_file(args.state_file.as_deref(), args.mode);
+    validate_baseline(args.baseline.as_deref(), args.mode);
+    validate_show_clock_skew(args.show_clock_skew, args.protocol, args.mode);
+    validate_anonymize(&args.anonymize, args.discover_mtu, args.replay.is_some());
+    validate_reresolve(reresolve, args.proxy.as_deref());
+    validate_packet_size(args.packet_size, args.fragment);
+    validate_fragment(
+        args.fragment,
+        args.packet_size,
+        args.protocol,
+        args.icmp_socket_mode,
+    );
+    validate_dont_fragment(args.dont_fragment, args.fragment, args.icmp_socket_mode);
+    validate_payload_hex(payload_hex.as_deref());
+    validate_multipath_strategy(args.multipath_strategy, args.protocol, args.packet_size);
+    validate_max_flows(args.max_flows, args.multipath_strategy);
+    validate_udp_dest_port(
+        args.udp_dest_port_mode,
+        args.udp_dest_port_base,
+        args.protocol,
+        args.multipath_strategy,
+        args.max_flows,
+        args.packet_size,
+    );
+    validate_tcp_dest_ports(&args.tcp_dest_ports, args.protocol, args.multipath_strategy);
+    validate_tcp_flags(&args.tcp_flags, args.protocol);
+    validate_source_port(&source_port, args.protocol, args.multipath_strategy);
     validate_tui_refresh_rate(tui_refresh_rate);
+    validate_tui_max_rounds(args.tui_max_rounds);
+    validate_max_hostname_width(args.max_hostname_width);
+    validate_tui_history_width(args.tui_history_width, args.tui_max_samples);
+    validate_tui_stale_rounds(args.tui_stale_rounds);
+    validate_loss_thresholds(args.loss_warn_threshold, args.loss_critical_threshold);
+    validate_pin_hop(&args.pin_hop);
+    validate_markdown_rich(args.markdown_rich, args.mode);
+    validate_unknown_hops(
+        args.unknown_hop.as_deref(),
+        args.collapse_unresponsive,
+        args.mode,
+    );
     validate_report_cycles(args.report_cycles);
-    validate_dns(args.dns_resolve_method, args.dns_lookup_as_info);
-    let resolver =
-        DnsResolver::start(DnsResolverConfig::new(args.dns_resolve_method, dns_timeout))?;
-    ensure_caps()?;
-    let traces: Vec<_> = targets
+    validate_rtt_precision(args.rtt_precision);
+    validate_progress(args.progress, args.mode);
+    validate_require_reachable(args.require_reachable, args.mode, args.report_cycles);
+    validate_no_dns(
+        args.no_dns,
+        &targets,
+        args.tui_address_mode,
+        args.dns_lookup_as_info,
+    );
+    validate_asn_names(args.asn_names.as_deref(), args.dns_lookup_as_info);
+    validate_report_columns(
+        &args.report_columns,
+        lookup_as_info,
+        args.geoip_mmdb.is_some(),
+        args.labels.is_some(),
+    );
+    validate_report_template(args.report_template.as_deref(), args.mode);
+    validate_trace_description(args.trace_description.as_deref(), args.mode);
+    validate_dns(args.dns_resolve_method, lookup_as_info);
+    validate_dns_endpoint(args.dns_resolve_method, args.dns_endpoint.as_deref());
+    validate_dns_resolve_all(args.dns_resolve_method, args.dns_resolve_all);
+    validate_interface(args.interface.as_deref());
+    validate_tos(args.tos);
+    validate_histogram_buckets(&histogram_buckets);
+    validate_percentiles(&args.percentiles, args.tui_show_percentiles);
+    let tui_percentiles = if args.tui_show_percentiles {
+        args.percentiles.clone()
+    } else {
+        vec![]
+    };
+    validate_proxy(args.proxy.as_deref(), args.protocol);
+    validate_icmp_id(args.icmp_id, args.protocol, &targets);
+    validate_discover_mtu(args.discover_mtu, args.protocol, args.proxy.as_deref());
+    validate_icmp_socket_mode(args.icmp_socket_mode, args.protocol, args.discover_mtu);
+    let rtt_format = RttFormat::new(args.rtt_unit, args.rtt_precision);
+    let publish_addr = args
+        .publish
+        .as_deref()
+        .map(str::parse::<PublishAddr>)
+        .transpose()?;
+    let influx_addr = args
+        .influx
+        .as_deref()
+        .map(str::parse::<InfluxAddr>)
+        .transpose()?;
+    let round_log = args
+        .columns_as_json
+        .as_deref()
+        .map(str::parse::<RoundLogTarget>)
+        .transpose()?;
+    let proxy = args
+        .proxy
+        .as_deref()
+        .map(str::parse::<ProxyAddr>)
+        .transpose()?;
+    let asn_names = args
+        .asn_names
+        .as_deref()
+        .map(|path| AsnNames::open(Path::new(path)))
+        .transpose()?;
+    let labels = args
+        .labels
+        .as_deref()
+        .map(|path| HostLabels::open(Path::new(path)))
+        .transpose()?;
+    let report_template = args
+        .report_template
+        .as_deref()
+        .map(|path| ReportTemplate::open(Path::new(path)))
+        .transpose()?;
+    let dns_endpoint = args
+        .dns_endpoint
+        .as_deref()
+        .map(str::parse::<DnsEndpoint>)
+        .transpose()?;
+    let resolver = DnsResolver::start(DnsResolverConfig {
+        resolve_method: args.dns_resolve_method,
+        timeout: dns_timeout,
+        cache: dns_cache,
+        disabled: args.no_dns,
+        asn_names,
+        labels,
+        endpoint: dns_endpoint,
+        resolve_all: args.dns_resolve_all,
+    })?;
+    let geoip = args
+        .geoip_mmdb
+        .as_deref()
+        .map(|path| GeoIpLookup::open(Path::new(path)))
+        .transpose()?;
+    let theme = match args.tui_theme_file.as_deref() {
+        Some(path) => Theme::named(args.tui_theme).with_file(Path::new(path))?,
+        None => Theme::named(args.tui_theme),
+    }
+    .with_loss_thresholds(args.loss_warn_threshold, args.loss_critical_threshold);
+    let baseline = args
+        .baseline
+        .as_deref()
+        .map(|path| Baseline::read(Path::new(path)))
+        .transpose()?;
+    if let Some(path) = &args.replay {
+        validate_replay(args.mode);
+        return run_replay(
+            Path::new(path),
+            &args,
+            tui_refresh_rate,
+            theme,
+            geoip,
+            baseline,
+        );
+    }
+    let raw_required = !matches!(
+        (args.protocol, args.icmp_socket_mode),
+        (
+            TraceProtocol::Icmp,
+            IcmpSocketMode::Dgram | IcmpSocketMode::Auto
+        )
+    ) || args.discover_mtu
+        || args.fragment
+        || args.dont_fragment.is_some()
+        || args.compare_protocols;
+    if !args.dry_run {
+        ensure_caps(raw_required)?;
+    }
+    install_shutdown_handler()?;
+    let trace_protocols: Vec<_> = targets
         .iter()
         .map(|target| {
-            let target_addr: IpAddr = resolver.lookup(target)?[0];
-            let trace_data = Arc::new(RwLock::new(Trace::new(args.tui_max_samples)));
-            Ok(TuiTraceInfo::new(
-                trace_data,
-                target.clone(),
-                target_addr,
-                protocol.to_string(),
-                args.first_ttl,
-                args.max_ttl,
+            Ok(match &proxy {
+                Some(proxy) => (target, proxy.host.clone(), proxy.addr.ip()),
+                None => (target, target.clone(), resolver.lookup(target)?[0]),
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?
+        .into_iter()
+        .flat_map(|(target, hostname, addr)| {
+            protocols
+                .iter()
+                .map(move |&protocol| (target, hostname.clone(), addr, protocol))
+        })
+        .collect();
+    let mut traces: Vec<_> = trace_protocols
+        .iter()
+        .map(|(_, target_hostname, target_addr, protocol)| {
+            let trace_data = Arc::new(RwLock::new(Trace::new(TraceConfig {
+                max_samples,
+                histogram_buckets: histogram_buckets.clone(),
+                percentiles: args.percentiles.clone(),
+                zero_loss_grace,
+                tcp_dest_ports: args.tcp_dest_ports.clone(),
+                ewma_alpha: args.ewma_alpha,
+                stats_window,
+                pinned_hops: args.pin_hop.iter().copied().collect(),
+            })));
+            TuiTraceInfo {
+                data: trace_data,
+                target_hostname: target_hostname.clone(),
+                target_addr: *target_addr,
+                protocol: protocol.to_string(),
+                first_ttl,
+                max_ttl,
+                ttl_stride: args.ttl_stride,
+                grace_duration,
+                min_round_duration,
+                tos: args.tos,
+                dont_fragment: args.dont_fragment,
+                description: args.trace_description.clone(),
+            }
+        })
+        .collect();
+    let target_addrs: Vec<_> = traces.iter().map(|info| info.target_addr).collect();
+    validate_source_address(args.source_address, &target_addrs);
+    if args.dry_run {
+        return run_dry_run(&traces, &args, matches!(args.mode, Mode::Json));
+    }
+    if args.discover_mtu {
+        return run_discover_mtu(
+            &traces,
+            &MtuOptions {
+                trace_identifier,
+                max_ttl,
+                probe_timeout: probe_timeout.unwrap_or(min_round_duration),
+                tos: args.tos,
+                interface: args.interface.as_deref(),
+                source_addr: args.source_address,
+            },
+            matches!(args.mode, Mode::Json),
+        );
+    }
+    let anonymizer = (!args.anonymize.is_empty()).then(|| {
+        Anonymizer::new(
+            args.anonymize.clone(),
+            args.anonymize_hops,
+            args.anonymize_strategy,
+        )
+    });
+    if let Some(anonymizer) = &anonymizer {
+        for info in &mut traces {
+            anonymizer.anonymize_target(info);
+        }
+    }
+    let state_file = args.state_file.as_deref().map(PathBuf::from);
+    if let Some(path) = &state_file {
+        load_state(path, &traces)?;
+    }
+    let rate_limiter = args.max_pps.map(RateLimiter::new);
+    let adaptive_rate = args.adaptive_rate.then(|| {
+        AdaptiveRate::new(
+            args.adaptive_loss_threshold,
+            args.adaptive_recover_threshold,
+        )
+    });
+    let pause = PauseHandle::new();
+    let pcap = args
+        .write_pcap
+        .as_deref()
+        .map(|path| PcapWriter::create(Path::new(path)))
+        .transpose()?;
+    let target_handles: Vec<_> = target_addrs
+        .iter()
+        .map(|&addr| reresolve.map(|_| TargetHandle::new(addr)))
+        .collect();
+    let backends = traces
+        .iter()
+        .zip(&trace_protocols)
+        .enumerate()
+        .map(|(i, (info, &(_, _, _, protocol)))| {
+            let tracer_config = TracerConfig::new(TracerConfigParams {
+                target_addr: target_addrs[i],
+                protocol,
+                multipath_strategy,
+                max_flows: args.max_flows,
+                max_rounds,
+                trace_identifier: trace_identifier.wrapping_add(i as u16),
+                first_ttl,
+                max_ttl,
+                ttl_stride: args.ttl_stride,
+                auto_ttl: args.auto_ttl,
                 grace_duration,
+                adaptive_grace,
+                max_inflight: args.max_inflight,
+                probes_per_round: args.probes_per_round,
+                schedule_strategy,
+                initial_sequence,
+                read_timeout,
+                probe_timeout,
                 min_round_duration,
-            ))
+                max_round_duration,
+                packet_size: args.packet_size,
+                fragment: args.fragment,
+                dont_fragment: args.dont_fragment,
+                payload_pattern: args.payload_pattern,
+                payload: payload_hex.clone(),
+                source_port: *source_port.start(),
+                source_port_count: source_port.end() - source_port.start() + 1,
+                udp_dest_port,
+                tcp_dest_ports: args.tcp_dest_ports.clone(),
+                tcp_flags: tcp_flags.clone(),
+                tos: args.tos,
+                interface: args.interface.clone(),
+                source_addr: args.source_address,
+                icmp_socket_mode,
+                rate_limiter: rate_limiter.clone(),
+                adaptive_rate,
+                pcap: pcap.clone(),
+                pause: matches!(args.mode, Mode::Tui).then(|| pause.clone()),
+                target: target_handles[i].clone(),
+            })?;
+            make_backend(tracer_config, info.data.clone(), anonymizer.clone())
         })
         .collect::<anyhow::Result<Vec<_>>>()?;
-    for (i, info) in traces.iter().enumerate() {
-        let tracer_config = TracerConfig::new(
-            info.target_addr,
-            protocol,
-            max_rounds,
-            pid + i as u16,
-            args.first_ttl,
-            args.max_ttl,
-            grace_duration,
-            args.max_inflight,
-            args.initial_sequence,
-            read_timeout,
+    let publisher = publish_addr.as_ref().map(Publisher::bind).transpose()?;
+    let round_log = round_log.as_ref().map(RoundLogTarget::open).transpose()?;
+    let metrics = args.metrics_addr.map(bind_metrics).transpose()?;
+    let statsd = args.statsd.as_deref().map(Statsd::connect).transpose()?;
+    let influx = influx_addr.as_ref().map(Influx::connect).transpose()?;
+    let websocket = args.ws_port.map(bind_websocket).transpose()?;
+    drop_caps()?;
+    if let Some(interval) = reresolve {
+        let targets = trace_protocols
+            .iter()
+            .zip(&target_handles)
+            .filter(|((target, ..), _)| target.parse::<IpAddr>().is_err())
+            .filter_map(|((target, ..), handle)| Some(((*target).clone(), handle.clone()?)))
+            .collect();
+        let log = !matches!(args.mode, Mode::Tui) && args.anonymize.is_empty();
+        run_reresolve(resolver.clone(), interval, targets, log)?;
+    }
+    if let Some(publisher) = publisher {
+        run_publisher(
+            publisher,
+            traces.clone(),
+            resolver.clone(),
+            geoip.clone(),
+            args.timestamp_format.clone(),
+        )?;
+    }
+    if let Some(websocket) = websocket {
+        run_websocket(
+            websocket,
+            traces.clone(),
+            resolver.clone(),
+            geoip.clone(),
+            args.timestamp_format.clone(),
+        )?;
+    }
+    if let Some(writer) = round_log {
+        run_round_log(
+            writer,
+            traces.clone(),
+            resolver.clone(),
+            geoip.clone(),
+            args.timestamp_format.clone(),
+        )?;
+    }
+    if let Some(metrics) = metrics {
+        run_metrics(metrics, traces.clone())?;
+    }
+    if let Some(statsd) = statsd {
+        run_statsd(statsd, traces.clone())?;
+    }
+    if let Some(influx) = influx {
+        run_influx(influx, traces.clone(), !matches!(args.mode, Mode::Tui))?;
+    }
+    if let Some(proxy) = proxy {
+        run_proxy_probe(
+            proxy,
+            targets[0].clone(),
             min_round_duration,
             max_round_duration,
-            args.packet_size,
-            args.payload_pattern,
-            source_port,
+            traces[0].data.clone(),
         )?;
-        start_backend(tracer_config, info.data.clone())?;
     }
-    drop_caps()?;
+    let export_histogram = args.export_histogram.as_deref().map(PathBuf::from);
+    if let Some(path) = &export_histogram {
+        run_histogram_export(path.clone(), traces.clone())?;
+    }
+    let export_dot = args.export_dot.as_deref().map(PathBuf::from);
+    if let Some(path) = &export_dot {
+        run_dot_export(
+            path.clone(),
+            traces.clone(),
+            resolver.clone(),
+            lookup_as_info,
+        )?;
+    }
+    if let Some(path) = &state_file {
+        run_state_export(path.clone(), traces.clone())?;
+    }
     match args.mode {
         Mode::Tui => {
-            let tui_config = TuiConfig::new(
-                tui_refresh_rate,
-                args.tui_preserve_screen,
-                args.tui_address_mode,
-                args.dns_lookup_as_info,
-                args.tui_max_addresses_per_hop,
-                args.tui_max_samples,
+            for backend in backends {
+                start_backend(backend)?;
+            }
+            let tui_config = TuiConfig {
+                refresh_rate: tui_refresh_rate,
+                preserve_screen: args.tui_preserve_screen,
+                address_mode,
+                lookup_as_info,
+                max_addrs: args.tui_max_addresses_per_hop,
+                max_hostname_width: args.max_hostname_width,
+                max_samples: args.tui_max_samples,
+                max_rounds: args.tui_max_rounds,
+                hop_window: args.tui_hop_window.clone(),
+                address_order: args.preserve_order,
+                show_ewma: args.tui_show_ewma,
+                show_clock_skew: args.show_clock_skew,
+                percentiles: tui_percentiles,
+                rtt_format,
+                history_width: args.tui_history_width,
+                stale_rounds: args.tui_stale_rounds,
+                unknown_hops: UnknownHops::new(
+                    args.unknown_hop.clone(),
+                    args.collapse_unresponsive,
+                ),
+                compare_protocols: args.compare_protocols,
+                theme,
+                geoip,
+                pause,
+                snapshot: args.snapshot.as_deref().map(PathBuf::from),
+                replay: None,
+                baseline,
+            };
+            frontend::run_frontend(traces.clone(), tui_config, resolver.clone())?;
+        }
+        Mode::Stream => {
+            for backend in backends {
+                start_backend(backend)?;
+            }
+            run_report_stream(
+                &traces[0].target_hostname,
+                traces[0].target_addr,
+                traces[0].min_round_duration,
+                rtt_format,
+                &traces[0].data,
             );
-            frontend::run_frontend(traces, tui_config, resolver)?;
         }
-        Mode::Stream => run_report_stream(
-            &traces[0].target_hostname,
-            traces[0].target_addr,
-            traces[0].min_round_duration,
-            &traces[0].data,
-        ),
-        Mode::Csv => run_report_csv(
-            &traces[0].target_hostname,
-            traces[0].target_addr,
-            report_cycles,
-            &resolver,
-            &traces[0].data,
-        ),
-        Mode::Json => run_report_json(
-            &traces[0].target_hostname,
-            traces[0].target_addr,
-            report_cycles,
-            &resolver,
-            &traces[0].data,
-        ),
-        Mode::Pretty => run_report_table_pretty(report_cycles, &resolver, &traces[0].data),
-        Mode::Markdown => run_report_table_markdown(report_cycles, &resolver, &traces[0].data),
+        Mode::JsonStream => {
+            for backend in backends {
+                start_backend(backend)?;
+            }
+            run_report_json_stream(&traces, &resolver, geoip.as_ref(), &args.timestamp_format)?;
+        }
+        Mode::Alert => {
+            for backend in backends {
+                start_backend(backend)?;
+            }
+            run_alerts(&traces, alert_thresholds, &args.timestamp_format)?;
+        }
+        Mode::Pretty | Mode::Markdown | Mode::Csv | Mode::Json => {
+            let mut buffer = vec![];
+            let mut stdout = io::stdout();
+            let out: &mut dyn Write = if args.output.is_some() {
+                &mut buffer
+            } else {
+                &mut stdout
+            };
+            run_reports(
+                out,
+                &traces,
+                backends,
+                args.mode,
+                report_template.as_ref(),
+                args.progress,
+                &ReportOptions {
+                    report_cycles,
+                    resolver: &resolver,
+                    lookup_as_info,
+                    geoip: geoip.as_ref(),
+                    columns: &args.report_columns,
+                    timestamp_format: &args.timestamp_format,
+                    rtt_format,
+                    unknown_hops: &UnknownHops::new(
+                        args.unknown_hop.clone(),
+                        args.collapse_unresponsive,
+                    ),
+                    markdown_rich: args.markdown_rich.then(|| {
+                        MarkdownRich::new(args.loss_warn_threshold, args.loss_critical_threshold)
+                    }),
+                },
+            )?;
+            if let Some(path) = &args.output {
+                write_report_file(Path::new(path), &buffer)
+                    .map_err(|err| anyhow!("failed to write report {}: {}", path, err))?;
+            }
+        }
+    }
+    if let Some(path) = &export_histogram {
+        write_histograms(path, &traces)?;
+    }
+    if let Some(path) = &export_dot {
+        write_dot(path, &traces, &resolver, lookup_as_info)?;
+    }
+    if let Some(path) = &state_file {
+        write_state(path, &traces)?;
+    }
+    if let Some(rounds) = args.require_reachable {
+        if !all_reachable(&traces, rounds) {
+            process::exit(EXIT_UNREACHABLE);
+        }
     }
     Ok(())
 }
 
-/// Create the network channel and then dropping all capabilities.
-fn start_backend(
+/// Check that every target responded within its latest `rounds` rounds, reporting any which did not to stderr.
+fn all_reachable(traces: &[TuiTraceInfo], rounds: usize) -> bool {
+    let mut reachable = true;
+    for info in traces {
+        if info.data.read().unreachable_rounds() >= rounds {
+            eprintln!(
+                "target {} ({}) did not respond in the last {} rounds",
+                info.target_hostname, info.target_addr, rounds
+            );
+            reachable = false;
+        }
+    }
+    reachable
+}
+
+/// Show the traces of the json report at `path` in the Tui, without tracing.
+///
+/// DNS is disabled, the hostnames recorded in the report are shown as is, and the Tui cannot pause or clear the traces.
+fn run_replay(
+    path: &Path,
+    args: &Args,
+    tui_refresh_rate: Duration,
+    theme: Theme,
+    geoip: Option<GeoIpLookup>,
+    baseline: Option<Baseline>,
+) -> anyhow::Result<()> {
+    let resolver = DnsResolver::start(DnsResolverConfig {
+        resolve_method: args.dns_resolve_method,
+        timeout: Duration::ZERO,
+        cache: None,
+        disabled: true,
+        asn_names: None,
+        labels: args
+            .labels
+            .as_deref()
+            .map(|path| HostLabels::open(Path::new(path)))
+            .transpose()?,
+        endpoint: None,
+        resolve_all: args.dns_resolve_all,
+    })?;
+    let traces = replay_traces(path, &resolver)?;
+    install_shutdown_handler()?;
+    let tui_config = TuiConfig {
+        refresh_rate: tui_refresh_rate,
+        preserve_screen: args.tui_preserve_screen,
+        address_mode: args.tui_address_mode.unwrap_or(AddressMode::Host),
+        lookup_as_info: false,
+        max_addrs: args.tui_max_addresses_per_hop,
+        max_hostname_width: args.max_hostname_width,
+        max_samples: args.tui_max_samples,
+        max_rounds: None,
+        hop_window: args.tui_hop_window.clone(),
+        address_order: args.preserve_order,
+        show_ewma: args.tui_show_ewma,
+        show_clock_skew: false,
+        percentiles: vec![],
+        rtt_format: RttFormat::new(args.rtt_unit, args.rtt_precision),
+        history_width: args.tui_history_width,
+        stale_rounds: args.tui_stale_rounds,
+        unknown_hops: UnknownHops::new(args.unknown_hop.clone(), args.collapse_unresponsive),
+        compare_protocols: false,
+        theme,
+        geoip,
+        pause: PauseHandle::new(),
+        snapshot: args.snapshot.as_deref().map(PathBuf::from),
+        replay: Some(path.display().to_string()),
+        baseline,
+    };
+    frontend::run_frontend(traces, tui_config, resolver)
+}
+
+/// Run a report for each trace in turn.
+///
+/// The tracer for each target is only started once the report for the prior target has completed.  If `progress` is
+/// set then the completed cycles of each target are shown on stderr, and cleared, before its report is written.  If a
+/// `template` is given then the pretty and markdown reports of each target are rendered with it rather than as a table.
+fn run_reports(
+    out: &mut dyn Write,
+    traces: &[TuiTraceInfo],
+    backends: Vec<Backend>,
+    mode: Mode,
+    template: Option<&ReportTemplate>,
+    progress: bool,
+    options: &ReportOptions<'_>,
+) -> anyhow::Result<()> {
+    let multi = traces.len() > 1;
+    let mut reports = vec![];
+    if let (Mode::Csv, Some(info)) = (mode, traces.first()) {
+        let percentiles = info.data.read().percentiles().to_vec();
+        run_report_csv_header(out, options.columns, &percentiles)?;
+    }
+    if let (Mode::Pretty | Mode::Markdown, None, Some(description)) = (
+        mode,
+        template,
+        traces.first().and_then(|info| info.description.as_ref()),
+    ) {
+        writeln!(out, "Description: {description}")?;
+    }
+    for (i, (info, backend)) in traces.iter().zip(backends).enumerate() {
+        if shutdown_requested() {
+            break;
+        }
+        start_backend(backend)?;
+        if progress {
+            let label = multi.then(|| info.target_hostname.clone());
+            let _progress = Progress::start(label, info.data.clone(), options.report_cycles)?;
+            wait_for_round(&info.data, options.report_cycles);
+        }
+        if let (Mode::Pretty | Mode::Markdown, Some(template)) = (mode, template) {
+            let report = report_json(info, options);
+            write!(out, "{}", template.render(&report))?;
+            continue;
+        }
+        if (multi || info.tos.is_some()) && matches!(mode, Mode::Pretty | Mode::Markdown) {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            let tos = info
+                .tos
+                .map(|tos| format!(" tos={tos:#04x}"))
+                .unwrap_or_default();
+            writeln!(
+                out,
+                "Target: {} ({}){}",
+                info.target_hostname,
+                info.current_target_addr(),
+                tos
+            )?;
+        }
+        match mode {
+            Mode::Csv => run_report_csv(out, info, options)?,
+            Mode::Json => reports.push(report_json(info, options)),
+            Mode::Pretty => run_report_table_pretty(out, &info.data, options)?,
+            Mode::Markdown => run_report_table_markdown(out, &info.data, options)?,
+            Mode::Tui | Mode::Stream | Mode::JsonStream | Mode::Alert => unreachable!(),
+        }
+    }
+    if matches!(mode, Mode::Json) {
+        run_report_json(out, &reports)?;
+    }
+    Ok(())
+}
+
+/// A tracer which has been configured but not yet started.
+struct Backend {
     tracer_config: TracerConfig,
+    channel: TracerChannel,
     trace_data: Arc<RwLock<Trace>>,
-) -> anyhow::Result<()> {
-    let channel = TracerChannel::new(
-        tracer_config.target_addr,
-        tracer_config.trace_identifier,
-        tracer_config.packet_size,
-        tracer_config.payload_pattern,
-        tracer_config.source_port,
-    )?;
+    anonymizer: Option<Anonymizer>,
+}
+
+/// Create the network channel for a tracer.
+///
+/// This must be called before dropping all capabilities.
+fn make_backend(
+    tracer_config: TracerConfig,
+    trace_data: Arc<RwLock<Trace>>,
+    anonymizer: Option<Anonymizer>,
+) -> anyhow::Result<Backend> {
+    let channel = TracerChannel::new(&tracer_config)?;
+    Ok(Backend {
+        tracer_config,
+        channel,
+        trace_data,
+        anonymizer,
+    })
+}
+
+/// Start the tracer in a new thread, dropping all capabilities.
+fn start_backend(backend: Backend) -> anyhow::Result<()> {
+    let Backend {
+        tracer_config,
+        channel,
+        trace_data,
+        anonymizer,
+    } = backend;
     thread::Builder::new()
         .name(format!("tracer-{}", tracer_config.trace_identifier.0))
         .spawn(move || {
             drop_caps().expect("failed to drop capabilities in tracer thread");
-            backend::run_backend(&tracer_config, channel, trace_data).expect("backend failed");
+            backend::run_backend(&tracer_config, channel, trace_data, anonymizer)
+                .expect("backend failed");
         })?;
-
     Ok(())
 }
@@ -0,0 +1,215 @@
+use crate::backend::{Hop, Trace};
+use crate::frontend::TuiTraceInfo;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+/// The maximum time to wait for a client to send a request or read a response.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The path at which metrics are served.
+const METRICS_PATH: &str = "/metrics";
+
+/// Bind the metrics server to `addr`.
+pub fn bind_metrics(addr: SocketAddr) -> anyhow::Result<TcpListener> {
+    Ok(TcpListener::bind(addr)?)
+}
+
+/// Serve metrics for all traces in the Prometheus text exposition format.
+///
+/// Metrics are rendered from the latest trace data on every request and so reflect the most recently completed round.
+pub fn run_metrics(listener: TcpListener, traces: Vec<TuiTraceInfo>) -> anyhow::Result<()> {
+    thread::Builder::new()
+        .name(String::from("metrics"))
+        .spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(err) = handle_request(stream, &traces) {
+                    eprintln!("metrics request failed: {err}");
+                }
+            }
+        })?;
+    Ok(())
+}
+
+/// Handle a single HTTP request.
+///
+/// Only `GET` requests to the metrics path are supported, all other requests receive a `404`.
+fn handle_request(mut stream: TcpStream, traces: &[TuiTraceInfo]) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim_end() != "" {
+        header.clear();
+    }
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(METRICS_PATH)) => {
+            let body = render_metrics(traces);
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        _ => {
+            String::from("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+        }
+    };
+    stream.write_all(response.as_bytes())?;
+    Ok(())
+}
+
+/// Render the metrics for all traces.
+///
+/// The `trippy_hop_rtt_seconds` gauge is only rendered for hops for which the most recent probe received a response such
+/// that the series becomes stale when a hop stops responding, rather than reporting an outdated round-trip time.
+fn render_metrics(traces: &[TuiTraceInfo]) -> String {
+    let snapshots: Vec<_> = traces
+        .iter()
+        .map(|info| (info.target_hostname.as_str(), info.data.read().clone()))
+        .collect();
+    let mut out = String::new();
+    write_metric(
+        &mut out,
+        "trippy_hop_rtt_seconds",
+        "The round-trip time of the most recent probe to the hop.",
+        &snapshots,
+        |hop| {
+            hop.is_responding()
+                .then(|| hop.last_ms().map(|last| last / 1000_f64))
+                .flatten()
+        },
+    );
+    write_metric(
+        &mut out,
+        "trippy_hop_loss_ratio",
+        "The ratio of probes to the hop which did not receive a response.",
+        &snapshots,
+        |hop| Some(hop.loss_pct() / 100_f64),
+    );
+    write_metric(
+        &mut out,
+        "trippy_hop_last_seq",
+        "The sequence number of the most recent probe sent to the hop.",
+        &snapshots,
+        |hop| hop.last_sequence().map(f64::from),
+    );
+    out
+}
+
+/// Render a single gauge for every hop of every trace.
+fn write_metric(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    snapshots: &[(&str, Trace)],
+    value: impl Fn(&Hop) -> Option<f64>,
+) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} gauge");
+    for (target, trace) in snapshots {
+        for hop in trace.hops() {
+            if let Some(value) = value(hop) {
+                let addr = hop
+                    .last_addr()
+                    .map(|addr| addr.to_string())
+                    .unwrap_or_default();
+                let _ = writeln!(
+                    out,
+                    "{name}{{target=\"{}\",ttl=\"{}\",addr=\"{}\"}} {value}",
+                    escape_label(target),
+                    hop.ttl(),
+                    addr
+                );
+            }
+        }
+    }
+}
+
+/// Escape a label value as required by the Prometheus text exposition format.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::trace;
+    use std::io::Read;
+
+    /// A trace to `target` of a round in which the first hop responded after 250ms and the probe of the second hop
+    /// was lost.
+    fn trace_info(target: &str) -> TuiTraceInfo {
+        crate::test_util::trace_info(target, trace(&[&[Some((1, 250)), None]]))
+    }
+
+    /// Send `request` to a metrics server of `traces` and read the response.
+    fn request(request: &str, traces: &[TuiTraceInfo]) -> String {
+        let listener = bind_metrics(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(request.as_bytes()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        handle_request(stream, traces).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_render_metrics() {
+        assert_eq!(
+            "# HELP trippy_hop_rtt_seconds The round-trip time of the most recent probe to the hop.\n\
+             # TYPE trippy_hop_rtt_seconds gauge\n\
+             trippy_hop_rtt_seconds{target=\"example.com\",ttl=\"1\",addr=\"10.0.0.1\"} 0.25\n\
+             # HELP trippy_hop_loss_ratio The ratio of probes to the hop which did not receive a response.\n\
+             # TYPE trippy_hop_loss_ratio gauge\n\
+             trippy_hop_loss_ratio{target=\"example.com\",ttl=\"1\",addr=\"10.0.0.1\"} 0\n\
+             trippy_hop_loss_ratio{target=\"example.com\",ttl=\"2\",addr=\"\"} 1\n\
+             # HELP trippy_hop_last_seq The sequence number of the most recent probe sent to the hop.\n\
+             # TYPE trippy_hop_last_seq gauge\n\
+             trippy_hop_last_seq{target=\"example.com\",ttl=\"1\",addr=\"10.0.0.1\"} 0\n\
+             trippy_hop_last_seq{target=\"example.com\",ttl=\"2\",addr=\"\"} 1\n",
+            render_metrics(&[trace_info("example.com")])
+        );
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!("example.com", escape_label("example.com"));
+        assert_eq!(r#"a\\b\"c\nd"#, escape_label("a\\b\"c\nd"));
+    }
+
+    #[test]
+    fn test_metrics_request() {
+        let traces = [trace_info("example.com")];
+        let response = request("GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n", &traces);
+        let body = render_metrics(&traces);
+        assert_eq!(
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            ),
+            response
+        );
+    }
+
+    #[test]
+    fn test_unknown_request() {
+        let traces = [trace_info("example.com")];
+        for request_line in ["GET / HTTP/1.1", "POST /metrics HTTP/1.1", ""] {
+            let response = request(&format!("{request_line}\r\n\r\n"), &traces);
+            assert_eq!(
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                response
+            );
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use crate::frontend::TuiTraceInfo;
+use comfy_table::presets::UTF8_FULL;
+use comfy_table::{ContentArrangement, Table};
+use serde::Serialize;
+use std::net::IpAddr;
+use std::time::Duration;
+use trippy::tracing::{discover_path_mtu, MtuConfig, MtuLimit, PathMtu, MAX_MTU};
+
+/// The discovered MTU of the path to a single target.
+#[derive(Debug, Serialize)]
+struct TargetMtu {
+    target: String,
+    addr: IpAddr,
+    mtu: Option<u16>,
+    limit: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit_addr: Option<IpAddr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit_ttl: Option<u8>,
+    probes: usize,
+    #[serde(skip)]
+    path_mtu: PathMtu,
+}
+
+impl TargetMtu {
+    fn new(info: &TuiTraceInfo, path_mtu: PathMtu) -> Self {
+        let (limit, limit_addr, limit_ttl) = match path_mtu.limit {
+            MtuLimit::None => ("none", None, None),
+            MtuLimit::Local => ("local", None, None),
+            MtuLimit::Hop { addr, ttl } => ("hop", Some(addr), ttl),
+            MtuLimit::Unknown => ("unknown", None, None),
+        };
+        Self {
+            target: info.target_hostname.clone(),
+            addr: info.target_addr,
+            mtu: path_mtu.mtu,
+            limit,
+            limit_addr,
+            limit_ttl,
+            probes: path_mtu.probes,
+            path_mtu,
+        }
+    }
+
+    /// Describe what limits the MTU of the path.
+    fn describe_limit(&self) -> String {
+        match self.path_mtu.limit {
+            MtuLimit::None => format!("none, the largest probe ({MAX_MTU}) reached the target"),
+            MtuLimit::Local => String::from("local host, interface or learned path MTU"),
+            MtuLimit::Hop {
+                addr,
+                ttl: Some(ttl),
+            } => format!("hop {ttl} ({addr})"),
+            MtuLimit::Hop { addr, ttl: None } => format!("hop ? ({addr})"),
+            MtuLimit::Unknown => String::from("unknown, larger probes were dropped"),
+        }
+    }
+}
+
+/// The options of the discovery of the MTU of the path to each target.
+pub struct MtuOptions<'a> {
+    /// The identifier of the probes to the first target, that of each later target is incremented.
+    pub trace_identifier: u16,
+    pub max_ttl: u8,
+    pub probe_timeout: Duration,
+    pub tos: Option<u8>,
+    pub interface: Option<&'a str>,
+    pub source_addr: Option<IpAddr>,
+}
+
+/// Discover the MTU of the path to each target in turn and print it, as a table or as JSON.
+pub fn run_discover_mtu(
+    traces: &[TuiTraceInfo],
+    options: &MtuOptions<'_>,
+    json: bool,
+) -> anyhow::Result<()> {
+    let results = traces
+        .iter()
+        .enumerate()
+        .map(|(i, info)| {
+            let config = MtuConfig::new(
+                info.target_addr,
+                options.trace_identifier.wrapping_add(i as u16),
+                options.max_ttl,
+                options.probe_timeout,
+                options.tos,
+                options.interface.map(String::from),
+                options.source_addr,
+            );
+            Ok(TargetMtu::new(info, discover_path_mtu(&config)?))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+    } else {
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(vec!["Target", "MTU", "Limited by", "Probes"]);
+        for result in &results {
+            table.add_row(vec![
+                format!("{} ({})", result.target, result.addr),
+                result
+                    .mtu
+                    .map_or_else(|| String::from("no response"), |mtu| mtu.to_string()),
+                result.describe_limit(),
+                result.probes.to_string(),
+            ]);
+        }
+        println!("{table}");
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use crate::backend::Trace;
+use parking_lot::RwLock;
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// How often the progress is refreshed.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A live indicator of the report cycles completed for a single trace, written to stderr until dropped.
+///
+/// If stderr is a terminal then a single line is redrawn in place as cycles complete and cleared once done, otherwise a
+/// line is written for each completed cycle.  Nothing is ever written to stdout such that the report is unaffected.
+pub struct Progress {
+    done: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Progress {
+    /// Start showing the progress of `trace_data` towards `report_cycles`, prefixed by `label` if given.
+    pub fn start(
+        label: Option<String>,
+        trace_data: Arc<RwLock<Trace>>,
+        report_cycles: usize,
+    ) -> anyhow::Result<Self> {
+        let done = Arc::new(AtomicBool::new(false));
+        let handle = thread::Builder::new()
+            .name(String::from("progress"))
+            .spawn({
+                let done = done.clone();
+                move || show_progress(label.as_deref(), &trace_data, report_cycles, &done)
+            })?;
+        Ok(Self {
+            done,
+            handle: Some(handle),
+        })
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn show_progress(
+    label: Option<&str>,
+    trace_data: &RwLock<Trace>,
+    report_cycles: usize,
+    done: &AtomicBool,
+) {
+    let mut stderr = std::io::stderr();
+    let terminal = stderr.is_terminal();
+    let prefix = label.map(|label| format!("{label}: ")).unwrap_or_default();
+    let mut shown = None;
+    loop {
+        let finished = done.load(Ordering::Relaxed);
+        let cycles = completed_cycles(&trace_data.read()).min(report_cycles);
+        if shown != Some(cycles) {
+            shown = Some(cycles);
+            let _ = if terminal {
+                write!(stderr, "\r\x1b[2K{prefix}cycle {cycles}/{report_cycles}")
+            } else {
+                writeln!(stderr, "{prefix}cycle {cycles}/{report_cycles}")
+            };
+            let _ = stderr.flush();
+        }
+        if finished {
+            break;
+        }
+        thread::sleep(PROGRESS_INTERVAL);
+    }
+    if terminal {
+        let _ = write!(stderr, "\r\x1b[2K");
+        let _ = stderr.flush();
+    }
+}
+
+/// The number of rounds of `trace` which have completed.
+fn completed_cycles(trace: &Trace) -> usize {
+    if trace.hops().is_empty() {
+        0
+    } else {
+        trace.round() + 1
+    }
+}
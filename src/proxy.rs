@@ -0,0 +1,323 @@
+use crate::backend::Trace;
+use anyhow::anyhow;
+use parking_lot::RwLock;
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+
+/// The port of the target to connect to via the proxy.
+pub const PROXY_TARGET_PORT: u16 = 80;
+
+/// The kind of proxy.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ProxyKind {
+    /// A `SOCKS5` proxy, i.e. `socks5://127.0.0.1:1080`.
+    Socks5,
+    /// A `HTTP` proxy which supports the `CONNECT` method, i.e. `http://127.0.0.1:3128`.
+    Http,
+}
+
+/// The address of a proxy.
+#[derive(Debug, Clone)]
+pub struct ProxyAddr {
+    pub kind: ProxyKind,
+    pub host: String,
+    pub addr: SocketAddr,
+}
+
+impl FromStr for ProxyAddr {
+    type Err = anyhow::Error;
+
+    /// Parse and resolve a proxy url such as `socks5://proxy.example.com:1080`.
+    ///
+    /// An IPv4 address is preferred, if the proxy host resolves to one, as IPv6 tracing is not supported.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, host_port) = if let Some(rest) = s.strip_prefix("socks5://") {
+            (ProxyKind::Socks5, rest)
+        } else if let Some(rest) = s.strip_prefix("http://") {
+            (ProxyKind::Http, rest)
+        } else {
+            return Err(anyhow!(
+                "invalid proxy {}: must start with socks5:// or http://",
+                s
+            ));
+        };
+        let host_port = host_port.trim_end_matches('/');
+        let host = host_port
+            .rsplit_once(':')
+            .map(|(host, _)| host.to_string())
+            .ok_or_else(|| anyhow!("invalid proxy {}: missing port", s))?;
+        let addrs: Vec<_> = host_port
+            .to_socket_addrs()
+            .map_err(|err| anyhow!("invalid proxy {}: {}", s, err))?
+            .collect();
+        let addr = addrs
+            .iter()
+            .find(|addr| addr.is_ipv4())
+            .or_else(|| addrs.first())
+            .copied()
+            .ok_or_else(|| anyhow!("invalid proxy {}: no addresses found", s))?;
+        Ok(Self { kind, host, addr })
+    }
+}
+
+impl Display for ProxyKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Socks5 => write!(f, "socks5"),
+            Self::Http => write!(f, "http"),
+        }
+    }
+}
+
+/// Connect to `target` via the proxy once per `interval` and record the results in the trace.
+///
+/// Each connection is abandoned, and recorded as lost, if it does not complete within `timeout`.
+pub fn run_proxy_probe(
+    proxy: ProxyAddr,
+    target: String,
+    interval: Duration,
+    timeout: Duration,
+    trace_data: Arc<RwLock<Trace>>,
+) -> anyhow::Result<()> {
+    let proxy_target = format!("{target}:{PROXY_TARGET_PORT}");
+    thread::Builder::new()
+        .name(String::from("proxy"))
+        .spawn(move || loop {
+            let start = SystemTime::now();
+            let rtt = connect_via(&proxy, &target, PROXY_TARGET_PORT, timeout).ok();
+            trace_data.write().update_from_proxy(&proxy_target, rtt);
+            sleep(interval.saturating_sub(start.elapsed().unwrap_or_default()));
+        })?;
+    Ok(())
+}
+
+/// Connect to `host` and `port` via the proxy and return the time taken for the proxy to establish the connection.
+///
+/// The connection to the proxy itself, and any handshake which precedes the connect request, is not included.
+fn connect_via(
+    proxy: &ProxyAddr,
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> anyhow::Result<Duration> {
+    let mut stream = TcpStream::connect_timeout(&proxy.addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    match proxy.kind {
+        ProxyKind::Socks5 => socks5_connect(&mut stream, host, port),
+        ProxyKind::Http => http_connect(stream, host, port),
+    }
+}
+
+/// Perform a `SOCKS5` handshake, without authentication, and issue a `CONNECT` request.
+///
+/// The hostname is passed to the proxy to resolve.
+fn socks5_connect(stream: &mut TcpStream, host: &str, port: u16) -> anyhow::Result<Duration> {
+    const VERSION: u8 = 0x05;
+    const NO_AUTH: u8 = 0x00;
+    const CONNECT: u8 = 0x01;
+    const ATYP_IPV4: u8 = 0x01;
+    const ATYP_DOMAIN: u8 = 0x03;
+    const ATYP_IPV6: u8 = 0x04;
+    let host_len = u8::try_from(host.len()).map_err(|_| anyhow!("hostname too long"))?;
+    stream.write_all(&[VERSION, 1, NO_AUTH])?;
+    let mut choice = [0_u8; 2];
+    stream.read_exact(&mut choice)?;
+    if choice != [VERSION, NO_AUTH] {
+        return Err(anyhow!("socks5 proxy requires authentication"));
+    }
+    let mut request = vec![VERSION, CONNECT, 0x00, ATYP_DOMAIN, host_len];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    let start = SystemTime::now();
+    stream.write_all(&request)?;
+    let mut reply = [0_u8; 4];
+    stream.read_exact(&mut reply)?;
+    let elapsed = start.elapsed().unwrap_or_default();
+    if reply[1] != 0x00 {
+        return Err(anyhow!("socks5 connect failed with reply {}", reply[1]));
+    }
+    let bound_len = match reply[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => {
+            let mut len = [0_u8; 1];
+            stream.read_exact(&mut len)?;
+            usize::from(len[0])
+        }
+        atyp => return Err(anyhow!("socks5 reply has unknown address type {}", atyp)),
+    };
+    let mut bound = vec![0_u8; bound_len + 2];
+    stream.read_exact(&mut bound)?;
+    Ok(elapsed)
+}
+
+/// Issue a `HTTP` `CONNECT` request.
+fn http_connect(mut stream: TcpStream, host: &str, port: u16) -> anyhow::Result<Duration> {
+    let start = SystemTime::now();
+    write!(
+        stream,
+        "CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n\r\n"
+    )?;
+    let mut reader = BufReader::new(stream);
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line)?;
+    let elapsed = start.elapsed().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(elapsed),
+        _ => Err(anyhow!("http connect failed: {}", status_line.trim_end())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// A proxy of `kind` on loopback which reads `request_len` bytes of each exchange and sends its reply.
+    ///
+    /// Returns the address of the proxy and the thread which returns the bytes read by the proxy.
+    fn fake_proxy(
+        kind: ProxyKind,
+        exchanges: Vec<(usize, Vec<u8>)>,
+    ) -> (ProxyAddr, thread::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = vec![];
+            for (request_len, reply) in exchanges {
+                let mut request = vec![0; request_len];
+                stream.read_exact(&mut request).unwrap();
+                received.extend(request);
+                stream.write_all(&reply).unwrap();
+            }
+            received
+        });
+        let proxy = ProxyAddr {
+            kind,
+            host: String::from("127.0.0.1"),
+            addr,
+        };
+        (proxy, handle)
+    }
+
+    #[test]
+    fn test_parse_proxy() {
+        let proxy = ProxyAddr::from_str("socks5://127.0.0.1:1080/").unwrap();
+        assert_eq!(ProxyKind::Socks5, proxy.kind);
+        assert_eq!("127.0.0.1", proxy.host);
+        assert_eq!(SocketAddr::from(([127, 0, 0, 1], 1080)), proxy.addr);
+        let proxy = ProxyAddr::from_str("http://[::1]:3128").unwrap();
+        assert_eq!(ProxyKind::Http, proxy.kind);
+        assert_eq!("[::1]", proxy.host);
+        assert_eq!(
+            SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], 3128)),
+            proxy.addr
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_proxy() {
+        let error = |s: &str| ProxyAddr::from_str(s).unwrap_err().to_string();
+        assert_eq!(
+            "invalid proxy 127.0.0.1:1080: must start with socks5:// or http://",
+            error("127.0.0.1:1080")
+        );
+        assert_eq!(
+            "invalid proxy https://127.0.0.1:3128: must start with socks5:// or http://",
+            error("https://127.0.0.1:3128")
+        );
+        assert_eq!(
+            "invalid proxy socks5://127.0.0.1: missing port",
+            error("socks5://127.0.0.1")
+        );
+        assert!(ProxyAddr::from_str("socks5://127.0.0.1:port").is_err());
+    }
+
+    #[test]
+    fn test_socks5_connect() {
+        let (proxy, handle) = fake_proxy(
+            ProxyKind::Socks5,
+            vec![
+                (3, vec![0x05, 0x00]),
+                (18, vec![0x05, 0x00, 0x00, 0x01, 10, 0, 0, 1, 0x1f, 0x90]),
+            ],
+        );
+        assert!(connect_via(&proxy, "example.com", 80, TIMEOUT).is_ok());
+        let mut expected = vec![0x05, 0x01, 0x00, 0x05, 0x01, 0x00, 0x03, 11];
+        expected.extend(b"example.com");
+        expected.extend(80_u16.to_be_bytes());
+        assert_eq!(expected, handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_socks5_connect_domain_reply() {
+        let mut reply = vec![0x05, 0x00, 0x00, 0x03, 5];
+        reply.extend(b"proxy");
+        reply.extend(80_u16.to_be_bytes());
+        let (proxy, handle) =
+            fake_proxy(ProxyKind::Socks5, vec![(3, vec![0x05, 0x00]), (10, reply)]);
+        assert!(connect_via(&proxy, "a.b", 80, TIMEOUT).is_ok());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_socks5_connect_refused() {
+        let (proxy, handle) = fake_proxy(
+            ProxyKind::Socks5,
+            vec![
+                (3, vec![0x05, 0x00]),
+                (18, vec![0x05, 0x05, 0x00, 0x01, 0, 0, 0, 0, 0, 0]),
+            ],
+        );
+        let err = connect_via(&proxy, "example.com", 80, TIMEOUT).unwrap_err();
+        assert_eq!("socks5 connect failed with reply 5", err.to_string());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_socks5_connect_auth_required() {
+        let (proxy, handle) = fake_proxy(ProxyKind::Socks5, vec![(3, vec![0x05, 0x02])]);
+        let err = connect_via(&proxy, "example.com", 80, TIMEOUT).unwrap_err();
+        assert_eq!("socks5 proxy requires authentication", err.to_string());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_http_connect() {
+        let request = b"CONNECT example.com:80 HTTP/1.1\r\nHost: example.com:80\r\n\r\n";
+        let (proxy, handle) = fake_proxy(
+            ProxyKind::Http,
+            vec![(
+                request.len(),
+                b"HTTP/1.1 200 Connection established\r\n\r\n".to_vec(),
+            )],
+        );
+        assert!(connect_via(&proxy, "example.com", 80, TIMEOUT).is_ok());
+        assert_eq!(request.to_vec(), handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_http_connect_forbidden() {
+        let request = b"CONNECT example.com:80 HTTP/1.1\r\nHost: example.com:80\r\n\r\n";
+        let (proxy, handle) = fake_proxy(
+            ProxyKind::Http,
+            vec![(request.len(), b"HTTP/1.1 403 Forbidden\r\n\r\n".to_vec())],
+        );
+        let err = connect_via(&proxy, "example.com", 80, TIMEOUT).unwrap_err();
+        assert_eq!(
+            "http connect failed: HTTP/1.1 403 Forbidden",
+            err.to_string()
+        );
+        handle.join().unwrap();
+    }
+}
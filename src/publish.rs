@@ -0,0 +1,344 @@
+use crate::config::TimestampFormat;
+use crate::dns::DnsResolver;
+use crate::frontend::TuiTraceInfo;
+use crate::geoip::GeoIpLookup;
+use crate::report::{RoundTracker, ROUND_POLL_INTERVAL};
+use anyhow::anyhow;
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+use std::thread::sleep;
+use std::time::Duration;
+
+/// The maximum time to block writing to a client before it is disconnected.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The address to publish tracing data to.
+#[derive(Debug, Clone)]
+pub enum PublishAddr {
+    /// Listen for `TCP` connections on a socket address, i.e. `127.0.0.1:1234`.
+    Tcp(SocketAddr),
+    /// Listen for connections on a Unix domain socket path, i.e. `unix:/tmp/trippy.sock` or `/tmp/trippy.sock`.
+    Unix(PathBuf),
+}
+
+impl FromStr for PublishAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            Ok(Self::Unix(PathBuf::from(path)))
+        } else if s.starts_with('/') || s.starts_with('.') {
+            Ok(Self::Unix(PathBuf::from(s)))
+        } else {
+            let addr = s
+                .strip_prefix("tcp:")
+                .unwrap_or(s)
+                .parse()
+                .map_err(|err| anyhow!("invalid publish address {}: {}", s, err))?;
+            Ok(Self::Tcp(addr))
+        }
+    }
+}
+
+/// A listening socket which accepts clients to publish to.
+pub enum Publisher {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Publisher {
+    /// Bind to the `PublishAddr`.
+    ///
+    /// A stale Unix domain socket at the same path is removed, any other file at the path is an error.
+    pub fn bind(addr: &PublishAddr) -> anyhow::Result<Self> {
+        let publisher = match addr {
+            PublishAddr::Tcp(addr) => Self::Tcp(TcpListener::bind(addr)?),
+            PublishAddr::Unix(path) => {
+                if let Ok(metadata) = std::fs::symlink_metadata(path) {
+                    if metadata.file_type().is_socket() {
+                        std::fs::remove_file(path)?;
+                    }
+                }
+                Self::Unix(UnixListener::bind(path)?)
+            }
+        };
+        match &publisher {
+            Self::Tcp(listener) => listener.set_nonblocking(true)?,
+            Self::Unix(listener) => listener.set_nonblocking(true)?,
+        }
+        Ok(publisher)
+    }
+
+    /// Accept all pending clients.
+    ///
+    /// A failure to accept a client, such as if it reset the connection before it was accepted or the process has run
+    /// out of file descriptors, is reported on stderr and any remaining clients are accepted by the next call.
+    fn accept(&self) -> Vec<Box<dyn Write>> {
+        let mut clients: Vec<Box<dyn Write>> = vec![];
+        loop {
+            let accepted: std::io::Result<Box<dyn Write>> = match self {
+                Self::Tcp(listener) => listener.accept().and_then(|(stream, _)| {
+                    stream.set_nonblocking(false)?;
+                    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+                    Ok(Box::new(stream) as Box<dyn Write>)
+                }),
+                Self::Unix(listener) => listener.accept().and_then(|(stream, _)| {
+                    stream.set_nonblocking(false)?;
+                    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+                    Ok(Box::new(stream) as Box<dyn Write>)
+                }),
+            };
+            match accepted {
+                Ok(client) => clients.push(client),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return clients,
+                Err(err) => {
+                    eprintln!("publisher failed to accept client: {err}");
+                    return clients;
+                }
+            }
+        }
+    }
+}
+
+/// Publish each completed round of tracing, for all traces, to all connected clients.
+///
+/// Each round is serialized as a single line of JSON.  Clients which disconnect, or which fail to read data in a
+/// timely manner, are dropped without affecting the trace.
+pub fn run_publisher(
+    publisher: Publisher,
+    traces: Vec<TuiTraceInfo>,
+    resolver: DnsResolver,
+    geoip: Option<GeoIpLookup>,
+    timestamp_format: TimestampFormat,
+) -> anyhow::Result<()> {
+    thread::Builder::new()
+        .name(String::from("publisher"))
+        .spawn(move || {
+            if let Err(err) = publish(
+                &publisher,
+                &traces,
+                &resolver,
+                geoip.as_ref(),
+                &timestamp_format,
+            ) {
+                eprintln!("publisher failed: {err}");
+            }
+        })?;
+    Ok(())
+}
+
+fn publish(
+    publisher: &Publisher,
+    traces: &[TuiTraceInfo],
+    resolver: &DnsResolver,
+    geoip: Option<&GeoIpLookup>,
+    timestamp_format: &TimestampFormat,
+) -> anyhow::Result<()> {
+    let mut clients: Vec<Box<dyn Write>> = vec![];
+    let mut trackers: Vec<_> = traces.iter().map(|_| RoundTracker::new()).collect();
+    loop {
+        clients.extend(publisher.accept());
+        for line in completed_rounds(traces, &mut trackers, resolver, geoip, timestamp_format)? {
+            clients.retain_mut(|client| client.write_all(line.as_bytes()).is_ok());
+        }
+        sleep(ROUND_POLL_INTERVAL);
+    }
+}
+
+/// Where to write the JSON log of each completed round.
+#[derive(Debug, Clone)]
+pub enum RoundLogTarget {
+    /// Write to stderr, i.e. `-`.
+    Stderr,
+    /// Append to a file, i.e. `rounds.json` or `/dev/fd/3`.
+    File(PathBuf),
+}
+
+impl FromStr for RoundLogTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Err(anyhow!("empty round log path")),
+            "-" => Ok(Self::Stderr),
+            path => Ok(Self::File(PathBuf::from(path))),
+        }
+    }
+}
+
+impl RoundLogTarget {
+    /// Open the writer of the target.
+    pub fn open(&self) -> anyhow::Result<Box<dyn Write + Send>> {
+        Ok(match self {
+            Self::Stderr => Box::new(std::io::stderr()),
+            Self::File(path) => Box::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|err| anyhow!("failed to open {}: {}", path.display(), err))?,
+            ),
+        })
+    }
+}
+
+/// Write each completed round of tracing, for all traces, to `writer` for as long as tracing continues.
+///
+/// Each round is serialized as a single line of JSON, as for `run_publisher`, and written with a single write from a
+/// dedicated thread such that lines are never interleaved with one another.  A failure to write ends the log without
+/// affecting the trace.
+pub fn run_round_log(
+    writer: Box<dyn Write + Send>,
+    traces: Vec<TuiTraceInfo>,
+    resolver: DnsResolver,
+    geoip: Option<GeoIpLookup>,
+    timestamp_format: TimestampFormat,
+) -> anyhow::Result<()> {
+    thread::Builder::new()
+        .name(String::from("round-log"))
+        .spawn(move || {
+            if let Err(err) = log_rounds(
+                writer,
+                &traces,
+                &resolver,
+                geoip.as_ref(),
+                &timestamp_format,
+            ) {
+                eprintln!("round log failed: {err}");
+            }
+        })?;
+    Ok(())
+}
+
+fn log_rounds(
+    mut writer: Box<dyn Write + Send>,
+    traces: &[TuiTraceInfo],
+    resolver: &DnsResolver,
+    geoip: Option<&GeoIpLookup>,
+    timestamp_format: &TimestampFormat,
+) -> anyhow::Result<()> {
+    let mut trackers: Vec<_> = traces.iter().map(|_| RoundTracker::new()).collect();
+    loop {
+        for line in completed_rounds(traces, &mut trackers, resolver, geoip, timestamp_format)? {
+            writer.write_all(line.as_bytes())?;
+            writer.flush()?;
+        }
+        sleep(ROUND_POLL_INTERVAL);
+    }
+}
+
+/// Serialize the rounds, of all traces, which have completed since the prior call, one line of JSON per round.
+pub fn completed_rounds(
+    traces: &[TuiTraceInfo],
+    trackers: &mut [RoundTracker],
+    resolver: &DnsResolver,
+    geoip: Option<&GeoIpLookup>,
+    timestamp_format: &TimestampFormat,
+) -> anyhow::Result<Vec<String>> {
+    let mut lines = vec![];
+    for (info, tracker) in traces.iter().zip(trackers) {
+        let trace = info.data.read().clone();
+        if let Some(round) = tracker.next_round(
+            &info.target_hostname,
+            info.target_addr,
+            resolver,
+            geoip,
+            timestamp_format,
+            &trace,
+        ) {
+            let mut line = serde_json::to_string(&round)?;
+            line.push('\n');
+            lines.push(line);
+        }
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+    use std::net::TcpStream;
+    use std::os::unix::net::UnixStream;
+    use std::path::Path;
+
+    #[test]
+    fn test_parse_publish_addr() {
+        assert!(matches!(
+            PublishAddr::from_str("127.0.0.1:1234").unwrap(),
+            PublishAddr::Tcp(addr) if addr == SocketAddr::from(([127, 0, 0, 1], 1234))
+        ));
+        assert!(matches!(
+            PublishAddr::from_str("tcp:[::1]:1234").unwrap(),
+            PublishAddr::Tcp(addr) if addr == SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], 1234))
+        ));
+        assert!(matches!(
+            PublishAddr::from_str("unix:trippy.sock").unwrap(),
+            PublishAddr::Unix(path) if path == Path::new("trippy.sock")
+        ));
+        assert!(matches!(
+            PublishAddr::from_str("/tmp/trippy.sock").unwrap(),
+            PublishAddr::Unix(path) if path == Path::new("/tmp/trippy.sock")
+        ));
+        assert!(matches!(
+            PublishAddr::from_str("./trippy.sock").unwrap(),
+            PublishAddr::Unix(path) if path == Path::new("./trippy.sock")
+        ));
+        assert!(PublishAddr::from_str("localhost:1234").is_err());
+        assert!(PublishAddr::from_str("tcp:1234").is_err());
+    }
+
+    #[test]
+    fn test_parse_round_log_target() {
+        assert!(matches!(
+            RoundLogTarget::from_str("-").unwrap(),
+            RoundLogTarget::Stderr
+        ));
+        assert!(matches!(
+            RoundLogTarget::from_str("/dev/fd/3").unwrap(),
+            RoundLogTarget::File(path) if path == Path::new("/dev/fd/3")
+        ));
+        assert!(RoundLogTarget::from_str("").is_err());
+    }
+
+    #[test]
+    fn test_accept_tcp() {
+        let publisher =
+            Publisher::bind(&PublishAddr::Tcp(SocketAddr::from(([127, 0, 0, 1], 0)))).unwrap();
+        assert!(publisher.accept().is_empty());
+        let addr = match &publisher {
+            Publisher::Tcp(listener) => listener.local_addr().unwrap(),
+            Publisher::Unix(_) => unreachable!(),
+        };
+        let _first = TcpStream::connect(addr).unwrap();
+        let _second = TcpStream::connect(addr).unwrap();
+        sleep(Duration::from_millis(50));
+        assert_eq!(2, publisher.accept().len());
+        assert!(publisher.accept().is_empty());
+    }
+
+    #[test]
+    fn test_bind_unix_stale_socket() {
+        let path = temp_path("stale.sock");
+        drop(UnixListener::bind(&path).unwrap());
+        let publisher = Publisher::bind(&PublishAddr::Unix(path.clone())).unwrap();
+        let _client = UnixStream::connect(&path).unwrap();
+        assert_eq!(1, publisher.accept().len());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_bind_unix_existing_file() {
+        let path = temp_path("file.sock");
+        std::fs::write(&path, b"").unwrap();
+        assert!(Publisher::bind(&PublishAddr::Unix(path.clone())).is_err());
+        assert!(path.is_file());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+use crate::backend::Trace;
+use crate::dns::DnsResolver;
+use crate::frontend::TuiTraceInfo;
+use crate::report::{read_reports, Host, Report, ReportHop};
+use anyhow::anyhow;
+use parking_lot::RwLock;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The protocol shown for a replayed trace, which is not recorded in the report.
+const REPLAY_PROTOCOL: &str = "n/a";
+
+/// Read the JSON report document at `path` and build a read-only trace of each report for the Tui.
+///
+/// The hostnames recorded in the report are seeded into `resolver` such that they are shown without any DNS lookups.
+pub fn replay_traces(path: &Path, resolver: &DnsResolver) -> anyhow::Result<Vec<TuiTraceInfo>> {
+    let reports = read_reports(path)?;
+    if reports.is_empty() {
+        return Err(anyhow!("report {} contains no traces", path.display()));
+    }
+    reports
+        .iter()
+        .map(|report| replay_trace(report, resolver))
+        .collect()
+}
+
+/// Build a read-only trace of a single report.
+fn replay_trace(report: &Report, resolver: &DnsResolver) -> anyhow::Result<TuiTraceInfo> {
+    let target = &report.info.target;
+    let target_addr: IpAddr = target
+        .ip
+        .parse()
+        .map_err(|err| anyhow!("invalid target address {}: {}", target.ip, err))?;
+    let tos = report
+        .info
+        .tos
+        .as_deref()
+        .map(|tos| u8::from_str_radix(tos.trim_start_matches("0x"), 16))
+        .transpose()
+        .map_err(|err| anyhow!("invalid tos: {}", err))?;
+    for host in report.hops.iter().flat_map(|hop| &hop.hosts) {
+        if let Some((addr, hostnames)) = recorded_hostnames(host) {
+            resolver.seed(addr, hostnames);
+        }
+    }
+    let stats: Vec<_> = report.hops.iter().map(ReportHop::stats).collect();
+    let destination_ttl = stats
+        .iter()
+        .find(|stats| stats.addrs.contains(&target_addr))
+        .map(|stats| stats.ttl);
+    let first_ttl = stats.iter().map(|stats| stats.ttl).min().unwrap_or(1);
+    let max_ttl = stats.iter().map(|stats| stats.ttl).max().unwrap_or(1);
+    Ok(TuiTraceInfo {
+        data: Arc::new(RwLock::new(Trace::replay(&stats, destination_ttl))),
+        target_hostname: target.hostname.clone(),
+        target_addr,
+        protocol: String::from(REPLAY_PROTOCOL),
+        first_ttl,
+        max_ttl,
+        ttl_stride: 1,
+        grace_duration: Duration::ZERO,
+        min_round_duration: Duration::ZERO,
+        tos,
+        dont_fragment: None,
+        description: report.info.description.clone(),
+    })
+}
+
+/// The address and reverse DNS hostnames of a host of a report, if it was resolved.
+///
+/// An unresolved host is recorded with its address as the hostname, possibly prefixed by the failure, and a host
+/// resolved with `AS` information is recorded with the `AS` number before the hostnames.
+fn recorded_hostnames(host: &Host) -> Option<(IpAddr, Vec<String>)> {
+    let addr: IpAddr = host.ip.parse().ok()?;
+    let hostname = host.hostname.trim();
+    if hostname == host.ip || hostname.starts_with("Failed: ") || hostname.starts_with("Timeout: ")
+    {
+        return None;
+    }
+    let mut names = hostname.split_whitespace().peekable();
+    if names
+        .peek()
+        .and_then(|name| name.strip_prefix("AS"))
+        .is_some_and(|asn| !asn.is_empty() && asn.chars().all(|c| c.is_ascii_digit()))
+    {
+        names.next();
+    }
+    let hostnames: Vec<_> = names.map(String::from).collect();
+    (!hostnames.is_empty()).then_some((addr, hostnames))
+}
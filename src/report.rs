@@ -1,88 +1,573 @@
+use crate::asn::format_asn;
+use crate::backend::{Hop, HopRow, HopStats, ProxyStats};
+use crate::config::{MarkdownRich, ReportColumn, RttFormat, TimestampFormat, UnknownHops};
+use crate::dns::{DnsEntry, Resolved};
+use crate::frontend::TuiTraceInfo;
+use crate::geoip::GeoIpLookup;
+use crate::signal::shutdown_requested;
 use crate::{DnsResolver, Trace};
+use anyhow::anyhow;
 use comfy_table::presets::{ASCII_MARKDOWN, UTF8_FULL};
 use comfy_table::{ContentArrangement, Table};
 use itertools::Itertools;
 use parking_lot::RwLock;
-use serde::{Serialize, Serializer};
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::Write;
 use std::net::IpAddr;
+use std::path::Path;
 use std::sync::Arc;
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
+use trippy::tracing::{TcpOutcome, UnreachableReason};
+
+/// How often to check for newly completed rounds.
+pub const ROUND_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The options of the report of a trace.
+pub struct ReportOptions<'a> {
+    /// The number of rounds to wait for before the report is generated.
+    pub report_cycles: usize,
+    pub resolver: &'a DnsResolver,
+    /// Include the autonomous system (AS) path of the trace.
+    pub lookup_as_info: bool,
+    pub geoip: Option<&'a GeoIpLookup>,
+    pub columns: &'a [ReportColumn],
+    pub timestamp_format: &'a TimestampFormat,
+    pub rtt_format: RttFormat,
+    pub unknown_hops: &'a UnknownHops,
+    /// Mark each hop of a markdown report with its status, see `run_report_table_markdown`.
+    pub markdown_rich: Option<MarkdownRich>,
+}
+
+/// Generate the header of a CSV report.
+pub fn run_report_csv_header(
+    out: &mut dyn Write,
+    columns: &[ReportColumn],
+    percentiles: &[f64],
+) -> io::Result<()> {
+    let headers = columns
+        .iter()
+        .flat_map(|column| column.headers(percentiles))
+        .join(",");
+    writeln!(out, "Target,TargetIp,{headers},Timestamp")
+}
 
 /// Generate a CSV report of trace data.
 pub fn run_report_csv(
-    hostname: &str,
-    target_addr: IpAddr,
-    report_cycles: usize,
-    resolver: &DnsResolver,
-    trace_data: &Arc<RwLock<Trace>>,
-) {
-    let trace = wait_for_round(trace_data, report_cycles);
-    println!("Target,TargetIp,Hop,Addrs,Loss%,Snt,Recv,Last,Avg,Best,Wrst,StdDev,");
-    for hop in trace.hops().iter() {
-        let ttl = hop.ttl();
-        let hosts = hop.addrs().map(|ip| resolver.reverse_lookup(*ip)).join(":");
-        let host = if hosts.is_empty() {
-            String::from("???")
-        } else {
-            hosts
+    out: &mut dyn Write,
+    info: &TuiTraceInfo,
+    options: &ReportOptions<'_>,
+) -> io::Result<()> {
+    let trace = wait_for_round(&info.data, options.report_cycles);
+    let timestamp = options.timestamp_format.format(chrono::Local::now());
+    if options.columns.contains(&ReportColumn::Asn) {
+        wait_for_asinfo(&trace, options.resolver);
+    }
+    let hostname = &info.target_hostname;
+    let target_addr = info.current_target_addr();
+    for hop in trace.hops() {
+        let values = options
+            .columns
+            .iter()
+            .flat_map(|column| column.values(hop, trace.percentiles(), true, options))
+            .join(",");
+        writeln!(out, "{hostname},{target_addr},{values},{timestamp}")?;
+    }
+    Ok(())
+}
+
+impl ReportColumn {
+    /// The headers of the column, one per percentile for `Percentiles` and one otherwise.
+    fn headers(self, percentiles: &[f64]) -> Vec<String> {
+        let header = match self {
+            Self::Hop => "Hop",
+            Self::Host => "Addrs",
+            Self::Ip => "Ip",
+            Self::Loss => "Loss%",
+            Self::Sent => "Snt",
+            Self::Recv => "Recv",
+            Self::Last => "Last",
+            Self::Avg => "Avg",
+            Self::Best => "Best",
+            Self::Worst => "Wrst",
+            Self::Stddev => "StdDev",
+            Self::Jitter => "Jttr",
+            Self::Ewma => "Ewma",
+            Self::Asn => "Asn",
+            Self::Geo => "Geo",
+            Self::Label => "Label",
+            Self::Percentiles => {
+                return percentiles
+                    .iter()
+                    .map(|percentile| format!("P{percentile}"))
+                    .collect()
+            }
         };
-        let sent = hop.total_sent();
-        let recv = hop.total_recv();
-        let last = hop
-            .last_ms()
-            .map_or_else(|| String::from("???"), |last| format!("{:.1}", last));
-        let best = hop
-            .best_ms()
-            .map_or_else(|| String::from("???"), |best| format!("{:.1}", best));
-        let worst = hop
-            .worst_ms()
-            .map_or_else(|| String::from("???"), |worst| format!("{:.1}", worst));
-        let stddev = hop.stddev_ms();
-        let avg = hop.avg_ms();
-        let loss_pct = hop.loss_pct();
-        println!(
-            "{},{},{},{},{:.1}%,{},{},{},{:.1},{},{},{:.1}",
-            hostname, target_addr, ttl, host, loss_pct, sent, recv, last, avg, best, worst, stddev
-        );
+        vec![header.to_string()]
+    }
+
+    /// The values of the column for `hop`, as for `headers`, `???` if unknown.
+    ///
+    /// Columns with a value per address are delimited by `:` for csv reports and by a newline otherwise.  The `Host` and
+    /// `Ip` columns of a hop which has never responded are the placeholder of the unknown hops.
+    fn values(
+        self,
+        hop: &Hop,
+        percentiles: &[f64],
+        csv: bool,
+        options: &ReportOptions<'_>,
+    ) -> Vec<String> {
+        let ReportOptions {
+            resolver,
+            geoip,
+            rtt_format,
+            unknown_hops,
+            ..
+        } = *options;
+        let unknown_hop = unknown_hops.placeholder("???");
+        let delimiter = if csv { ":" } else { "\n" };
+        let per_addr_or = |value: &dyn Fn(IpAddr) -> Option<String>, unknown: &str| {
+            let values = hop
+                .addrs()
+                .map(|addr| value(*addr).unwrap_or_else(|| String::from("???")))
+                .join(delimiter);
+            if values.is_empty() {
+                String::from(if hop.is_skipped() { "skipped" } else { unknown })
+            } else {
+                values
+            }
+        };
+        let per_addr = |value: &dyn Fn(IpAddr) -> Option<String>| per_addr_or(value, "???");
+        let rtt = |rtt: Option<f64>| {
+            rtt.map_or_else(|| String::from("???"), |rtt| rtt_format.format(rtt))
+        };
+        let value = match self {
+            Self::Hop => hop.ttl().to_string(),
+            Self::Host => per_addr_or(
+                &|addr| Some(resolver.reverse_lookup(addr).to_string()),
+                unknown_hop,
+            ),
+            Self::Ip => per_addr_or(&|addr| Some(addr.to_string()), unknown_hop),
+            Self::Loss if csv => format!("{:.1}%", hop.loss_pct()),
+            Self::Loss => format!("{:.1}", hop.loss_pct()),
+            Self::Sent => hop.total_sent().to_string(),
+            Self::Recv => hop.total_recv().to_string(),
+            Self::Last => rtt(hop.last_ms()),
+            Self::Avg => rtt_format.format(hop.avg_ms()),
+            Self::Best => rtt(hop.best_ms()),
+            Self::Worst => rtt(hop.worst_ms()),
+            Self::Stddev => rtt_format.format(hop.stddev_ms()),
+            Self::Jitter => rtt_format.format(hop.jitter_ms()),
+            Self::Ewma => rtt(hop.ewma_ms()),
+            Self::Asn => per_addr(&|addr| match resolver.reverse_lookup_with_asinfo(addr) {
+                DnsEntry::Resolved(Resolved::WithAsInfo(_, _, asinfo))
+                    if !asinfo.asn.is_empty() =>
+                {
+                    Some(format_asn(
+                        &asinfo.asn,
+                        resolver.config().asn_names.as_ref(),
+                    ))
+                }
+                _ => None,
+            }),
+            Self::Geo => per_addr(&|addr| geoip.and_then(|geoip| geoip.lookup(addr))),
+            Self::Label => per_addr(&|addr| resolver.label(addr)),
+            Self::Percentiles => {
+                return hop
+                    .percentiles_ms(percentiles)
+                    .into_iter()
+                    .map(rtt)
+                    .collect()
+            }
+        };
+        vec![value]
+    }
+
+    /// The values of the column for a run of unresponsive `hops` collapsed into a single row, as for `headers`.
+    ///
+    /// Only the ttl range, the count of hops, the loss and the probe counts are shown.
+    fn collapsed_values(self, hops: &[Hop], percentiles: &[f64]) -> Vec<String> {
+        let value = match self {
+            Self::Hop => match (hops.first(), hops.last()) {
+                (Some(first), Some(last)) => format!("{}-{}", first.ttl(), last.ttl()),
+                _ => String::new(),
+            },
+            Self::Host | Self::Ip => UnknownHops::collapsed(hops.len()),
+            Self::Loss => format!(
+                "{:.1}",
+                hops.iter().map(Hop::loss_pct).fold(0_f64, f64::max)
+            ),
+            Self::Sent => hops.iter().map(Hop::total_sent).sum::<usize>().to_string(),
+            Self::Recv => String::from("0"),
+            Self::Percentiles => return vec![String::new(); percentiles.len()],
+            _ => String::new(),
+        };
+        vec![value]
     }
 }
 
-#[derive(Serialize)]
+/// The report of a single target.
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct Report {
     pub info: ReportInfo,
     pub hops: Vec<ReportHop>,
+    pub flows: Vec<ReportFlow>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub as_path: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<ReportProxy>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct ReportInfo {
     pub target: Host,
+    #[serde(default)]
+    pub timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tos: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
 }
 
-#[derive(Serialize)]
+/// The statistics of a single hop, all times are in milliseconds and formatted to 2 decimal places.
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct ReportHop {
+    pub ttl: u8,
+    pub hosts: Vec<Host>,
+    #[serde(serialize_with = "fixed_width", deserialize_with = "from_fixed_width")]
+    #[schemars(with = "String")]
+    pub loss_pct: f64,
+    sent: usize,
+    #[serde(serialize_with = "fixed_width", deserialize_with = "from_fixed_width")]
+    #[schemars(with = "String")]
+    last: f64,
+    recv: usize,
+    #[serde(default)]
+    pending: usize,
+    #[serde(serialize_with = "fixed_width", deserialize_with = "from_fixed_width")]
+    #[schemars(with = "String")]
+    pub avg: f64,
+    #[serde(serialize_with = "fixed_width", deserialize_with = "from_fixed_width")]
+    #[schemars(with = "String")]
+    best: f64,
+    #[serde(serialize_with = "fixed_width", deserialize_with = "from_fixed_width")]
+    #[schemars(with = "String")]
+    worst: f64,
+    #[serde(serialize_with = "fixed_width", deserialize_with = "from_fixed_width")]
+    #[schemars(with = "String")]
+    stddev: f64,
+    #[serde(serialize_with = "fixed_width", deserialize_with = "from_fixed_width")]
+    #[schemars(with = "String")]
+    jitter: f64,
+    #[serde(
+        default,
+        serialize_with = "fixed_width",
+        deserialize_with = "from_fixed_width"
+    )]
+    #[schemars(with = "String")]
+    ewma: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mpls: Option<Vec<ReportMplsLabel>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icmp_timestamps: Option<ReportIcmpTimestamps>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tcp_outcome: Option<ReportTcpOutcome>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tcp_port_outcomes: Vec<ReportTcpPortOutcome>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tcp_dest_ports: Vec<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    unreachable: Option<ReportUnreachable>,
+    #[serde(default)]
+    return_path_hint: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    percentiles: Vec<ReportPercentile>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    addr_change_count: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    addr_changes: Vec<ReportAddrChange>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    skipped: bool,
+}
+
+/// A new address which responded at a hop after another address had already responded, and the round it first did.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReportAddrChange {
+    ip: String,
+    round: usize,
+}
+
+/// The round-trip time at a percentile of the recent responses from a hop, zero if there are none.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReportPercentile {
+    percentile: f64,
+    #[serde(serialize_with = "fixed_width", deserialize_with = "from_fixed_width")]
+    #[schemars(with = "String")]
+    rtt: f64,
+}
+
+/// A single entry of the `MPLS` label stack returned by a hop.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReportMplsLabel {
+    label: u32,
+    tc: u8,
+    bottom_of_stack: bool,
     ttl: u8,
-    hosts: Vec<Host>,
-    #[serde(serialize_with = "fixed_width")]
+}
+
+/// The timestamps of the most recent `ICMP` `TimestampReply` from a hop, in milliseconds since midnight UT.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReportIcmpTimestamps {
+    originate: u32,
+    receive: u32,
+    transmit: u32,
+    #[serde(default)]
+    arrive: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    clock_offset_ms: Option<i64>,
+}
+
+/// Whether the port of the most recent `TCP` `SYN-ACK` or `RST` received from the target host was open, or unfiltered if
+/// the probe was an `ACK`.
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportTcpOutcome {
+    Open,
+    Closed,
+    Unfiltered,
+}
+
+impl From<TcpOutcome> for ReportTcpOutcome {
+    fn from(outcome: TcpOutcome) -> Self {
+        match outcome {
+            TcpOutcome::Open => Self::Open,
+            TcpOutcome::Closed => Self::Closed,
+            TcpOutcome::Unfiltered => Self::Unfiltered,
+        }
+    }
+}
+
+/// The code of the most recent `ICMP` `DestinationUnreachable` from a hop and the reason it gives.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReportUnreachable {
+    code: u8,
+    reason: ReportUnreachableReason,
+}
+
+/// The reason given by the code of an `ICMP` `DestinationUnreachable`, `port` for a `udp` probe which reached the
+/// target host and `admin_prohibited` for a firewall.
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportUnreachableReason {
+    Net,
+    Host,
+    Protocol,
+    Port,
+    FragmentationNeeded,
+    AdminProhibited,
+    Other,
+}
+
+impl From<UnreachableReason> for ReportUnreachableReason {
+    fn from(reason: UnreachableReason) -> Self {
+        match reason {
+            UnreachableReason::Net => Self::Net,
+            UnreachableReason::Host => Self::Host,
+            UnreachableReason::Protocol => Self::Protocol,
+            UnreachableReason::Port => Self::Port,
+            UnreachableReason::FragmentationNeeded => Self::FragmentationNeeded,
+            UnreachableReason::AdminProhibited => Self::AdminProhibited,
+            UnreachableReason::Other => Self::Other,
+        }
+    }
+}
+
+/// Whether a configured `tcp` destination port was most recently found open or closed at the target host.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReportTcpPortOutcome {
+    port: u16,
+    outcome: ReportTcpOutcome,
+}
+
+impl ReportHop {
+    pub fn new(
+        hop: &Hop,
+        resolver: &DnsResolver,
+        geoip: Option<&GeoIpLookup>,
+        percentiles: &[f64],
+    ) -> Self {
+        let hosts: Vec<_> = hop
+            .addrs()
+            .map(|ip| Host {
+                ip: ip.to_string(),
+                hostname: resolver.reverse_lookup(*ip).to_string(),
+                geo: geoip.and_then(|geoip| geoip.lookup(*ip)),
+                label: resolver.label(*ip),
+            })
+            .collect();
+        Self {
+            ttl: hop.ttl(),
+            hosts,
+            loss_pct: hop.loss_pct(),
+            sent: hop.total_sent(),
+            last: hop.last_ms().unwrap_or_default(),
+            recv: hop.total_recv(),
+            pending: hop.total_pending(),
+            avg: hop.avg_ms(),
+            best: hop.best_ms().unwrap_or_default(),
+            worst: hop.worst_ms().unwrap_or_default(),
+            stddev: hop.stddev_ms(),
+            jitter: hop.jitter_ms(),
+            ewma: hop.ewma_ms().unwrap_or_default(),
+            mpls: hop.mpls().map(|mpls| {
+                mpls.labels()
+                    .iter()
+                    .map(|label| ReportMplsLabel {
+                        label: label.label,
+                        tc: label.tc,
+                        bottom_of_stack: label.bottom_of_stack,
+                        ttl: label.ttl,
+                    })
+                    .collect()
+            }),
+            icmp_timestamps: hop
+                .icmp_timestamps()
+                .map(|timestamps| ReportIcmpTimestamps {
+                    originate: timestamps.originate,
+                    receive: timestamps.receive,
+                    transmit: timestamps.transmit,
+                    arrive: timestamps.arrive,
+                    clock_offset_ms: timestamps.clock_offset_ms(),
+                }),
+            tcp_outcome: hop.tcp_outcome().map(ReportTcpOutcome::from),
+            tcp_port_outcomes: hop
+                .tcp_port_outcomes()
+                .map(|(port, outcome)| ReportTcpPortOutcome {
+                    port,
+                    outcome: outcome.into(),
+                })
+                .collect(),
+            tcp_dest_ports: hop.tcp_dest_ports().collect(),
+            unreachable: hop.unreachable_code().map(|code| ReportUnreachable {
+                code: code.0,
+                reason: code.reason().into(),
+            }),
+            return_path_hint: hop.return_path_hint(),
+            percentiles: percentiles
+                .iter()
+                .zip(hop.percentiles_ms(percentiles))
+                .map(|(&percentile, rtt)| ReportPercentile {
+                    percentile,
+                    rtt: rtt.unwrap_or_default(),
+                })
+                .collect(),
+            addr_change_count: hop.addr_change_count(),
+            addr_changes: hop
+                .addr_changes()
+                .iter()
+                .map(|change| ReportAddrChange {
+                    ip: change.addr.to_string(),
+                    round: change.round,
+                })
+                .collect(),
+            skipped: hop.is_skipped(),
+        }
+    }
+}
+
+impl ReportHop {
+    /// The statistics of this hop, from which the hop is replayed.
+    pub fn stats(&self) -> HopStats {
+        HopStats {
+            ttl: self.ttl,
+            addrs: self
+                .hosts
+                .iter()
+                .filter_map(|host| host.ip.parse().ok())
+                .collect(),
+            sent: self.sent,
+            recv: self.recv,
+            pending: self.pending,
+            last_ms: self.last,
+            avg_ms: self.avg,
+            best_ms: self.best,
+            worst_ms: self.worst,
+            stddev_ms: self.stddev,
+            jitter_ms: self.jitter,
+            ewma_ms: self.ewma,
+            tcp_dest_ports: self.tcp_dest_ports.clone(),
+            return_path_hint: self.return_path_hint,
+        }
+    }
+}
+
+/// The statistics of connections to the target via a proxy, formatted as for a hop.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReportProxy {
+    target: String,
+    #[serde(serialize_with = "fixed_width", deserialize_with = "from_fixed_width")]
+    #[schemars(with = "String")]
     loss_pct: f64,
     sent: usize,
-    #[serde(serialize_with = "fixed_width")]
+    #[serde(serialize_with = "fixed_width", deserialize_with = "from_fixed_width")]
+    #[schemars(with = "String")]
     last: f64,
     recv: usize,
-    #[serde(serialize_with = "fixed_width")]
+    #[serde(serialize_with = "fixed_width", deserialize_with = "from_fixed_width")]
+    #[schemars(with = "String")]
     avg: f64,
-    #[serde(serialize_with = "fixed_width")]
+    #[serde(serialize_with = "fixed_width", deserialize_with = "from_fixed_width")]
+    #[schemars(with = "String")]
     best: f64,
-    #[serde(serialize_with = "fixed_width")]
+    #[serde(serialize_with = "fixed_width", deserialize_with = "from_fixed_width")]
+    #[schemars(with = "String")]
     worst: f64,
-    #[serde(serialize_with = "fixed_width")]
-    stddev: f64,
 }
 
-#[derive(Serialize)]
+impl From<&ProxyStats> for ReportProxy {
+    fn from(proxy: &ProxyStats) -> Self {
+        Self {
+            target: proxy.target().to_string(),
+            loss_pct: proxy.loss_pct(),
+            sent: proxy.total_sent(),
+            last: proxy.last_ms().unwrap_or_default(),
+            recv: proxy.total_recv(),
+            avg: proxy.avg_ms(),
+            best: proxy.best_ms().unwrap_or_default(),
+            worst: proxy.worst_ms().unwrap_or_default(),
+        }
+    }
+}
+
+/// The host observed at each hop for a single flow, `???` if none.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReportFlow {
+    flow_id: u16,
+    hops: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct Host {
     pub ip: String,
     pub hostname: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geo: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+const fn is_false(val: &bool) -> bool {
+    !*val
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+const fn is_zero(val: &usize) -> bool {
+    *val == 0
 }
 
 #[allow(clippy::trivially_copy_pass_by_ref)]
@@ -90,119 +575,322 @@ fn fixed_width<S>(val: &f64, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    serializer.serialize_str(&format!("{:.2}", val))
+    serializer.serialize_str(&format!("{val:.2}"))
 }
 
-/// Generate a CSV report of trace data.
-pub fn run_report_json(
-    hostname: &str,
-    target_addr: IpAddr,
-    report_cycles: usize,
-    resolver: &DnsResolver,
-    trace_data: &Arc<RwLock<Trace>>,
-) {
-    let trace = wait_for_round(trace_data, report_cycles);
+/// Parse a value formatted by `fixed_width`.
+fn from_fixed_width<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(serde::de::Error::custom)
+}
+
+/// The JSON report document, a single report is rendered as an object and multiple reports are rendered as an array.
+#[derive(Serialize, JsonSchema)]
+#[serde(untagged)]
+enum ReportDocument<'a> {
+    Single(&'a Report),
+    Multiple(&'a [Report]),
+}
+
+/// Generate a JSON report of trace data.
+pub fn run_report_json(out: &mut dyn Write, reports: &[Report]) -> io::Result<()> {
+    let document = match reports {
+        [report] => ReportDocument::Single(report),
+        reports => ReportDocument::Multiple(reports),
+    };
+    writeln!(out, "{}", serde_json::to_string_pretty(&document).unwrap())
+}
+
+/// A JSON report document as read, either a single report or an array of reports.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ReportInput {
+    Single(Box<Report>),
+    Multiple(Vec<Report>),
+}
+
+/// Read the reports from the JSON report document at `path`.
+pub fn read_reports(path: &Path) -> anyhow::Result<Vec<Report>> {
+    let data = fs::read_to_string(path)
+        .map_err(|err| anyhow!("failed to read report {}: {}", path.display(), err))?;
+    let document = serde_json::from_str(&data)
+        .map_err(|err| anyhow!("failed to parse report {}: {}", path.display(), err))?;
+    Ok(match document {
+        ReportInput::Single(report) => vec![*report],
+        ReportInput::Multiple(reports) => reports,
+    })
+}
+
+/// Print the JSON schema of the JSON report document.
+pub fn print_json_schema() {
+    let schema = schema_for!(ReportDocument<'_>);
+    println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+}
+
+/// Build a `Report` of trace data.
+pub fn report_json(info: &TuiTraceInfo, options: &ReportOptions<'_>) -> Report {
+    let ReportOptions {
+        report_cycles,
+        resolver,
+        lookup_as_info,
+        geoip,
+        timestamp_format,
+        ..
+    } = *options;
+    let trace = wait_for_round(&info.data, report_cycles);
+    let timestamp = timestamp_format.format(chrono::Local::now());
     let hops: Vec<ReportHop> = trace
         .hops()
         .iter()
-        .map(|hop| {
-            let hosts: Vec<_> = hop
-                .addrs()
-                .map(|ip| Host {
-                    ip: ip.to_string(),
-                    hostname: resolver.reverse_lookup(*ip).to_string(),
-                })
-                .collect();
-            ReportHop {
-                ttl: hop.ttl(),
-                hosts,
-                loss_pct: hop.loss_pct(),
-                sent: hop.total_sent(),
-                last: hop.last_ms().unwrap_or_default(),
-                recv: hop.total_recv(),
-                avg: hop.avg_ms(),
-                best: hop.best_ms().unwrap_or_default(),
-                worst: hop.worst_ms().unwrap_or_default(),
-                stddev: hop.stddev_ms(),
-            }
+        .map(|hop| ReportHop::new(hop, resolver, geoip, trace.percentiles()))
+        .collect();
+    let flows: Vec<ReportFlow> = trace
+        .flows()
+        .map(|(flow_id, hosts)| ReportFlow {
+            flow_id,
+            hops: hosts
+                .iter()
+                .map(|host| host.map_or_else(|| String::from("???"), |ip| ip.to_string()))
+                .collect(),
         })
         .collect();
 
-    let report = Report {
+    Report {
         info: ReportInfo {
             target: Host {
-                ip: target_addr.to_string(),
-                hostname: hostname.to_string(),
+                ip: info.current_target_addr().to_string(),
+                hostname: info.target_hostname.clone(),
+                geo: None,
+                label: None,
             },
+            timestamp,
+            tos: info.tos.map(|tos| format!("{tos:#04x}")),
+            description: info.description.clone(),
         },
         hops,
-    };
-    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        flows,
+        as_path: lookup_as_info.then(|| as_path(&trace, resolver)),
+        proxy: trace.proxy().map(ReportProxy::from),
+    }
 }
 
 /// Generate a markdown table report of trace data.
+///
+/// If `markdown_rich` is set then each hop is marked with its status, by its loss, and the values of the target hop
+/// are in bold.
 pub fn run_report_table_markdown(
-    report_cycles: usize,
-    resolver: &DnsResolver,
+    out: &mut dyn Write,
     trace_data: &Arc<RwLock<Trace>>,
-) {
-    run_report_table(report_cycles, resolver, trace_data, ASCII_MARKDOWN);
+    options: &ReportOptions<'_>,
+) -> io::Result<()> {
+    run_report_table(
+        out,
+        trace_data,
+        options,
+        options.markdown_rich,
+        ASCII_MARKDOWN,
+    )
+}
+
+/// The cells of a row of a table report of a hop with `loss_pct` loss, if not skipped.
+///
+/// If `rich` then the row is prefixed with the status of the hop and, if `bold`, each value is in bold.
+fn table_row(
+    values: impl Iterator<Item = String>,
+    rich: Option<MarkdownRich>,
+    loss_pct: Option<f64>,
+    bold: bool,
+) -> Vec<String> {
+    match rich {
+        Some(rich) => {
+            let status = loss_pct.map_or("", |loss_pct| rich.status(loss_pct));
+            std::iter::once(status.to_string())
+                .chain(values.map(|value| {
+                    if bold && !value.is_empty() {
+                        format!("**{value}**")
+                    } else {
+                        value
+                    }
+                }))
+                .collect()
+        }
+        None => values.collect(),
+    }
 }
 
 /// Generate a pretty table report of trace data.
 pub fn run_report_table_pretty(
-    report_cycles: usize,
-    resolver: &DnsResolver,
+    out: &mut dyn Write,
     trace_data: &Arc<RwLock<Trace>>,
-) {
-    run_report_table(report_cycles, resolver, trace_data, UTF8_FULL);
+    options: &ReportOptions<'_>,
+) -> io::Result<()> {
+    run_report_table(out, trace_data, options, None, UTF8_FULL)
 }
 
 fn run_report_table(
-    report_cycles: usize,
-    resolver: &DnsResolver,
+    out: &mut dyn Write,
     trace_data: &Arc<RwLock<Trace>>,
+    options: &ReportOptions<'_>,
+    rich: Option<MarkdownRich>,
     preset: &str,
-) {
+) -> io::Result<()> {
+    let ReportOptions {
+        report_cycles,
+        resolver,
+        lookup_as_info,
+        columns,
+        timestamp_format,
+        unknown_hops,
+        ..
+    } = *options;
     let trace = wait_for_round(trace_data, report_cycles);
-    let columns = vec![
-        "Hop", "Addrs", "Loss%", "Snt", "Recv", "Last", "Avg", "Best", "Wrst", "StdDev",
-    ];
+    let timestamp = timestamp_format.format(chrono::Local::now());
+    if columns.contains(&ReportColumn::Asn) {
+        wait_for_asinfo(&trace, resolver);
+    }
     let mut table = Table::new();
     table
         .load_preset(preset)
         .set_content_arrangement(ContentArrangement::Dynamic)
-        .set_header(columns);
-    for hop in trace.hops().iter() {
-        let ttl = hop.ttl().to_string();
-        let hosts = hop
-            .addrs()
-            .map(|ip| resolver.reverse_lookup(*ip).to_string())
-            .join("\n");
-        let host = if hosts.is_empty() {
-            String::from("???")
-        } else {
-            hosts
-        };
-        let sent = hop.total_sent().to_string();
-        let recv = hop.total_recv().to_string();
-        let last = hop
-            .last_ms()
-            .map_or_else(|| String::from("???"), |last| format!("{:.1}", last));
-        let best = hop
-            .best_ms()
-            .map_or_else(|| String::from("???"), |best| format!("{:.1}", best));
-        let worst = hop
-            .worst_ms()
-            .map_or_else(|| String::from("???"), |worst| format!("{:.1}", worst));
-        let stddev = format!("{:.1}", hop.stddev_ms());
-        let avg = format!("{:.1}", hop.avg_ms());
-        let loss_pct = format!("{:.1}", hop.loss_pct());
-        table.add_row(vec![
-            &ttl, &host, &loss_pct, &sent, &recv, &last, &avg, &best, &worst, &stddev,
-        ]);
-    }
-    println!("{table}");
+        .set_header(
+            rich.map(|_| String::from("Status")).into_iter().chain(
+                columns
+                    .iter()
+                    .flat_map(|column| column.headers(trace.percentiles())),
+            ),
+        );
+    let hops = trace.hops();
+    let unpinned_hops = trace.unpinned_hops();
+    let order = (0..hops.len()).collect::<Vec<_>>();
+    for row in trace.hop_rows(&order, unknown_hops.collapse) {
+        match row {
+            HopRow::Hop(index) | HopRow::Unpinned(index) => {
+                let hop = if matches!(row, HopRow::Unpinned(_)) {
+                    &unpinned_hops[index]
+                } else {
+                    &hops[index]
+                };
+                let values = columns
+                    .iter()
+                    .flat_map(|column| column.values(hop, trace.percentiles(), false, options));
+                let loss_pct = (!hop.is_skipped()).then(|| hop.loss_pct());
+                table.add_row(table_row(values, rich, loss_pct, trace.is_target(hop)));
+            }
+            HopRow::Unresponsive(range) => {
+                let values = columns.iter().flat_map(|column| {
+                    column.collapsed_values(&hops[range.clone()], trace.percentiles())
+                });
+                table.add_row(table_row(values, rich, Some(100_f64), false));
+            }
+        }
+    }
+    writeln!(out, "{table}")?;
+    writeln!(out, "Timestamp: {timestamp}")?;
+    if lookup_as_info {
+        writeln!(out, "AS Path: {}", as_path(&trace, resolver).join(" -> "))?;
+    }
+    if let Some(proxy) = trace.proxy() {
+        writeln!(
+            out,
+            "Proxy: {} Loss%: {:.1} Snt: {} Recv: {} Last: {} Avg: {:.1} Best: {} Wrst: {}",
+            proxy.target(),
+            proxy.loss_pct(),
+            proxy.total_sent(),
+            proxy.total_recv(),
+            proxy
+                .last_ms()
+                .map_or_else(|| String::from("???"), |last| format!("{last:.1}")),
+            proxy.avg_ms(),
+            proxy
+                .best_ms()
+                .map_or_else(|| String::from("???"), |best| format!("{best:.1}")),
+            proxy
+                .worst_ms()
+                .map_or_else(|| String::from("???"), |worst| format!("{worst:.1}")),
+        )?;
+    }
+    Ok(())
+}
+
+/// Write a report to the file at `path`, creating any missing parent directories.
+///
+/// The report is first written to a temporary file alongside `path` which is then renamed such that a partially
+/// written report is never observed.
+pub fn write_report_file(path: &Path, report: &[u8]) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, report)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// The sequence of autonomous systems (AS) traversed by the trace, such as `AS13335 -> * -> AS15169`.
+///
+/// The AS of each hop is taken from the address which responded most frequently at that hop, hops without an AS
+/// (such as private addresses and hops which did not respond) are shown as `*` and consecutive identical entries are
+/// deduplicated.
+///
+/// As reverse DNS lookups are performed in the background we wait, up to the DNS timeout, for any pending lookups
+/// to complete.
+fn as_path(trace: &Trace, resolver: &DnsResolver) -> Vec<String> {
+    let addrs: Vec<_> = trace
+        .hops()
+        .iter()
+        .map(|hop| {
+            hop.addrs_with_counts()
+                .max_by_key(|(_, &count)| count)
+                .map(|(addr, _)| *addr)
+        })
+        .collect();
+    let start = SystemTime::now();
+    loop {
+        let entries: Vec<_> = addrs
+            .iter()
+            .map(|addr| addr.map(|addr| resolver.reverse_lookup_with_asinfo(addr)))
+            .collect();
+        let pending = entries
+            .iter()
+            .any(|entry| matches!(entry, Some(DnsEntry::Pending(_))));
+        let elapsed = start.elapsed().unwrap_or_default();
+        if !pending || elapsed > resolver.config().timeout {
+            return entries
+                .into_iter()
+                .map(|entry| match entry {
+                    Some(DnsEntry::Resolved(Resolved::WithAsInfo(_, _, asinfo)))
+                        if !asinfo.asn.is_empty() =>
+                    {
+                        format!("AS{}", asinfo.asn)
+                    }
+                    _ => String::from("*"),
+                })
+                .dedup()
+                .collect();
+        }
+        sleep(ROUND_POLL_INTERVAL);
+    }
+}
+
+/// Wait, up to the DNS timeout, for the reverse DNS lookups with AS information of every address of the trace to
+/// complete.
+fn wait_for_asinfo(trace: &Trace, resolver: &DnsResolver) {
+    let addrs: Vec<_> = trace.hops().iter().flat_map(Hop::addrs).copied().collect();
+    let start = SystemTime::now();
+    while addrs.iter().any(|addr| {
+        matches!(
+            resolver.reverse_lookup_with_asinfo(*addr),
+            DnsEntry::Pending(_)
+        )
+    }) && start.elapsed().unwrap_or_default() <= resolver.config().timeout
+    {
+        sleep(ROUND_POLL_INTERVAL);
+    }
 }
 
 /// Display a continuous stream of trace data.
@@ -210,44 +898,169 @@ pub fn run_report_stream(
     hostname: &str,
     target_addr: IpAddr,
     interval: Duration,
+    rtt_format: RttFormat,
     trace_data: &Arc<RwLock<Trace>>,
 ) {
-    println!("Tracing to {} ({})", hostname, target_addr);
-    loop {
+    println!("Tracing to {hostname} ({target_addr})");
+    while !shutdown_requested() {
         let trace_data = trace_data.read().clone();
         for hop in trace_data.hops() {
             let ttl = hop.ttl();
             let addrs = hop.addrs().collect::<Vec<_>>();
             let sent = hop.total_sent();
             let recv = hop.total_recv();
+            let pending = hop.total_pending();
             let last = hop
                 .last_ms()
-                .map(|last| format!("{:.1}", last))
+                .map(|last| rtt_format.format(last))
                 .unwrap_or_default();
             let best = hop
                 .best_ms()
-                .map(|best| format!("{:.1}", best))
+                .map(|best| rtt_format.format(best))
                 .unwrap_or_default();
             let worst = hop
                 .worst_ms()
-                .map(|worst| format!("{:.1}", worst))
+                .map(|worst| rtt_format.format(worst))
+                .unwrap_or_default();
+            let stddev = rtt_format.format(hop.stddev_ms());
+            let jitter = rtt_format.format(hop.jitter_ms());
+            let avg = rtt_format.format(hop.avg_ms());
+            let ewma = hop
+                .ewma_ms()
+                .map(|ewma| rtt_format.format(ewma))
                 .unwrap_or_default();
-            let stddev = hop.stddev_ms();
-            let avg = hop.avg_ms();
             let loss_pct = hop.loss_pct();
             println!(
-                "ttl={} addrs={:?} loss_pct={:.1}, sent={} recv={} last={} best={} worst={} avg={:.1} stddev={:.1}",
-                ttl, addrs, loss_pct, sent, recv, last, best, worst, avg, stddev
+                "ttl={ttl} addrs={addrs:?} loss_pct={loss_pct:.1}, sent={sent} recv={recv} pending={pending} last={last} best={best} worst={worst} avg={avg} ewma={ewma} stddev={stddev} jitter={jitter}"
             );
         }
         sleep(interval);
     }
 }
 
+/// A completed round of tracing for a single target.
+#[derive(Serialize)]
+pub struct RoundReport {
+    round: usize,
+    timestamp: String,
+    target: Host,
+    hops: Vec<RoundReportHop>,
+}
+
+/// The state of a hop after a round, including the RTT samples recorded since the prior round was reported.
+#[derive(Serialize)]
+struct RoundReportHop {
+    #[serde(flatten)]
+    hop: ReportHop,
+    samples: Vec<String>,
+}
+
+/// Tracks the rounds of a trace which have been reported such that each completed round is reported once.
+pub struct RoundTracker {
+    round: Option<usize>,
+    sent: HashMap<u8, usize>,
+}
+
+impl RoundTracker {
+    pub fn new() -> Self {
+        Self {
+            round: None,
+            sent: HashMap::new(),
+        }
+    }
+
+    /// Build a `RoundReport` if a round has completed since the prior call.
+    ///
+    /// If several rounds have completed since the prior call then a single `RoundReport` is built for the latest
+    /// round which includes all samples recorded since the prior call.
+    pub fn next_round(
+        &mut self,
+        hostname: &str,
+        target_addr: IpAddr,
+        resolver: &DnsResolver,
+        geoip: Option<&GeoIpLookup>,
+        timestamp_format: &TimestampFormat,
+        trace: &Trace,
+    ) -> Option<RoundReport> {
+        if trace.hops().is_empty() || self.round == Some(trace.round()) {
+            return None;
+        }
+        self.round = Some(trace.round());
+        let hops = trace
+            .hops()
+            .iter()
+            .map(|hop| {
+                let prior = self.sent.insert(hop.ttl(), hop.total_sent()).unwrap_or(0);
+                let new_samples = hop.total_sent().saturating_sub(prior);
+                let samples = hop
+                    .samples()
+                    .iter()
+                    .take(new_samples)
+                    .rev()
+                    .map(|sample| format!("{:.2}", sample.as_secs_f64() * 1000_f64))
+                    .collect();
+                RoundReportHop {
+                    hop: ReportHop::new(hop, resolver, geoip, trace.percentiles()),
+                    samples,
+                }
+            })
+            .collect();
+        Some(RoundReport {
+            round: trace.round(),
+            timestamp: timestamp_format.format(chrono::Local::now()),
+            target: Host {
+                ip: target_addr.to_string(),
+                hostname: hostname.to_string(),
+                geo: None,
+                label: None,
+            },
+            hops,
+        })
+    }
+}
+
+/// Display a continuous stream of trace data as JSON, one compact object per completed round.
+///
+/// Each object is flushed to stdout immediately.  The stream ends cleanly if stdout is closed.
+pub fn run_report_json_stream(
+    traces: &[TuiTraceInfo],
+    resolver: &DnsResolver,
+    geoip: Option<&GeoIpLookup>,
+    timestamp_format: &TimestampFormat,
+) -> anyhow::Result<()> {
+    let mut trackers: Vec<_> = traces.iter().map(|_| RoundTracker::new()).collect();
+    let stdout = io::stdout();
+    while !shutdown_requested() {
+        for (info, tracker) in traces.iter().zip(&mut trackers) {
+            let trace = info.data.read().clone();
+            if let Some(round) = tracker.next_round(
+                &info.target_hostname,
+                info.target_addr,
+                resolver,
+                geoip,
+                timestamp_format,
+                &trace,
+            ) {
+                let mut stdout = stdout.lock();
+                let written = writeln!(stdout, "{}", serde_json::to_string(&round)?)
+                    .and_then(|()| stdout.flush());
+                match written {
+                    Err(err) if err.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                    written => written?,
+                }
+            }
+        }
+        sleep(ROUND_POLL_INTERVAL);
+    }
+    Ok(())
+}
+
 /// Block until trace data for round `round` is available.
-fn wait_for_round(trace_data: &Arc<RwLock<Trace>>, round: usize) -> Trace {
+///
+/// If a shutdown is requested then the trace data of the rounds completed so far is returned immediately.
+pub fn wait_for_round(trace_data: &Arc<RwLock<Trace>>, round: usize) -> Trace {
     let mut trace = trace_data.read().clone();
-    while trace.round() < round - 1 {
+    while trace.round() < round - 1 && !shutdown_requested() {
         trace = trace_data.read().clone();
         sleep(Duration::from_millis(100));
     }
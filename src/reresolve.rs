@@ -0,0 +1,55 @@
+use crate::dns::DnsResolver;
+use std::net::IpAddr;
+use std::thread;
+use std::thread::sleep;
+use std::time::Duration;
+use trippy::tracing::TargetHandle;
+
+/// Resolve the hostname of each target every `interval` and trace to the new address if it no longer resolves to the
+/// address being traced.
+///
+/// The address being traced is kept for as long as the hostname resolves to it, such that a target whose hostname
+/// resolves to several addresses in turn is not switched every interval.  Otherwise the first address of the same
+/// family is traced from the next round.  If `log` is set then each change, and any failure to resolve, is reported on
+/// stderr.
+pub fn run_reresolve(
+    resolver: DnsResolver,
+    interval: Duration,
+    targets: Vec<(String, TargetHandle)>,
+    log: bool,
+) -> anyhow::Result<()> {
+    thread::Builder::new()
+        .name(String::from("reresolve"))
+        .spawn(move || loop {
+            sleep(interval);
+            for (hostname, target) in &targets {
+                match resolver.lookup(hostname) {
+                    Ok(addrs) => {
+                        let current = target.addr();
+                        if let Some(&addr) = next_addr(&addrs, current) {
+                            if log {
+                                eprintln!("target {hostname} re-resolved from {current} to {addr}");
+                            }
+                            target.set(addr);
+                        }
+                    }
+                    Err(err) if log => {
+                        eprintln!("failed to re-resolve target {hostname}: {err}");
+                    }
+                    Err(_) => {}
+                }
+            }
+        })?;
+    Ok(())
+}
+
+/// The address to trace from `addrs`, if `current` is not among them.
+fn next_addr(addrs: &[IpAddr], current: IpAddr) -> Option<&IpAddr> {
+    if addrs.contains(&current) {
+        None
+    } else {
+        addrs
+            .iter()
+            .find(|addr| addr.is_ipv4() == current.is_ipv4())
+    }
+}
@@ -0,0 +1,27 @@
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::flag;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// Set once a `SIGINT` or `SIGTERM` has been received.
+static SHUTDOWN: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// Install handlers for `SIGINT` and `SIGTERM` which request a graceful shutdown.
+///
+/// The first signal only sets the shutdown flag such that any partially collected data can be reported, a second
+/// signal terminates the process immediately.
+pub fn install_shutdown_handler() -> anyhow::Result<()> {
+    let shutdown = SHUTDOWN.get_or_init(|| Arc::new(AtomicBool::new(false)));
+    for signal in [SIGINT, SIGTERM] {
+        flag::register_conditional_shutdown(signal, 1, shutdown.clone())?;
+        flag::register(signal, shutdown.clone())?;
+    }
+    Ok(())
+}
+
+/// Has a graceful shutdown been requested?
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN
+        .get()
+        .is_some_and(|shutdown| shutdown.load(Ordering::Relaxed))
+}
@@ -0,0 +1,181 @@
+use crate::backend::TraceState;
+use crate::frontend::TuiTraceInfo;
+use crate::report::ROUND_POLL_INTERVAL;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::thread::sleep;
+
+/// The accumulated statistics of all traces, as saved to a state file.
+#[derive(Serialize, Deserialize)]
+struct SavedState {
+    targets: Vec<TargetState>,
+}
+
+/// The accumulated statistics of a single trace and the target they were collected for.
+#[derive(Serialize, Deserialize)]
+struct TargetState {
+    target: String,
+    addr: IpAddr,
+    protocol: String,
+    trace: TraceState,
+}
+
+impl TargetState {
+    /// Describe the target of the trace, such as `example.com (93.184.216.34) icmp`.
+    fn describe(target: &str, addr: IpAddr, protocol: &str) -> String {
+        format!("{target} ({addr}) {protocol}")
+    }
+}
+
+/// Restore the statistics of every trace from the state file at `path`, if it exists.
+///
+/// The state file must be for the same targets, in the same order, traced with the same protocol, otherwise nothing is
+/// restored and an error is returned such that the state file is never replaced by that of a different trace.
+pub fn load_state(path: &Path, traces: &[TuiTraceInfo]) -> anyhow::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let data = fs::read_to_string(path)
+        .map_err(|err| anyhow!("failed to read state file {}: {}", path.display(), err))?;
+    let saved: SavedState = serde_json::from_str(&data)
+        .map_err(|err| anyhow!("invalid state file {}: {}", path.display(), err))?;
+    let stored: Vec<_> = saved
+        .targets
+        .iter()
+        .map(|state| TargetState::describe(&state.target, state.addr, &state.protocol))
+        .collect();
+    let current: Vec<_> = traces
+        .iter()
+        .map(|info| TargetState::describe(&info.target_hostname, info.target_addr, &info.protocol))
+        .collect();
+    if stored != current {
+        return Err(anyhow!(
+            "state file {} is for {}, not {}",
+            path.display(),
+            stored.join(", "),
+            current.join(", ")
+        ));
+    }
+    for (info, state) in traces.iter().zip(saved.targets) {
+        info.data.write().restore(state.trace);
+    }
+    Ok(())
+}
+
+/// Save the statistics of all traces to `path` every time any trace completes a round.
+pub fn run_state_export(path: PathBuf, traces: Vec<TuiTraceInfo>) -> anyhow::Result<()> {
+    thread::Builder::new()
+        .name(String::from("state"))
+        .spawn(move || {
+            let mut rounds: Vec<Option<usize>> = vec![None; traces.len()];
+            loop {
+                let latest: Vec<_> = traces
+                    .iter()
+                    .map(|info| Some(info.data.read().round()))
+                    .collect();
+                if latest != rounds {
+                    if let Err(err) = write_state(&path, &traces) {
+                        eprintln!("state file export failed: {err}");
+                    }
+                    rounds = latest;
+                }
+                sleep(ROUND_POLL_INTERVAL);
+            }
+        })?;
+    Ok(())
+}
+
+/// Write the statistics of all traces to `path` as JSON.
+///
+/// The state is first written to a temporary file which then replaces `path` so that a crash while writing never
+/// loses the previously saved state.
+pub fn write_state(path: &Path, traces: &[TuiTraceInfo]) -> anyhow::Result<()> {
+    let saved = SavedState {
+        targets: traces
+            .iter()
+            .map(|info| TargetState {
+                target: info.target_hostname.clone(),
+                addr: info.target_addr,
+                protocol: info.protocol.clone(),
+                trace: info.data.read().state(),
+            })
+            .collect(),
+    };
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, serde_json::to_string(&saved)?)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{temp_path, trace, Response};
+
+    /// A trace to `target` with `protocol` of `rounds` rounds in which the target responded at the second hop.
+    fn trace_info(target: &str, protocol: &str, rounds: usize) -> TuiTraceInfo {
+        let responses: &[Response] = &[None, Some((10, 10))];
+        TuiTraceInfo {
+            protocol: protocol.to_string(),
+            ..crate::test_util::trace_info(target, trace(&vec![responses; rounds]))
+        }
+    }
+
+    fn sent_recv(info: &TuiTraceInfo) -> Vec<(u8, usize, usize)> {
+        info.data
+            .read()
+            .hops()
+            .iter()
+            .map(|hop| (hop.ttl(), hop.total_sent(), hop.total_recv()))
+            .collect()
+    }
+
+    #[test]
+    fn test_restore() {
+        let path = temp_path("restore.json");
+        write_state(&path, &[trace_info("example.com", "icmp", 3)]).unwrap();
+        let traces = [trace_info("example.com", "icmp", 0)];
+        load_state(&path, &traces).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(2, traces[0].data.read().round());
+        assert_eq!(vec![(1, 3, 0), (2, 3, 3)], sent_recv(&traces[0]));
+    }
+
+    #[test]
+    fn test_restore_missing_file() {
+        let traces = [trace_info("example.com", "icmp", 0)];
+        load_state(&temp_path("missing.json"), &traces).unwrap();
+        assert!(sent_recv(&traces[0]).is_empty());
+    }
+
+    #[test]
+    fn test_restore_other_target() {
+        let path = temp_path("other.json");
+        write_state(&path, &[trace_info("example.com", "icmp", 1)]).unwrap();
+        let traces = [trace_info("example.com", "udp", 0)];
+        let err = load_state(&path, &traces).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            format!(
+                "state file {} is for example.com (10.0.0.10) icmp, not example.com (10.0.0.10) udp",
+                path.display()
+            ),
+            err.to_string()
+        );
+        assert!(sent_recv(&traces[0]).is_empty());
+    }
+
+    #[test]
+    fn test_restore_invalid_file() {
+        let path = temp_path("invalid.json");
+        fs::write(&path, "{}").unwrap();
+        let result = load_state(&path, &[trace_info("example.com", "icmp", 0)]);
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+}
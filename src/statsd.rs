@@ -0,0 +1,199 @@
+use crate::backend::{Hop, Trace};
+use crate::frontend::TuiTraceInfo;
+use crate::report::ROUND_POLL_INTERVAL;
+use anyhow::anyhow;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::thread;
+use std::thread::sleep;
+
+/// The prefix of the name of every metric.
+const METRIC_PREFIX: &str = "trippy";
+
+/// The maximum size of a datagram of metrics, such that it does not fragment on a typical path.
+const MAX_DATAGRAM: usize = 1432;
+
+/// A `UDP` socket which pushes metrics to a `StatsD` endpoint.
+pub struct Statsd {
+    socket: UdpSocket,
+    addr: SocketAddr,
+}
+
+impl Statsd {
+    /// Resolve the `StatsD` endpoint `addr`, i.e. `127.0.0.1:8125` or `statsd.local:8125`, and bind a socket to send to it.
+    ///
+    /// The socket never blocks such that a slow or absent endpoint does not delay the trace.
+    pub fn connect(addr: &str) -> anyhow::Result<Self> {
+        let addr = addr
+            .to_socket_addrs()
+            .map_err(|err| anyhow!("invalid statsd address {}: {}", addr, err))?
+            .next()
+            .ok_or_else(|| anyhow!("invalid statsd address {}: no address found", addr))?;
+        let local = match addr {
+            SocketAddr::V4(_) => SocketAddr::from(([0, 0, 0, 0], 0)),
+            SocketAddr::V6(_) => SocketAddr::from(([0_u16; 8], 0)),
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket, addr })
+    }
+
+    /// Send the metrics in as few datagrams as possible, ignoring any failure to send.
+    fn send(&self, metrics: &[String]) {
+        let mut datagram = String::new();
+        for metric in metrics {
+            if !datagram.is_empty() && datagram.len() + metric.len() + 1 > MAX_DATAGRAM {
+                let _ = self.socket.send_to(datagram.as_bytes(), self.addr);
+                datagram.clear();
+            }
+            if !datagram.is_empty() {
+                datagram.push('\n');
+            }
+            datagram.push_str(metric);
+        }
+        if !datagram.is_empty() {
+            let _ = self.socket.send_to(datagram.as_bytes(), self.addr);
+        }
+    }
+}
+
+/// Push the round-trip time and loss of every hop to `StatsD` every time a trace completes a round.
+///
+/// Each metric is a gauge named `trippy.<target>.<ttl>.<addr>.<metric>`, where any character of the target and address
+/// which is not permitted in a metric name, such as `.` and `:`, is replaced with `_`.  Metrics are fire-and-forget,
+/// failures to send are not reported and never retried.
+pub fn run_statsd(statsd: Statsd, traces: Vec<TuiTraceInfo>) -> anyhow::Result<()> {
+    thread::Builder::new()
+        .name(String::from("statsd"))
+        .spawn(move || {
+            let mut rounds: Vec<Option<usize>> = vec![None; traces.len()];
+            loop {
+                for (info, round) in traces.iter().zip(&mut rounds) {
+                    let trace = info.data.read().clone();
+                    if trace.hops().is_empty() || *round == Some(trace.round()) {
+                        continue;
+                    }
+                    *round = Some(trace.round());
+                    statsd.send(&render_metrics(&info.target_hostname, &trace));
+                }
+                sleep(ROUND_POLL_INTERVAL);
+            }
+        })?;
+    Ok(())
+}
+
+/// Render the metrics of every hop of a trace.
+///
+/// The `rtt_ms` gauge is only rendered for hops for which the most recent probe received a response, as for the
+/// Prometheus metrics.
+fn render_metrics(target: &str, trace: &Trace) -> Vec<String> {
+    let target = metric_component(target);
+    let mut metrics = vec![];
+    for hop in trace.hops() {
+        let addr = hop_addr(hop);
+        let mut gauge = |name: &str, value: f64| {
+            metrics.push(format!(
+                "{METRIC_PREFIX}.{target}.{}.{addr}.{name}:{value:.3}|g",
+                hop.ttl()
+            ));
+        };
+        if let Some(last) = hop.last_ms().filter(|_| hop.is_responding()) {
+            gauge("rtt_ms", last);
+        }
+        if hop.total_recv() > 0 {
+            gauge("avg_ms", hop.avg_ms());
+        }
+        gauge("loss_pct", hop.loss_pct());
+    }
+    metrics
+}
+
+/// The address of the most recent response from the hop as a metric name component, `unknown` if none.
+fn hop_addr(hop: &Hop) -> String {
+    hop.last_addr().map_or_else(
+        || String::from("unknown"),
+        |addr| metric_component(&addr.to_string()),
+    )
+}
+
+/// Replace the characters which are not permitted within a component of a `StatsD` or Graphite metric name.
+fn metric_component(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '.' | ':' | '|' | '@' | '/' | ' ' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// A trace of a round in which the first hop responded after 10ms and the probe of the second hop was lost.
+    fn trace() -> Trace {
+        crate::test_util::trace(&[&[Some((1, 10)), None]])
+    }
+
+    /// A `Statsd` which sends to a loopback socket, and that socket.
+    fn statsd() -> (Statsd, UdpSocket) {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let statsd = Statsd::connect(&server.local_addr().unwrap().to_string()).unwrap();
+        (statsd, server)
+    }
+
+    fn recv(server: &UdpSocket) -> String {
+        let mut buf = [0; 2 * MAX_DATAGRAM];
+        let len = server.recv(&mut buf).unwrap();
+        String::from_utf8(buf[..len].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_metric_component() {
+        assert_eq!("example_com", metric_component("example.com"));
+        assert_eq!("fe80__1", metric_component("fe80::1"));
+        assert_eq!("a_b_c_d_e", metric_component("a|b@c/d e"));
+        assert_eq!("a-b_c", metric_component("a-b_c"));
+    }
+
+    #[test]
+    fn test_render_metrics() {
+        assert_eq!(
+            vec![
+                "trippy.example_com.1.10_0_0_1.rtt_ms:10.000|g",
+                "trippy.example_com.1.10_0_0_1.avg_ms:10.000|g",
+                "trippy.example_com.1.10_0_0_1.loss_pct:0.000|g",
+                "trippy.example_com.2.unknown.loss_pct:100.000|g",
+            ],
+            render_metrics("example.com", &trace())
+        );
+    }
+
+    #[test]
+    fn test_send() {
+        let (statsd, server) = statsd();
+        statsd.send(&render_metrics("example.com", &trace()));
+        assert_eq!(
+            render_metrics("example.com", &trace()).join("\n"),
+            recv(&server)
+        );
+    }
+
+    #[test]
+    fn test_send_split() {
+        let (statsd, server) = statsd();
+        let metrics: Vec<_> = (0..100).map(|i| format!("trippy.{i:020}:1|g")).collect();
+        statsd.send(&metrics);
+        let mut received = vec![];
+        while received.len() < metrics.len() {
+            let datagram = recv(&server);
+            assert!(datagram.len() <= MAX_DATAGRAM);
+            received.extend(datagram.split('\n').map(String::from));
+        }
+        assert_eq!(metrics, received);
+    }
+}
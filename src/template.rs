@@ -0,0 +1,405 @@
+use crate::report::Report;
+use anyhow::anyhow;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// A template with which a report is rendered, read from a local file.
+///
+/// The template is text with mustache-like tags:
+///
+/// - `{{name}}` renders the value of a field of the report, such as `{{target}}`, `{{addr}}`, `{{timestamp}}` or
+///   `{{info.target.ip}}`, and nothing if the field is absent
+/// - `{{#name}}...{{/name}}` renders its content once for each element of a list, such as `{{#hops}}...{{/hops}}`,
+///   or once if the field is present and not `false` or empty
+/// - `{{^name}}...{{/name}}` renders its content only if the field is absent, `false` or empty
+/// - `{{! comment }}` renders nothing
+///
+/// Within a section the current element is named by the section without any trailing `s`, such as `{{hop.avg}}`
+/// within `{{#hops}}` or `{{host.hostname}}` within `{{#hop.hosts}}`, and is also `{{.}}`.  The fields of the current
+/// element may be used without the prefix, such as `{{avg}}`.  A section tag alone on a line renders no line at all.
+#[derive(Debug)]
+pub struct ReportTemplate {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Value(String),
+    Section {
+        name: String,
+        inverted: bool,
+        nodes: Vec<Self>,
+    },
+}
+
+/// A section being rendered and its current element.
+struct Frame<'a> {
+    name: Option<&'a str>,
+    value: &'a Value,
+}
+
+impl ReportTemplate {
+    /// Read and parse the template at `path`.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let data = fs::read_to_string(path)
+            .map_err(|err| anyhow!("failed to read report template {}: {}", path.display(), err))?;
+        let nodes = parse_template(&data)
+            .map_err(|err| anyhow!("invalid report template {}: {}", path.display(), err))?;
+        Ok(Self { nodes })
+    }
+
+    /// Render `report` with this template.
+    ///
+    /// The fields are those of the json report, along with `target`, `addr`, `timestamp` and `description`, if any, for
+    /// convenience.
+    pub fn render(&self, report: &Report) -> String {
+        let mut root = serde_json::to_value(report).unwrap_or_default();
+        if let Value::Object(fields) = &mut root {
+            fields.insert(
+                String::from("target"),
+                Value::from(report.info.target.hostname.as_str()),
+            );
+            fields.insert(
+                String::from("addr"),
+                Value::from(report.info.target.ip.as_str()),
+            );
+            fields.insert(
+                String::from("timestamp"),
+                Value::from(report.info.timestamp.as_str()),
+            );
+            if let Some(description) = &report.info.description {
+                fields.insert(
+                    String::from("description"),
+                    Value::from(description.as_str()),
+                );
+            }
+        }
+        let mut out = String::new();
+        let mut frames = vec![Frame {
+            name: None,
+            value: &root,
+        }];
+        render_nodes(&self.nodes, &mut frames, &mut out);
+        out
+    }
+}
+
+fn parse_template(template: &str) -> anyhow::Result<Vec<Node>> {
+    let mut nodes = vec![];
+    let mut open: Vec<(String, bool, Vec<Node>, usize)> = vec![];
+    let mut offset = 0;
+    while let Some(start) = template[offset..].find("{{").map(|start| offset + start) {
+        let line_number = template[..start].matches('\n').count() + 1;
+        let end = template[start..]
+            .find("}}")
+            .map(|end| start + end)
+            .ok_or_else(|| anyhow!("line {} has an unclosed tag", line_number))?;
+        let tag = template[start + 2..end].trim();
+        let (text_end, next) = match tag.chars().next() {
+            Some('#' | '^' | '/' | '!') => standalone_line(template, offset, start, end + 2),
+            _ => (start, end + 2),
+        };
+        if text_end > offset {
+            innermost(&mut nodes, &mut open)
+                .push(Node::Text(template[offset..text_end].to_string()));
+        }
+        if let Some(name) = tag.strip_prefix('#') {
+            open.push((tag_name(name, line_number)?, false, vec![], line_number));
+        } else if let Some(name) = tag.strip_prefix('^') {
+            open.push((tag_name(name, line_number)?, true, vec![], line_number));
+        } else if let Some(name) = tag.strip_prefix('/') {
+            let name = tag_name(name, line_number)?;
+            let (open_name, inverted, section, _) = open.pop().ok_or_else(|| {
+                anyhow!(
+                    "line {} closes section {} which is not open",
+                    line_number,
+                    name
+                )
+            })?;
+            if open_name != name {
+                return Err(anyhow!(
+                    "line {} closes section {} but section {} is open",
+                    line_number,
+                    name,
+                    open_name
+                ));
+            }
+            innermost(&mut nodes, &mut open).push(Node::Section {
+                name,
+                inverted,
+                nodes: section,
+            });
+        } else if !tag.starts_with('!') {
+            innermost(&mut nodes, &mut open).push(Node::Value(tag_name(tag, line_number)?));
+        }
+        offset = next;
+    }
+    if let Some((name, _, _, line_number)) = open.last() {
+        return Err(anyhow!(
+            "line {} opens section {} which is not closed",
+            line_number,
+            name
+        ));
+    }
+    if offset < template.len() {
+        nodes.push(Node::Text(template[offset..].to_string()));
+    }
+    Ok(nodes)
+}
+
+/// The nodes of the innermost open section, or of the template if no section is open.
+fn innermost<'a>(
+    nodes: &'a mut Vec<Node>,
+    open: &'a mut [(String, bool, Vec<Node>, usize)],
+) -> &'a mut Vec<Node> {
+    open.last_mut().map_or(nodes, |(_, _, nodes, _)| nodes)
+}
+
+/// The end of the text before the tag at `start..end` and the start of the text after it, excluding the line of the
+/// tag if the tag is alone on its line.
+fn standalone_line(template: &str, offset: usize, start: usize, end: usize) -> (usize, usize) {
+    let line_start = template[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = template[end..]
+        .find('\n')
+        .map_or(template.len(), |i| end + i + 1);
+    let blank = |text: &str| {
+        text.chars()
+            .all(|c| c == ' ' || c == '\t' || c == '\r' || c == '\n')
+    };
+    if line_start >= offset
+        && blank(&template[line_start..start])
+        && blank(&template[end..line_end])
+    {
+        (line_start, line_end)
+    } else {
+        (start, end)
+    }
+}
+
+/// Check that the name of a tag is `.` or a dotted path of fields, such as `hop.avg`.
+fn tag_name(name: &str, line_number: usize) -> anyhow::Result<String> {
+    let name = name.trim();
+    let valid = name == "."
+        || name.split('.').all(|field| {
+            !field.is_empty() && field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+    if valid {
+        Ok(name.to_string())
+    } else {
+        Err(anyhow!(
+            "line {} has an invalid tag: {{{{{}}}}}",
+            line_number,
+            name
+        ))
+    }
+}
+
+fn render_nodes<'a>(nodes: &'a [Node], frames: &mut Vec<Frame<'a>>, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Value(name) => {
+                if let Some(value) = lookup(frames, name) {
+                    render_value(value, out);
+                }
+            }
+            Node::Section {
+                name,
+                inverted,
+                nodes,
+            } => {
+                let value = lookup(frames, name).filter(|&value| is_present(value));
+                match (value, inverted) {
+                    (None, true) => render_nodes(nodes, frames, out),
+                    (Some(value), false) => {
+                        let field = name.rsplit('.').next().unwrap_or(name);
+                        let element = field.strip_suffix('s').unwrap_or(field);
+                        let elements = match value {
+                            Value::Array(elements) => elements.iter().collect(),
+                            value => vec![value],
+                        };
+                        for value in elements {
+                            frames.push(Frame {
+                                name: Some(element),
+                                value,
+                            });
+                            render_nodes(nodes, frames, out);
+                            frames.pop();
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Find the value of a field by name in the innermost section in which it is found.
+fn lookup<'a>(frames: &[Frame<'a>], name: &str) -> Option<&'a Value> {
+    if name == "." {
+        return frames.last().map(|frame| frame.value);
+    }
+    let mut fields = name.split('.');
+    let first = fields.next()?;
+    let value = frames.iter().rev().find_map(|frame| {
+        if frame.name == Some(first) {
+            Some(frame.value)
+        } else {
+            frame.value.get(first)
+        }
+    })?;
+    fields.try_fold(value, |value, field| value.get(field))
+}
+
+/// Is the value rendered by a section, i.e. not `null`, `false` or empty?
+fn is_present(value: &Value) -> bool {
+    match value {
+        Value::Null | Value::Bool(false) => false,
+        Value::String(value) => !value.is_empty(),
+        Value::Array(values) => !values.is_empty(),
+        _ => true,
+    }
+}
+
+/// Render a value, with the elements of a list separated by commas.
+fn render_value(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => {}
+        Value::String(value) => out.push_str(value),
+        Value::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                render_value(value, out);
+            }
+        }
+        value => out.push_str(&value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn render(template: &str, root: &Value) -> String {
+        let nodes = parse_template(template).unwrap();
+        let mut out = String::new();
+        render_nodes(
+            &nodes,
+            &mut vec![Frame {
+                name: None,
+                value: root,
+            }],
+            &mut out,
+        );
+        out
+    }
+
+    fn report() -> Report {
+        serde_json::from_value(json!({
+            "info": {
+                "target": {"ip": "10.0.0.10", "hostname": "example.com"},
+                "timestamp": "2022-01-01T00:00:00Z",
+                "description": "nightly",
+            },
+            "hops": [{
+                "ttl": 1,
+                "hosts": [{"ip": "10.0.0.1", "hostname": "gw"}, {"ip": "10.0.0.2", "hostname": "10.0.0.2"}],
+                "loss_pct": "0.00",
+                "sent": 1,
+                "last": "1.50",
+                "recv": 1,
+                "avg": "1.50",
+                "best": "1.50",
+                "worst": "1.50",
+                "stddev": "0.00",
+                "jitter": "0.00",
+            }],
+            "flows": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_values() {
+        let root = json!({"a": "x", "b": {"c": 2}, "d": [1, "y"], "e": null});
+        assert_eq!(
+            "x 2 1, y  !",
+            render("{{a}} {{ b.c }} {{d}} {{e}}{{missing}} !", &root)
+        );
+    }
+
+    #[test]
+    fn test_sections() {
+        let root = json!({"hops": [{"ttl": 1, "ok": true}, {"ttl": 2, "ok": false}], "name": "n"});
+        assert_eq!(
+            "1:yes:n 2:no:n ",
+            render(
+                "{{#hops}}{{hop.ttl}}:{{#ok}}yes{{/ok}}{{^ok}}no{{/ok}}:{{name}} {{/hops}}",
+                &root
+            )
+        );
+        assert_eq!("1 2 ", render("{{#hops}}{{ttl}} {{/hops}}", &root));
+        assert_eq!(
+            "empty",
+            render("{{#none}}x{{/none}}{{^none}}empty{{/none}}", &root)
+        );
+        assert_eq!(
+            "[a][b]",
+            render("{{#list}}[{{.}}]{{/list}}", &json!({"list": ["a", "b"]}))
+        );
+        assert_eq!(
+            "",
+            render(
+                "{{#list}}x{{/list}}{{#s}}y{{/s}}",
+                &json!({"list": [], "s": ""})
+            )
+        );
+    }
+
+    #[test]
+    fn test_standalone_lines() {
+        let root = json!({"hops": [{"ttl": 1}, {"ttl": 2}]});
+        assert_eq!(
+            "start\nhop 1\nhop 2\nend\n",
+            render(
+                "start\n  {{#hops}}\nhop {{ttl}}\n{{/hops}}  \n{{! comment }}\nend\n",
+                &root
+            )
+        );
+        assert_eq!("a 1 2 b", render("a {{#hops}}{{ttl}} {{/hops}}b", &root));
+    }
+
+    #[test]
+    fn test_render_report() {
+        let template = parse_template(
+            "{{target}} ({{addr}}) {{description}} {{timestamp}}\n{{#hops}}{{ttl}} {{avg}}{{#hop.hosts}} {{host.hostname}}{{/hop.hosts}}\n{{/hops}}",
+        )
+        .unwrap();
+        assert_eq!(
+            "example.com (10.0.0.10) nightly 2022-01-01T00:00:00Z\n1 1.50 gw 10.0.0.2\n",
+            ReportTemplate { nodes: template }.render(&report())
+        );
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        let error = |template: &str| parse_template(template).unwrap_err().to_string();
+        assert_eq!("line 2 has an unclosed tag", error("a\n{{b"));
+        assert_eq!("line 1 has an invalid tag: {{a b}}", error("{{a b}}"));
+        assert_eq!("line 1 has an invalid tag: {{a..b}}", error("{{a..b}}"));
+        assert_eq!("line 1 closes section a which is not open", error("{{/a}}"));
+        assert_eq!(
+            "line 1 closes section b but section a is open",
+            error("{{#a}}{{/b}}")
+        );
+        assert_eq!(
+            "line 2 opens section a which is not closed",
+            error("\n{{#a}}")
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! Fixtures shared by the tests of the modules of `trip`.
+
+use crate::backend::{Trace, TraceConfig};
+use crate::config::DnsResolveMethod;
+use crate::dns::{DnsResolver, DnsResolverConfig};
+use crate::frontend::TuiTraceInfo;
+use parking_lot::RwLock;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use trippy::tracing::{CompletionReason, Probe, ProbeStatus, TracerRound};
+
+/// The target address of every trace.
+pub const TARGET: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 10));
+
+/// The response to the probe of a hop in a round, the host `10.0.0.<host>` and the round-trip time in ms, or `None`
+/// if the probe was lost.
+pub type Response = Option<(u8, u64)>;
+
+/// A trace of a round per each of `rounds`, see `update`.
+pub fn trace(rounds: &[&[Response]]) -> Trace {
+    trace_with_buckets(&[], rounds)
+}
+
+/// A trace with the histogram buckets `buckets_ms` of a round per each of `rounds`, see `update`.
+pub fn trace_with_buckets(buckets_ms: &[u64], rounds: &[&[Response]]) -> Trace {
+    let mut trace = Trace::new(TraceConfig {
+        histogram_buckets: buckets_ms
+            .iter()
+            .copied()
+            .map(Duration::from_millis)
+            .collect(),
+        ..config()
+    });
+    for (round, responses) in rounds.iter().enumerate() {
+        update(&mut trace, round, responses);
+    }
+    trace
+}
+
+/// The configuration of every trace, which records up to 16 samples per hop and has no histogram buckets,
+/// percentiles or stats window.
+pub fn config() -> TraceConfig {
+    TraceConfig {
+        max_samples: 16,
+        histogram_buckets: vec![],
+        percentiles: vec![],
+        zero_loss_grace: Duration::ZERO,
+        tcp_dest_ports: vec![],
+        ewma_alpha: 0.1,
+        stats_window: None,
+        pinned_hops: BTreeMap::new(),
+    }
+}
+
+/// Update `trace` from `round`, in which a probe was sent at the epoch to each ttl from 1 with a response of each of
+/// `responses`.
+///
+/// The round completes when the target responded to the probe of the last ttl, otherwise it times out.
+pub fn update(trace: &mut Trace, round: usize, responses: &[Response]) {
+    update_at(trace, round, SystemTime::UNIX_EPOCH, responses);
+}
+
+/// Update `trace` from `round`, as for `update`, in which each probe was sent at `sent`.
+pub fn update_at(trace: &mut Trace, round: usize, sent: SystemTime, responses: &[Response]) {
+    let probes: Vec<_> = responses
+        .iter()
+        .zip(1_u8..)
+        .map(|(response, ttl)| {
+            let sequence = round * responses.len() + usize::from(ttl) - 1;
+            let probe = Probe::new(
+                (sequence as u16).into(),
+                ttl.into(),
+                round.into(),
+                0.into(),
+                sent,
+            );
+            match response {
+                Some((host, rtt_ms)) => probe
+                    .with_status(ProbeStatus::Complete)
+                    .with_host(IpAddr::V4(Ipv4Addr::new(10, 0, 0, *host)))
+                    .with_received(sent + Duration::from_millis(*rtt_ms)),
+                None => probe,
+            }
+        })
+        .collect();
+    let reason = match probes.last() {
+        Some(probe) if probe.host == Some(TARGET) => CompletionReason::TargetFound,
+        _ => CompletionReason::RoundTimeLimitExceeded,
+    };
+    trace.update_from_round(&TracerRound::new(
+        &probes,
+        (probes.len() as u8).into(),
+        reason,
+        1,
+        TARGET,
+    ));
+}
+
+/// The trace info of `trace` to the target `target`.
+pub fn trace_info(target: &str, trace: Trace) -> TuiTraceInfo {
+    TuiTraceInfo {
+        data: Arc::new(RwLock::new(trace)),
+        target_hostname: target.to_string(),
+        target_addr: TARGET,
+        protocol: String::from("icmp"),
+        first_ttl: 1,
+        max_ttl: 64,
+        ttl_stride: 1,
+        grace_duration: Duration::from_millis(100),
+        min_round_duration: Duration::from_secs(1),
+        tos: None,
+        dont_fragment: None,
+        description: None,
+    }
+}
+
+/// A resolver which does not perform any lookups, hostnames may be given with `DnsResolver::seed`.
+pub fn resolver() -> DnsResolver {
+    DnsResolver::start(DnsResolverConfig {
+        resolve_method: DnsResolveMethod::System,
+        timeout: Duration::ZERO,
+        cache: None,
+        disabled: true,
+        asn_names: None,
+        labels: None,
+        endpoint: None,
+        resolve_all: false,
+    })
+    .unwrap()
+}
+
+/// A path in the temporary directory which is unique to this process.
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("trippy-{}-{name}", std::process::id()))
+}
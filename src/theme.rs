@@ -0,0 +1,332 @@
+use crate::config::TuiTheme;
+use anyhow::anyhow;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use tui::style::Color;
+
+/// The average round-trip time (ms) at or above which a hop is shown with the `rtt-warn` color.
+pub const RTT_WARN_MS: f64 = 100_f64;
+
+/// The average round-trip time (ms) at or above which a hop is shown with the `rtt-bad` color.
+pub const RTT_BAD_MS: f64 = 250_f64;
+
+/// The default loss (%) at or above which a hop with any loss is shown with the `loss-low` color.
+pub const LOSS_WARN_PCT: f64 = 0_f64;
+
+/// The default loss (%) at or above which a hop is shown with the `loss-high` color, rather than `loss-low`.
+pub const LOSS_HIGH_PCT: f64 = 20_f64;
+
+/// The colors of each semantic role of the Tui.
+///
+/// A color of `Color::Reset` uses the default color of the terminal.
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    /// The borders of every block.
+    pub border: Color,
+    /// The text of the table headers.
+    pub header_fg: Color,
+    /// The background of the table headers.
+    pub header_bg: Color,
+    /// The name of each target in the list of traces.
+    pub tab_fg: Color,
+    /// The background of the selected target in the list of traces.
+    pub tab_selected_bg: Color,
+    /// The chart of samples.
+    pub samples: Color,
+    /// The chart of the frequency of samples.
+    pub frequency: Color,
+    /// The background of the help dialog.
+    pub help_bg: Color,
+    /// The round-trip time of a hop below `RTT_WARN_MS`.
+    pub rtt_good: Color,
+    /// The round-trip time of a hop below `RTT_BAD_MS`.
+    pub rtt_warn: Color,
+    /// The round-trip time of a hop at or above `RTT_BAD_MS`.
+    pub rtt_bad: Color,
+    /// The loss of a hop at or above `loss_warn_pct`, and above zero, but below `loss_critical_pct`.
+    pub loss_low: Color,
+    /// The loss of a hop at or above `loss_critical_pct`.
+    pub loss_high: Color,
+    /// The host of a hop which responded from an address not in the baseline route.
+    pub baseline_address: Color,
+    /// The host of a hop which responded from an `AS` not in the baseline route.
+    pub baseline_asn: Color,
+    /// The loss (%) at or above which a hop is shown with the `loss_low` color.
+    pub loss_warn_pct: f64,
+    /// The loss (%) at or above which a hop is shown with the `loss_high` color.
+    pub loss_critical_pct: f64,
+}
+
+impl Theme {
+    /// The built-in theme `theme`.
+    pub const fn named(theme: TuiTheme) -> Self {
+        match theme {
+            TuiTheme::Default => Self {
+                border: Color::Reset,
+                header_fg: Color::Black,
+                header_bg: Color::White,
+                tab_fg: Color::Green,
+                tab_selected_bg: Color::Black,
+                samples: Color::Yellow,
+                frequency: Color::Green,
+                help_bg: Color::Blue,
+                rtt_good: Color::Reset,
+                rtt_warn: Color::Reset,
+                rtt_bad: Color::Reset,
+                loss_low: Color::Reset,
+                loss_high: Color::Reset,
+                baseline_address: Color::Yellow,
+                baseline_asn: Color::Red,
+                loss_warn_pct: LOSS_WARN_PCT,
+                loss_critical_pct: LOSS_HIGH_PCT,
+            },
+            TuiTheme::Mono => Self {
+                border: Color::Reset,
+                header_fg: Color::Reset,
+                header_bg: Color::Reset,
+                tab_fg: Color::Reset,
+                tab_selected_bg: Color::Reset,
+                samples: Color::Reset,
+                frequency: Color::Reset,
+                help_bg: Color::Reset,
+                rtt_good: Color::Reset,
+                rtt_warn: Color::Reset,
+                rtt_bad: Color::Reset,
+                loss_low: Color::Reset,
+                loss_high: Color::Reset,
+                baseline_address: Color::Reset,
+                baseline_asn: Color::Reset,
+                loss_warn_pct: LOSS_WARN_PCT,
+                loss_critical_pct: LOSS_HIGH_PCT,
+            },
+            TuiTheme::HighContrast => Self {
+                border: Color::White,
+                header_fg: Color::Black,
+                header_bg: Color::LightYellow,
+                tab_fg: Color::LightCyan,
+                tab_selected_bg: Color::Blue,
+                samples: Color::LightYellow,
+                frequency: Color::LightCyan,
+                help_bg: Color::Black,
+                rtt_good: Color::LightBlue,
+                rtt_warn: Color::LightYellow,
+                rtt_bad: Color::LightMagenta,
+                loss_low: Color::LightYellow,
+                loss_high: Color::LightMagenta,
+                baseline_address: Color::LightYellow,
+                baseline_asn: Color::LightMagenta,
+                loss_warn_pct: LOSS_WARN_PCT,
+                loss_critical_pct: LOSS_HIGH_PCT,
+            },
+        }
+    }
+
+    /// Read the `toml` (or `yaml`) theme file at `path` and apply the colors it defines over this theme.
+    ///
+    /// The keys of the file are the names of the roles, such as `rtt-bad`, and the values are a color name (i.e.
+    /// `lightred` or `reset`), an `#rrggbb` value or a 256 color palette index.  Unknown roles and colors are an error.
+    pub fn with_file(mut self, path: &Path) -> anyhow::Result<Self> {
+        let data = fs::read_to_string(path)
+            .map_err(|err| anyhow!("failed to read theme file {}: {}", path.display(), err))?;
+        let entries: BTreeMap<String, String> = match path.extension().and_then(|ext| ext.to_str())
+        {
+            Some("yaml" | "yml") => serde_yaml::from_str(&data)
+                .map_err(|err| anyhow!("failed to parse theme file {}: {}", path.display(), err))?,
+            _ => toml::from_str(&data)
+                .map_err(|err| anyhow!("failed to parse theme file {}: {}", path.display(), err))?,
+        };
+        for (role, color) in entries {
+            let color = parse_color(&color)
+                .map_err(|err| anyhow!("invalid theme file {}: {}", path.display(), err))?;
+            let field = match role.as_str() {
+                "border" => &mut self.border,
+                "header-fg" => &mut self.header_fg,
+                "header-bg" => &mut self.header_bg,
+                "tab-fg" => &mut self.tab_fg,
+                "tab-selected-bg" => &mut self.tab_selected_bg,
+                "samples" => &mut self.samples,
+                "frequency" => &mut self.frequency,
+                "help-bg" => &mut self.help_bg,
+                "rtt-good" => &mut self.rtt_good,
+                "rtt-warn" => &mut self.rtt_warn,
+                "rtt-bad" => &mut self.rtt_bad,
+                "loss-low" => &mut self.loss_low,
+                "loss-high" => &mut self.loss_high,
+                "baseline-address" => &mut self.baseline_address,
+                "baseline-asn" => &mut self.baseline_asn,
+                _ => {
+                    return Err(anyhow!(
+                        "invalid theme file {}: unknown role `{}`",
+                        path.display(),
+                        role
+                    ))
+                }
+            };
+            *field = color;
+        }
+        Ok(self)
+    }
+
+    /// Show the loss of a hop with the `loss_low` color from `warn_pct` and the `loss_high` color from `critical_pct`.
+    pub const fn with_loss_thresholds(self, warn_pct: f64, critical_pct: f64) -> Self {
+        Self {
+            loss_warn_pct: warn_pct,
+            loss_critical_pct: critical_pct,
+            ..self
+        }
+    }
+
+    /// The color of an average round-trip time of `rtt_ms`.
+    pub fn rtt(&self, rtt_ms: f64) -> Color {
+        if rtt_ms >= RTT_BAD_MS {
+            self.rtt_bad
+        } else if rtt_ms >= RTT_WARN_MS {
+            self.rtt_warn
+        } else {
+            self.rtt_good
+        }
+    }
+
+    /// The color of a loss of `loss_pct`.
+    pub fn loss(&self, loss_pct: f64) -> Color {
+        if loss_pct >= self.loss_critical_pct {
+            self.loss_high
+        } else if loss_pct > 0_f64 && loss_pct >= self.loss_warn_pct {
+            self.loss_low
+        } else {
+            Color::Reset
+        }
+    }
+}
+
+/// Parse a color name, such as `lightred`, an `#rrggbb` value or a 256 color palette index.
+fn parse_color(color: &str) -> anyhow::Result<Color> {
+    let color = color.trim().to_lowercase();
+    Ok(match color.as_str() {
+        "reset" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" => Color::Gray,
+        "darkgray" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => {
+            if let Some(hex) = color
+                .strip_prefix('#')
+                .filter(|hex| hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+            {
+                let rgb = u32::from_str_radix(hex, 16)
+                    .map_err(|_| anyhow!("invalid color `{}`", color))?;
+                Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+            } else {
+                Color::Indexed(
+                    color
+                        .parse()
+                        .map_err(|_| anyhow!("invalid color `{}`", color))?,
+                )
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    /// Write `data` to the theme file `name` in the temporary directory.
+    fn theme_file(name: &str, data: &str) -> std::path::PathBuf {
+        let path = temp_path(name);
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_parse_color_names() {
+        assert_eq!(Color::Reset, parse_color("reset").unwrap());
+        assert_eq!(Color::LightRed, parse_color("lightred").unwrap());
+        assert_eq!(Color::DarkGray, parse_color(" DarkGray ").unwrap());
+        assert_eq!(Color::White, parse_color("WHITE").unwrap());
+    }
+
+    #[test]
+    fn test_parse_color_rgb() {
+        assert_eq!(
+            Color::Rgb(0xff, 0x80, 0x00),
+            parse_color("#ff8000").unwrap()
+        );
+        assert_eq!(
+            Color::Rgb(0x0a, 0xbc, 0xde),
+            parse_color("#0ABCDE").unwrap()
+        );
+        assert!(parse_color("#ff800").is_err());
+        assert!(parse_color("#ff80000").is_err());
+        assert!(parse_color("#+f8000").is_err());
+        assert!(parse_color("#gg8000").is_err());
+    }
+
+    #[test]
+    fn test_parse_color_indexed() {
+        assert_eq!(Color::Indexed(0), parse_color("0").unwrap());
+        assert_eq!(Color::Indexed(208), parse_color("208").unwrap());
+        assert!(parse_color("256").is_err());
+        assert!(parse_color("-1").is_err());
+        assert!(parse_color("purple").is_err());
+        assert!(parse_color("").is_err());
+    }
+
+    #[test]
+    fn test_with_file() {
+        let path = theme_file("theme.toml", "border = \"red\"\nrtt-bad = \"#ff0000\"\n");
+        let theme = Theme::named(TuiTheme::Mono).with_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(Color::Red, theme.border);
+        assert_eq!(Color::Rgb(0xff, 0, 0), theme.rtt_bad);
+        assert_eq!(Color::Reset, theme.rtt_warn);
+        let path = theme_file("theme.yaml", "loss-high: lightmagenta\nheader-bg: '17'\n");
+        let theme = Theme::named(TuiTheme::Mono).with_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(Color::LightMagenta, theme.loss_high);
+        assert_eq!(Color::Indexed(17), theme.header_bg);
+    }
+
+    #[test]
+    fn test_with_file_invalid() {
+        for data in [
+            "borders = \"red\"\n",
+            "border = \"purple\"\n",
+            "border = 1\n",
+        ] {
+            let path = theme_file("invalid.toml", data);
+            assert!(Theme::named(TuiTheme::Default).with_file(&path).is_err());
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let theme = Theme::named(TuiTheme::HighContrast).with_loss_thresholds(5_f64, 50_f64);
+        assert_eq!(theme.rtt_good, theme.rtt(RTT_WARN_MS - 1_f64));
+        assert_eq!(theme.rtt_warn, theme.rtt(RTT_WARN_MS));
+        assert_eq!(theme.rtt_bad, theme.rtt(RTT_BAD_MS));
+        assert_eq!(Color::Reset, theme.loss(0_f64));
+        assert_eq!(Color::Reset, theme.loss(4.9));
+        assert_eq!(theme.loss_low, theme.loss(5_f64));
+        assert_eq!(theme.loss_high, theme.loss(50_f64));
+        let theme = Theme::named(TuiTheme::HighContrast);
+        assert_eq!(Color::Reset, theme.loss(0_f64));
+        assert_eq!(theme.loss_low, theme.loss(0.1));
+        assert_eq!(theme.loss_high, theme.loss(LOSS_HIGH_PCT));
+    }
+}
@@ -1,12 +1,28 @@
 mod config;
 mod error;
+mod mtu;
 mod net;
+mod pause;
+mod pcap;
 mod probe;
+mod rate;
+mod target;
 mod tracer;
 mod types;
 mod util;
 
-pub use config::{TracerConfig, TracerProtocol};
-pub use net::TracerChannel;
-pub use probe::{IcmpPacketType, Probe, ProbeStatus};
-pub use tracer::{Tracer, TracerRound};
+pub use config::{
+    IcmpSocketMode, MultipathStrategy, ScheduleStrategy, TcpFlag, TracerConfig, TracerConfigParams,
+    TracerProtocol, UdpDestPort,
+};
+pub use mtu::{discover_path_mtu, MtuConfig, MtuLimit, PathMtu, MAX_MTU, MIN_MTU};
+pub use net::{discover_src_addr, TracerChannel};
+pub use pause::PauseHandle;
+pub use pcap::PcapWriter;
+pub use probe::{
+    IcmpPacketType, IcmpTimestamps, MplsLabel, MplsLabelStack, Probe, ProbeStatus, TcpOutcome,
+    UnreachableCode, UnreachableReason,
+};
+pub use rate::{AdaptiveRate, RateLimiter};
+pub use target::TargetHandle;
+pub use tracer::{CompletionReason, Tracer, TracerRound};
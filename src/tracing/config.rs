@@ -1,7 +1,9 @@
 use crate::tracing::error::{TraceResult, TracerError};
 use crate::tracing::types::{
-    MaxInflight, MaxRounds, PacketSize, PayloadPattern, Sequence, SourcePort, TimeToLive, TraceId,
+    MaxFlows, MaxInflight, MaxRounds, PacketSize, PayloadPattern, ProbesPerRound, Sequence,
+    SourcePort, TimeToLive, TraceId,
 };
+use crate::tracing::{AdaptiveRate, PauseHandle, PcapWriter, RateLimiter, TargetHandle};
 use std::fmt::{Display, Formatter};
 use std::net::IpAddr;
 use std::time::Duration;
@@ -14,11 +16,16 @@ const MAX_TTL: u8 = 254;
 /// This ensures that there are sufficient sequence numbers available for at least one round.
 const MAX_SEQUENCE: u16 = u16::MAX - MAX_TTL as u16 - 1;
 
+/// The maximum number of probes sent for each time-to-live in a round.
+const MAX_PROBES_PER_ROUND: u8 = 16;
+
 /// The tracing protocol.
 #[derive(Debug, Copy, Clone)]
 pub enum TracerProtocol {
     /// Internet Control Message Protocol
     Icmp,
+    /// Internet Control Message Protocol timestamp requests
+    IcmpTimestamp,
     /// User Datagram Protocol
     Udp,
     /// Transmission Control Protocol
@@ -29,85 +36,502 @@ impl Display for TracerProtocol {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Icmp => write!(f, "icmp"),
+            Self::IcmpTimestamp => write!(f, "icmp-timestamp"),
             Self::Udp => write!(f, "udp"),
             Self::Tcp => write!(f, "tcp"),
         }
     }
 }
 
-/// TODO
+/// The strategy used to handle multiple paths through load-balanced (ECMP) networks.
+#[derive(Debug, Copy, Clone)]
+pub enum MultipathStrategy {
+    /// Encode the probe sequence number in a field which forms part of the flow identifier.
+    ///
+    /// For `udp` and `tcp` the sequence is encoded in the destination port and for `icmp` in the echo request
+    /// sequence number.  As the flow identifier changes for every probe, successive probes may follow different paths.
+    Classic,
+    /// Keep the flow identifier constant for all probes and encode the probe sequence number elsewhere.
+    ///
+    /// For `udp` the destination port is held fixed and the sequence is encoded in the `UDP` checksum, by adjusting the
+    /// first two bytes of the payload.  For `tcp` the destination port is held fixed and the sequence is encoded in the
+    /// `TCP` sequence number.  For `icmp` the identifier is held fixed and the first two bytes of the payload are
+    /// adjusted such that the `ICMP` checksum is the same for every probe.
+    ///
+    /// When tracing with multiple flows, the flow identifier is offset by the flow id.  For `udp` and `tcp` the
+    /// destination port is offset and for `icmp` the checksum is offset.
+    Paris,
+}
+
+impl Display for MultipathStrategy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Classic => write!(f, "classic"),
+            Self::Paris => write!(f, "paris"),
+        }
+    }
+}
+
+/// How the probes of each round are scheduled.
+#[derive(Debug, Copy, Clone)]
+pub enum ScheduleStrategy {
+    /// Send each probe as soon as the maximum in-flight allows, such that the probes of a round are sent in a burst.
+    Sync,
+    /// Space the probes of a round evenly across the minimum round duration.
+    ///
+    /// This reduces the instantaneous load on the network, and on the `ICMP` rate limiters of the hops, at the cost of
+    /// rounds which last at least the minimum round duration.
+    RoundRobin,
+}
+
+impl Display for ScheduleStrategy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sync => write!(f, "sync"),
+            Self::RoundRobin => write!(f, "round-robin"),
+        }
+    }
+}
+
+/// How the destination port of `udp` probes is chosen.
 #[derive(Debug, Copy, Clone)]
+pub enum UdpDestPort {
+    /// Use the probe sequence number as the destination port.
+    ///
+    /// The destination port increments for every probe, starting from the initial sequence number, and responses are
+    /// matched to probes by the destination port of the original probe.  This may only be used with the `Classic`
+    /// multipath strategy.
+    Sequence,
+    /// Use a fixed destination port for all probes.
+    ///
+    /// The sequence is encoded in the `UDP` checksum, by adjusting the first two bytes of the payload, and responses
+    /// are matched to probes by the checksum of the original probe.  For the `Paris` multipath strategy the port is
+    /// offset by the flow id.
+    Fixed(u16),
+}
+
+/// A flag set in the header of `tcp` probes.
+///
+/// Probes with the `Ack` flag appear to belong to an established connection, and so may pass a stateless filter which
+/// drops `Syn` probes, and are answered by the target host with a `RST` whether its port is open or closed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TcpFlag {
+    Syn,
+    Ack,
+    Fin,
+    Psh,
+    Urg,
+}
+
+/// The kind of socket used to send `icmp` probes and receive their responses.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IcmpSocketMode {
+    /// A raw socket, which requires `CAP_NET_RAW` on Linux and `root` elsewhere.
+    Raw,
+    /// An unprivileged datagram socket (`SOCK_DGRAM` with `IPPROTO_ICMP`), where the OS permits one.
+    ///
+    /// On Linux the group of the process must be within the `net.ipv4.ping_group_range` sysctl, the OS sets the
+    /// identifier of each probe to the local port the socket is bound to, which is the trace identifier, and reports
+    /// `TimeExceeded` and `DestinationUnreachable` responses on the error queue of the socket, without any `MPLS`
+    /// labels.  On macOS no privileges are needed and the socket receives all `ICMP` packets as for a raw socket.
+    Dgram,
+    /// A raw socket if permitted, otherwise an unprivileged datagram socket.
+    Auto,
+}
+
+impl Display for IcmpSocketMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Raw => write!(f, "raw"),
+            Self::Dgram => write!(f, "dgram"),
+            Self::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+/// TODO
+#[derive(Debug, Clone)]
 pub struct TracerConfig {
     pub target_addr: IpAddr,
     pub protocol: TracerProtocol,
+    pub multipath_strategy: MultipathStrategy,
+    pub max_flows: MaxFlows,
     pub trace_identifier: TraceId,
     pub max_rounds: Option<MaxRounds>,
     pub first_ttl: TimeToLive,
     pub max_ttl: TimeToLive,
+    pub ttl_stride: TimeToLive,
+    pub auto_ttl: bool,
     pub grace_duration: Duration,
+    pub adaptive_grace: Option<Duration>,
     pub max_inflight: MaxInflight,
+    pub probes_per_round: ProbesPerRound,
+    pub schedule_strategy: ScheduleStrategy,
     pub initial_sequence: Sequence,
     pub read_timeout: Duration,
+    pub probe_timeout: Option<Duration>,
     pub min_round_duration: Duration,
     pub max_round_duration: Duration,
     pub packet_size: PacketSize,
+    pub fragment: bool,
+    pub dont_fragment: Option<bool>,
     pub payload_pattern: PayloadPattern,
+    pub payload: Option<Vec<u8>>,
     pub source_port: SourcePort,
+    pub source_port_count: u16,
+    pub udp_dest_port: UdpDestPort,
+    pub tcp_dest_ports: Vec<u16>,
+    pub tcp_flags: Vec<TcpFlag>,
+    pub tos: Option<u8>,
+    pub interface: Option<String>,
+    pub source_addr: Option<IpAddr>,
+    pub icmp_socket_mode: IcmpSocketMode,
+    pub rate_limiter: Option<RateLimiter>,
+    pub adaptive_rate: Option<AdaptiveRate>,
+    pub pcap: Option<PcapWriter>,
+    pub pause: Option<PauseHandle>,
+    pub target: Option<TargetHandle>,
+}
+
+/// The unvalidated parameters of a `TracerConfig`.
+#[derive(Debug, Clone)]
+pub struct TracerConfigParams {
+    pub target_addr: IpAddr,
+    pub protocol: TracerProtocol,
+    pub multipath_strategy: MultipathStrategy,
+    pub max_flows: u16,
+    pub max_rounds: Option<usize>,
+    pub trace_identifier: u16,
+    pub first_ttl: u8,
+    pub max_ttl: u8,
+    pub ttl_stride: u8,
+    pub auto_ttl: bool,
+    pub grace_duration: Duration,
+    pub adaptive_grace: Option<Duration>,
+    pub max_inflight: u8,
+    pub probes_per_round: u8,
+    pub schedule_strategy: ScheduleStrategy,
+    pub initial_sequence: u16,
+    pub read_timeout: Duration,
+    pub probe_timeout: Option<Duration>,
+    pub min_round_duration: Duration,
+    pub max_round_duration: Duration,
+    pub packet_size: u16,
+    pub fragment: bool,
+    pub dont_fragment: Option<bool>,
+    pub payload_pattern: u8,
+    pub payload: Option<Vec<u8>>,
+    pub source_port: u16,
+    pub source_port_count: u16,
+    pub udp_dest_port: UdpDestPort,
+    pub tcp_dest_ports: Vec<u16>,
+    pub tcp_flags: Vec<TcpFlag>,
+    pub tos: Option<u8>,
+    pub interface: Option<String>,
+    pub source_addr: Option<IpAddr>,
+    pub icmp_socket_mode: IcmpSocketMode,
+    pub rate_limiter: Option<RateLimiter>,
+    pub adaptive_rate: Option<AdaptiveRate>,
+    pub pcap: Option<PcapWriter>,
+    pub pause: Option<PauseHandle>,
+    pub target: Option<TargetHandle>,
 }
 
 impl TracerConfig {
-    #[allow(clippy::too_many_arguments)]
-    pub fn new(
-        target_addr: IpAddr,
-        protocol: TracerProtocol,
-        max_rounds: Option<usize>,
-        trace_identifier: u16,
-        first_ttl: u8,
-        max_ttl: u8,
-        grace_duration: Duration,
-        max_inflight: u8,
-        initial_sequence: u16,
-        read_timeout: Duration,
-        min_round_duration: Duration,
-        max_round_duration: Duration,
-        packet_size: u16,
-        payload_pattern: u8,
-        source_port: u16,
-    ) -> TraceResult<Self> {
+    /// Validate the `params` of a tracer.
+    #[allow(clippy::too_many_lines)]
+    pub fn new(params: TracerConfigParams) -> TraceResult<Self> {
+        let TracerConfigParams {
+            target_addr,
+            protocol,
+            multipath_strategy,
+            max_flows,
+            max_rounds,
+            trace_identifier,
+            first_ttl,
+            max_ttl,
+            ttl_stride,
+            auto_ttl,
+            grace_duration,
+            adaptive_grace,
+            max_inflight,
+            probes_per_round,
+            schedule_strategy,
+            initial_sequence,
+            read_timeout,
+            probe_timeout,
+            min_round_duration,
+            max_round_duration,
+            packet_size,
+            fragment,
+            dont_fragment,
+            payload_pattern,
+            payload,
+            source_port,
+            source_port_count,
+            udp_dest_port,
+            tcp_dest_ports,
+            tcp_flags,
+            tos,
+            interface,
+            source_addr,
+            icmp_socket_mode,
+            rate_limiter,
+            adaptive_rate,
+            pcap,
+            pause,
+            target,
+        } = params;
         if first_ttl > MAX_TTL {
             return Err(TracerError::BadConfig(format!(
-                "first_ttl ({}) > {}",
-                first_ttl, MAX_TTL
+                "first_ttl ({first_ttl}) > {MAX_TTL}"
             )));
         }
         if max_ttl > MAX_TTL {
             return Err(TracerError::BadConfig(format!(
-                "max_ttl ({}) > {}",
-                first_ttl, MAX_TTL
+                "max_ttl ({max_ttl}) > {MAX_TTL}"
+            )));
+        }
+        if ttl_stride == 0 || (ttl_stride > 1 && first_ttl.saturating_add(ttl_stride) > max_ttl) {
+            return Err(TracerError::BadConfig(format!(
+                "ttl_stride ({ttl_stride}) must be > 0 and first_ttl ({first_ttl}) + ttl_stride must be <= max_ttl ({max_ttl})"
+            )));
+        }
+        if max_flows == 0 {
+            return Err(TracerError::BadConfig(String::from(
+                "max_flows must be > 0",
+            )));
+        }
+        if !(1..=MAX_PROBES_PER_ROUND).contains(&probes_per_round) {
+            return Err(TracerError::BadConfig(format!(
+                "probes_per_round ({probes_per_round}) must be between 1 and {MAX_PROBES_PER_ROUND}"
             )));
         }
         if initial_sequence > MAX_SEQUENCE {
             return Err(TracerError::BadConfig(format!(
-                "initial_sequence ({}) > {}",
-                initial_sequence, MAX_SEQUENCE
+                "initial_sequence ({initial_sequence}) > {MAX_SEQUENCE}"
+            )));
+        }
+        if let Some(adaptive_grace) = adaptive_grace {
+            if adaptive_grace < grace_duration {
+                return Err(TracerError::BadConfig(format!(
+                    "adaptive_grace ({adaptive_grace:?}) must be >= grace_duration ({grace_duration:?})"
+                )));
+            }
+        }
+        if let Some(probe_timeout) = probe_timeout {
+            if probe_timeout.is_zero() || probe_timeout > min_round_duration {
+                return Err(TracerError::BadConfig(format!(
+                    "probe_timeout ({probe_timeout:?}) must be > 0 and <= min_round_duration ({min_round_duration:?})"
+                )));
+            }
+        }
+        let max_flows = flow_count(protocol, multipath_strategy, max_flows, &tcp_dest_ports)?;
+        match (protocol, multipath_strategy, udp_dest_port) {
+            (TracerProtocol::IcmpTimestamp, MultipathStrategy::Paris, _) => {
+                return Err(TracerError::BadConfig(String::from(
+                    "the paris multipath strategy is not supported for icmp-timestamp",
+                )));
+            }
+            (TracerProtocol::Udp, MultipathStrategy::Paris, UdpDestPort::Sequence) => {
+                return Err(TracerError::BadConfig(String::from(
+                    "udp_dest_port must be fixed for the paris multipath strategy",
+                )));
+            }
+            (TracerProtocol::Udp, _, UdpDestPort::Fixed(port))
+                if u32::from(port) + u32::from(max_flows) - 1 > u32::from(u16::MAX) =>
+            {
+                return Err(TracerError::BadConfig(format!(
+                    "udp_dest_port ({port}) + max_flows ({max_flows}) - 1 > {}",
+                    u16::MAX
+                )));
+            }
+            _ => {}
+        }
+        validate_source_ports(protocol, multipath_strategy, source_port, source_port_count)?;
+        validate_tcp_flags(&tcp_flags)?;
+        if let Some(source_addr) = source_addr {
+            if source_addr.is_ipv4() != target_addr.is_ipv4() {
+                return Err(TracerError::BadConfig(format!(
+                    "source_addr ({source_addr}) and target_addr ({target_addr}) must be of the same family"
+                )));
+            }
+        }
+        validate_icmp_socket_mode(protocol, icmp_socket_mode)?;
+        if fragment && icmp_socket_mode == IcmpSocketMode::Dgram {
+            return Err(TracerError::BadConfig(format!(
+                "fragment may not be used with icmp_socket_mode ({icmp_socket_mode}) as fragments are sent on a raw socket"
+            )));
+        }
+        if fragment && dont_fragment == Some(true) {
+            return Err(TracerError::BadConfig(String::from(
+                "dont_fragment may not be set for fragmented probes",
+            )));
+        }
+        if dont_fragment.is_some() && icmp_socket_mode == IcmpSocketMode::Dgram {
+            return Err(TracerError::BadConfig(format!(
+                "dont_fragment may not be used with icmp_socket_mode ({icmp_socket_mode}) as probes are sent on a raw socket"
             )));
         }
+        if let Some(adaptive_rate) = adaptive_rate {
+            validate_adaptive_rate(adaptive_rate)?;
+        }
         Ok(Self {
             target_addr,
             protocol,
+            multipath_strategy,
+            max_flows: MaxFlows::from(max_flows),
             trace_identifier: TraceId::from(trace_identifier),
             max_rounds: max_rounds.map(MaxRounds::from),
             first_ttl: TimeToLive::from(first_ttl),
             max_ttl: TimeToLive::from(max_ttl),
+            ttl_stride: TimeToLive::from(ttl_stride),
+            auto_ttl,
             grace_duration,
+            adaptive_grace,
             max_inflight: MaxInflight::from(max_inflight),
+            probes_per_round: ProbesPerRound::from(probes_per_round),
+            schedule_strategy,
             initial_sequence: Sequence::from(initial_sequence),
             read_timeout,
+            probe_timeout,
             min_round_duration,
             max_round_duration,
             packet_size: PacketSize::from(packet_size),
+            fragment,
+            dont_fragment,
             payload_pattern: PayloadPattern::from(payload_pattern),
+            payload,
             source_port: SourcePort::from(source_port),
+            source_port_count,
+            udp_dest_port,
+            tcp_dest_ports,
+            tcp_flags,
+            tos,
+            interface,
+            source_addr,
+            icmp_socket_mode,
+            rate_limiter,
+            adaptive_rate,
+            pcap,
+            pause,
+            target,
         })
     }
 }
+
+/// Validate the range of `source_port_count` source ports from `source_port` which probes rotate across.
+///
+/// Only the `UDP` and `TCP` protocols have a source port, and rotating it would move each round of a `Paris` trace
+/// onto a different flow.
+fn validate_source_ports(
+    protocol: TracerProtocol,
+    multipath_strategy: MultipathStrategy,
+    source_port: u16,
+    source_port_count: u16,
+) -> TraceResult<()> {
+    if source_port_count == 0
+        || u32::from(source_port) + u32::from(source_port_count) - 1 > u32::from(u16::MAX)
+    {
+        return Err(TracerError::BadConfig(format!(
+            "source_port_count ({source_port_count}) must be > 0 and source_port ({source_port}) + source_port_count - 1 must be <= {}",
+            u16::MAX
+        )));
+    }
+    if source_port_count > 1 && !matches!(protocol, TracerProtocol::Udp | TracerProtocol::Tcp) {
+        return Err(TracerError::BadConfig(format!(
+            "source_port_count ({source_port_count}) must be 1 for protocol ({protocol})"
+        )));
+    }
+    if source_port_count > 1 && matches!(multipath_strategy, MultipathStrategy::Paris) {
+        return Err(TracerError::BadConfig(format!(
+            "source_port_count ({source_port_count}) must be 1 for the paris multipath strategy"
+        )));
+    }
+    Ok(())
+}
+
+/// Validate the thresholds of the adaptive rate controller.
+fn validate_adaptive_rate(adaptive_rate: AdaptiveRate) -> TraceResult<()> {
+    let AdaptiveRate {
+        loss_threshold,
+        recover_threshold,
+    } = adaptive_rate;
+    if !(0_f64..=100_f64).contains(&loss_threshold)
+        || !(0_f64..=100_f64).contains(&recover_threshold)
+    {
+        return Err(TracerError::BadConfig(format!(
+            "adaptive rate thresholds ({loss_threshold}, {recover_threshold}) must be between 0 and 100"
+        )));
+    }
+    if recover_threshold >= loss_threshold {
+        return Err(TracerError::BadConfig(format!(
+            "adaptive rate recover_threshold ({recover_threshold}) must be < loss_threshold ({loss_threshold})"
+        )));
+    }
+    Ok(())
+}
+
+/// The number of flows to trace.
+///
+/// If `tcp_dest_ports` are configured then each port is traced as a distinct flow, otherwise `max_flows` are traced.
+fn flow_count(
+    protocol: TracerProtocol,
+    multipath_strategy: MultipathStrategy,
+    max_flows: u16,
+    tcp_dest_ports: &[u16],
+) -> TraceResult<u16> {
+    if tcp_dest_ports.is_empty() {
+        return Ok(max_flows);
+    }
+    if max_flows > 1 {
+        return Err(TracerError::BadConfig(String::from(
+            "max_flows may not be used with tcp_dest_ports, each port is traced as a distinct flow",
+        )));
+    }
+    if !matches!(
+        (protocol, multipath_strategy),
+        (TracerProtocol::Tcp, MultipathStrategy::Classic)
+    ) {
+        return Err(TracerError::BadConfig(String::from(
+            "tcp_dest_ports may only be used with the tcp protocol and the classic multipath strategy",
+        )));
+    }
+    u16::try_from(tcp_dest_ports.len())
+        .map_err(|_| TracerError::BadConfig(String::from("too many tcp_dest_ports")))
+}
+
+/// Validate that an unprivileged datagram socket is only used for the `icmp` protocol.
+///
+/// Datagram `ICMP` sockets only send `EchoRequest` packets and so cannot be used for `icmp-timestamp` probes.
+fn validate_icmp_socket_mode(
+    protocol: TracerProtocol,
+    icmp_socket_mode: IcmpSocketMode,
+) -> TraceResult<()> {
+    if icmp_socket_mode == IcmpSocketMode::Dgram && !matches!(protocol, TracerProtocol::Icmp) {
+        return Err(TracerError::BadConfig(format!(
+            "icmp_socket_mode ({icmp_socket_mode}) may only be used with the icmp protocol, not {protocol}"
+        )));
+    }
+    Ok(())
+}
+
+/// Validate that the `tcp_flags` of probes are a combination to which the target host responds.
+///
+/// A probe must set at least one of `Syn`, `Ack` or `Fin`, and may not set both `Syn` and `Fin`.
+fn validate_tcp_flags(tcp_flags: &[TcpFlag]) -> TraceResult<()> {
+    if !tcp_flags
+        .iter()
+        .any(|flag| matches!(flag, TcpFlag::Syn | TcpFlag::Ack | TcpFlag::Fin))
+    {
+        return Err(TracerError::BadConfig(String::from(
+            "tcp_flags must include syn, ack or fin",
+        )));
+    }
+    if tcp_flags.contains(&TcpFlag::Syn) && tcp_flags.contains(&TcpFlag::Fin) {
+        return Err(TracerError::BadConfig(String::from(
+            "tcp_flags may not include both syn and fin",
+        )));
+    }
+    Ok(())
+}
@@ -11,6 +11,16 @@ pub enum TracerError {
     InvalidPacketSize(usize),
     #[error("unknown default interface")]
     UnknownDefaultInterface,
+    #[error("unknown interface: {0}")]
+    UnknownInterface(String),
+    #[error("no {1} address for interface: {0}")]
+    NoInterfaceAddress(String, &'static str),
+    #[error("unsupported: {0}")]
+    Unsupported(&'static str),
+    #[error("unprivileged dgram icmp socket not permitted: {0}")]
+    IcmpDgramSocket(io::Error),
+    #[error("no icmp socket available, a raw socket requires privileges ({0}) and an unprivileged dgram socket is not permitted ({1})")]
+    NoIcmpSocket(io::Error, io::Error),
     #[error("invalid config: {0}")]
     BadConfig(String),
     #[error("missing required field: {0}")]
@@ -0,0 +1,377 @@
+use crate::tracing::error::{TraceResult, TracerError};
+use crate::tracing::net::{bind_addr, bind_interface, discover_src_addr, extract_echo_request};
+use crate::tracing::types::{Sequence, TimeToLive, TraceId};
+use crate::tracing::util::Required;
+use pnet::packet::icmp::destination_unreachable::{DestinationUnreachablePacket, IcmpCodes};
+use pnet::packet::icmp::echo_reply::EchoReplyPacket;
+use pnet::packet::icmp::echo_request::MutableEchoRequestPacket;
+use pnet::packet::icmp::time_exceeded::TimeExceededPacket;
+use pnet::packet::icmp::{echo_request, IcmpTypes};
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::{Ipv4Flags, Ipv4Packet, MutableIpv4Packet};
+use pnet::packet::Packet;
+use pnet::transport::{
+    icmp_packet_iter, transport_channel, TransportChannelType, TransportProtocol,
+    TransportReceiver, TransportSender,
+};
+use pnet::util;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
+
+/// The minimum MTU of an IPv4 path (RFC 791).
+pub const MIN_MTU: u16 = 68;
+
+/// The largest path MTU probed, that of a jumbo frame.
+pub const MAX_MTU: u16 = 9000;
+
+/// The number of probes of a single size sent before the size is considered to be dropped.
+const PROBE_ATTEMPTS: usize = 2;
+
+/// The size of the buffer into which responses are read, large enough for the echo reply to the largest probe.
+const RECV_BUF_SIZE: usize = MAX_MTU as usize;
+
+/// Path MTU discovery configuration.
+#[derive(Debug, Clone)]
+pub struct MtuConfig {
+    pub target_addr: IpAddr,
+    pub trace_identifier: TraceId,
+    pub max_ttl: TimeToLive,
+    pub probe_timeout: Duration,
+    pub tos: Option<u8>,
+    pub interface: Option<String>,
+    pub source_addr: Option<IpAddr>,
+}
+
+impl MtuConfig {
+    #[must_use]
+    pub fn new(
+        target_addr: IpAddr,
+        trace_identifier: u16,
+        max_ttl: u8,
+        probe_timeout: Duration,
+        tos: Option<u8>,
+        interface: Option<String>,
+        source_addr: Option<IpAddr>,
+    ) -> Self {
+        Self {
+            target_addr,
+            trace_identifier: TraceId(trace_identifier),
+            max_ttl: TimeToLive(max_ttl),
+            probe_timeout,
+            tos,
+            interface,
+            source_addr,
+        }
+    }
+}
+
+/// What limits the MTU of a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MtuLimit {
+    /// Nothing, the largest probe (`MAX_MTU`) reached the target.
+    None,
+    /// The local host, which refused to send larger probes.
+    ///
+    /// This is the MTU of the local interface, or a path MTU the kernel learned from an earlier "fragmentation needed",
+    /// in which case the hop which returned it is not known.
+    Local,
+    /// A hop which returned an `ICMP` `DestinationUnreachable` "fragmentation needed" for larger probes.
+    ///
+    /// The ttl of the hop is `None` if it could not be determined.
+    Hop { addr: IpAddr, ttl: Option<u8> },
+    /// Unknown, larger probes were dropped without any `ICMP` error (a "black hole").
+    Unknown,
+}
+
+/// The discovered MTU of the path to a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathMtu {
+    /// The size of the largest probe which reached the target, `None` if no probe did.
+    pub mtu: Option<u16>,
+    /// What limits the MTU of the path.
+    pub limit: MtuLimit,
+    /// The number of probes sent.
+    pub probes: usize,
+}
+
+/// Discover the MTU of the path to the target.
+///
+/// `ICMP` `EchoRequest` probes are sent with the don't-fragment bit set and the size of the largest probe which
+/// reaches the target is found by binary search between `MIN_MTU` and `MAX_MTU`.  Whenever a probe is too large for a
+/// hop the next-hop MTU of the "fragmentation needed" response (RFC 1191) is probed next, such that a path with a
+/// single limiting hop is typically resolved in a few probes.  A size is only considered to be dropped once
+/// `PROBE_ATTEMPTS` probes of it went unanswered.
+///
+/// The ttl of a limiting hop is found by sending a `MIN_MTU` probe at every ttl up to `max_ttl` and taking the ttl
+/// which returned `TimeExceeded` from the same address as the "fragmentation needed".
+///
+/// This operation requires the `CAP_NET_RAW` capability on Linux.
+pub fn discover_path_mtu(config: &MtuConfig) -> TraceResult<PathMtu> {
+    let mut prober = MtuProber::new(config)?;
+    let mut lower = MIN_MTU;
+    let mut upper = MAX_MTU;
+    let mut reached = false;
+    let mut limit = MtuLimit::None;
+    let mut size = MAX_MTU;
+    while lower < upper {
+        let next_hop_mtu = match prober.probe(size, config.max_ttl)? {
+            Outcome::Reached => {
+                lower = size;
+                reached = true;
+                None
+            }
+            Outcome::TooLarge(too_large, next_hop_mtu) => {
+                limit = too_large;
+                let next_hop_mtu = next_hop_mtu.filter(|mtu| (lower..size).contains(mtu));
+                upper = next_hop_mtu.unwrap_or(size - 1);
+                next_hop_mtu
+            }
+            Outcome::Dropped => {
+                limit = MtuLimit::Unknown;
+                upper = size - 1;
+                None
+            }
+        };
+        size = next_hop_mtu.unwrap_or_else(|| lower + (upper - lower).div_ceil(2));
+    }
+    if !reached {
+        reached = matches!(prober.probe(lower, config.max_ttl)?, Outcome::Reached);
+    }
+    if let MtuLimit::Hop { addr, ttl: None } = limit {
+        limit = MtuLimit::Hop {
+            addr,
+            ttl: prober.hop_ttl(addr, config.max_ttl)?,
+        };
+    }
+    Ok(PathMtu {
+        mtu: reached.then_some(lower),
+        limit,
+        probes: prober.probes,
+    })
+}
+
+/// The outcome of probing a single size.
+enum Outcome {
+    /// The probe reached the target.
+    Reached,
+    /// The probe was too large, and the next-hop MTU reported, if any.
+    TooLarge(MtuLimit, Option<u16>),
+    /// Every probe went unanswered, or was rejected for a reason other than its size.
+    Dropped,
+}
+
+/// A response to an MTU probe.
+enum MtuResponse {
+    EchoReply(Sequence),
+    TimeExceeded(Sequence, IpAddr),
+    FragmentationNeeded(Sequence, IpAddr, u16),
+    Unreachable(Sequence),
+}
+
+impl MtuResponse {
+    const fn sequence(&self) -> Sequence {
+        match self {
+            Self::EchoReply(sequence)
+            | Self::TimeExceeded(sequence, _)
+            | Self::FragmentationNeeded(sequence, _, _)
+            | Self::Unreachable(sequence) => *sequence,
+        }
+    }
+}
+
+/// Sends don't-fragment `ICMP` probes of a given size and awaits their responses.
+///
+/// The IPv4 header is built here (`IP_HDRINCL`) such that the don't-fragment bit is set on every probe.
+struct MtuProber {
+    src_addr: IpAddr,
+    dest_addr: IpAddr,
+    identifier: TraceId,
+    tos: u8,
+    probe_timeout: Duration,
+    sequence: Sequence,
+    probes: usize,
+    tx: TransportSender,
+    rx: TransportReceiver,
+}
+
+impl MtuProber {
+    fn new(config: &MtuConfig) -> TraceResult<Self> {
+        if config.target_addr.is_ipv6() {
+            return Err(TracerError::Unsupported(
+                "discovering the MTU of IPv6 paths",
+            ));
+        }
+        let src_addr = discover_src_addr(
+            config.target_addr,
+            config.interface.as_deref(),
+            config.source_addr,
+        )?;
+        let (tx, _) = transport_channel(
+            RECV_BUF_SIZE,
+            TransportChannelType::Layer3(IpNextHeaderProtocols::Icmp),
+        )?;
+        let (_, rx) = transport_channel(
+            RECV_BUF_SIZE,
+            TransportChannelType::Layer4(TransportProtocol::Ipv4(IpNextHeaderProtocols::Icmp)),
+        )?;
+        if let Some(interface) = &config.interface {
+            bind_interface(tx.socket.fd, interface, src_addr)?;
+        } else if config.source_addr.is_some() {
+            bind_addr(tx.socket.fd, src_addr)?;
+        }
+        Ok(Self {
+            src_addr,
+            dest_addr: config.target_addr,
+            identifier: config.trace_identifier,
+            tos: config.tos.unwrap_or_default(),
+            probe_timeout: config.probe_timeout,
+            sequence: Sequence(0),
+            probes: 0,
+            tx,
+            rx,
+        })
+    }
+
+    /// Probe a single size, retrying unanswered probes.
+    fn probe(&mut self, size: u16, ttl: TimeToLive) -> TraceResult<Outcome> {
+        for _ in 0..PROBE_ATTEMPTS {
+            let Some(sequence) = self.send(size, ttl)? else {
+                return Ok(Outcome::TooLarge(MtuLimit::Local, None));
+            };
+            match self.await_response(sequence)? {
+                Some(MtuResponse::EchoReply(_)) => return Ok(Outcome::Reached),
+                Some(MtuResponse::FragmentationNeeded(_, addr, mtu)) => {
+                    let limit = MtuLimit::Hop { addr, ttl: None };
+                    return Ok(Outcome::TooLarge(limit, (mtu > 0).then_some(mtu)));
+                }
+                Some(MtuResponse::TimeExceeded(_, _)) => {
+                    return Err(TracerError::BadConfig(format!(
+                        "{} is not reachable within {} hops",
+                        self.dest_addr, ttl.0
+                    )))
+                }
+                Some(MtuResponse::Unreachable(_)) => return Ok(Outcome::Dropped),
+                None => {}
+            }
+        }
+        Ok(Outcome::Dropped)
+    }
+
+    /// The ttl of the hop with address `addr`, if it returned `TimeExceeded` to a probe at any ttl up to `max_ttl`.
+    fn hop_ttl(&mut self, addr: IpAddr, max_ttl: TimeToLive) -> TraceResult<Option<u8>> {
+        let mut ttls = HashMap::new();
+        for ttl in 1..=max_ttl.0 {
+            if let Some(sequence) = self.send(MIN_MTU, TimeToLive(ttl))? {
+                ttls.insert(sequence.0, ttl);
+            }
+        }
+        let deadline = SystemTime::now() + self.probe_timeout;
+        while let Some(remaining) = remaining(deadline) {
+            if let Some(MtuResponse::TimeExceeded(sequence, hop_addr)) = self.recv(remaining)? {
+                if hop_addr == addr {
+                    if let Some(&ttl) = ttls.get(&sequence.0) {
+                        return Ok(Some(ttl));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Send a don't-fragment probe of `size` bytes, returning `None` if it is too large for the local interface.
+    fn send(&mut self, size: u16, ttl: TimeToLive) -> TraceResult<Option<Sequence>> {
+        let (IpAddr::V4(src_addr), IpAddr::V4(dest_addr)) = (self.src_addr, self.dest_addr) else {
+            return Err(TracerError::Unsupported(
+                "discovering the MTU of IPv6 paths",
+            ));
+        };
+        self.sequence += Sequence(1);
+        self.probes += 1;
+        let ip_header_size = Ipv4Packet::minimum_packet_size();
+        let mut buf = vec![0_u8; usize::from(size)];
+        let mut echo = MutableEchoRequestPacket::new(&mut buf[ip_header_size..]).req()?;
+        echo.set_icmp_type(IcmpTypes::EchoRequest);
+        echo.set_icmp_code(echo_request::IcmpCodes::NoCode);
+        echo.set_identifier(self.identifier.0);
+        echo.set_sequence_number(self.sequence.0);
+        echo.set_checksum(util::checksum(echo.packet(), 1));
+        let mut ip = MutableIpv4Packet::new(&mut buf).req()?;
+        ip.set_version(4);
+        ip.set_header_length(5);
+        ip.set_dscp(self.tos >> 2);
+        ip.set_ecn(self.tos & 0x03);
+        ip.set_total_length(size);
+        ip.set_flags(Ipv4Flags::DontFragment);
+        ip.set_ttl(ttl.0);
+        ip.set_next_level_protocol(IpNextHeaderProtocols::Icmp);
+        ip.set_source(src_addr);
+        ip.set_destination(dest_addr);
+        ip.set_checksum(pnet::packet::ipv4::checksum(&ip.to_immutable()));
+        match self.tx.send_to(ip.to_immutable(), self.dest_addr) {
+            Ok(_) => Ok(Some(self.sequence)),
+            Err(err) if err.raw_os_error() == Some(nix::errno::Errno::EMSGSIZE as i32) => Ok(None),
+            Err(err) => Err(TracerError::IoError(err)),
+        }
+    }
+
+    /// Await the response to the probe with `sequence`, ignoring the responses to any earlier probes.
+    fn await_response(&mut self, sequence: Sequence) -> TraceResult<Option<MtuResponse>> {
+        let deadline = SystemTime::now() + self.probe_timeout;
+        while let Some(remaining) = remaining(deadline) {
+            match self.recv(remaining)? {
+                Some(response) if response.sequence() == sequence => return Ok(Some(response)),
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// Receive the next response to any of our probes.
+    fn recv(&mut self, timeout: Duration) -> TraceResult<Option<MtuResponse>> {
+        let mut iter = icmp_packet_iter(&mut self.rx);
+        let Some((icmp, addr)) = iter.next_with_timeout(timeout)? else {
+            return Ok(None);
+        };
+        let (identifier, response) = match icmp.get_icmp_type() {
+            IcmpTypes::EchoReply => {
+                let packet = EchoReplyPacket::new(icmp.packet()).req()?;
+                let sequence = Sequence(packet.get_sequence_number());
+                (packet.get_identifier(), MtuResponse::EchoReply(sequence))
+            }
+            IcmpTypes::TimeExceeded => {
+                let packet = TimeExceededPacket::new(icmp.packet()).req()?;
+                let echo_request = extract_echo_request(packet.payload())?;
+                let sequence = Sequence(echo_request.get_sequence_number());
+                (
+                    echo_request.get_identifier(),
+                    MtuResponse::TimeExceeded(sequence, addr),
+                )
+            }
+            IcmpTypes::DestinationUnreachable => {
+                let packet = DestinationUnreachablePacket::new(icmp.packet()).req()?;
+                let echo_request = extract_echo_request(packet.payload())?;
+                let sequence = Sequence(echo_request.get_sequence_number());
+                let response =
+                    if packet.get_icmp_code() == IcmpCodes::FragmentationRequiredAndDFFlagSet {
+                        // the next-hop MTU is held in the low-order 16 bits of the unused field (RFC 1191)
+                        let mtu = (packet.get_unused() & 0xffff) as u16;
+                        MtuResponse::FragmentationNeeded(sequence, addr, mtu)
+                    } else {
+                        MtuResponse::Unreachable(sequence)
+                    };
+                (echo_request.get_identifier(), response)
+            }
+            _ => return Ok(None),
+        };
+        Ok((identifier == self.identifier.0).then_some(response))
+    }
+}
+
+/// The time remaining until `deadline`, if it has not passed.
+fn remaining(deadline: SystemTime) -> Option<Duration> {
+    deadline
+        .duration_since(SystemTime::now())
+        .ok()
+        .filter(|remaining| !remaining.is_zero())
+}
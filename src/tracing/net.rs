@@ -1,53 +1,84 @@
 use crate::tracing::error::{TraceResult, TracerError};
+use crate::tracing::pcap::PcapWriter;
+use crate::tracing::probe::MILLIS_PER_DAY;
 use crate::tracing::types::{PacketSize, PayloadPattern, SourcePort, TraceId};
 use crate::tracing::util::Required;
-use crate::tracing::Probe;
+use crate::tracing::{
+    IcmpSocketMode, IcmpTimestamps, MplsLabel, MplsLabelStack, MultipathStrategy, Probe, TcpFlag,
+    TcpOutcome, TracerConfig, TracerProtocol, UdpDestPort, UnreachableCode,
+};
 use pnet::datalink::interfaces;
 use pnet::ipnetwork::IpNetwork;
 use pnet::packet::icmp::destination_unreachable::DestinationUnreachablePacket;
 use pnet::packet::icmp::echo_reply::EchoReplyPacket;
 use pnet::packet::icmp::echo_request::{EchoRequestPacket, MutableEchoRequestPacket};
+use pnet::packet::icmp::time_exceeded;
 use pnet::packet::icmp::time_exceeded::TimeExceededPacket;
-use pnet::packet::icmp::{echo_request, IcmpTypes};
-use pnet::packet::ip::IpNextHeaderProtocols;
-use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::icmp::{echo_request, IcmpPacket, IcmpType, IcmpTypes};
+use pnet::packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use pnet::packet::ipv4::{Ipv4Flags, Ipv4Packet, MutableIpv4Packet};
 use pnet::packet::tcp::{ipv4_checksum, MutableTcpPacket, TcpFlags, TcpPacket};
+use pnet::packet::udp;
 use pnet::packet::udp::{MutableUdpPacket, UdpPacket};
 use pnet::packet::Packet;
 use pnet::transport::{
-    icmp_packet_iter, transport_channel, TransportChannelType, TransportProtocol,
+    icmp_packet_iter, tcp_packet_iter, transport_channel, TransportChannelType, TransportProtocol,
     TransportReceiver, TransportSender,
 };
 use pnet::util;
-use std::net::IpAddr;
-use std::time::{Duration, SystemTime};
+use socket2::{Domain, SockAddr, SockRef, Socket, Type};
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// The maximum size of the IP packet we allow.
 const MAX_PACKET_SIZE: usize = 1024;
 
+/// The maximum size of the IP packet we allow if probes are fragmented.
+const MAX_FRAGMENTED_PACKET_SIZE: usize = 8192;
+
+/// The maximum size of each fragment of a fragmented probe, including the IP header.
+///
+/// This is the size of datagram which every IPv4 host must be able to receive (RFC 791), such that a probe is only ever
+/// fragmented here and never again by the local host.
+const FRAGMENT_SIZE: usize = 576;
+
 /// The maximum size of ICMP packet we allow.
-const MAX_ICMP_BUF: usize = MAX_PACKET_SIZE - Ipv4Packet::minimum_packet_size();
+const MAX_ICMP_BUF: usize = MAX_FRAGMENTED_PACKET_SIZE - Ipv4Packet::minimum_packet_size();
 
 /// The maximum ICMP payload size we allow.
 const MAX_ICMP_PAYLOAD_BUF: usize = MAX_ICMP_BUF - EchoRequestPacket::minimum_packet_size();
 
 /// The maximum size of UDP packet we allow.
-const MAX_UDP_BUF: usize = MAX_PACKET_SIZE - Ipv4Packet::minimum_packet_size();
+const MAX_UDP_BUF: usize = MAX_FRAGMENTED_PACKET_SIZE - Ipv4Packet::minimum_packet_size();
 
 /// The maximum UDP payload size we allow.
 const MAX_UDP_PAYLOAD_BUF: usize = MAX_UDP_BUF - UdpPacket::minimum_packet_size();
 
 /// The maximum size of TCP packet we allow.
-const MAX_TCP_BUF: usize = MAX_PACKET_SIZE - Ipv4Packet::minimum_packet_size();
+const MAX_TCP_BUF: usize = MAX_FRAGMENTED_PACKET_SIZE - Ipv4Packet::minimum_packet_size();
 
 /// The maximum TCP payload size we allow.
 const MAX_TCP_PAYLOAD_BUF: usize = MAX_UDP_BUF - TcpPacket::minimum_packet_size();
 
+/// The base destination port used for `TCP` probes in `Paris` mode, offset by the flow id.
+const PARIS_TCP_DEST_PORT: u16 = 80;
+
+/// The size of an `ICMP` `Timestamp` or `TimestampReply` packet.
+const ICMP_TIMESTAMP_SIZE: usize = 20;
+
+/// The number of payload bytes required to adjust the checksum in `Paris` mode, or for a fixed `UDP` destination port.
+const PARIS_PAYLOAD_SIZE: usize = 2;
+
 /// An abstraction over a network interface for tracing.
 pub trait Network {
     /// Send an `ICMP` `Probe`
     fn send_icmp_probe(&mut self, probe: Probe) -> TraceResult<()>;
 
+    /// Send an `ICMP` `Timestamp` `Probe`.
+    fn send_icmp_timestamp_probe(&mut self, probe: Probe) -> TraceResult<()>;
+
     /// Send a `UDP` `Probe`.
     fn send_udp_probe(&mut self, probe: Probe) -> TraceResult<()>;
 
@@ -56,6 +87,8 @@ pub trait Network {
 
     /// Receive the next Icmp packet and return an `IcmpResponse` for a ICMP probe.
     ///
+    /// This is used for both `EchoRequest` and `Timestamp` probes, which share the same identifier and sequence fields.
+    ///
     /// Returns `None` if the read times out or the packet read is not one of the types expected.
     fn recv_probe_resp_icmp(&mut self, timeout: Duration) -> TraceResult<Option<ProbeResponse>>;
 
@@ -64,52 +97,277 @@ pub trait Network {
     /// Returns `None` if the read times out or the packet read is not one of the types expected.
     fn recv_probe_resp_udp(&mut self, timeout: Duration) -> TraceResult<Option<ProbeResponse>>;
 
-    /// Receive the next Icmp packet and return an `IcmpResponse` for a `Tcp` probe.
+    /// Receive the next Icmp packet, or `TCP` `SYN-ACK` or `RST` packet from the target host, and return an
+    /// `IcmpResponse` for a `Tcp` probe.
     ///
     /// Returns `None` if the read times out or the packet read is not one of the types expected.
     fn recv_probe_resp_tcp(&mut self, timeout: Duration) -> TraceResult<Option<ProbeResponse>>;
+
+    /// Send all later probes to, and only accept `TCP` responses from, the target host `addr`.
+    fn set_target(&mut self, addr: IpAddr);
 }
 
 /// A channel for sending and receiving `ICMP` packets.
 pub struct TracerChannel {
     src_addr: IpAddr,
     dest_addr: IpAddr,
+    multipath_strategy: MultipathStrategy,
     identifier: TraceId,
     packet_size: PacketSize,
     payload_pattern: PayloadPattern,
+    payload: Option<Vec<u8>>,
     source_port: SourcePort,
+    source_port_count: u16,
+    udp_dest_port: UdpDestPort,
+    tcp_dest_ports: Vec<u16>,
+    tcp_flags: u16,
+    tos: Option<u8>,
+    pcap: Option<PcapWriter>,
+    sockets: Sockets,
+    header_sender: Option<HeaderSender>,
+}
+
+/// The sockets used to send probes and receive their responses.
+enum Sockets {
+    /// Raw sockets, which require privileges, for probes of every protocol.
+    Raw(RawSockets),
+    /// An unprivileged datagram socket, for `icmp` probes only.
+    Dgram(Socket),
+}
+
+impl Sockets {
+    /// The raw sockets, which are required for all but `icmp` probes.
+    fn raw(&mut self) -> TraceResult<&mut RawSockets> {
+        match self {
+            Self::Raw(raw) => Ok(raw),
+            Self::Dgram(_) => Err(TracerError::Unsupported(
+                "probes other than icmp with an unprivileged dgram icmp socket",
+            )),
+        }
+    }
+
+    /// The file descriptors of the sockets which probes are sent on.
+    fn send_fds(&self) -> Vec<RawFd> {
+        match self {
+            Self::Raw(raw) => vec![
+                raw.icmp_tx.socket.fd,
+                raw.udp_tx.socket.fd,
+                raw.tcp_tx.socket.fd,
+            ],
+            Self::Dgram(socket) => vec![socket.as_raw_fd()],
+        }
+    }
+}
+
+/// The raw sockets used to send probes of every protocol and to receive the `ICMP` and `TCP` responses.
+struct RawSockets {
     icmp_tx: TransportSender,
     icmp_rx: TransportReceiver,
     udp_tx: TransportSender,
     tcp_tx: TransportSender,
+    tcp_rx: TransportReceiver,
 }
 
-impl TracerChannel {
-    /// Create an `IcmpChannel`.
-    ///
-    /// This operation requires the `CAP_NET_RAW` capability on Linux.
-    pub fn new(
-        dest_addr: IpAddr,
-        identifier: TraceId,
-        packet_size: PacketSize,
-        payload_pattern: PayloadPattern,
-        source_port: SourcePort,
-    ) -> TraceResult<Self> {
-        let src_addr = discover_default_src_addr()?;
+impl RawSockets {
+    fn new() -> TraceResult<Self> {
         let (icmp_tx, icmp_rx) = make_icmp_channel()?;
         let (udp_tx, _) = make_udp_channel()?;
-        let (tcp_tx, _) = make_tcp_channel()?;
+        let (tcp_tx, tcp_rx) = make_tcp_channel()?;
         Ok(Self {
-            src_addr,
-            dest_addr,
-            identifier,
-            packet_size,
-            payload_pattern,
-            source_port,
             icmp_tx,
             icmp_rx,
             udp_tx,
             tcp_tx,
+            tcp_rx,
+        })
+    }
+}
+
+/// Sends probes on a raw socket which builds the IP header (`IP_HDRINCL`), such that the don't-fragment bit and the
+/// fragmentation of probes are controlled here rather than by the OS.
+struct HeaderSender {
+    tx: TransportSender,
+    src_addr: Ipv4Addr,
+    dest_addr: Ipv4Addr,
+    tos: u8,
+    fragment: bool,
+    dont_fragment: bool,
+}
+
+impl HeaderSender {
+    fn new(
+        src_addr: IpAddr,
+        dest_addr: IpAddr,
+        tos: Option<u8>,
+        fragment: bool,
+        dont_fragment: bool,
+    ) -> TraceResult<Self> {
+        let (IpAddr::V4(src_addr), IpAddr::V4(dest_addr)) = (src_addr, dest_addr) else {
+            return Err(TracerError::Unsupported(
+                "building the IP header of IPv6 probes",
+            ));
+        };
+        let (tx, _) = transport_channel(
+            FRAGMENT_SIZE,
+            TransportChannelType::Layer3(IpNextHeaderProtocols::Reserved),
+        )?;
+        Ok(Self {
+            tx,
+            src_addr,
+            dest_addr,
+            tos: tos.unwrap_or_default(),
+            fragment,
+            dont_fragment,
+        })
+    }
+
+    /// Send the transport layer `packet` of a probe.
+    ///
+    /// If `fragment` is set the packet is sent as fragments of at most `FRAGMENT_SIZE` bytes, or whole if it fits,
+    /// otherwise it is sent whole with the don't-fragment bit set as per `dont_fragment`.  Packets, or their fragments,
+    /// are identified by the sequence number of the probe, such that the fragments of probes which are in flight at the
+    /// same time are never reassembled together.
+    fn send(
+        &mut self,
+        protocol: IpNextHeaderProtocol,
+        probe: Probe,
+        packet: &[u8],
+    ) -> TraceResult<()> {
+        let ip_header_size = Ipv4Packet::minimum_packet_size();
+        let max_payload = if self.fragment {
+            (FRAGMENT_SIZE - ip_header_size) & !7
+        } else {
+            packet.len().max(1)
+        };
+        let mut buf = [0_u8; MAX_PACKET_SIZE];
+        let chunks = packet.chunks(max_payload);
+        let count = chunks.len();
+        for (i, chunk) in chunks.enumerate() {
+            let size = ip_header_size + chunk.len();
+            let mut ip = MutableIpv4Packet::new(&mut buf[..size]).req()?;
+            ip.set_version(4);
+            ip.set_header_length(5);
+            ip.set_dscp(self.tos >> 2);
+            ip.set_ecn(self.tos & 0x03);
+            ip.set_total_length(size as u16);
+            ip.set_identification(probe.sequence.0);
+            ip.set_flags(if i + 1 < count {
+                Ipv4Flags::MoreFragments
+            } else if self.dont_fragment {
+                Ipv4Flags::DontFragment
+            } else {
+                0
+            });
+            ip.set_fragment_offset((i * max_payload / 8) as u16);
+            ip.set_ttl(probe.ttl.0);
+            ip.set_next_level_protocol(protocol);
+            ip.set_source(self.src_addr);
+            ip.set_destination(self.dest_addr);
+            ip.set_payload(chunk);
+            ip.set_checksum(pnet::packet::ipv4::checksum(&ip.to_immutable()));
+            self.tx
+                .send_to(ip.to_immutable(), IpAddr::V4(self.dest_addr))?;
+        }
+        Ok(())
+    }
+}
+
+impl TracerChannel {
+    /// Create an `IcmpChannel`.
+    ///
+    /// If an `interface` is configured then all sockets are bound to it, otherwise the OS chooses.  If a `source_addr`
+    /// is configured then all sockets are also bound to it, such that it is the source address of every probe.
+    ///
+    /// This operation requires the `CAP_NET_RAW` capability on Linux, unless `icmp` probes are sent on an unprivileged
+    /// datagram socket as per the `icmp_socket_mode`.  In `Auto` mode a datagram socket is used only if creating the
+    /// raw sockets is not permitted.
+    ///
+    /// If `fragment` is configured then probes are sent as fragments on an additional raw socket, which always requires
+    /// the `CAP_NET_RAW` capability on Linux.  Likewise if `dont_fragment` is configured then probes are sent on that
+    /// socket with the don't-fragment bit set or clear, rather than as the OS chooses.
+    ///
+    /// Tracing to `IpAddr::V6` targets is not yet supported.
+    pub fn new(config: &TracerConfig) -> TraceResult<Self> {
+        if config.target_addr.is_ipv6() {
+            return Err(TracerError::Unsupported("tracing to IPv6 targets"));
+        }
+        let src_addr = discover_src_addr(
+            config.target_addr,
+            config.interface.as_deref(),
+            config.source_addr,
+        )?;
+        let sockets = match (config.protocol, config.icmp_socket_mode) {
+            (TracerProtocol::Icmp, IcmpSocketMode::Dgram) => Sockets::Dgram(
+                make_icmp_dgram_socket(config.trace_identifier, config.source_addr)
+                    .map_err(TracerError::IcmpDgramSocket)?,
+            ),
+            (TracerProtocol::Icmp, IcmpSocketMode::Auto)
+                if !config.fragment && config.dont_fragment.is_none() =>
+            {
+                match RawSockets::new() {
+                    Ok(raw) => Sockets::Raw(raw),
+                    Err(TracerError::IoError(raw_err))
+                        if raw_err.kind() == ErrorKind::PermissionDenied =>
+                    {
+                        Sockets::Dgram(
+                            make_icmp_dgram_socket(config.trace_identifier, config.source_addr)
+                                .map_err(|err| TracerError::NoIcmpSocket(raw_err, err))?,
+                        )
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            _ => Sockets::Raw(RawSockets::new()?),
+        };
+        let header_sender = (config.fragment || config.dont_fragment.is_some())
+            .then(|| {
+                HeaderSender::new(
+                    src_addr,
+                    config.target_addr,
+                    config.tos,
+                    config.fragment,
+                    config.dont_fragment.unwrap_or_default(),
+                )
+            })
+            .transpose()?;
+        let fds = sockets.send_fds();
+        if let Some(tos) = config.tos {
+            for fd in &fds {
+                set_tos(*fd, tos)?;
+            }
+        }
+        if let Some(interface) = &config.interface {
+            for fd in &fds {
+                bind_interface(*fd, interface, src_addr)?;
+            }
+            if let Some(header_sender) = &header_sender {
+                bind_interface(header_sender.tx.socket.fd, interface, src_addr)?;
+            }
+        }
+        // On Linux a datagram socket is bound to the source address when created, along with the identifier.
+        let bound = cfg!(target_os = "linux") && matches!(sockets, Sockets::Dgram(_));
+        if config.source_addr.is_some() && !bound {
+            for fd in &fds {
+                bind_addr(*fd, src_addr)?;
+            }
+        }
+        Ok(Self {
+            src_addr,
+            dest_addr: config.target_addr,
+            multipath_strategy: config.multipath_strategy,
+            identifier: config.trace_identifier,
+            packet_size: config.packet_size,
+            payload_pattern: config.payload_pattern,
+            payload: config.payload.clone(),
+            source_port: config.source_port,
+            source_port_count: config.source_port_count,
+            udp_dest_port: config.udp_dest_port,
+            tcp_dest_ports: config.tcp_dest_ports.clone(),
+            tcp_flags: tcp_flag_bits(&config.tcp_flags),
+            tos: config.tos,
+            pcap: config.pcap.clone(),
+            sockets,
+            header_sender,
         })
     }
 }
@@ -117,187 +375,507 @@ impl TracerChannel {
 impl Network for TracerChannel {
     fn send_icmp_probe(&mut self, probe: Probe) -> TraceResult<()> {
         let packet_size = usize::from(self.packet_size.0);
-        if packet_size > MAX_PACKET_SIZE {
+        if packet_size > self.max_packet_size() {
             return Err(TracerError::InvalidPacketSize(packet_size));
         }
         let ip_header_size = Ipv4Packet::minimum_packet_size();
         let icmp_header_size = EchoRequestPacket::minimum_packet_size();
         let mut icmp_buf = [0_u8; MAX_ICMP_BUF];
-        let mut payload_buf = [0_u8; MAX_ICMP_PAYLOAD_BUF];
+        let payload_buf = self.payload_buf::<MAX_ICMP_PAYLOAD_BUF>();
         let icmp_buf_size = packet_size - ip_header_size;
         let payload_size = packet_size - icmp_header_size - ip_header_size;
-        payload_buf
-            .iter_mut()
-            .for_each(|x| *x = self.payload_pattern.0);
         let mut req = MutableEchoRequestPacket::new(&mut icmp_buf[..icmp_buf_size]).req()?;
         req.set_icmp_type(IcmpTypes::EchoRequest);
         req.set_icmp_code(echo_request::IcmpCodes::NoCode);
         req.set_identifier(self.identifier.0);
         req.set_payload(&payload_buf[..payload_size]);
         req.set_sequence_number(probe.sequence.0);
+        if matches!(self.multipath_strategy, MultipathStrategy::Paris) {
+            if payload_size < PARIS_PAYLOAD_SIZE {
+                return Err(TracerError::InvalidPacketSize(packet_size));
+            }
+            // Cancel out the sequence number such that the checksum is constant for all probes of a flow.
+            let adjustment = ones_complement_add(!probe.sequence.0, probe.flow_id.0);
+            let mut payload = payload_buf;
+            payload[..PARIS_PAYLOAD_SIZE].copy_from_slice(&adjustment.to_be_bytes());
+            req.set_payload(&payload[..payload_size]);
+        }
         req.set_checksum(util::checksum(req.packet(), 1));
-        self.icmp_tx.set_ttl(probe.ttl.0)?;
-        self.icmp_tx.send_to(req.to_immutable(), self.dest_addr)?;
-        Ok(())
+        match (&mut self.sockets, &mut self.header_sender) {
+            (Sockets::Raw(_), Some(header_sender)) => {
+                header_sender.send(IpNextHeaderProtocols::Icmp, probe, req.packet())?;
+            }
+            (Sockets::Raw(raw), None) => {
+                raw.icmp_tx.set_ttl(probe.ttl.0)?;
+                raw.icmp_tx.send_to(req.to_immutable(), self.dest_addr)?;
+            }
+            (Sockets::Dgram(socket), _) => {
+                socket.set_ttl(u32::from(probe.ttl.0))?;
+                socket.send_to(
+                    req.packet(),
+                    &SockAddr::from(SocketAddr::new(self.dest_addr, 0)),
+                )?;
+            }
+        }
+        self.capture_sent(IpNextHeaderProtocols::Icmp, probe.ttl.0, req.packet())
+    }
+
+    /// Send an `ICMP` `Timestamp` request, which has a fixed size and so the configured packet size is not used.
+    ///
+    /// The originate timestamp is the time the probe was sent, the receive and transmit timestamps are set to zero.
+    fn send_icmp_timestamp_probe(&mut self, probe: Probe) -> TraceResult<()> {
+        let mut icmp_buf = [0_u8; ICMP_TIMESTAMP_SIZE];
+        let sent = probe.sent.unwrap_or_else(SystemTime::now);
+        icmp_buf[0] = IcmpTypes::Timestamp.0;
+        icmp_buf[4..6].copy_from_slice(&self.identifier.0.to_be_bytes());
+        icmp_buf[6..8].copy_from_slice(&probe.sequence.0.to_be_bytes());
+        icmp_buf[8..12].copy_from_slice(&millis_since_midnight(sent).to_be_bytes());
+        let checksum = util::checksum(&icmp_buf, 1);
+        icmp_buf[2..4].copy_from_slice(&checksum.to_be_bytes());
+        let req = IcmpPacket::new(&icmp_buf).req()?;
+        let raw = self.sockets.raw()?;
+        if let Some(header_sender) = &mut self.header_sender {
+            header_sender.send(IpNextHeaderProtocols::Icmp, probe, &icmp_buf)?;
+        } else {
+            raw.icmp_tx.set_ttl(probe.ttl.0)?;
+            raw.icmp_tx.send_to(req, self.dest_addr)?;
+        }
+        self.capture_sent(IpNextHeaderProtocols::Icmp, probe.ttl.0, &icmp_buf)
     }
 
     fn send_udp_probe(&mut self, probe: Probe) -> TraceResult<()> {
         let packet_size = usize::from(self.packet_size.0);
-        if packet_size > MAX_PACKET_SIZE {
+        if packet_size > self.max_packet_size() {
             return Err(TracerError::InvalidPacketSize(packet_size));
         }
         let ip_header_size = Ipv4Packet::minimum_packet_size();
         let udp_header_size = UdpPacket::minimum_packet_size();
         let mut udp_buf = [0_u8; MAX_UDP_BUF];
-        let mut payload_buf = [0_u8; MAX_UDP_PAYLOAD_BUF];
+        let payload_buf = self.payload_buf::<MAX_UDP_PAYLOAD_BUF>();
         let udp_buf_size = packet_size - ip_header_size;
         let mut udp = MutableUdpPacket::new(&mut udp_buf[..udp_buf_size]).req()?;
-        udp.set_source(self.source_port.0);
-        udp.set_destination(probe.sequence.0);
+        udp.set_source(
+            self.source_port
+                .rotated(probe.round, self.source_port_count)
+                .0,
+        );
         let payload_size = packet_size - udp_header_size - ip_header_size;
         udp.set_length((UdpPacket::minimum_packet_size() + payload_size) as u16);
-        payload_buf
-            .iter_mut()
-            .for_each(|x| *x = self.payload_pattern.0);
         udp.set_payload(&payload_buf[..payload_size]);
-        self.udp_tx.set_ttl(probe.ttl.0)?;
-        self.udp_tx.send_to(udp.to_immutable(), self.dest_addr)?;
-        Ok(())
+        match self.udp_dest_port {
+            UdpDestPort::Sequence => udp.set_destination(probe.sequence.0),
+            UdpDestPort::Fixed(port) => {
+                if payload_size < PARIS_PAYLOAD_SIZE {
+                    return Err(TracerError::InvalidPacketSize(packet_size));
+                }
+                udp.set_destination(port + probe.flow_id.0);
+                // Adjust the payload such that the checksum is equal to the sequence number.
+                let mut payload = payload_buf;
+                payload[..PARIS_PAYLOAD_SIZE].fill(0);
+                udp.set_payload(&payload[..payload_size]);
+                let initial = udp_checksum(&udp.to_immutable(), &self.src_addr, &self.dest_addr);
+                let adjustment = ones_complement_add(!probe.sequence.0, initial);
+                payload[..PARIS_PAYLOAD_SIZE].copy_from_slice(&adjustment.to_be_bytes());
+                udp.set_payload(&payload[..payload_size]);
+                udp.set_checksum(udp_checksum(
+                    &udp.to_immutable(),
+                    &self.src_addr,
+                    &self.dest_addr,
+                ));
+            }
+        }
+        let raw = self.sockets.raw()?;
+        if let Some(header_sender) = &mut self.header_sender {
+            header_sender.send(IpNextHeaderProtocols::Udp, probe, udp.packet())?;
+        } else {
+            raw.udp_tx.set_ttl(probe.ttl.0)?;
+            raw.udp_tx.send_to(udp.to_immutable(), self.dest_addr)?;
+        }
+        self.capture_sent(IpNextHeaderProtocols::Udp, probe.ttl.0, udp.packet())
     }
 
     fn send_tcp_probe(&mut self, probe: Probe) -> TraceResult<()> {
         let packet_size = usize::from(self.packet_size.0);
-        if packet_size > MAX_PACKET_SIZE {
+        if packet_size > self.max_packet_size() {
             return Err(TracerError::InvalidPacketSize(packet_size));
         }
         let ip_header_size = Ipv4Packet::minimum_packet_size();
         let tcp_header_size = TcpPacket::minimum_packet_size();
         let mut tcp_buf = [0_u8; MAX_TCP_BUF];
-        let mut payload_buf = [0_u8; MAX_TCP_PAYLOAD_BUF];
+        let payload_buf = self.payload_buf::<MAX_TCP_PAYLOAD_BUF>();
         let tcp_buf_size = packet_size - ip_header_size;
         let payload_size = packet_size - tcp_header_size - ip_header_size;
         let mut tcp = MutableTcpPacket::new(&mut tcp_buf[..tcp_buf_size]).req()?;
-        tcp.set_source(self.source_port.0);
-        tcp.set_destination(probe.sequence.0);
-        tcp.set_flags(TcpFlags::SYN);
+        tcp.set_source(
+            self.source_port
+                .rotated(probe.round, self.source_port_count)
+                .0,
+        );
+        match self.multipath_strategy {
+            MultipathStrategy::Classic if self.tcp_dest_ports.is_empty() => {
+                tcp.set_destination(probe.sequence.0);
+            }
+            MultipathStrategy::Classic => {
+                let port_index = usize::from(probe.flow_id.0) % self.tcp_dest_ports.len();
+                tcp.set_destination(self.tcp_dest_ports[port_index]);
+                tcp.set_sequence(u32::from(probe.sequence.0));
+            }
+            MultipathStrategy::Paris => {
+                tcp.set_destination(PARIS_TCP_DEST_PORT + probe.flow_id.0);
+                tcp.set_sequence(u32::from(probe.sequence.0));
+            }
+        }
+        if self.tcp_flags & TcpFlags::ACK != 0 {
+            tcp.set_acknowledgement(u32::from(probe.sequence.0));
+        }
+        tcp.set_flags(self.tcp_flags);
         tcp.set_data_offset(5);
+        tcp.set_payload(&payload_buf[..payload_size]);
         tcp.set_checksum(tcp_checksum(
             &tcp.to_immutable(),
             &self.src_addr,
             &self.dest_addr,
         ));
-        payload_buf
-            .iter_mut()
-            .for_each(|x| *x = self.payload_pattern.0);
-        tcp.set_payload(&payload_buf[..payload_size]);
-        self.tcp_tx.set_ttl(probe.ttl.0)?;
-        self.tcp_tx.send_to(tcp.to_immutable(), self.dest_addr)?;
-        Ok(())
+        let raw = self.sockets.raw()?;
+        if let Some(header_sender) = &mut self.header_sender {
+            header_sender.send(IpNextHeaderProtocols::Tcp, probe, tcp.packet())?;
+        } else {
+            raw.tcp_tx.set_ttl(probe.ttl.0)?;
+            raw.tcp_tx.send_to(tcp.to_immutable(), self.dest_addr)?;
+        }
+        self.capture_sent(IpNextHeaderProtocols::Tcp, probe.ttl.0, tcp.packet())
     }
 
+    /// Responses read from an unprivileged datagram socket are not written to the pcap capture, as the OS does not
+    /// expose the packet received.
     fn recv_probe_resp_icmp(&mut self, timeout: Duration) -> TraceResult<Option<ProbeResponse>> {
-        Ok(
-            match icmp_packet_iter(&mut self.icmp_rx).next_with_timeout(timeout)? {
-                Some((icmp, ip)) => {
-                    let recv = SystemTime::now();
-                    match icmp.get_icmp_type() {
-                        IcmpTypes::TimeExceeded => {
-                            let packet = TimeExceededPacket::new(icmp.packet()).req()?;
-                            let echo_request = extract_echo_request(packet.payload())?;
-                            let identifier = echo_request.get_identifier();
-                            let sequence = echo_request.get_sequence_number();
-                            Some(ProbeResponse::TimeExceeded(ProbeResponseData::new(
-                                recv, ip, identifier, sequence,
-                            )))
-                        }
-                        IcmpTypes::DestinationUnreachable => {
-                            let packet = DestinationUnreachablePacket::new(icmp.packet()).req()?;
-                            let echo_request = extract_echo_request(packet.payload())?;
-                            let identifier = echo_request.get_identifier();
-                            let sequence = echo_request.get_sequence_number();
-                            Some(ProbeResponse::DestinationUnreachable(
-                                ProbeResponseData::new(recv, ip, identifier, sequence),
-                            ))
-                        }
-                        IcmpTypes::EchoReply => {
-                            let packet = EchoReplyPacket::new(icmp.packet()).req()?;
-                            let identifier = packet.get_identifier();
-                            let sequence = packet.get_sequence_number();
-                            Some(ProbeResponse::EchoReply(ProbeResponseData::new(
-                                recv, ip, identifier, sequence,
-                            )))
-                        }
-                        _ => None,
-                    }
-                }
-                None => None,
-            },
-        )
+        match &mut self.sockets {
+            Sockets::Raw(raw) => {
+                let response =
+                    match icmp_packet_iter(&mut raw.icmp_rx).next_with_timeout(timeout)? {
+                        Some((icmp, ip)) => extract_icmp_response(&icmp, ip, SystemTime::now())?,
+                        None => None,
+                    };
+                capture_received(self.pcap.as_ref(), response.as_ref(), &raw.icmp_rx.buffer)?;
+                Ok(response)
+            }
+            Sockets::Dgram(socket) => recv_icmp_dgram(socket, timeout),
+        }
     }
 
     fn recv_probe_resp_udp(&mut self, timeout: Duration) -> TraceResult<Option<ProbeResponse>> {
-        Ok(
-            match icmp_packet_iter(&mut self.icmp_rx).next_with_timeout(timeout)? {
-                Some((icmp, ip)) => {
-                    let recv = SystemTime::now();
-                    match icmp.get_icmp_type() {
-                        IcmpTypes::TimeExceeded => {
-                            let packet = TimeExceededPacket::new(icmp.packet()).req()?;
-                            let sequence = extract_udp_probe(packet.payload())?;
-                            Some(ProbeResponse::TimeExceeded(ProbeResponseData::new(
-                                recv, ip, 0, sequence,
-                            )))
-                        }
-                        IcmpTypes::DestinationUnreachable => {
-                            let packet = DestinationUnreachablePacket::new(icmp.packet()).req()?;
-                            let sequence = extract_udp_probe(packet.payload())?;
-                            Some(ProbeResponse::DestinationUnreachable(
-                                ProbeResponseData::new(recv, ip, 0, sequence),
-                            ))
-                        }
-                        _ => None,
+        let raw = self.sockets.raw()?;
+        let response = match icmp_packet_iter(&mut raw.icmp_rx).next_with_timeout(timeout)? {
+            Some((icmp, ip)) => {
+                let recv = SystemTime::now();
+                match icmp.get_icmp_type() {
+                    IcmpTypes::TimeExceeded if quotes_probe(&icmp, IpNextHeaderProtocols::Udp) => {
+                        let packet = TimeExceededPacket::new(icmp.packet()).req()?;
+                        let (sequence, src_port) =
+                            extract_udp_probe(packet.payload(), self.udp_dest_port)?;
+                        let mpls = extract_mpls(icmp.packet());
+                        Some(ProbeResponse::TimeExceeded(ProbeResponseData::new(
+                            recv, ip, 0, sequence, src_port, mpls,
+                        )))
                     }
+                    IcmpTypes::DestinationUnreachable
+                        if quotes_probe(&icmp, IpNextHeaderProtocols::Udp) =>
+                    {
+                        let packet = DestinationUnreachablePacket::new(icmp.packet()).req()?;
+                        let (sequence, src_port) =
+                            extract_udp_probe(packet.payload(), self.udp_dest_port)?;
+                        let mpls = extract_mpls(icmp.packet());
+                        Some(ProbeResponse::DestinationUnreachable(
+                            ProbeResponseData::new(recv, ip, 0, sequence, src_port, mpls),
+                            UnreachableCode(icmp.get_icmp_code().0),
+                        ))
+                    }
+                    _ => None,
                 }
-                None => None,
-            },
-        )
+            }
+            None => None,
+        };
+        capture_received(self.pcap.as_ref(), response.as_ref(), &raw.icmp_rx.buffer)?;
+        Ok(response)
     }
 
     fn recv_probe_resp_tcp(&mut self, timeout: Duration) -> TraceResult<Option<ProbeResponse>> {
-        Ok(
-            match icmp_packet_iter(&mut self.icmp_rx).next_with_timeout(timeout)? {
-                Some((icmp, ip)) => {
-                    let recv = SystemTime::now();
-                    match icmp.get_icmp_type() {
-                        IcmpTypes::TimeExceeded => {
-                            let packet = TimeExceededPacket::new(icmp.packet()).req()?;
-                            let sequence = extract_tcp_probe(packet.payload())?;
-                            Some(ProbeResponse::TimeExceeded(ProbeResponseData::new(
-                                recv, ip, 0, sequence,
-                            )))
-                        }
-                        IcmpTypes::DestinationUnreachable => {
-                            let packet = DestinationUnreachablePacket::new(icmp.packet()).req()?;
-                            let sequence = extract_tcp_probe(packet.payload())?;
-                            Some(ProbeResponse::DestinationUnreachable(
-                                ProbeResponseData::new(recv, ip, 0, sequence),
-                            ))
-                        }
-                        _ => None,
+        let fixed_dest_port = self.fixed_tcp_dest_port();
+        let raw = self.sockets.raw()?;
+        let (icmp_ready, tcp_ready) = poll_readable(&raw.icmp_rx, &raw.tcp_rx, timeout)?;
+        if tcp_ready {
+            let response = self.recv_tcp_reply(fixed_dest_port)?;
+            if response.is_some() {
+                let buffer = &self.sockets.raw()?.tcp_rx.buffer;
+                capture_received(self.pcap.as_ref(), response.as_ref(), buffer)?;
+                return Ok(response);
+            }
+        }
+        if !icmp_ready {
+            return Ok(None);
+        }
+        let raw = self.sockets.raw()?;
+        let response = match icmp_packet_iter(&mut raw.icmp_rx).next_with_timeout(timeout)? {
+            Some((icmp, ip)) => {
+                let recv = SystemTime::now();
+                match icmp.get_icmp_type() {
+                    IcmpTypes::TimeExceeded if quotes_probe(&icmp, IpNextHeaderProtocols::Tcp) => {
+                        let packet = TimeExceededPacket::new(icmp.packet()).req()?;
+                        let (sequence, src_port) =
+                            extract_tcp_probe(packet.payload(), fixed_dest_port)?;
+                        let mpls = extract_mpls(icmp.packet());
+                        Some(ProbeResponse::TimeExceeded(ProbeResponseData::new(
+                            recv, ip, 0, sequence, src_port, mpls,
+                        )))
+                    }
+                    IcmpTypes::DestinationUnreachable
+                        if quotes_probe(&icmp, IpNextHeaderProtocols::Tcp) =>
+                    {
+                        let packet = DestinationUnreachablePacket::new(icmp.packet()).req()?;
+                        let (sequence, src_port) =
+                            extract_tcp_probe(packet.payload(), fixed_dest_port)?;
+                        let mpls = extract_mpls(icmp.packet());
+                        Some(ProbeResponse::DestinationUnreachable(
+                            ProbeResponseData::new(recv, ip, 0, sequence, src_port, mpls),
+                            UnreachableCode(icmp.get_icmp_code().0),
+                        ))
                     }
+                    _ => None,
                 }
-                None => None,
-            },
-        )
+            }
+            None => None,
+        };
+        capture_received(self.pcap.as_ref(), response.as_ref(), &raw.icmp_rx.buffer)?;
+        Ok(response)
+    }
+
+    fn set_target(&mut self, addr: IpAddr) {
+        self.dest_addr = addr;
+        if let (Some(header_sender), IpAddr::V4(addr)) = (&mut self.header_sender, addr) {
+            header_sender.dest_addr = addr;
+        }
     }
 }
 
+impl TracerChannel {
+    /// Read the next `TCP` packet, which must be readable, and return a `TcpReply` if it is the `SYN-ACK` or `RST` sent
+    /// by the target host in response to one of our probes.
+    ///
+    /// The raw `TCP` socket receives every `TCP` packet delivered to the host and so packets from other hosts, or to
+    /// ports other than the source ports of our probes, are ignored.  If the destination port of probes varies then the sequence number is the port the
+    /// reply was sent from, otherwise it is acknowledged in the acknowledgement number of the reply.  A `SYN-ACK`
+    /// acknowledges only the `SYN` whereas a `RST` also acknowledges the payload of the probe.
+    ///
+    /// A probe with the `ACK` flag, which carries the sequence number in its acknowledgement number, is answered with a
+    /// `RST` whose sequence number is that acknowledgement number, whether the port is open or closed.
+    fn recv_tcp_reply(&mut self, fixed_dest_port: bool) -> TraceResult<Option<ProbeResponse>> {
+        let (dest_addr, source_port) = (self.dest_addr, self.source_port.0);
+        let source_ports =
+            u32::from(source_port)..u32::from(source_port) + u32::from(self.source_port_count);
+        let mut packets = tcp_packet_iter(&mut self.sockets.raw()?.tcp_rx);
+        let Some((tcp, ip)) = packets.next_with_timeout(Duration::from_millis(1))? else {
+            return Ok(None);
+        };
+        if ip != dest_addr || !source_ports.contains(&u32::from(tcp.get_destination())) {
+            return Ok(None);
+        }
+        let flags = tcp.get_flags();
+        let payload_size = u32::from(self.packet_size.0).saturating_sub(
+            (Ipv4Packet::minimum_packet_size() + TcpPacket::minimum_packet_size()) as u32,
+        );
+        let probe_syn = self.tcp_flags & TcpFlags::SYN != 0;
+        let probe_ack = self.tcp_flags & TcpFlags::ACK != 0;
+        let probe_fin = self.tcp_flags & TcpFlags::FIN != 0;
+        let (outcome, acknowledged) = if flags & TcpFlags::RST != 0 && probe_ack {
+            (TcpOutcome::Unfiltered, 0)
+        } else if flags & TcpFlags::RST != 0 {
+            (
+                TcpOutcome::Closed,
+                u32::from(probe_syn) + u32::from(probe_fin) + payload_size,
+            )
+        } else if flags & (TcpFlags::SYN | TcpFlags::ACK) == TcpFlags::SYN | TcpFlags::ACK
+            && probe_syn
+            && !probe_ack
+        {
+            (TcpOutcome::Open, 1)
+        } else {
+            return Ok(None);
+        };
+        let sequence = match (fixed_dest_port, probe_ack) {
+            (true, true) => tcp.get_sequence() as u16,
+            (true, false) => tcp.get_acknowledgement().wrapping_sub(acknowledged) as u16,
+            (false, _) => tcp.get_source(),
+        };
+        Ok(Some(ProbeResponse::TcpReply(
+            ProbeResponseData::new(
+                SystemTime::now(),
+                ip,
+                0,
+                sequence,
+                tcp.get_destination(),
+                None,
+            ),
+            outcome,
+        )))
+    }
+
+    /// Is the destination port of `tcp` probes fixed for each flow, such that the sequence number is encoded in the
+    /// `TCP` sequence number rather than the destination port?
+    fn fixed_tcp_dest_port(&self) -> bool {
+        matches!(self.multipath_strategy, MultipathStrategy::Paris)
+            || !self.tcp_dest_ports.is_empty()
+    }
+
+    /// The maximum size of probe, which is larger if probes are fragmented.
+    fn max_packet_size(&self) -> usize {
+        if self
+            .header_sender
+            .as_ref()
+            .is_some_and(|sender| sender.fragment)
+        {
+            MAX_FRAGMENTED_PACKET_SIZE
+        } else {
+            MAX_PACKET_SIZE
+        }
+    }
+
+    /// A payload buffer of `N` bytes, either the custom payload, truncated or zero padded, or the repeating pattern.
+    fn payload_buf<const N: usize>(&self) -> [u8; N] {
+        match &self.payload {
+            Some(payload) => {
+                let mut buf = [0_u8; N];
+                let len = payload.len().min(N);
+                buf[..len].copy_from_slice(&payload[..len]);
+                buf
+            }
+            None => [self.payload_pattern.0; N],
+        }
+    }
+
+    /// Write a sent probe to the pcap capture, if configured.
+    ///
+    /// The `IPv4` header of an outgoing probe is built by the OS and so is reconstructed here from the values the OS is
+    /// asked to use, the identification and flags fields are always zero and may differ from those on the wire.  A
+    /// fragmented probe is captured whole, as it would be once reassembled.
+    fn capture_sent(
+        &self,
+        protocol: IpNextHeaderProtocol,
+        ttl: u8,
+        payload: &[u8],
+    ) -> TraceResult<()> {
+        let (Some(pcap), IpAddr::V4(src_addr), IpAddr::V4(dest_addr)) =
+            (&self.pcap, self.src_addr, self.dest_addr)
+        else {
+            return Ok(());
+        };
+        let ip_header_size = Ipv4Packet::minimum_packet_size();
+        let mut ip_buf = [0_u8; MAX_FRAGMENTED_PACKET_SIZE];
+        let mut ip = MutableIpv4Packet::new(&mut ip_buf[..ip_header_size + payload.len()]).req()?;
+        let tos = self.tos.unwrap_or_default();
+        ip.set_version(4);
+        ip.set_header_length(5);
+        ip.set_dscp(tos >> 2);
+        ip.set_ecn(tos & 0x03);
+        ip.set_total_length((ip_header_size + payload.len()) as u16);
+        ip.set_ttl(ttl);
+        ip.set_next_level_protocol(protocol);
+        ip.set_source(src_addr);
+        ip.set_destination(dest_addr);
+        ip.set_payload(payload);
+        ip.set_checksum(pnet::packet::ipv4::checksum(&ip.to_immutable()));
+        pcap.write_packet(SystemTime::now(), ip.packet())
+    }
+}
+
+/// Write a received probe response to the pcap capture, if configured.
+///
+/// The receive `buffer` of the raw socket the response was read from holds the complete `IPv4` packet, including the
+/// header.
+fn capture_received(
+    pcap: Option<&PcapWriter>,
+    response: Option<&ProbeResponse>,
+    buffer: &[u8],
+) -> TraceResult<()> {
+    let (Some(pcap), Some(response)) = (pcap, response) else {
+        return Ok(());
+    };
+    let ip = Ipv4Packet::new(buffer).req()?;
+    let len = usize::from(ip.get_total_length()).min(buffer.len());
+    pcap.write_packet(response.data().recv, &buffer[..len])
+}
+
+/// Extract the response to an `icmp` or `icmp-timestamp` probe from an `ICMP` packet sent by `ip`.
+///
+/// Returns `None` if the packet is not one of the types expected.
+fn extract_icmp_response(
+    icmp: &IcmpPacket<'_>,
+    ip: IpAddr,
+    recv: SystemTime,
+) -> TraceResult<Option<ProbeResponse>> {
+    Ok(match icmp.get_icmp_type() {
+        IcmpTypes::TimeExceeded if quotes_probe(icmp, IpNextHeaderProtocols::Icmp) => {
+            let packet = TimeExceededPacket::new(icmp.packet()).req()?;
+            let echo_request = extract_echo_request(packet.payload())?;
+            let identifier = echo_request.get_identifier();
+            let sequence = echo_request.get_sequence_number();
+            let mpls = extract_mpls(icmp.packet());
+            Some(ProbeResponse::TimeExceeded(ProbeResponseData::new(
+                recv, ip, identifier, sequence, 0, mpls,
+            )))
+        }
+        IcmpTypes::DestinationUnreachable if quotes_probe(icmp, IpNextHeaderProtocols::Icmp) => {
+            let packet = DestinationUnreachablePacket::new(icmp.packet()).req()?;
+            let echo_request = extract_echo_request(packet.payload())?;
+            let identifier = echo_request.get_identifier();
+            let sequence = echo_request.get_sequence_number();
+            let mpls = extract_mpls(icmp.packet());
+            Some(ProbeResponse::DestinationUnreachable(
+                ProbeResponseData::new(recv, ip, identifier, sequence, 0, mpls),
+                UnreachableCode(icmp.get_icmp_code().0),
+            ))
+        }
+        IcmpTypes::EchoReply => {
+            let packet = EchoReplyPacket::new(icmp.packet()).req()?;
+            let identifier = packet.get_identifier();
+            let sequence = packet.get_sequence_number();
+            Some(ProbeResponse::EchoReply(ProbeResponseData::new(
+                recv, ip, identifier, sequence, 0, None,
+            )))
+        }
+        IcmpTypes::TimestampReply => extract_timestamp_reply(icmp.packet(), recv).map(
+            |(identifier, sequence, timestamps)| {
+                ProbeResponse::TimestampReply(
+                    ProbeResponseData::new(recv, ip, identifier, sequence, 0, None),
+                    timestamps,
+                )
+            },
+        ),
+        _ => None,
+    })
+}
+
 /// The response to a probe.
 #[derive(Debug, Copy, Clone)]
 pub enum ProbeResponse {
     TimeExceeded(ProbeResponseData),
-    DestinationUnreachable(ProbeResponseData),
+    DestinationUnreachable(ProbeResponseData, UnreachableCode),
     EchoReply(ProbeResponseData),
+    TimestampReply(ProbeResponseData, IcmpTimestamps),
+    TcpReply(ProbeResponseData, TcpOutcome),
+}
+
+impl ProbeResponse {
+    /// The data in the probe response.
+    #[must_use]
+    pub const fn data(&self) -> &ProbeResponseData {
+        match self {
+            Self::TimeExceeded(data)
+            | Self::DestinationUnreachable(data, _)
+            | Self::EchoReply(data)
+            | Self::TimestampReply(data, _)
+            | Self::TcpReply(data, _) => data,
+        }
+    }
 }
 
 /// The data in probe response.
@@ -307,19 +885,47 @@ pub struct ProbeResponseData {
     pub addr: IpAddr,
     pub identifier: u16,
     pub sequence: u16,
+    /// The source port of the `UDP` or `TCP` probe the response is to, or 0 for `ICMP` probes.
+    pub src_port: u16,
+    pub mpls: Option<MplsLabelStack>,
 }
 
 impl ProbeResponseData {
-    pub fn new(recv: SystemTime, addr: IpAddr, identifier: u16, sequence: u16) -> Self {
+    pub fn new(
+        recv: SystemTime,
+        addr: IpAddr,
+        identifier: u16,
+        sequence: u16,
+        src_port: u16,
+        mpls: Option<MplsLabelStack>,
+    ) -> Self {
         Self {
             recv,
             addr,
             identifier,
             sequence,
+            src_port,
+            mpls,
         }
     }
 }
 
+/// Discover the source `IpAddr` from which probes to `target_addr` are sent.
+///
+/// This is the `source_addr` if configured, otherwise the address of the `interface` if configured, otherwise the
+/// default source address.
+pub fn discover_src_addr(
+    target_addr: IpAddr,
+    interface: Option<&str>,
+    source_addr: Option<IpAddr>,
+) -> TraceResult<IpAddr> {
+    match (source_addr, interface) {
+        (Some(source_addr), _) => Ok(source_addr),
+        (None, Some(interface)) => discover_interface_src_addr(interface, target_addr),
+        (None, None) => discover_default_src_addr(),
+    }
+}
+
 /// Discover the default `IpAddr::V4` that will be used by the transport channel.
 ///
 /// This is needed so we can can compute checksums for outgoing `TCP` and `UDP` packets.
@@ -329,7 +935,7 @@ impl ProbeResponseData {
 /// > If you need the default network interface, you can choose the first
 /// > one that is up, not loopback and has an IP. This is not guaranteed to
 /// > work on each system but should work for basic packet sniffing
-fn discover_default_src_addr() -> TraceResult<IpAddr> {
+pub fn discover_default_src_addr() -> TraceResult<IpAddr> {
     let all_interfaces = interfaces();
     let default_ipv4 = all_interfaces
         .iter()
@@ -346,6 +952,71 @@ fn discover_default_src_addr() -> TraceResult<IpAddr> {
     }
 }
 
+/// Discover the `IpAddr` of the named interface, of the same family as the target, that will be used by the transport
+/// channel.
+pub fn discover_interface_src_addr(interface: &str, target_addr: IpAddr) -> TraceResult<IpAddr> {
+    let all_interfaces = interfaces();
+    let interface = all_interfaces
+        .iter()
+        .find(|e| e.name == interface)
+        .ok_or_else(|| TracerError::UnknownInterface(interface.to_string()))?;
+    let addr = interface.ips.iter().find_map(|ip| match (ip, target_addr) {
+        (IpNetwork::V4(ipv4), IpAddr::V4(_)) => Some(IpAddr::V4(ipv4.ip())),
+        (IpNetwork::V6(ipv6), IpAddr::V6(_)) => Some(IpAddr::V6(ipv6.ip())),
+        _ => None,
+    });
+    addr.ok_or_else(|| {
+        let family = if target_addr.is_ipv4() {
+            "IPv4"
+        } else {
+            "IPv6"
+        };
+        TracerError::NoInterfaceAddress(interface.name.clone(), family)
+    })
+}
+
+/// Bind the socket to the named interface.
+///
+/// On Linux we use `SO_BINDTODEVICE` and on all other platforms we bind to the address of the interface.
+#[cfg(target_os = "linux")]
+pub fn bind_interface(fd: RawFd, interface: &str, _src_addr: IpAddr) -> TraceResult<()> {
+    use nix::sys::socket::{setsockopt, sockopt};
+    setsockopt(
+        fd,
+        sockopt::BindToDevice,
+        &std::ffi::OsString::from(interface),
+    )
+    .map_err(|err| TracerError::IoError(err.into()))
+}
+
+/// Bind the socket to the named interface.
+///
+/// On Linux we use `SO_BINDTODEVICE` and on all other platforms we bind to the address of the interface.
+#[cfg(not(target_os = "linux"))]
+pub fn bind_interface(fd: RawFd, _interface: &str, src_addr: IpAddr) -> TraceResult<()> {
+    bind_addr(fd, src_addr)
+}
+
+/// Bind the socket to the source address.
+pub fn bind_addr(fd: RawFd, src_addr: IpAddr) -> TraceResult<()> {
+    use nix::sys::socket::{bind, SockaddrStorage};
+    let addr = SockaddrStorage::from(SocketAddr::new(src_addr, 0));
+    bind(fd, &addr).map_err(|err| TracerError::IoError(err.into()))
+}
+
+/// Set the type-of-service (`IP_TOS`) byte of all packets sent on the socket.
+fn set_tos(fd: RawFd, tos: u8) -> TraceResult<()> {
+    /// A borrowed raw socket.
+    struct RawSocket(RawFd);
+    impl AsRawFd for RawSocket {
+        fn as_raw_fd(&self) -> RawFd {
+            self.0
+        }
+    }
+    SockRef::from(&RawSocket(fd)).set_tos(u32::from(tos))?;
+    Ok(())
+}
+
 /// Create the communication channel needed for sending and receiving ICMP packets.
 fn make_icmp_channel() -> TraceResult<(TransportSender, TransportReceiver)> {
     let protocol = TransportProtocol::Ipv4(IpNextHeaderProtocols::Icmp);
@@ -367,8 +1038,215 @@ fn make_tcp_channel() -> TraceResult<(TransportSender, TransportReceiver)> {
     Ok(transport_channel(1600, channel_type)?)
 }
 
+/// Create an unprivileged datagram socket for sending `icmp` probes.
+///
+/// On Linux the OS sets the identifier of each probe to the local port of the socket, and so the socket is bound to the
+/// trace `identifier`, and to the `source_addr` if configured, and `IP_RECVERR` is enabled such that `TimeExceeded` and
+/// `DestinationUnreachable` responses are reported on the error queue of the socket.
+fn make_icmp_dgram_socket(
+    identifier: TraceId,
+    source_addr: Option<IpAddr>,
+) -> std::io::Result<Socket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(socket2::Protocol::ICMPV4))?;
+    #[cfg(target_os = "linux")]
+    {
+        use nix::sys::socket::{setsockopt, sockopt};
+        let local_addr = source_addr.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        socket.bind(&SockAddr::from(SocketAddr::new(local_addr, identifier.0)))?;
+        setsockopt(socket.as_raw_fd(), sockopt::Ipv4RecvErr, &true)?;
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (identifier, source_addr);
+    Ok(socket)
+}
+
+/// Receive the next response to an `icmp` probe from an unprivileged datagram socket, waiting at most `timeout`.
+///
+/// On Linux only the `EchoReply` packets to probes sent on the socket are received, without the `IPv4` header, and
+/// the `TimeExceeded` and `DestinationUnreachable` responses are read from the error queue.
+#[cfg(target_os = "linux")]
+fn recv_icmp_dgram(socket: &Socket, timeout: Duration) -> TraceResult<Option<ProbeResponse>> {
+    use nix::poll::{poll, PollFd, PollFlags};
+    use nix::sys::socket::{recvfrom, SockaddrIn};
+    let fd = socket.as_raw_fd();
+    let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+    let timeout_ms = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+    poll(&mut fds, timeout_ms).map_err(std::io::Error::from)?;
+    let revents = fds[0].revents().unwrap_or_else(PollFlags::empty);
+    if revents.contains(PollFlags::POLLERR) {
+        return recv_icmp_dgram_error(fd);
+    }
+    if !revents.contains(PollFlags::POLLIN) {
+        return Ok(None);
+    }
+    let mut buf = [0_u8; MAX_PACKET_SIZE];
+    let (len, addr) = recvfrom::<SockaddrIn>(fd, &mut buf).map_err(std::io::Error::from)?;
+    let Some(addr) = addr else {
+        return Ok(None);
+    };
+    let ip = IpAddr::V4(Ipv4Addr::from(addr.ip()));
+    let icmp = IcmpPacket::new(&buf[..len]).req()?;
+    extract_icmp_response(&icmp, ip, SystemTime::now())
+}
+
+/// Read the next error from the error queue of an unprivileged datagram socket.
+///
+/// The error holds the `ICMP` header of the original probe, the type of the `ICMP` response and the address of the host
+/// which sent it.
+#[cfg(target_os = "linux")]
+fn recv_icmp_dgram_error(fd: RawFd) -> TraceResult<Option<ProbeResponse>> {
+    use nix::sys::socket::{recvmsg, ControlMessageOwned, MsgFlags, SockaddrIn};
+    let mut buf = [0_u8; MAX_PACKET_SIZE];
+    let mut cmsg_buf = nix::cmsg_space!(nix::libc::sock_extended_err, nix::libc::sockaddr_in);
+    let (len, error) = {
+        let mut iov = [std::io::IoSliceMut::new(&mut buf)];
+        let msg = recvmsg::<SockaddrIn>(fd, &mut iov, Some(&mut cmsg_buf), MsgFlags::MSG_ERRQUEUE)
+            .map_err(std::io::Error::from)?;
+        let error = msg.cmsgs().find_map(|cmsg| match cmsg {
+            ControlMessageOwned::Ipv4RecvErr(err, Some(offender))
+                if err.ee_origin == nix::libc::SO_EE_ORIGIN_ICMP =>
+            {
+                let ip = IpAddr::V4(Ipv4Addr::from(u32::from_be(offender.sin_addr.s_addr)));
+                Some((err.ee_type, err.ee_code, ip))
+            }
+            _ => None,
+        });
+        (msg.bytes, error)
+    };
+    let Some((icmp_type, icmp_code, ip)) = error else {
+        return Ok(None);
+    };
+    let recv = SystemTime::now();
+    let echo_request = EchoRequestPacket::new(&buf[..len]).req()?;
+    let data = ProbeResponseData::new(
+        recv,
+        ip,
+        echo_request.get_identifier(),
+        echo_request.get_sequence_number(),
+        0,
+        None,
+    );
+    Ok(match IcmpType::new(icmp_type) {
+        IcmpTypes::TimeExceeded => Some(ProbeResponse::TimeExceeded(data)),
+        IcmpTypes::DestinationUnreachable => Some(ProbeResponse::DestinationUnreachable(
+            data,
+            UnreachableCode(icmp_code),
+        )),
+        _ => None,
+    })
+}
+
+/// Receive the next response to an `icmp` probe from an unprivileged datagram socket, waiting at most `timeout`.
+///
+/// Other than on Linux, the socket receives every `ICMP` packet delivered to the host, including the `IPv4` header, as
+/// for a raw socket.
+#[cfg(not(target_os = "linux"))]
+fn recv_icmp_dgram(socket: &Socket, timeout: Duration) -> TraceResult<Option<ProbeResponse>> {
+    use nix::poll::{poll, PollFd, PollFlags};
+    use nix::sys::socket::recv;
+    let fd = socket.as_raw_fd();
+    let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+    let timeout_ms = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+    if poll(&mut fds, timeout_ms).map_err(std::io::Error::from)? == 0 {
+        return Ok(None);
+    }
+    let mut buf = [0_u8; MAX_PACKET_SIZE];
+    let len =
+        recv(fd, &mut buf, nix::sys::socket::MsgFlags::empty()).map_err(std::io::Error::from)?;
+    let ip = Ipv4Packet::new(&buf[..len]).req()?;
+    let icmp = IcmpPacket::new(ip.payload()).req()?;
+    extract_icmp_response(&icmp, IpAddr::V4(ip.get_source()), SystemTime::now())
+}
+
+/// Wait, for at most `timeout`, until either of the `ICMP` or `TCP` receivers is readable, and return which are.
+fn poll_readable(
+    icmp_rx: &TransportReceiver,
+    tcp_rx: &TransportReceiver,
+    timeout: Duration,
+) -> TraceResult<(bool, bool)> {
+    use nix::poll::{poll, PollFd, PollFlags};
+    let mut fds = [
+        PollFd::new(icmp_rx.socket.fd, PollFlags::POLLIN),
+        PollFd::new(tcp_rx.socket.fd, PollFlags::POLLIN),
+    ];
+    let timeout_ms = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+    poll(&mut fds, timeout_ms).map_err(std::io::Error::from)?;
+    let readable = |fd: &PollFd| {
+        fd.revents()
+            .is_some_and(|revents| revents.contains(PollFlags::POLLIN))
+    };
+    Ok((readable(&fds[0]), readable(&fds[1])))
+}
+
+/// Get the `MPLS` label stack from the `ICMP` extension structure of a `TimeExceeded` or `DestinationUnreachable`
+/// packet, if any.
+///
+/// The extension structure (RFC 4884) follows the original datagram, the length of which is given in 32 bit words in
+/// the sixth byte of the `ICMP` header.  Routers which predate RFC 4884 leave the length as zero and pad the original
+/// datagram to 128 bytes, in which case we assume the extension structure follows the first 128 bytes.
+///
+/// The label stack is held in an `MPLS` Label Stack object (class 1, c-type 1), each entry of which is 4 bytes (RFC
+/// 4950).
+fn extract_mpls(icmp: &[u8]) -> Option<MplsLabelStack> {
+    const ICMP_HEADER_LEN: usize = 8;
+    const COMPAT_ORIGINAL_DATAGRAM_LEN: usize = 128;
+    const EXTENSION_VERSION: u8 = 2;
+    const MPLS_CLASS: u8 = 1;
+    const MPLS_CTYPE: u8 = 1;
+    let payload = icmp.get(ICMP_HEADER_LEN..)?;
+    let original_len = match usize::from(*icmp.get(5)?) * 4 {
+        0 => COMPAT_ORIGINAL_DATAGRAM_LEN,
+        len => len,
+    };
+    let extensions = payload.get(original_len..)?;
+    if extensions.len() < 4 || extensions[0] >> 4 != EXTENSION_VERSION {
+        return None;
+    }
+    let mut objects = &extensions[4..];
+    while objects.len() >= 4 {
+        let len = usize::from(u16::from_be_bytes([objects[0], objects[1]]));
+        if len < 4 || len > objects.len() {
+            return None;
+        }
+        if objects[2] == MPLS_CLASS && objects[3] == MPLS_CTYPE {
+            let mut stack = MplsLabelStack::default();
+            for entry in objects[4..len].chunks_exact(4) {
+                let entry = u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]);
+                stack.push(MplsLabel {
+                    label: entry >> 12,
+                    tc: ((entry >> 9) & 0x7) as u8,
+                    bottom_of_stack: (entry >> 8) & 0x1 == 1,
+                    ttl: (entry & 0xff) as u8,
+                });
+            }
+            return Some(stack);
+        }
+        objects = &objects[len..];
+    }
+    None
+}
+
+/// Does an `ICMP` error quote the first fragment of a probe of `protocol`, such that the probe can be identified from it?
+///
+/// Errors for packets of other protocols, such as the probes of another tracer, are ignored.  An error for any later
+/// fragment of a fragmented probe quotes only part of the payload of the probe.  A `TimeExceeded` sent because the
+/// fragments of a probe were not all received in time to be reassembled is also ignored, as the probe did not get
+/// through, such that it is counted as lost.
+fn quotes_probe(icmp: &IcmpPacket<'_>, protocol: IpNextHeaderProtocol) -> bool {
+    let reassembly = icmp.get_icmp_type() == IcmpTypes::TimeExceeded
+        && icmp.get_icmp_code() == time_exceeded::IcmpCodes::FragmentReasemblyTimeExceeded;
+    let quoted = icmp
+        .packet()
+        .get(8..)
+        .and_then(Ipv4Packet::new)
+        .is_some_and(|ip| {
+            ip.get_fragment_offset() == 0 && ip.get_next_level_protocol() == protocol
+        });
+    !reassembly && quoted
+}
+
 /// Get the original `EchoRequestPacket` packet embedded in the payload.
-fn extract_echo_request(payload: &[u8]) -> TraceResult<EchoRequestPacket<'_>> {
+pub fn extract_echo_request(payload: &[u8]) -> TraceResult<EchoRequestPacket<'_>> {
     let ip4 = Ipv4Packet::new(payload).req()?;
     let header_len = usize::from(ip4.get_header_length() * 4);
     let nested_icmp = &payload[header_len..];
@@ -376,16 +1254,49 @@ fn extract_echo_request(payload: &[u8]) -> TraceResult<EchoRequestPacket<'_>> {
     Ok(nested_echo)
 }
 
-/// Get the original `UdpPacket` packet embedded in the payload.
-fn extract_udp_probe(payload: &[u8]) -> TraceResult<u16> {
+/// Get the sequence number and source port of the original `UdpPacket` packet embedded in the payload.
+///
+/// The sequence number is held in the destination port for a `Sequence` destination port and in the checksum for a
+/// `Fixed` destination port.
+fn extract_udp_probe(payload: &[u8], udp_dest_port: UdpDestPort) -> TraceResult<(u16, u16)> {
     let ip4 = Ipv4Packet::new(payload).req()?;
     let header_len = usize::from(ip4.get_header_length() * 4);
     let nested_udp = &payload[header_len..];
     let nested = UdpPacket::new(nested_udp).req()?;
-    Ok(nested.get_destination())
+    let sequence = match udp_dest_port {
+        UdpDestPort::Sequence => nested.get_destination(),
+        UdpDestPort::Fixed(_) => nested.get_checksum(),
+    };
+    Ok((sequence, nested.get_source()))
 }
 
-/// Get the original `TcpPacket` packet embedded in the payload.
+/// Get the identifier, sequence number and timestamps from an `ICMP` `TimestampReply` packet received at `recv`.
+///
+/// Returns `None` if the packet is too short to be a `TimestampReply`.
+fn extract_timestamp_reply(packet: &[u8], recv: SystemTime) -> Option<(u16, u16, IcmpTimestamps)> {
+    let packet = packet.get(..ICMP_TIMESTAMP_SIZE)?;
+    let u16_at = |i: usize| u16::from_be_bytes([packet[i], packet[i + 1]]);
+    let u32_at =
+        |i: usize| u32::from_be_bytes([packet[i], packet[i + 1], packet[i + 2], packet[i + 3]]);
+    let timestamps = IcmpTimestamps {
+        originate: u32_at(8),
+        receive: u32_at(12),
+        transmit: u32_at(16),
+        arrive: millis_since_midnight(recv),
+    };
+    Some((u16_at(4), u16_at(6), timestamps))
+}
+
+/// The number of milliseconds since midnight UT, as used in `ICMP` timestamps.
+fn millis_since_midnight(time: SystemTime) -> u32 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    (since_epoch.as_millis() % u128::from(MILLIS_PER_DAY)) as u32
+}
+
+/// Get the sequence number and source port of the original `TcpPacket` packet embedded in the payload.
+///
+/// The sequence number is held in the destination port for `Classic` mode and in the `TCP` sequence number if the
+/// destination port is fixed, as for `Paris` mode, both of which fall within the first 8 bytes of the `TCP` header.
 ///
 /// Unlike the embedded `ICMP` and `UDP` packets, which have a minimum header size of 8 bytes, the `TCP` packet header
 /// is a minimum of 20 bytes.
@@ -395,19 +1306,41 @@ fn extract_udp_probe(payload: &[u8]) -> TraceResult<u16> {
 ///
 /// We therefore have to detect this situation and ensure we provide buffer a large enough for a complete TCP packet
 /// header.
-fn extract_tcp_probe(payload: &[u8]) -> TraceResult<u16> {
+fn extract_tcp_probe(payload: &[u8], fixed_dest_port: bool) -> TraceResult<(u16, u16)> {
     let ip4 = Ipv4Packet::new(payload).unwrap();
     let header_len = usize::from(ip4.get_header_length() * 4);
     let nested_tcp = &payload[header_len..];
+    let sequence = |tcp: &TcpPacket<'_>| {
+        let sequence = if fixed_dest_port {
+            tcp.get_sequence() as u16
+        } else {
+            tcp.get_destination()
+        };
+        (sequence, tcp.get_source())
+    };
     if nested_tcp.len() < TcpPacket::minimum_packet_size() {
         let mut buf = [0_u8; TcpPacket::minimum_packet_size()];
         buf[..nested_tcp.len()].copy_from_slice(nested_tcp);
-        Ok(TcpPacket::new(&buf).req()?.get_destination())
+        Ok(sequence(&TcpPacket::new(&buf).req()?))
     } else {
-        Ok(TcpPacket::new(nested_tcp).req()?.get_destination())
+        Ok(sequence(&TcpPacket::new(nested_tcp).req()?))
     }
 }
 
+/// The flags of the `TCP` header of probes with the given `tcp_flags`.
+fn tcp_flag_bits(tcp_flags: &[TcpFlag]) -> u16 {
+    tcp_flags
+        .iter()
+        .map(|flag| match flag {
+            TcpFlag::Syn => TcpFlags::SYN,
+            TcpFlag::Ack => TcpFlags::ACK,
+            TcpFlag::Fin => TcpFlags::FIN,
+            TcpFlag::Psh => TcpFlags::PSH,
+            TcpFlag::Urg => TcpFlags::URG,
+        })
+        .fold(0, |bits, flag| bits | flag)
+}
+
 /// Calculate the TCP IPv4 checksum.
 ///
 /// Currently this only supports Ipv4.
@@ -417,3 +1350,19 @@ fn tcp_checksum(tcp: &TcpPacket<'_>, src_addr: &IpAddr, dest_addr: &IpAddr) -> u
         _ => unreachable!(),
     }
 }
+
+/// Calculate the UDP IPv4 checksum.
+///
+/// Currently this only supports Ipv4.
+fn udp_checksum(udp: &UdpPacket<'_>, src_addr: &IpAddr, dest_addr: &IpAddr) -> u16 {
+    match (src_addr, dest_addr) {
+        (IpAddr::V4(src), IpAddr::V4(dest)) => udp::ipv4_checksum(udp, src, dest),
+        _ => unreachable!(),
+    }
+}
+
+/// Add two 16 bit values using ones' complement arithmetic.
+fn ones_complement_add(a: u16, b: u16) -> u16 {
+    let sum = u32::from(a) + u32::from(b);
+    ((sum & 0xffff) + (sum >> 16)) as u16
+}
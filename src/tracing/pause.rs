@@ -0,0 +1,30 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A handle to pause and resume tracing which may be shared between tracers.
+///
+/// A paused tracer completes the round in progress, such that every probe sent is either answered or lost as normal,
+/// and then waits until resumed before starting the next round.
+#[derive(Debug, Clone, Default)]
+pub struct PauseHandle {
+    paused: Arc<AtomicBool>,
+}
+
+impl PauseHandle {
+    /// Create a `PauseHandle` which is not paused.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Is tracing paused?
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Pause tracing if it is running, or resume tracing if it is paused.
+    pub fn toggle(&self) {
+        self.paused.fetch_xor(true, Ordering::Relaxed);
+    }
+}
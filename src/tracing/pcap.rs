@@ -0,0 +1,67 @@
+use crate::tracing::error::TraceResult;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The pcap magic number for a capture with nanosecond resolution timestamps.
+const MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+
+/// The pcap format major version.
+const VERSION_MAJOR: u16 = 2;
+
+/// The pcap format minor version.
+const VERSION_MINOR: u16 = 4;
+
+/// The maximum number of bytes captured per packet.
+const SNAP_LEN: u32 = 65535;
+
+/// The link type for raw IP packets with no link layer header.
+const LINKTYPE_RAW: u32 = 101;
+
+/// A writer of captured packets in the pcap format which may be shared between tracers.
+///
+/// Each packet record is written to the file as a single write, without buffering, such that the capture is complete
+/// and readable however the process exits.
+#[derive(Debug, Clone)]
+pub struct PcapWriter {
+    inner: Arc<Mutex<File>>,
+}
+
+impl PcapWriter {
+    /// Create (or truncate) the file at `path` and write the pcap global header.
+    pub fn create(path: &Path) -> TraceResult<Self> {
+        let mut file = File::create(path)?;
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&MAGIC_NANOS.to_le_bytes());
+        header.extend_from_slice(&VERSION_MAJOR.to_le_bytes());
+        header.extend_from_slice(&VERSION_MINOR.to_le_bytes());
+        header.extend_from_slice(&0_i32.to_le_bytes());
+        header.extend_from_slice(&0_u32.to_le_bytes());
+        header.extend_from_slice(&SNAP_LEN.to_le_bytes());
+        header.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+        file.write_all(&header)?;
+        Ok(Self {
+            inner: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Write a record for an IP `packet` which was sent or received at `timestamp`.
+    pub fn write_packet(&self, timestamp: SystemTime, packet: &[u8]) -> TraceResult<()> {
+        let since_epoch = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let len = u32::try_from(packet.len()).unwrap_or(u32::MAX);
+        let captured = &packet[..packet.len().min(SNAP_LEN as usize)];
+        let mut record = Vec::with_capacity(16 + captured.len());
+        record.extend_from_slice(&(since_epoch.as_secs() as u32).to_le_bytes());
+        record.extend_from_slice(&since_epoch.subsec_nanos().to_le_bytes());
+        record.extend_from_slice(&(captured.len() as u32).to_le_bytes());
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(captured);
+        self.inner
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .write_all(&record)?;
+        Ok(())
+    }
+}
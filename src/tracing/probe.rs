@@ -1,7 +1,10 @@
-use crate::tracing::types::{Round, Sequence, TimeToLive};
+use crate::tracing::types::{FlowId, Round, Sequence, TimeToLive};
 use std::net::IpAddr;
 use std::time::{Duration, SystemTime};
 
+/// The number of milliseconds in a day, after which `ICMP` timestamps wrap around.
+pub const MILLIS_PER_DAY: u32 = 86_400_000;
+
 /// The state of an ICMP echo request/response
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct Probe {
@@ -11,6 +14,8 @@ pub struct Probe {
     pub ttl: TimeToLive,
     /// Which round the probe belongs to.
     pub round: Round,
+    /// The flow the probe belongs to.
+    pub flow_id: FlowId,
     /// Timestamp when the probe was sent.
     pub sent: Option<SystemTime>,
     /// The status of the probe.
@@ -21,20 +26,39 @@ pub struct Probe {
     pub received: Option<SystemTime>,
     /// The type of ICMP response packet received for the probe.
     pub icmp_packet_type: Option<IcmpPacketType>,
+    /// The `MPLS` label stack of the response to the probe, if any.
+    pub mpls: Option<MplsLabelStack>,
+    /// The timestamps of the `ICMP` `TimestampReply` to the probe, if any.
+    pub icmp_timestamps: Option<IcmpTimestamps>,
+    /// The outcome of a `TCP` probe which reached the target host, if any.
+    pub tcp_outcome: Option<TcpOutcome>,
+    /// The code of the `ICMP` `DestinationUnreachable` response to the probe, if any.
+    pub unreachable_code: Option<UnreachableCode>,
 }
 
 impl Probe {
     #[must_use]
-    pub const fn new(sequence: Sequence, ttl: TimeToLive, round: Round, sent: SystemTime) -> Self {
+    pub const fn new(
+        sequence: Sequence,
+        ttl: TimeToLive,
+        round: Round,
+        flow_id: FlowId,
+        sent: SystemTime,
+    ) -> Self {
         Self {
             sequence,
             ttl,
             round,
+            flow_id,
             sent: Some(sent),
             status: ProbeStatus::Awaited,
             host: None,
             received: None,
             icmp_packet_type: None,
+            mpls: None,
+            icmp_timestamps: None,
+            tcp_outcome: None,
+            unreachable_code: None,
         }
     }
 
@@ -69,6 +93,35 @@ impl Probe {
         }
     }
 
+    #[must_use]
+    pub const fn with_mpls(self, mpls: Option<MplsLabelStack>) -> Self {
+        Self { mpls, ..self }
+    }
+
+    #[must_use]
+    pub const fn with_icmp_timestamps(self, icmp_timestamps: IcmpTimestamps) -> Self {
+        Self {
+            icmp_timestamps: Some(icmp_timestamps),
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub const fn with_tcp_outcome(self, tcp_outcome: TcpOutcome) -> Self {
+        Self {
+            tcp_outcome: Some(tcp_outcome),
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub const fn with_unreachable_code(self, unreachable_code: UnreachableCode) -> Self {
+        Self {
+            unreachable_code: Some(unreachable_code),
+            ..self
+        }
+    }
+
     #[must_use]
     pub const fn with_received(self, received: SystemTime) -> Self {
         Self {
@@ -79,27 +132,234 @@ impl Probe {
 }
 
 /// The status of a `Echo` for a single TTL.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum ProbeStatus {
     /// The probe has not been sent.
+    #[default]
     NotSent,
     /// The probe has been sent and we are awaiting the response.
     Awaited,
-    /// The probe has been sent and a response (`EchoReply`, `DestinationUnreachable` or `TimeExceeded`) has
-    /// been received.
+    /// The probe has been sent and a response (`EchoReply`, `TimestampReply`, `DestinationUnreachable`,
+    /// `TimeExceeded` or a `TCP` `SYN-ACK` or `RST`) has been received.
     Complete,
 }
 
-impl Default for ProbeStatus {
-    fn default() -> Self {
-        Self::NotSent
-    }
-}
-
 /// The type of ICMP packet received.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum IcmpPacketType {
     TimeExceeded,
     EchoReply,
+    TimestampReply,
     Unreachable,
 }
+
+/// The outcome of a `TCP` probe which reached the target host, as given by the flags it responded with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpOutcome {
+    /// The target host responded with a `SYN-ACK`, the port is open.
+    Open,
+    /// The target host responded with a `RST`, the port is closed.
+    Closed,
+    /// The target host responded to a probe with the `ACK` flag with a `RST`, the port is not filtered but may be open
+    /// or closed.
+    Unfiltered,
+}
+
+/// The code of an `ICMP` `DestinationUnreachable`, which gives the reason the probe could not be delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnreachableCode(pub u8);
+
+impl UnreachableCode {
+    /// The reason the probe could not be delivered, as defined for the code by RFC 792 and RFC 1812.
+    #[must_use]
+    pub const fn reason(self) -> UnreachableReason {
+        match self.0 {
+            0 | 6 | 11 => UnreachableReason::Net,
+            1 | 7 | 12 => UnreachableReason::Host,
+            2 => UnreachableReason::Protocol,
+            3 => UnreachableReason::Port,
+            4 => UnreachableReason::FragmentationNeeded,
+            9 | 10 | 13 => UnreachableReason::AdminProhibited,
+            _ => UnreachableReason::Other,
+        }
+    }
+}
+
+/// The reason given by the code of an `ICMP` `DestinationUnreachable`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnreachableReason {
+    /// The network of the target is unreachable or unknown.
+    Net,
+    /// The target host is unreachable or unknown.
+    Host,
+    /// The protocol of the probe is not supported by the target host.
+    Protocol,
+    /// The destination port of the probe is closed at the target host, as for a `UDP` probe which reached its
+    /// destination.
+    Port,
+    /// The probe needed to be fragmented to be forwarded but the don't-fragment bit was set.
+    FragmentationNeeded,
+    /// Communication with the target is administratively prohibited, typically by a firewall.
+    AdminProhibited,
+    /// Any other code, such as source route failed or precedence cutoff.
+    Other,
+}
+
+/// The timestamps of an `ICMP` `TimestampReply` (RFC 792).
+///
+/// Each timestamp is the number of milliseconds since midnight UT, as reported by the host which set it, and so
+/// wraps around once per day.  Hosts which do not keep time to the millisecond, or do not know the time, may set any
+/// value they choose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IcmpTimestamps {
+    /// The time at which the request was sent, as set by us.
+    pub originate: u32,
+    /// The time at which the request was received, as set by the remote host.
+    pub receive: u32,
+    /// The time at which the reply was sent, as set by the remote host.
+    pub transmit: u32,
+    /// The time at which the reply was received, as set by us.
+    pub arrive: u32,
+}
+
+impl IcmpTimestamps {
+    /// The apparent offset (ms) of the clock of the remote host from ours, if the timestamps are plausible.
+    ///
+    /// The offset is estimated as for `NTP`, as the mean of the offsets of the receive and transmit timestamps from the
+    /// originate and arrive timestamps, which assumes the forward and return paths have the same latency and so is
+    /// only accurate to within half the round-trip time.  Each difference is taken across the midnight wraparound, such
+    /// that the offset is clamped to within half a day.
+    ///
+    /// The timestamps are considered bogus, and no offset is given, if any is not a valid time of day (which includes
+    /// the non-standard timestamps with the high order bit set), if the remote host did not set them, if the reply
+    /// appears to have arrived before the request was sent, such as when our clock is stepped back, or if the remote
+    /// host appears to have sent the reply before it received the request, or to have held it for longer than the
+    /// round-trip time.
+    #[must_use]
+    pub fn clock_offset_ms(&self) -> Option<i64> {
+        let timestamps = [self.originate, self.receive, self.transmit, self.arrive];
+        if timestamps.iter().any(|&ts| ts >= MILLIS_PER_DAY)
+            || (self.receive == 0 && self.transmit == 0)
+        {
+            return None;
+        }
+        let rtt = wrapping_diff_ms(self.arrive, self.originate);
+        let held = wrapping_diff_ms(self.transmit, self.receive);
+        if rtt < 0 || held < 0 || held > rtt {
+            return None;
+        }
+        let forward = wrapping_diff_ms(self.receive, self.originate);
+        let backward = wrapping_diff_ms(self.transmit, self.arrive);
+        Some(forward.midpoint(backward))
+    }
+}
+
+/// The difference (ms) of the times of day `a` and `b`, taken the shortest way around midnight.
+fn wrapping_diff_ms(a: u32, b: u32) -> i64 {
+    let day = i64::from(MILLIS_PER_DAY);
+    let diff = (i64::from(a) - i64::from(b)).rem_euclid(day);
+    if diff > day / 2 {
+        diff - day
+    } else {
+        diff
+    }
+}
+
+/// The maximum number of `MPLS` label stack entries recorded for a probe.
+pub const MAX_MPLS_LABELS: usize = 4;
+
+/// A single `MPLS` label stack entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MplsLabel {
+    /// The 20 bit label value.
+    pub label: u32,
+    /// The 3 bit traffic class.
+    pub tc: u8,
+    /// Is this the bottom of the label stack?
+    pub bottom_of_stack: bool,
+    /// The time-to-live.
+    pub ttl: u8,
+}
+
+/// The `MPLS` label stack returned in the `ICMP` extensions of a response (RFC 4950).
+///
+/// Only the first `MAX_MPLS_LABELS` entries of the label stack are recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MplsLabelStack {
+    labels: [MplsLabel; MAX_MPLS_LABELS],
+    len: usize,
+}
+
+impl MplsLabelStack {
+    /// The entries of the label stack, outermost first.
+    #[must_use]
+    pub fn labels(&self) -> &[MplsLabel] {
+        &self.labels[..self.len]
+    }
+
+    /// Add an entry to the bottom of the label stack, ignored if the stack is full.
+    pub(crate) fn push(&mut self, label: MplsLabel) {
+        if self.len < MAX_MPLS_LABELS {
+            self.labels[self.len] = label;
+            self.len += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestamps(originate: u32, receive: u32, transmit: u32, arrive: u32) -> IcmpTimestamps {
+        IcmpTimestamps {
+            originate,
+            receive,
+            transmit,
+            arrive,
+        }
+    }
+
+    #[test]
+    fn test_clock_offset() {
+        assert_eq!(
+            Some(500),
+            timestamps(1000, 1510, 1520, 1030).clock_offset_ms()
+        );
+        assert_eq!(
+            Some(-500),
+            timestamps(1000, 510, 520, 1030).clock_offset_ms()
+        );
+    }
+
+    #[test]
+    fn test_clock_offset_midnight() {
+        assert_eq!(
+            Some(5),
+            timestamps(MILLIS_PER_DAY - 10, 5, 5, 10).clock_offset_ms()
+        );
+        assert_eq!(
+            Some(-20),
+            timestamps(5, MILLIS_PER_DAY - 5, MILLIS_PER_DAY - 5, 25).clock_offset_ms()
+        );
+    }
+
+    #[test]
+    fn test_clock_offset_backwards() {
+        assert_eq!(None, timestamps(1000, 1000, 1000, 990).clock_offset_ms());
+        assert_eq!(
+            None,
+            timestamps(10, 5, 5, MILLIS_PER_DAY - 10).clock_offset_ms()
+        );
+        assert_eq!(None, timestamps(1000, 1500, 1400, 1030).clock_offset_ms());
+    }
+
+    #[test]
+    fn test_clock_offset_bogus() {
+        assert_eq!(None, timestamps(1000, 0, 0, 1030).clock_offset_ms());
+        assert_eq!(
+            None,
+            timestamps(1000, 0x8000_0000, 0x8000_0000, 1030).clock_offset_ms()
+        );
+        assert_eq!(None, timestamps(1000, 1000, 1040, 1030).clock_offset_ms());
+    }
+}
@@ -0,0 +1,141 @@
+use crate::tracing::types::TimeToLive;
+use crate::tracing::{Probe, ProbeStatus};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// A token bucket rate limiter which may be shared between tracers.
+///
+/// The bucket holds at most a single token such that probes are never sent in a burst and the send rate never exceeds
+/// the limit over any period.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    inner: Arc<Mutex<TokenBucket>>,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    /// The maximum number of tokens held by the bucket.
+    const CAPACITY: f64 = 1_f64;
+
+    /// Create a `RateLimiter` which allows `per_second` probes to be sent per second.
+    #[must_use]
+    pub fn new(per_second: u32) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(TokenBucket {
+                rate: f64::from(per_second),
+                tokens: Self::CAPACITY,
+                last_refill: Instant::now(),
+            })),
+        }
+    }
+
+    /// Take a token if one is available, returns `false` if the probe must not be sent yet.
+    ///
+    /// This never blocks, the caller is expected to try again later.
+    pub fn try_acquire(&self) -> bool {
+        let mut bucket = self
+            .inner
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = elapsed
+            .mul_add(bucket.rate, bucket.tokens)
+            .min(Self::CAPACITY);
+        bucket.last_refill = now;
+        if bucket.tokens >= 1_f64 {
+            bucket.tokens -= 1_f64;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// The thresholds of the adaptive rate controller.
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveRate {
+    /// The loss (%) of any hop above which the probe rate is halved.
+    pub loss_threshold: f64,
+    /// The loss (%) of every hop below which the probe rate is doubled, up to the configured rate.
+    pub recover_threshold: f64,
+}
+
+impl AdaptiveRate {
+    #[must_use]
+    pub const fn new(loss_threshold: f64, recover_threshold: f64) -> Self {
+        Self {
+            loss_threshold,
+            recover_threshold,
+        }
+    }
+}
+
+/// Reduces the probe rate of a tracer while the loss of any hop is high and recovers it once the loss subsides.
+///
+/// The loss of each hop is the exponentially weighted moving average of the loss of its probes over the completed
+/// rounds, only hops which have responded at least once are considered such that a hop which never responds does not
+/// hold the rate down.  The probe rate is reduced by scaling the round duration by the backoff, which is doubled after
+/// every round in which the loss of any hop is above the loss threshold, up to `MAX_BACKOFF`, and halved after every
+/// round in which the loss of all hops is below the recover threshold.
+#[derive(Debug)]
+pub struct RateController {
+    config: AdaptiveRate,
+    loss: Vec<Option<f64>>,
+    backoff: u32,
+}
+
+impl RateController {
+    /// The maximum factor by which the round duration is scaled.
+    pub const MAX_BACKOFF: u32 = 8;
+
+    /// The weight of the most recent round in the loss of each hop.
+    const LOSS_ALPHA: f64 = 0.25;
+
+    #[must_use]
+    pub fn new(config: AdaptiveRate) -> Self {
+        Self {
+            config,
+            loss: vec![None; usize::from(u8::MAX) + 1],
+            backoff: 1,
+        }
+    }
+
+    /// The factor by which the round duration is scaled.
+    #[must_use]
+    pub const fn backoff(&self) -> u32 {
+        self.backoff
+    }
+
+    /// Update the loss of each hop, up to `largest_ttl`, from the probes of a completed round and adjust the backoff.
+    pub fn observe(&mut self, probes: &[Probe], largest_ttl: TimeToLive) {
+        for probe in probes
+            .iter()
+            .filter(|probe| probe.ttl <= largest_ttl && probe.status != ProbeStatus::NotSent)
+        {
+            let round_loss = if probe.status == ProbeStatus::Complete {
+                0_f64
+            } else {
+                100_f64
+            };
+            let hop_loss = &mut self.loss[usize::from(probe.ttl.0)];
+            *hop_loss = match *hop_loss {
+                Some(loss) => Some(Self::LOSS_ALPHA.mul_add(round_loss - loss, loss)),
+                None if probe.status == ProbeStatus::Complete => Some(0_f64),
+                None => None,
+            };
+        }
+        let worst = self.loss.iter().flatten().copied().fold(0_f64, f64::max);
+        if worst > self.config.loss_threshold {
+            self.backoff = (self.backoff * 2).min(Self::MAX_BACKOFF);
+        } else if worst < self.config.recover_threshold {
+            self.backoff = (self.backoff / 2).max(1);
+        }
+    }
+}
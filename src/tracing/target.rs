@@ -0,0 +1,32 @@
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// A handle to change the target of a tracer, such as when the hostname of the target resolves to a new address.
+///
+/// The tracer completes the round in progress to the prior target and then traces to the new target from the next
+/// round.  The new target must be of the same family as the prior target.
+#[derive(Debug, Clone)]
+pub struct TargetHandle {
+    addr: Arc<Mutex<IpAddr>>,
+}
+
+impl TargetHandle {
+    /// Create a `TargetHandle` for the initial target `addr`.
+    #[must_use]
+    pub fn new(addr: IpAddr) -> Self {
+        Self {
+            addr: Arc::new(Mutex::new(addr)),
+        }
+    }
+
+    /// The target to trace.
+    #[must_use]
+    pub fn addr(&self) -> IpAddr {
+        *self.addr.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Trace to `addr` from the next round.
+    pub fn set(&self, addr: IpAddr) {
+        *self.addr.lock().unwrap_or_else(PoisonError::into_inner) = addr;
+    }
+}
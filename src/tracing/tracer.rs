@@ -1,12 +1,22 @@
 use self::state::TracerState;
 use crate::tracing::error::TraceResult;
 use crate::tracing::net::{Network, ProbeResponse};
-use crate::tracing::types::{MaxInflight, MaxRounds, Sequence, TimeToLive, TraceId};
-use crate::tracing::TracerProtocol;
+use crate::tracing::rate::RateController;
+use crate::tracing::types::{
+    MaxFlows, MaxInflight, MaxRounds, ProbesPerRound, Sequence, SourcePort, TimeToLive, TraceId,
+};
+use crate::tracing::{
+    AdaptiveRate, PauseHandle, RateLimiter, ScheduleStrategy, TargetHandle, TracerProtocol,
+};
 use crate::tracing::{IcmpPacketType, ProbeStatus};
 use crate::tracing::{Probe, TracerConfig};
+use std::net::IpAddr;
+use std::thread::sleep;
 use std::time::{Duration, SystemTime};
 
+/// The interval at which a paused tracer checks whether it has been resumed.
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 /// The output from a round of tracing.
 #[derive(Debug, Clone)]
 pub struct TracerRound<'a> {
@@ -16,15 +26,27 @@ pub struct TracerRound<'a> {
     pub largest_ttl: TimeToLive,
     /// Indicates what triggered the completion of the tracing round.
     pub reason: CompletionReason,
+    /// The factor by which the round duration of the next round is scaled by the adaptive rate controller, if any.
+    pub backoff: u32,
+    /// The target host of the round.
+    pub target_addr: IpAddr,
 }
 
 impl<'a> TracerRound<'a> {
     #[must_use]
-    pub fn new(probes: &'a [Probe], largest_ttl: TimeToLive, reason: CompletionReason) -> Self {
+    pub fn new(
+        probes: &'a [Probe],
+        largest_ttl: TimeToLive,
+        reason: CompletionReason,
+        backoff: u32,
+        target_addr: IpAddr,
+    ) -> Self {
         Self {
             probes,
             largest_ttl,
             reason,
+            backoff,
+            target_addr,
         }
     }
 }
@@ -41,34 +63,62 @@ pub enum CompletionReason {
 /// Trace a path to a target.
 #[derive(Debug, Clone)]
 pub struct Tracer<F> {
+    target_addr: IpAddr,
     protocol: TracerProtocol,
     trace_identifier: TraceId,
+    max_flows: MaxFlows,
     max_rounds: Option<MaxRounds>,
     first_ttl: TimeToLive,
     max_ttl: TimeToLive,
+    ttl_stride: TimeToLive,
+    auto_ttl: bool,
     grace_duration: Duration,
+    adaptive_grace: Option<Duration>,
     max_inflight: MaxInflight,
+    probes_per_round: ProbesPerRound,
+    schedule_strategy: ScheduleStrategy,
     initial_sequence: Sequence,
     read_timeout: Duration,
+    probe_timeout: Option<Duration>,
     min_round_duration: Duration,
     max_round_duration: Duration,
+    source_port: SourcePort,
+    source_port_count: u16,
+    rate_limiter: Option<RateLimiter>,
+    adaptive_rate: Option<AdaptiveRate>,
+    pause: Option<PauseHandle>,
+    target: Option<TargetHandle>,
     publish: F,
 }
 
 impl<F: Fn(&TracerRound<'_>)> Tracer<F> {
     pub fn new(config: &TracerConfig, publish: F) -> Self {
         Self {
+            target_addr: config.target_addr,
             protocol: config.protocol,
             trace_identifier: config.trace_identifier,
+            max_flows: config.max_flows,
             max_rounds: config.max_rounds,
             first_ttl: config.first_ttl,
             max_ttl: config.max_ttl,
+            ttl_stride: config.ttl_stride,
+            auto_ttl: config.auto_ttl,
             grace_duration: config.grace_duration,
+            adaptive_grace: config.adaptive_grace,
             max_inflight: config.max_inflight,
+            probes_per_round: config.probes_per_round,
+            schedule_strategy: config.schedule_strategy,
             initial_sequence: config.initial_sequence,
             read_timeout: config.read_timeout,
+            probe_timeout: config.probe_timeout,
             min_round_duration: config.min_round_duration,
             max_round_duration: config.max_round_duration,
+            source_port: config.source_port,
+            source_port_count: config.source_port_count,
+            rate_limiter: config.rate_limiter.clone(),
+            adaptive_rate: config.adaptive_rate,
+            pause: config.pause.clone(),
+            target: config.target.clone(),
             publish,
         }
     }
@@ -76,36 +126,95 @@ impl<F: Fn(&TracerRound<'_>)> Tracer<F> {
     /// Run a continuous trace and publish results.
     ///
     /// TODO describe algorithm
-    pub fn trace<N: Network>(self, mut network: N) -> TraceResult<()> {
-        let mut state = TracerState::new(self.first_ttl, self.initial_sequence);
+    pub fn trace<N: Network>(mut self, mut network: N) -> TraceResult<()> {
+        let mut state = TracerState::new(
+            self.first_ttl,
+            self.ttl_stride,
+            self.initial_sequence,
+            self.max_flows,
+            self.probes_per_round,
+        );
+        let mut controller = self.adaptive_rate.map(RateController::new);
+        let mut grace = self.grace_duration;
         while !state.finished(self.max_rounds) {
             self.send_request(&mut network, &mut state)?;
             self.recv_response(&mut network, &mut state)?;
-            self.update_round(&mut state);
+            if self.update_round(&mut state, controller.as_mut(), &mut grace) {
+                self.retarget(&mut network, &mut state);
+            }
         }
         Ok(())
     }
 
+    /// Trace to the target of the target handle, if configured and it has changed, from the round which has started.
+    fn retarget<N: Network>(&mut self, network: &mut N, st: &mut TracerState) {
+        if let Some(addr) = self
+            .target
+            .as_ref()
+            .map(TargetHandle::addr)
+            .filter(|&addr| {
+                addr != self.target_addr && addr.is_ipv4() == self.target_addr.is_ipv4()
+            })
+        {
+            network.set_target(addr);
+            self.target_addr = addr;
+            st.clear_target();
+        }
+    }
+
     /// Send the next probe if required.
     ///
     /// Send a `Probe` for the next time-to-live (ttl) if all of the following are true:
     ///
-    /// 1 - the target host has not been found
+    /// 1 - the target host has not been found, or the probes of the ttl of the target host are still being sent
     /// 2 - the next ttl is not greater than the maximum allowed ttl
     /// 3 - if the target ttl of the target is known:
     ///       - the next ttl is not greater than the ttl of the target host observed from the prior round
     ///     otherwise:
-    ///       - the number of unknown-in-flight probes is lower than the maximum allowed
+    ///       - the number of unknown-in-flight ttls is lower than the maximum allowed
+    /// 4 - if the probes are scheduled round-robin, the next probe is due
+    /// 5 - the rate limit, if any, allows another probe to be sent
+    ///
+    /// If a probe timeout is configured then probes which have been awaiting a response for longer than the timeout
+    /// are considered lost and are no longer counted as in-flight.
+    ///
+    /// Each ttl is sent `probes_per_round` probes in succession before the next ttl, the maximum in-flight is counted
+    /// in ttls and so up to `max_inflight` times `probes_per_round` probes may be unknown-in-flight.
     fn send_request<N: Network>(&self, network: &mut N, st: &mut TracerState) -> TraceResult<()> {
+        if let Some(probe_timeout) = self.probe_timeout {
+            st.expire_probes(SystemTime::now(), probe_timeout);
+        }
         let can_send_ttl = if let Some(target_ttl) = st.target_ttl() {
             st.ttl() <= target_ttl
         } else {
-            st.ttl() - st.max_received_ttl().unwrap_or_default()
-                < TimeToLive::from(self.max_inflight.0)
+            st.ttl()
+                - st.max_received_ttl()
+                    .max(st.max_expired_ttl())
+                    .unwrap_or_default()
+                < TimeToLive::from(self.max_inflight.0.saturating_mul(self.ttl_stride.0))
         };
-        if !st.target_found() && st.ttl() <= self.max_ttl && can_send_ttl {
+        let target_burst = st.in_burst() && st.target_ttl().is_some_and(|ttl| st.ttl() <= ttl);
+        if (!st.target_found() || target_burst) && st.ttl() <= self.max_ttl && can_send_ttl {
+            let now = SystemTime::now();
+            if matches!(self.schedule_strategy, ScheduleStrategy::RoundRobin)
+                && !self.is_due(st, now)
+            {
+                return Ok(());
+            }
+            if !self
+                .rate_limiter
+                .as_ref()
+                .is_none_or(RateLimiter::try_acquire)
+            {
+                st.throttle(now);
+                return Ok(());
+            }
+            st.unthrottle(now);
             match self.protocol {
                 TracerProtocol::Icmp => network.send_icmp_probe(st.next_probe())?,
+                TracerProtocol::IcmpTimestamp => {
+                    network.send_icmp_timestamp_probe(st.next_probe())?;
+                }
                 TracerProtocol::Udp => network.send_udp_probe(st.next_probe())?,
                 TracerProtocol::Tcp => network.send_tcp_probe(st.next_probe())?,
             }
@@ -113,6 +222,21 @@ impl<F: Fn(&TracerRound<'_>)> Tracer<F> {
         Ok(())
     }
 
+    /// Is the next probe of the round due at `now`, when the probes of a round are spaced evenly across the minimum round
+    /// duration?
+    ///
+    /// The probes of a round are those of every ttl up to the ttl of the target host observed in a prior round, or up to
+    /// the maximum ttl if the target host has not yet been found.
+    fn is_due(&self, st: &TracerState, now: SystemTime) -> bool {
+        let last_ttl = st
+            .target_ttl()
+            .map_or(self.max_ttl, |ttl| ttl.min(self.max_ttl));
+        let ttls = u32::from(last_ttl.0.saturating_sub(self.first_ttl.0) / self.ttl_stride.0) + 1;
+        let interval = self.min_round_duration / (ttls * u32::from(self.probes_per_round.0));
+        let sent = u32::try_from(st.probes().len()).unwrap_or(u32::MAX);
+        now.duration_since(st.round_start()).unwrap_or_default() >= interval.saturating_mul(sent)
+    }
+
     /// Read and process the next incoming `ICMP` packet.
     ///
     /// We allow multiple probes to be in-flight at any time and we cannot guaranteed that responses will be
@@ -125,13 +249,29 @@ impl<F: Fn(&TracerRound<'_>)> Tracer<F> {
     /// `identifier` that we set which we can now validate to ensure we only process responses which correspond to
     /// packets sent from this process.  For The `UDP` and `TCP` protocols, only packets destined for our src port will
     /// be delivered to us by the OS and so no other `identifier` is needed and so we allow the special case value of 0.
+    /// For the `ICMP` protocols the `identifier` must always match, such that concurrent tracers do not process the
+    /// responses to each other's probes.
+    ///
+    /// The source port of `UDP` and `TCP` probes may rotate by round and so the source port of the probe a response is
+    /// to must be the source port of the round in which the probe of that sequence was sent.
     ///
     /// When we process an `EchoReply` from the target host we extract the time-to-live from the corresponding
     /// original `EchoRequest`.  Note that this may not be the greatest time-to-live that was sent in the round as
     /// the algorithm will send `EchoRequest` wih larger time-to-live values before the `EchoReply` is received.
+    ///
+    /// A `TCP` `SYN-ACK` or `RST` can only originate from the target host and so, as for an `EchoReply`, is always
+    /// treated as the target host having been found, recording whether the port was open or closed.
+    ///
+    /// If `auto_ttl` is enabled then any response which originates from the target host, such as the `ICMP`
+    /// `DestinationUnreachable` returned for a `UDP` probe, is also treated as the target host having been found.
+    ///
+    /// Responses received after the probe timeout, if configured, are discarded as the probe is already considered lost.
+    #[allow(clippy::too_many_lines)]
     fn recv_response<N: Network>(&self, network: &mut N, st: &mut TracerState) -> TraceResult<()> {
         let next = match self.protocol {
-            TracerProtocol::Icmp => network.recv_probe_resp_icmp(self.read_timeout)?,
+            TracerProtocol::Icmp | TracerProtocol::IcmpTimestamp => {
+                network.recv_probe_resp_icmp(self.read_timeout)?
+            }
             TracerProtocol::Udp => network.recv_probe_resp_udp(self.read_timeout)?,
             TracerProtocol::Tcp => network.recv_probe_resp_tcp(self.read_timeout)?,
         };
@@ -140,40 +280,49 @@ impl<F: Fn(&TracerRound<'_>)> Tracer<F> {
                 let sequence = Sequence(data.sequence);
                 let received = data.recv;
                 let ip = data.addr;
-                let trace_id = TraceId::from(data.identifier);
-                if (self.trace_identifier == trace_id || trace_id == TraceId::from(0))
+                if self.is_own_identifier(TraceId::from(data.identifier))
                     && st.in_round(sequence)
+                    && self.is_own_port(st.probe_at(sequence), data.src_port)
+                    && !self.is_expired(st.probe_at(sequence), received)
                 {
                     let probe = st
                         .probe_at(sequence)
                         .with_status(ProbeStatus::Complete)
                         .with_icmp_packet_type(IcmpPacketType::TimeExceeded)
                         .with_host(ip)
+                        .with_mpls(data.mpls)
                         .with_received(received);
-                    st.update_probe(sequence, probe, received, false);
+                    st.update_probe(sequence, probe, received, self.is_target(ip));
                 }
             }
-            Some(ProbeResponse::DestinationUnreachable(data)) => {
+            Some(ProbeResponse::DestinationUnreachable(data, code)) => {
                 let sequence = Sequence(data.sequence);
                 let received = data.recv;
                 let ip = data.addr;
-                let trace_id = TraceId::from(data.identifier);
-                if self.trace_identifier == trace_id && st.in_round(sequence) {
+                if self.is_own_identifier(TraceId::from(data.identifier))
+                    && st.in_round(sequence)
+                    && self.is_own_port(st.probe_at(sequence), data.src_port)
+                    && !self.is_expired(st.probe_at(sequence), received)
+                {
                     let probe = st
                         .probe_at(sequence)
                         .with_status(ProbeStatus::Complete)
                         .with_icmp_packet_type(IcmpPacketType::Unreachable)
+                        .with_unreachable_code(code)
                         .with_host(ip)
+                        .with_mpls(data.mpls)
                         .with_received(received);
-                    st.update_probe(sequence, probe, received, false);
+                    st.update_probe(sequence, probe, received, self.is_target(ip));
                 }
             }
             Some(ProbeResponse::EchoReply(data)) => {
                 let sequence = Sequence(data.sequence);
                 let received = data.recv;
                 let ip = data.addr;
-                let trace_id = TraceId::from(data.identifier);
-                if self.trace_identifier == trace_id && st.in_round(sequence) {
+                if self.trace_identifier == TraceId::from(data.identifier)
+                    && st.in_round(sequence)
+                    && !self.is_expired(st.probe_at(sequence), received)
+                {
                     let probe = st
                         .probe_at(sequence)
                         .with_status(ProbeStatus::Complete)
@@ -183,11 +332,81 @@ impl<F: Fn(&TracerRound<'_>)> Tracer<F> {
                     st.update_probe(sequence, probe, received, true);
                 }
             }
+            Some(ProbeResponse::TimestampReply(data, timestamps)) => {
+                let sequence = Sequence(data.sequence);
+                let received = data.recv;
+                let ip = data.addr;
+                if self.trace_identifier == TraceId::from(data.identifier)
+                    && st.in_round(sequence)
+                    && !self.is_expired(st.probe_at(sequence), received)
+                {
+                    let probe = st
+                        .probe_at(sequence)
+                        .with_status(ProbeStatus::Complete)
+                        .with_icmp_packet_type(IcmpPacketType::TimestampReply)
+                        .with_host(ip)
+                        .with_icmp_timestamps(timestamps)
+                        .with_received(received);
+                    st.update_probe(sequence, probe, received, true);
+                }
+            }
+            Some(ProbeResponse::TcpReply(data, outcome)) => {
+                let sequence = Sequence(data.sequence);
+                let received = data.recv;
+                if st.in_round(sequence)
+                    && self.is_own_port(st.probe_at(sequence), data.src_port)
+                    && !self.is_expired(st.probe_at(sequence), received)
+                {
+                    let probe = st
+                        .probe_at(sequence)
+                        .with_status(ProbeStatus::Complete)
+                        .with_host(data.addr)
+                        .with_tcp_outcome(outcome)
+                        .with_received(received);
+                    st.update_probe(sequence, probe, received, true);
+                }
+            }
             None => {}
         }
         Ok(())
     }
 
+    /// Was `probe` sent from `src_port`, the source port of the `UDP` or `TCP` probe a response is to?
+    ///
+    /// The `ICMP` protocols have no source port and so always match.
+    fn is_own_port(&self, probe: Probe, src_port: u16) -> bool {
+        match self.protocol {
+            TracerProtocol::Udp | TracerProtocol::Tcp => {
+                self.source_port
+                    .rotated(probe.round, self.source_port_count)
+                    .0
+                    == src_port
+            }
+            TracerProtocol::Icmp | TracerProtocol::IcmpTimestamp => true,
+        }
+    }
+
+    /// Was the response to `probe`, `received` at the given time, received after the probe timeout, if configured?
+    fn is_expired(&self, probe: Probe, received: SystemTime) -> bool {
+        self.probe_timeout
+            .is_some_and(|probe_timeout| exceeds(probe.sent, received, probe_timeout))
+    }
+
+    /// Is `addr` the target host and should a response from it end the round?
+    fn is_target(&self, addr: IpAddr) -> bool {
+        self.auto_ttl && addr == self.target_addr
+    }
+
+    /// Is `trace_id`, read from the original probe within an `ICMP` error response, that of a probe sent by this
+    /// tracer?
+    ///
+    /// The `UDP` and `TCP` protocols do not carry our identifier and so the special case value of 0 is also allowed.
+    fn is_own_identifier(&self, trace_id: TraceId) -> bool {
+        self.trace_identifier == trace_id
+            || (trace_id == TraceId::from(0)
+                && matches!(self.protocol, TracerProtocol::Udp | TracerProtocol::Tcp))
+    }
+
     /// Check if the round is complete and publish the results.
     ///
     /// A round is considered to be complete when:
@@ -197,16 +416,58 @@ impl<F: Fn(&TracerRound<'_>)> Tracer<F> {
     /// 3 - either:
     ///     A - the target has been found OR
     ///     B - the target has not been found and the round has exceeded the maximum round duration
-    fn update_round(&self, st: &mut TracerState) {
+    ///
+    /// Time spent waiting for the rate limit, if any, does not count towards the maximum round duration such that a
+    /// round is not ended before all probes have been sent.
+    ///
+    /// If tracing is paused then the next round does not start until tracing is resumed, the time spent paused is
+    /// therefore never part of any round.
+    ///
+    /// If an adaptive rate controller is configured then the minimum and maximum round duration are scaled by its
+    /// backoff, which is updated from the probes of each completed round before it is published.
+    ///
+    /// If adaptive grace is configured then the grace period is exceeded as soon as every probe sent before the target
+    /// host has a response, and otherwise the `grace` period is that of the slowest response of the prior round, no
+    /// shorter than the grace duration and no longer than the adaptive grace, such that slow hops are not missed.
+    ///
+    /// Returns true if the round completed and the next round has started.
+    fn update_round(
+        &self,
+        st: &mut TracerState,
+        controller: Option<&mut RateController>,
+        grace: &mut Duration,
+    ) -> bool {
         let now = SystemTime::now();
+        let backoff = controller.as_deref().map_or(1, RateController::backoff);
         let round_duration = now.duration_since(st.round_start()).unwrap_or_default();
-        let round_min = round_duration > self.min_round_duration;
-        let grace_exceeded = exceeds(st.received_time(), now, self.grace_duration);
-        let round_max = round_duration > self.max_round_duration;
+        let round_min = round_duration > self.min_round_duration * backoff;
+        let grace_exceeded = (self.adaptive_grace.is_some() && st.settled())
+            || exceeds(st.received_time(), now, *grace);
+        let round_max =
+            round_duration.saturating_sub(st.throttled(now)) > self.max_round_duration * backoff;
         let target_found = st.target_found();
         if round_min && grace_exceeded && target_found || round_max {
-            self.publish_trace(st);
+            let largest_ttl = largest_ttl(st);
+            let backoff = controller.map_or(1, |controller| {
+                controller.observe(st.probes(), largest_ttl);
+                controller.backoff()
+            });
+            if let Some(adaptive_grace) = self.adaptive_grace {
+                *grace = adapted_grace(st.probes(), self.grace_duration, adaptive_grace);
+            }
+            self.publish_trace(st, largest_ttl, backoff);
+            self.wait_while_paused();
             st.advance_round(self.first_ttl);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Block until tracing is resumed, if paused.
+    fn wait_while_paused(&self) {
+        while self.pause.as_ref().is_some_and(PauseHandle::is_paused) {
+            sleep(PAUSE_POLL_INTERVAL);
         }
     }
 
@@ -214,25 +475,37 @@ impl<F: Fn(&TracerRound<'_>)> Tracer<F> {
     ///
     /// If the round completed without receiving an `EchoReply` from the target host then we also publish the next
     /// `Probe` which is assumed to represent the TTL of the target host.
-    fn publish_trace(&self, state: &TracerState) {
-        let max_received_ttl = if let Some(target_ttl) = state.target_ttl() {
-            target_ttl
-        } else {
-            state
-                .max_received_ttl()
-                .map_or(TimeToLive(0), |max_received_ttl| {
-                    let max_sent_ttl = state.ttl() - TimeToLive(1);
-                    max_sent_ttl.min(max_received_ttl + TimeToLive(1))
-                })
-        };
+    fn publish_trace(&self, state: &TracerState, largest_ttl: TimeToLive, backoff: u32) {
         let probes = state.probes();
-        let largest_ttl = max_received_ttl;
         let reason = if state.target_found() {
             CompletionReason::TargetFound
         } else {
             CompletionReason::RoundTimeLimitExceeded
         };
-        (self.publish)(&TracerRound::new(probes, largest_ttl, reason));
+        (self.publish)(&TracerRound::new(
+            probes,
+            largest_ttl,
+            reason,
+            backoff,
+            self.target_addr,
+        ));
+    }
+}
+
+/// The largest time-to-live (ttl) of the completed round.
+///
+/// If the round completed without receiving an `EchoReply` from the target host then this is the ttl of the next
+/// `Probe` which is assumed to represent the TTL of the target host.
+fn largest_ttl(state: &TracerState) -> TimeToLive {
+    if let Some(target_ttl) = state.target_ttl() {
+        target_ttl
+    } else {
+        state
+            .max_received_ttl()
+            .map_or(TimeToLive(0), |max_received_ttl| {
+                let max_sent_ttl = state.max_sent_ttl();
+                max_sent_ttl.min(max_received_ttl + state.ttl_stride())
+            })
     }
 }
 
@@ -241,27 +514,23 @@ impl<F: Fn(&TracerRound<'_>)> Tracer<F> {
 /// This is contained within a sub-module to ensure that mutations are only performed via methods on the
 /// `TracerState` struct.
 mod state {
-    use crate::tracing::types::{MaxRounds, Round, Sequence, TimeToLive};
-    use crate::tracing::Probe;
-    use std::time::SystemTime;
+    use crate::tracing::types::{
+        FlowId, MaxFlows, MaxRounds, ProbesPerRound, Round, Sequence, TimeToLive,
+    };
+    use crate::tracing::{Probe, ProbeStatus};
+    use std::time::{Duration, SystemTime};
 
-    /// The maximum number of `Probe` entries in the buffer.
+    /// The maximum number of `Probe` entries in the buffer for each probe per round.
     ///
     /// This is effectively also the maximum number of time-to-live (TTL) we can support.  We only ever send TTL in
     /// the range 1..255 so this is technically one larger than we need.
     const BUFFER_SIZE: u16 = 256;
 
-    /// The maximum sequence number.
-    ///
-    /// The sequence number is only ever wrapped between rounds and so we need to ensure that there are enough sequence
-    /// numbers for a complete round (i.e. the max TTL, which is `BUFFER_SIZE`).
-    const MAX_SEQUENCE: Sequence = Sequence(u16::MAX - BUFFER_SIZE);
-
     /// Mutable state needed for the tracing algorithm.
     #[derive(Debug)]
     pub struct TracerState {
-        /// The state of all `Probe` requests and responses.
-        buffer: [Probe; BUFFER_SIZE as usize],
+        /// The state of all `Probe` requests and responses, `BUFFER_SIZE` entries for each probe per round.
+        buffer: Vec<Probe>,
         /// The initial sequence number configuration, used to reset sequence when it wraps around.
         initial_sequence: Sequence,
         /// An increasing sequence number for every `EchoRequest`.
@@ -270,8 +539,18 @@ mod state {
         round_sequence: Sequence,
         /// The time-to-live for the _next_ `EchoRequest` packet to be sent.
         ttl: TimeToLive,
+        /// The increment of the time-to-live between successive probes of a round.
+        ttl_stride: TimeToLive,
+        /// The number of probes sent for each time-to-live in a round.
+        probes_per_round: ProbesPerRound,
+        /// The number of probes sent for the current time-to-live.
+        burst: u8,
         /// The current round.
         round: Round,
+        /// The number of distinct flows to cycle through, one per round.
+        max_flows: MaxFlows,
+        /// The flow of the current round.
+        flow_id: FlowId,
         /// The timestamp of when the current round started.
         round_start: SystemTime,
         /// Did we receive an `EchoReply` from the target host in this round?
@@ -284,35 +563,68 @@ mod state {
         target_seq: Option<Sequence>,
         /// The timestamp of the echo response packet.
         received_time: Option<SystemTime>,
+        /// The maximum time-to-live of the probes which have exceeded the probe timeout without a response.
+        max_expired_ttl: Option<TimeToLive>,
+        /// The total time spent waiting for the rate limit in the current round, excluding the current wait.
+        throttled: Duration,
+        /// The timestamp of when we started waiting for the rate limit, if we are currently waiting.
+        throttled_since: Option<SystemTime>,
     }
 
     impl TracerState {
-        pub fn new(first_ttl: TimeToLive, initial_sequence: Sequence) -> Self {
+        /// Create the state of a new trace.
+        ///
+        /// The probe buffer is allocated once per trace, sized for the `probes_per_round` of every ttl.
+        pub fn new(
+            first_ttl: TimeToLive,
+            ttl_stride: TimeToLive,
+            initial_sequence: Sequence,
+            max_flows: MaxFlows,
+            probes_per_round: ProbesPerRound,
+        ) -> Self {
             Self {
-                buffer: [Probe::default(); BUFFER_SIZE as usize],
+                buffer: vec![
+                    Probe::default();
+                    usize::from(BUFFER_SIZE) * usize::from(probes_per_round.0.max(1))
+                ],
                 initial_sequence,
                 sequence: initial_sequence,
                 round_sequence: initial_sequence,
                 ttl: first_ttl,
+                ttl_stride,
+                probes_per_round,
+                burst: 0,
                 round: Round::from(0),
+                max_flows,
+                flow_id: FlowId::from(0),
                 round_start: SystemTime::now(),
                 target_found: false,
                 max_received_ttl: None,
                 target_ttl: None,
                 target_seq: None,
                 received_time: None,
+                max_expired_ttl: None,
+                throttled: Duration::default(),
+                throttled_since: None,
             }
         }
 
         /// Get a slice of `Probe` for the current round.
         pub fn probes(&self) -> &[Probe] {
-            let round_size = self.sequence - self.round_sequence;
-            &self.buffer[..round_size.0 as usize]
+            &self.buffer[..usize::from(self.offset(self.sequence))]
         }
 
         /// Get the `Probe` for `sequence`
         pub fn probe_at(&self, sequence: Sequence) -> Probe {
-            self.buffer[usize::from(sequence - self.round_sequence)]
+            self.buffer[usize::from(self.offset(sequence))]
+        }
+
+        /// The offset of `sequence` from the starting sequence number of the current round.
+        ///
+        /// The offset wraps around such that a round in which the sequence number wraps past `u16::MAX` to zero is
+        /// handled correctly, and a `sequence` prior to the start of the round has an offset beyond the round.
+        const fn offset(&self, sequence: Sequence) -> u16 {
+            sequence.0.wrapping_sub(self.round_sequence.0)
         }
 
         pub const fn ttl(&self) -> TimeToLive {
@@ -327,6 +639,32 @@ mod state {
             self.target_found
         }
 
+        pub const fn ttl_stride(&self) -> TimeToLive {
+            self.ttl_stride
+        }
+
+        /// Have some, but not all, of the probes of the current ttl been sent?
+        pub const fn in_burst(&self) -> bool {
+            self.burst > 0
+        }
+
+        /// The time-to-live of the last `Probe` sent, which is the current ttl if its probes are still being sent.
+        pub fn max_sent_ttl(&self) -> TimeToLive {
+            if self.burst > 0 {
+                self.ttl
+            } else {
+                self.ttl - self.ttl_stride
+            }
+        }
+
+        /// The maximum sequence number.
+        ///
+        /// The sequence number is only ever wrapped between rounds and so we need to ensure that there are enough
+        /// sequence numbers for a complete round (i.e. the size of the buffer).
+        pub fn max_sequence(&self) -> Sequence {
+            Sequence(u16::MAX - u16::try_from(self.buffer.len()).unwrap_or(u16::MAX))
+        }
+
         pub const fn max_received_ttl(&self) -> Option<TimeToLive> {
             self.max_received_ttl
         }
@@ -339,9 +677,67 @@ mod state {
             self.received_time
         }
 
+        /// Has the target host been found and has every probe of this round before it received a response, such that
+        /// no further responses are expected?
+        pub fn settled(&self) -> bool {
+            match self.target_ttl {
+                Some(target_ttl) if self.target_found => self
+                    .probes()
+                    .iter()
+                    .filter(|probe| probe.ttl < target_ttl)
+                    .all(|probe| probe.status != ProbeStatus::Awaited),
+                _ => false,
+            }
+        }
+
+        pub const fn max_expired_ttl(&self) -> Option<TimeToLive> {
+            self.max_expired_ttl
+        }
+
+        /// Record the maximum time-to-live of the probes in the current round which have been awaiting a response for
+        /// longer than the `probe_timeout` at `now`.
+        pub fn expire_probes(&mut self, now: SystemTime, probe_timeout: Duration) {
+            let expired = self
+                .probes()
+                .iter()
+                .filter(|probe| {
+                    probe.status == ProbeStatus::Awaited
+                        && probe.sent.is_some_and(|sent| {
+                            now.duration_since(sent).unwrap_or_default() > probe_timeout
+                        })
+                })
+                .map(|probe| probe.ttl)
+                .max();
+            self.max_expired_ttl = self.max_expired_ttl.max(expired);
+        }
+
+        /// The total time spent waiting for the rate limit in the current round.
+        pub fn throttled(&self, now: SystemTime) -> Duration {
+            let current = self
+                .throttled_since
+                .map(|since| now.duration_since(since).unwrap_or_default())
+                .unwrap_or_default();
+            self.throttled + current
+        }
+
+        /// Record that we are waiting for the rate limit to send the next `Probe`.
+        pub fn throttle(&mut self, now: SystemTime) {
+            self.throttled_since.get_or_insert(now);
+        }
+
+        /// Record that we are no longer waiting for the rate limit.
+        pub fn unthrottle(&mut self, now: SystemTime) {
+            if let Some(since) = self.throttled_since.take() {
+                self.throttled += now.duration_since(since).unwrap_or_default();
+            }
+        }
+
         /// Is `sequence` in the current round?
+        ///
+        /// Only the sequence numbers of the probes sent in the current round, which begins at the initial sequence
+        /// number or at the sequence number following the prior round, are in the round.
         pub fn in_round(&self, sequence: Sequence) -> bool {
-            sequence >= self.round_sequence
+            self.offset(sequence) < self.offset(self.sequence)
         }
 
         /// Have all round completed?
@@ -354,15 +750,26 @@ mod state {
 
         /// Create and return the next `Probe` at the current `sequence` and `ttl`.
         ///
-        /// We post-increment `ttl` here and so in practice we only allow `ttl` values in the range `1..254` to allow
-        /// us to use a `u8`.
+        /// We post-increment `ttl` by the `ttl_stride` here, once `probes_per_round` probes have been sent for it, and
+        /// so in practice we only allow `ttl` values in the range `1..254` to allow us to use a `u8`, the increment
+        /// saturates such that the next `ttl` is beyond any `max_ttl`.
         pub fn next_probe(&mut self) -> Probe {
-            let probe = Probe::new(self.sequence, self.ttl, self.round, SystemTime::now());
-            self.buffer[usize::from(self.sequence - self.round_sequence)] = probe;
+            let probe = Probe::new(
+                self.sequence,
+                self.ttl,
+                self.round,
+                self.flow_id,
+                SystemTime::now(),
+            );
+            let offset = usize::from(self.offset(self.sequence));
+            self.buffer[offset] = probe;
             debug_assert!(self.ttl < TimeToLive(u8::MAX));
-            self.ttl += TimeToLive::from(1);
-            debug_assert!(self.sequence < Sequence(u16::MAX));
-            self.sequence += Sequence(1);
+            self.burst += 1;
+            if self.burst >= self.probes_per_round.0 {
+                self.burst = 0;
+                self.ttl = TimeToLive(self.ttl.0.saturating_add(self.ttl_stride.0));
+            }
+            self.sequence = Sequence(self.sequence.0.wrapping_add(1));
             probe
         }
 
@@ -389,13 +796,16 @@ mod state {
                     self.target_ttl = Some(probe.ttl);
                     self.target_seq = Some(sequence);
                 }
-                (Some(_), Some(target_seq)) if found && sequence < target_seq => {
+                (Some(_), Some(target_seq))
+                    if found && self.offset(sequence) < self.offset(target_seq) =>
+                {
                     self.target_ttl = Some(probe.ttl);
                     self.target_seq = Some(sequence);
                 }
                 _ => {}
             }
-            self.buffer[usize::from(sequence - self.round_sequence)] = probe;
+            let offset = usize::from(self.offset(sequence));
+            self.buffer[offset] = probe;
             self.max_received_ttl = match self.max_received_ttl {
                 Some(max_received_ttl) => Some(max_received_ttl.max(probe.ttl)),
                 None => Some(probe.ttl),
@@ -406,20 +816,33 @@ mod state {
 
         /// Advance to the next round.
         ///
-        /// If, during the rond which just completed, we went above the max sequence number then we reset it here.
-        /// We do this here to avoid having to deal with the sequence number wrapping during a round, which is more
-        /// problematic.
+        /// If, during the rond which just completed, we went above the max sequence number, or wrapped past
+        /// `u16::MAX`, then we reset it here.  We do this here to avoid having to deal with the sequence number
+        /// wrapping during a round, which is more problematic.
+        ///
+        /// Each round uses the next flow, wrapping around after `max_flows` rounds.
         pub fn advance_round(&mut self, first_ttl: TimeToLive) {
-            if self.sequence >= MAX_SEQUENCE {
+            if self.sequence >= self.max_sequence() || self.sequence < self.round_sequence {
                 self.sequence = self.initial_sequence;
             }
             self.target_found = false;
             self.round_sequence = self.sequence;
             self.received_time = None;
+            self.max_expired_ttl = None;
             self.round_start = SystemTime::now();
             self.max_received_ttl = None;
             self.round += Round::from(1);
+            self.flow_id = FlowId::from((self.round.0 % usize::from(self.max_flows.0)) as u16);
             self.ttl = first_ttl;
+            self.burst = 0;
+            self.target_seq = None;
+            self.throttled = Duration::default();
+            self.throttled_since = None;
+        }
+
+        /// Forget the ttl of the target host, such as when the target changes, such that it is found afresh.
+        pub fn clear_target(&mut self) {
+            self.target_ttl = None;
             self.target_seq = None;
         }
     }
@@ -439,7 +862,13 @@ mod state {
         )]
         #[test]
         fn test_state() {
-            let mut state = TracerState::new(TimeToLive::from(1), Sequence(33000));
+            let mut state = TracerState::new(
+                TimeToLive::from(1),
+                TimeToLive::from(1),
+                Sequence(33000),
+                MaxFlows(1),
+                ProbesPerRound(1),
+            );
 
             // Validate the initial TracerState
             assert_eq!(state.round, Round(0));
@@ -621,7 +1050,13 @@ mod state {
         fn test_sequence_wrap1() {
             // Start from MAX_SEQUENCE - 1 which is (65279 - 1) == 65278
             let initial_sequence = Sequence(65278);
-            let mut state = TracerState::new(TimeToLive::from(1), initial_sequence);
+            let mut state = TracerState::new(
+                TimeToLive::from(1),
+                TimeToLive::from(1),
+                initial_sequence,
+                MaxFlows(1),
+                ProbesPerRound(1),
+            );
             assert_eq!(state.round, Round(0));
             assert_eq!(state.sequence, initial_sequence);
             assert_eq!(state.round_sequence, initial_sequence);
@@ -661,7 +1096,13 @@ mod state {
         fn test_sequence_wrap2() {
             let total_rounds = 2000;
             let max_probe_per_round = 254;
-            let mut state = TracerState::new(TimeToLive::from(1), Sequence(33000));
+            let mut state = TracerState::new(
+                TimeToLive::from(1),
+                TimeToLive::from(1),
+                Sequence(33000),
+                MaxFlows(1),
+                ProbesPerRound(1),
+            );
             for _ in 0..total_rounds {
                 for _ in 0..max_probe_per_round {
                     let _probe = state.next_probe();
@@ -677,7 +1118,13 @@ mod state {
         fn test_sequence_wrap3() {
             let total_rounds = 2000;
             let max_probe_per_round = 20;
-            let mut state = TracerState::new(TimeToLive::from(1), Sequence(33000));
+            let mut state = TracerState::new(
+                TimeToLive::from(1),
+                TimeToLive::from(1),
+                Sequence(33000),
+                MaxFlows(1),
+                ProbesPerRound(1),
+            );
             let mut rng = rand::thread_rng();
             for _ in 0..total_rounds {
                 for _ in 0..rng.gen_range(0..max_probe_per_round) {
@@ -686,12 +1133,541 @@ mod state {
                 state.advance_round(TimeToLive::from(1));
             }
         }
+
+        #[test]
+        fn test_sequence_wrap_u16() {
+            let initial_sequence = Sequence(65534);
+            let mut state = TracerState::new(
+                TimeToLive::from(1),
+                TimeToLive::from(1),
+                initial_sequence,
+                MaxFlows(1),
+                ProbesPerRound(1),
+            );
+            let sequences: Vec<_> = (0..4).map(|_| state.next_probe().sequence).collect();
+            assert_eq!(
+                sequences,
+                [Sequence(65534), Sequence(65535), Sequence(0), Sequence(1)]
+            );
+            assert_eq!(state.sequence, Sequence(2));
+            assert_eq!(state.probes().len(), 4);
+            assert!(!state.in_round(Sequence(65533)));
+            assert!(state.in_round(Sequence(65534)));
+            assert!(state.in_round(Sequence(65535)));
+            assert!(state.in_round(Sequence(0)));
+            assert!(state.in_round(Sequence(1)));
+            assert!(!state.in_round(Sequence(2)));
+            assert_eq!(state.probe_at(Sequence(0)).ttl, TimeToLive(3));
+            assert_eq!(state.probe_at(Sequence(1)).ttl, TimeToLive(4));
+
+            // The target responds to both of the probes either side of the wrap, the earlier probe is used
+            let received = SystemTime::now();
+            let probe_0 = state
+                .probe_at(Sequence(0))
+                .with_status(ProbeStatus::Complete)
+                .with_received(received);
+            state.update_probe(Sequence(0), probe_0, received, true);
+            let probe_65535 = state
+                .probe_at(Sequence(65535))
+                .with_status(ProbeStatus::Complete)
+                .with_received(received);
+            state.update_probe(Sequence(65535), probe_65535, received, true);
+            assert_eq!(state.target_seq, Some(Sequence(65535)));
+            assert_eq!(state.target_ttl, Some(TimeToLive(2)));
+            assert_eq!(state.probes()[1].status, ProbeStatus::Complete);
+            assert_eq!(state.probes()[2].status, ProbeStatus::Complete);
+
+            // The sequence is reset at the round boundary and the probes of the prior round are no longer in the round
+            state.advance_round(TimeToLive::from(1));
+            assert_eq!(state.sequence, initial_sequence);
+            assert_eq!(state.round_sequence, initial_sequence);
+            assert!(!state.in_round(Sequence(65535)));
+            assert!(!state.in_round(Sequence(0)));
+            assert_eq!(state.next_probe().sequence, Sequence(65534));
+            assert!(state.in_round(Sequence(65534)));
+            assert!(!state.in_round(Sequence(65535)));
+            assert!(!state.in_round(Sequence(0)));
+        }
+
+        #[test]
+        fn test_sequence_wrap_round_boundary() {
+            let mut state = TracerState::new(
+                TimeToLive::from(1),
+                TimeToLive::from(1),
+                Sequence(65100),
+                MaxFlows(1),
+                ProbesPerRound(1),
+            );
+            for _ in 0..5 {
+                for _ in 0..254 {
+                    let probe = state.next_probe();
+                    assert!(state.in_round(probe.sequence));
+                }
+                assert!(state.sequence < state.max_sequence() + Sequence(BUFFER_SIZE));
+                state.advance_round(TimeToLive::from(1));
+                assert!(state.probes().is_empty());
+            }
+            // Every round begins at the initial sequence as each round exceeds the max sequence
+            assert_eq!(state.round_sequence, Sequence(65100));
+        }
+
+        #[test]
+        fn test_in_round() {
+            let mut state = TracerState::new(
+                TimeToLive::from(1),
+                TimeToLive::from(1),
+                Sequence(1234),
+                MaxFlows(1),
+                ProbesPerRound(1),
+            );
+            assert!(!state.in_round(Sequence(1234)));
+            state.next_probe();
+            state.next_probe();
+            assert!(!state.in_round(Sequence(1233)));
+            assert!(state.in_round(Sequence(1234)));
+            assert!(state.in_round(Sequence(1235)));
+            assert!(!state.in_round(Sequence(1236)));
+            assert!(!state.in_round(Sequence(33000)));
+            state.advance_round(TimeToLive::from(1));
+            assert!(!state.in_round(Sequence(1235)));
+            state.next_probe();
+            assert!(state.in_round(Sequence(1236)));
+        }
+
+        #[test]
+        fn test_flow_cycle() {
+            let mut state = TracerState::new(
+                TimeToLive::from(1),
+                TimeToLive::from(1),
+                Sequence(33000),
+                MaxFlows(3),
+                ProbesPerRound(1),
+            );
+            for expected in [0, 1, 2, 0, 1] {
+                assert_eq!(state.next_probe().flow_id, FlowId(expected));
+                assert_eq!(state.next_probe().flow_id, FlowId(expected));
+                state.advance_round(TimeToLive::from(1));
+            }
+        }
+
+        #[test]
+        fn test_ttl_stride() {
+            let mut state = TracerState::new(
+                TimeToLive::from(1),
+                TimeToLive::from(4),
+                Sequence(33000),
+                MaxFlows(1),
+                ProbesPerRound(1),
+            );
+            let ttls: Vec<_> = (0..3).map(|_| state.next_probe().ttl).collect();
+            assert_eq!(ttls, [TimeToLive(1), TimeToLive(5), TimeToLive(9)]);
+            assert_eq!(state.ttl(), TimeToLive(13));
+            assert_eq!(state.probes().len(), 3);
+            state.advance_round(TimeToLive::from(1));
+            assert_eq!(state.ttl(), TimeToLive(1));
+        }
+
+        #[test]
+        fn test_throttled() {
+            let mut state = TracerState::new(
+                TimeToLive::from(1),
+                TimeToLive::from(1),
+                Sequence(33000),
+                MaxFlows(1),
+                ProbesPerRound(1),
+            );
+            let start = SystemTime::now();
+            let at = |millis| start + Duration::from_millis(millis);
+            assert_eq!(state.throttled(at(0)), Duration::from_millis(0));
+            state.throttle(at(10));
+            state.throttle(at(20));
+            assert_eq!(state.throttled(at(30)), Duration::from_millis(20));
+            state.unthrottle(at(40));
+            assert_eq!(state.throttled(at(100)), Duration::from_millis(30));
+            state.throttle(at(100));
+            state.unthrottle(at(150));
+            state.unthrottle(at(200));
+            assert_eq!(state.throttled(at(200)), Duration::from_millis(80));
+            state.advance_round(TimeToLive::from(1));
+            assert_eq!(state.throttled(at(300)), Duration::from_millis(0));
+        }
+
+        #[test]
+        fn test_expire_probes() {
+            let mut state = TracerState::new(
+                TimeToLive::from(1),
+                TimeToLive::from(1),
+                Sequence(33000),
+                MaxFlows(1),
+                ProbesPerRound(1),
+            );
+            let start = SystemTime::now();
+            let timeout = Duration::from_secs(1);
+            let _ = state.next_probe();
+            let _ = state.next_probe();
+            let probe_3 = state.next_probe();
+            state.expire_probes(start, timeout);
+            assert_eq!(state.max_expired_ttl(), None);
+            let received = probe_3.sent.unwrap();
+            let probe_3 = probe_3
+                .with_status(ProbeStatus::Complete)
+                .with_received(received);
+            state.update_probe(Sequence(33002), probe_3, received, false);
+            state.expire_probes(start + Duration::from_secs(10), timeout);
+            assert_eq!(state.max_expired_ttl(), Some(TimeToLive::from(2)));
+            state.advance_round(TimeToLive::from(1));
+            assert_eq!(state.max_expired_ttl(), None);
+        }
+
+        #[test]
+        fn test_settled() {
+            let mut state = TracerState::new(
+                TimeToLive::from(1),
+                TimeToLive::from(1),
+                Sequence(33000),
+                MaxFlows(1),
+                ProbesPerRound(1),
+            );
+            let probes = [state.next_probe(), state.next_probe(), state.next_probe()];
+            assert!(!state.settled());
+            let complete = |probe: Probe| {
+                let received = probe.sent.unwrap();
+                (
+                    probe
+                        .with_status(ProbeStatus::Complete)
+                        .with_received(received),
+                    received,
+                )
+            };
+            let (probe_3, received) = complete(probes[2]);
+            state.update_probe(Sequence(33002), probe_3, received, true);
+            assert!(!state.settled());
+            let (probe_1, received) = complete(probes[0]);
+            state.update_probe(Sequence(33000), probe_1, received, false);
+            assert!(!state.settled());
+            let (probe_2, received) = complete(probes[1]);
+            state.update_probe(Sequence(33001), probe_2, received, false);
+            assert!(state.settled());
+            state.advance_round(TimeToLive::from(1));
+            assert!(!state.settled());
+        }
+
+        #[test]
+        fn test_probes_per_round() {
+            let mut state = TracerState::new(
+                TimeToLive::from(1),
+                TimeToLive::from(2),
+                Sequence(33000),
+                MaxFlows(1),
+                ProbesPerRound(3),
+            );
+            assert_eq!(state.max_sequence(), Sequence(u16::MAX - BUFFER_SIZE * 3));
+            let ttls: Vec<_> = (0..7).map(|_| state.next_probe().ttl.0).collect();
+            assert_eq!(ttls, vec![1, 1, 1, 3, 3, 3, 5]);
+            assert_eq!(state.ttl(), TimeToLive(5));
+            assert_eq!(state.max_sent_ttl(), TimeToLive(5));
+            state.next_probe();
+            state.next_probe();
+            assert_eq!(state.ttl(), TimeToLive(7));
+            assert_eq!(state.max_sent_ttl(), TimeToLive(5));
+            assert_eq!(state.probes().len(), 9);
+            state.advance_round(TimeToLive::from(1));
+            assert_eq!(state.next_probe().ttl, TimeToLive(1));
+            assert_eq!(state.ttl(), TimeToLive(1));
+        }
     }
 }
 
 /// Returns true if the duration between start and end is grater than a duration, false otherwise.
 fn exceeds(start: Option<SystemTime>, end: SystemTime, dur: Duration) -> bool {
-    start.map_or(false, |start| {
-        end.duration_since(start).unwrap_or_default() > dur
-    })
+    start.is_some_and(|start| end.duration_since(start).unwrap_or_default() > dur)
+}
+
+/// The grace period adapted to the slowest response to the `probes` of a round, between `min_grace` and `max_grace`.
+fn adapted_grace(probes: &[Probe], min_grace: Duration, max_grace: Duration) -> Duration {
+    probes
+        .iter()
+        .filter_map(|probe| probe.received?.duration_since(probe.sent?).ok())
+        .max()
+        .unwrap_or_default()
+        .clamp(min_grace, max_grace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tracing::net::ProbeResponseData;
+    use crate::tracing::types::{FlowId, MaxFlows, Round};
+    use crate::tracing::UnreachableCode;
+    use std::collections::VecDeque;
+    use std::net::Ipv4Addr;
+
+    /// A `Network` which sends nothing and receives a fixed sequence of responses.
+    struct MockNetwork {
+        responses: VecDeque<ProbeResponse>,
+    }
+
+    impl MockNetwork {
+        fn recv(&mut self) -> Option<ProbeResponse> {
+            self.responses.pop_front()
+        }
+    }
+
+    impl Network for MockNetwork {
+        fn send_icmp_probe(&mut self, _probe: Probe) -> TraceResult<()> {
+            Ok(())
+        }
+
+        fn send_icmp_timestamp_probe(&mut self, _probe: Probe) -> TraceResult<()> {
+            Ok(())
+        }
+
+        fn send_udp_probe(&mut self, _probe: Probe) -> TraceResult<()> {
+            Ok(())
+        }
+
+        fn send_tcp_probe(&mut self, _probe: Probe) -> TraceResult<()> {
+            Ok(())
+        }
+
+        fn recv_probe_resp_icmp(
+            &mut self,
+            _timeout: Duration,
+        ) -> TraceResult<Option<ProbeResponse>> {
+            Ok(self.recv())
+        }
+
+        fn recv_probe_resp_udp(
+            &mut self,
+            _timeout: Duration,
+        ) -> TraceResult<Option<ProbeResponse>> {
+            Ok(self.recv())
+        }
+
+        fn recv_probe_resp_tcp(
+            &mut self,
+            _timeout: Duration,
+        ) -> TraceResult<Option<ProbeResponse>> {
+            Ok(self.recv())
+        }
+
+        fn set_target(&mut self, _addr: IpAddr) {}
+    }
+
+    fn tracer(protocol: TracerProtocol, trace_identifier: u16) -> Tracer<fn(&TracerRound<'_>)> {
+        Tracer {
+            target_addr: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 10)),
+            protocol,
+            trace_identifier: TraceId(trace_identifier),
+            max_flows: MaxFlows(1),
+            max_rounds: None,
+            first_ttl: TimeToLive(1),
+            max_ttl: TimeToLive(64),
+            ttl_stride: TimeToLive(1),
+            auto_ttl: false,
+            grace_duration: Duration::from_millis(100),
+            adaptive_grace: None,
+            max_inflight: MaxInflight(24),
+            probes_per_round: ProbesPerRound(1),
+            schedule_strategy: ScheduleStrategy::Sync,
+            initial_sequence: Sequence(33000),
+            read_timeout: Duration::from_millis(10),
+            probe_timeout: None,
+            min_round_duration: Duration::from_secs(1),
+            max_round_duration: Duration::from_secs(1),
+            source_port: SourcePort(0),
+            source_port_count: 1,
+            rate_limiter: None,
+            adaptive_rate: None,
+            pause: None,
+            target: None,
+            publish: |_| {},
+        }
+    }
+
+    fn response(identifier: u16, sequence: u16, host: u8) -> ProbeResponseData {
+        ProbeResponseData::new(
+            SystemTime::now(),
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, host)),
+            identifier,
+            sequence,
+            0,
+            None,
+        )
+    }
+
+    /// Two concurrent `icmp` tracers with identifiers 1000 and 2000 receive every response on their raw sockets, only
+    /// the responses to the probes of each tracer may be matched to its probes.
+    #[test]
+    fn test_icmp_identifier_streams() {
+        let responses = || {
+            VecDeque::from([
+                ProbeResponse::TimeExceeded(response(1000, 33000, 1)),
+                ProbeResponse::TimeExceeded(response(2000, 33000, 2)),
+                ProbeResponse::TimeExceeded(response(2000, 33001, 2)),
+                ProbeResponse::TimeExceeded(response(1000, 33001, 1)),
+                ProbeResponse::DestinationUnreachable(response(0, 33002, 3), UnreachableCode(3)),
+                ProbeResponse::EchoReply(response(2000, 33002, 10)),
+                ProbeResponse::EchoReply(response(1000, 33003, 10)),
+            ])
+        };
+        for (identifier, host) in [(1000, 1), (2000, 2)] {
+            let tracer = tracer(TracerProtocol::Icmp, identifier);
+            let mut network = MockNetwork {
+                responses: responses(),
+            };
+            let mut state = TracerState::new(
+                TimeToLive(1),
+                TimeToLive(1),
+                Sequence(33000),
+                MaxFlows(1),
+                ProbesPerRound(1),
+            );
+            for _ in 0..4 {
+                state.next_probe();
+            }
+            while !network.responses.is_empty() {
+                tracer.recv_response(&mut network, &mut state).unwrap();
+            }
+            let hosts: Vec<_> = state.probes().iter().map(|probe| probe.host).collect();
+            let host = Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, host)));
+            let target = Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 10)));
+            if identifier == 1000 {
+                assert_eq!(hosts, vec![host, host, None, target]);
+            } else {
+                assert_eq!(hosts, vec![host, host, target, None]);
+            }
+        }
+    }
+
+    /// The original probe within the `ICMP` error responses to `udp` and `tcp` probes has no identifier of ours, the
+    /// special case value of 0 is only matched for these protocols.
+    #[test]
+    fn test_zero_identifier() {
+        for (protocol, matched) in [
+            (TracerProtocol::Icmp, false),
+            (TracerProtocol::Udp, true),
+            (TracerProtocol::Tcp, true),
+        ] {
+            let tracer = tracer(protocol, 1000);
+            let mut network = MockNetwork {
+                responses: VecDeque::from([ProbeResponse::TimeExceeded(response(0, 33000, 1))]),
+            };
+            let mut state = TracerState::new(
+                TimeToLive(1),
+                TimeToLive(1),
+                Sequence(33000),
+                MaxFlows(1),
+                ProbesPerRound(1),
+            );
+            state.next_probe();
+            tracer.recv_response(&mut network, &mut state).unwrap();
+            let status = state.probe_at(Sequence(33000)).status;
+            let expected = if matched {
+                ProbeStatus::Complete
+            } else {
+                ProbeStatus::Awaited
+            };
+            assert_eq!(status, expected);
+        }
+    }
+
+    /// The source port of `udp` probes rotates by round, a response quoting the source port of another round is not
+    /// matched to the probe of the same sequence.
+    #[test]
+    fn test_source_port_rotation() {
+        let mut tracer = tracer(TracerProtocol::Udp, 0);
+        tracer.source_port = SourcePort(40000);
+        tracer.source_port_count = 4;
+        for (round, src_port, matched) in [(2, 40002, true), (2, 40001, false), (5, 40001, true)] {
+            let mut state = TracerState::new(
+                TimeToLive(1),
+                TimeToLive(1),
+                Sequence(33000),
+                MaxFlows(1),
+                ProbesPerRound(1),
+            );
+            for _ in 0..round {
+                state.advance_round(TimeToLive(1));
+            }
+            let probe = state.next_probe();
+            let mut network = MockNetwork {
+                responses: VecDeque::from([ProbeResponse::TimeExceeded(ProbeResponseData {
+                    src_port,
+                    ..response(0, probe.sequence.0, 1)
+                })]),
+            };
+            tracer.recv_response(&mut network, &mut state).unwrap();
+            let status = state.probe_at(probe.sequence).status;
+            assert_eq!(status == ProbeStatus::Complete, matched);
+        }
+    }
+
+    /// The probes of a round are sent in a burst by the sync schedule and are spaced across the minimum round duration by
+    /// the round-robin schedule.
+    #[test]
+    fn test_schedule_strategy() {
+        for (schedule_strategy, sent) in [
+            (ScheduleStrategy::Sync, 3),
+            (ScheduleStrategy::RoundRobin, 1),
+        ] {
+            let mut tracer = tracer(TracerProtocol::Icmp, 1);
+            tracer.schedule_strategy = schedule_strategy;
+            tracer.max_ttl = TimeToLive(4);
+            let mut state = TracerState::new(
+                TimeToLive(1),
+                TimeToLive(1),
+                Sequence(33000),
+                MaxFlows(1),
+                ProbesPerRound(1),
+            );
+            let mut network = MockNetwork {
+                responses: VecDeque::new(),
+            };
+            for _ in 0..3 {
+                tracer.send_request(&mut network, &mut state).unwrap();
+            }
+            assert_eq!(state.probes().len(), sent);
+        }
+    }
+
+    /// The backoff of the adaptive rate controller rises while a responding hop is lossy, is not held up by a hop which
+    /// never responds, and recovers once the loss subsides.
+    #[test]
+    fn test_adaptive_rate_backoff() {
+        let round = |lost: bool| {
+            (1..=3)
+                .map(|ttl| {
+                    let mut probe = Probe::new(
+                        Sequence(33000 + u16::from(ttl)),
+                        TimeToLive(ttl),
+                        Round(0),
+                        FlowId(0),
+                        SystemTime::now(),
+                    );
+                    if ttl == 1 || ttl == 2 && !lost {
+                        probe.status = ProbeStatus::Complete;
+                    }
+                    probe
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut controller = RateController::new(AdaptiveRate::new(20_f64, 5_f64));
+        controller.observe(&round(false), TimeToLive(3));
+        assert_eq!(controller.backoff(), 1);
+        let backoffs: Vec<_> = (0..4)
+            .map(|_| {
+                controller.observe(&round(true), TimeToLive(3));
+                controller.backoff()
+            })
+            .collect();
+        assert_eq!(backoffs, vec![2, 4, 8, 8]);
+        let recovered = (1..=20)
+            .find(|_| {
+                controller.observe(&round(false), TimeToLive(3));
+                controller.backoff() == 1
+            })
+            .unwrap();
+        assert!(recovered > 3);
+    }
 }
@@ -20,6 +20,14 @@ pub struct TimeToLive(pub u8);
 )]
 pub struct Sequence(pub u16);
 
+/// `FlowId` newtype.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Ord, PartialOrd, Hash, From)]
+pub struct FlowId(pub u16);
+
+/// `MaxFlows` newtype.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Ord, PartialOrd, From)]
+pub struct MaxFlows(pub u16);
+
 /// `TraceId` newtype.
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Ord, PartialOrd, From)]
 pub struct TraceId(pub u16);
@@ -28,6 +36,10 @@ pub struct TraceId(pub u16);
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Ord, PartialOrd, From)]
 pub struct MaxInflight(pub u8);
 
+/// `ProbesPerRound` newtype.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Ord, PartialOrd, From)]
+pub struct ProbesPerRound(pub u8);
+
 /// `PacketSize` newtype.
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Ord, PartialOrd, From)]
 pub struct PacketSize(pub u16);
@@ -40,6 +52,15 @@ pub struct PayloadPattern(pub u8);
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Ord, PartialOrd, From)]
 pub struct SourcePort(pub u16);
 
+impl SourcePort {
+    /// The source port of the probes of `round` when rotating across `count` ports from this port, one per round.
+    #[must_use]
+    pub fn rotated(self, round: Round, count: u16) -> Self {
+        let offset = round.0 % usize::from(count.max(1));
+        Self(self.0.wrapping_add(offset as u16))
+    }
+}
+
 impl From<Sequence> for usize {
     fn from(sequence: Sequence) -> Self {
         sequence.0 as Self
@@ -0,0 +1,396 @@
+use crate::config::TimestampFormat;
+use crate::dns::DnsResolver;
+use crate::frontend::TuiTraceInfo;
+use crate::geoip::GeoIpLookup;
+use crate::publish::completed_rounds;
+use crate::report::{RoundTracker, ROUND_POLL_INTERVAL};
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::thread::sleep;
+use std::time::Duration;
+
+/// The maximum time to wait for a client to complete the opening handshake or read a frame.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The GUID which is appended to the key of the client to compute the accept key, as defined by RFC 6455.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The maximum size of a frame sent by a client, which only ever need to send control frames.
+const MAX_CLIENT_FRAME: usize = 4096;
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+/// Bind the WebSocket server to `port` on all interfaces.
+pub fn bind_websocket(port: u16) -> anyhow::Result<TcpListener> {
+    Ok(TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], port)))?)
+}
+
+/// Push each completed round of tracing, for all traces, to all connected WebSocket clients.
+///
+/// Each round is sent as a single text message holding the same JSON as for `run_publisher`.  Clients are accepted,
+/// and complete the opening handshake, on a dedicated thread such that a slow client does not delay the rounds sent to
+/// others.  Clients which close the connection, or which fail to read data in a timely manner, are dropped without
+/// affecting the trace.
+pub fn run_websocket(
+    listener: TcpListener,
+    traces: Vec<TuiTraceInfo>,
+    resolver: DnsResolver,
+    geoip: Option<GeoIpLookup>,
+    timestamp_format: TimestampFormat,
+) -> anyhow::Result<()> {
+    let (client_tx, client_rx) = unbounded();
+    thread::Builder::new()
+        .name(String::from("websocket-accept"))
+        .spawn(move || accept_clients(&listener, &client_tx))?;
+    thread::Builder::new()
+        .name(String::from("websocket"))
+        .spawn(move || {
+            if let Err(err) = push_rounds(
+                &client_rx,
+                &traces,
+                &resolver,
+                geoip.as_ref(),
+                &timestamp_format,
+            ) {
+                eprintln!("websocket failed: {err}");
+            }
+        })?;
+    Ok(())
+}
+
+/// Accept clients and complete the opening handshake of each, until the listener fails.
+fn accept_clients(listener: &TcpListener, client_tx: &Sender<WsClient>) {
+    for stream in listener.incoming().flatten() {
+        match WsClient::handshake(stream) {
+            Ok(client) => {
+                if client_tx.send(client).is_err() {
+                    return;
+                }
+            }
+            Err(err) => eprintln!("websocket handshake failed: {err}"),
+        }
+    }
+}
+
+fn push_rounds(
+    client_rx: &Receiver<WsClient>,
+    traces: &[TuiTraceInfo],
+    resolver: &DnsResolver,
+    geoip: Option<&GeoIpLookup>,
+    timestamp_format: &TimestampFormat,
+) -> anyhow::Result<()> {
+    let mut clients: Vec<WsClient> = vec![];
+    let mut trackers: Vec<_> = traces.iter().map(|_| RoundTracker::new()).collect();
+    loop {
+        clients.extend(client_rx.try_iter());
+        clients.retain_mut(|client| client.poll().unwrap_or(false));
+        for line in completed_rounds(traces, &mut trackers, resolver, geoip, timestamp_format)? {
+            clients
+                .retain_mut(|client| client.send(OPCODE_TEXT, line.trim_end().as_bytes()).is_ok());
+        }
+        sleep(ROUND_POLL_INTERVAL);
+    }
+}
+
+/// A client which has completed the opening handshake.
+struct WsClient {
+    stream: TcpStream,
+    /// The bytes received from the client which do not yet form a complete frame.
+    received: Vec<u8>,
+}
+
+impl WsClient {
+    /// Read the HTTP upgrade request of the client and respond to complete the opening handshake.
+    ///
+    /// Any path is accepted, a request which is not a WebSocket upgrade receives a `400`.
+    fn handshake(mut stream: TcpStream) -> anyhow::Result<Self> {
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut key = None;
+        let mut upgrade = false;
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 0 && header.trim_end() != "" {
+            if let Some((name, value)) = header.split_once(':') {
+                let value = value.trim();
+                if name.eq_ignore_ascii_case("sec-websocket-key") {
+                    key = Some(value.to_string());
+                } else if name.eq_ignore_ascii_case("upgrade") {
+                    upgrade = value.eq_ignore_ascii_case("websocket");
+                }
+            }
+            header.clear();
+        }
+        match (request_line.split_whitespace().next(), key) {
+            (Some("GET"), Some(key)) if upgrade => {
+                let response = format!(
+                    "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                    accept_key(&key)
+                );
+                stream.write_all(response.as_bytes())?;
+                Ok(Self {
+                    stream,
+                    received: vec![],
+                })
+            }
+            _ => {
+                stream.write_all(
+                    b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )?;
+                Err(anyhow::anyhow!("not a websocket upgrade request"))
+            }
+        }
+    }
+
+    /// Send a single unfragmented frame.
+    ///
+    /// Frames sent by the server are never masked.
+    fn send(&mut self, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+        let mut frame = Vec::with_capacity(payload.len() + 10);
+        frame.push(0x80 | opcode);
+        let len = payload.len();
+        if len < 126 {
+            frame.push(u8::try_from(len).unwrap_or_default());
+        } else if let Ok(len) = u16::try_from(len) {
+            frame.push(126);
+            frame.extend(len.to_be_bytes());
+        } else {
+            frame.push(127);
+            frame.extend(u64::try_from(len).unwrap_or(u64::MAX).to_be_bytes());
+        }
+        frame.extend(payload);
+        self.stream.write_all(&frame)
+    }
+
+    /// Read and handle any frames sent by the client, without blocking.
+    ///
+    /// Pings are answered with a pong and all other messages are ignored.  Returns `false` if the client closed the
+    /// connection, either with a close frame, which is echoed to complete the closing handshake, or otherwise.
+    fn poll(&mut self) -> std::io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut buf = [0; MAX_CLIENT_FRAME];
+        let open = loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => break false,
+                Ok(n) => self.received.extend(&buf[..n]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break true,
+                Err(err) => return Err(err),
+            }
+        };
+        self.stream.set_nonblocking(false)?;
+        while let Some((opcode, payload)) = self.next_frame()? {
+            match opcode {
+                OPCODE_CLOSE => {
+                    self.send(OPCODE_CLOSE, &payload[..payload.len().min(2)])?;
+                    return Ok(false);
+                }
+                OPCODE_PING => self.send(OPCODE_PONG, &payload)?,
+                _ => {}
+            }
+        }
+        Ok(open)
+    }
+
+    /// Take the next complete frame received from the client, if any, and unmask its payload.
+    ///
+    /// Frames from clients must be masked and no larger than `MAX_CLIENT_FRAME`, any other frame is an error.
+    fn next_frame(&mut self) -> std::io::Result<Option<(u8, Vec<u8>)>> {
+        let protocol_error = |msg| std::io::Error::new(ErrorKind::InvalidData, msg);
+        let (opcode, second) = match self.received[..] {
+            [first, second, ..] => (first & 0x0f, second),
+            _ => return Ok(None),
+        };
+        if second & 0x80 == 0 {
+            return Err(protocol_error("unmasked client frame"));
+        }
+        let (len, header_len) = match second & 0x7f {
+            126 => match self.received.get(2..4) {
+                Some(len) => (usize::from(u16::from_be_bytes([len[0], len[1]])), 4),
+                None => return Ok(None),
+            },
+            127 => return Err(protocol_error("client frame too large")),
+            len => (usize::from(len), 2),
+        };
+        if len > MAX_CLIENT_FRAME {
+            return Err(protocol_error("client frame too large"));
+        }
+        let frame_len = header_len + 4 + len;
+        if self.received.len() < frame_len {
+            return Ok(None);
+        }
+        let frame: Vec<u8> = self.received.drain(..frame_len).collect();
+        let (mask, payload) = frame[header_len..].split_at(4);
+        let payload = payload
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ mask[i % 4])
+            .collect();
+        Ok(Some((opcode, payload)))
+    }
+}
+
+/// The `Sec-WebSocket-Accept` key for the `Sec-WebSocket-Key` of a client.
+fn accept_key(key: &str) -> String {
+    let hash = digest(
+        &SHA1_FOR_LEGACY_USE_ONLY,
+        format!("{key}{WEBSOCKET_GUID}").as_bytes(),
+    );
+    base64::encode(hash.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A client of a connected loopback stream, and the peer of that stream.
+    fn client() -> (WsClient, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let client = WsClient {
+            stream,
+            received: vec![],
+        };
+        (client, peer)
+    }
+
+    /// A masked client frame.
+    fn masked_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let mut frame = vec![0x80 | opcode];
+        if payload.len() < 126 {
+            frame.push(0x80 | payload.len() as u8);
+        } else {
+            frame.push(0xfe);
+            frame.extend((payload.len() as u16).to_be_bytes());
+        }
+        frame.extend(mask);
+        frame.extend(
+            payload
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| byte ^ mask[i % 4]),
+        );
+        frame
+    }
+
+    fn read_exact(peer: &mut TcpStream, len: usize) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        peer.read_exact(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_accept_key() {
+        assert_eq!(
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=",
+            accept_key("dGhlIHNhbXBsZSBub25jZQ==")
+        );
+    }
+
+    #[test]
+    fn test_next_frame() {
+        let (mut client, _peer) = client();
+        client.received = masked_frame(OPCODE_PING, b"hello");
+        client.received.extend(masked_frame(OPCODE_TEXT, b"world"));
+        assert_eq!(
+            Some((OPCODE_PING, b"hello".to_vec())),
+            client.next_frame().unwrap()
+        );
+        assert_eq!(
+            Some((OPCODE_TEXT, b"world".to_vec())),
+            client.next_frame().unwrap()
+        );
+        assert_eq!(None, client.next_frame().unwrap());
+    }
+
+    #[test]
+    fn test_next_frame_partial() {
+        let (mut client, _peer) = client();
+        let frame = masked_frame(OPCODE_PING, &[0xaa; 300]);
+        for partial in [1, 3, 7, frame.len() - 1] {
+            client.received = frame[..partial].to_vec();
+            assert_eq!(None, client.next_frame().unwrap());
+            assert_eq!(partial, client.received.len());
+        }
+        client.received = frame;
+        assert_eq!(
+            Some((OPCODE_PING, vec![0xaa; 300])),
+            client.next_frame().unwrap()
+        );
+        assert!(client.received.is_empty());
+    }
+
+    #[test]
+    fn test_next_frame_invalid() {
+        let (mut client, _peer) = client();
+        client.received = vec![0x80 | OPCODE_PING, 0x00];
+        assert!(client.next_frame().is_err());
+        client.received = vec![0x80 | OPCODE_TEXT, 0xff, 0, 0, 0, 0, 0, 1, 0, 0];
+        assert!(client.next_frame().is_err());
+        client.received = masked_frame(OPCODE_TEXT, &[0; MAX_CLIENT_FRAME + 1]);
+        assert!(client.next_frame().is_err());
+    }
+
+    #[test]
+    fn test_send() {
+        let (mut client, mut peer) = client();
+        client.send(OPCODE_TEXT, b"round").unwrap();
+        assert_eq!(b"\x81\x05round".to_vec(), read_exact(&mut peer, 7));
+        client.send(OPCODE_TEXT, &[b'x'; 300]).unwrap();
+        assert_eq!(vec![0x81, 126, 0x01, 0x2c], read_exact(&mut peer, 4));
+        assert_eq!(vec![b'x'; 300], read_exact(&mut peer, 300));
+    }
+
+    #[test]
+    fn test_poll() {
+        let (mut client, mut peer) = client();
+        peer.write_all(&masked_frame(OPCODE_PING, b"ping")).unwrap();
+        peer.flush().unwrap();
+        sleep(Duration::from_millis(50));
+        assert!(client.poll().unwrap());
+        assert_eq!(b"\x8a\x04ping".to_vec(), read_exact(&mut peer, 6));
+        peer.write_all(&masked_frame(OPCODE_CLOSE, &[0x03, 0xe8, b'x']))
+            .unwrap();
+        sleep(Duration::from_millis(50));
+        assert!(!client.poll().unwrap());
+        assert_eq!(vec![0x88, 0x02, 0x03, 0xe8], read_exact(&mut peer, 4));
+    }
+
+    #[test]
+    fn test_handshake() {
+        let (client, mut peer) = client();
+        peer.write_all(
+            b"GET /rounds HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+        )
+        .unwrap();
+        assert!(WsClient::handshake(client.stream).is_ok());
+        let mut response = String::new();
+        peer.set_read_timeout(Some(CLIENT_TIMEOUT)).unwrap();
+        let mut reader = BufReader::new(peer);
+        while reader.read_line(&mut response).unwrap() > 2 {}
+        assert_eq!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n",
+            response
+        );
+    }
+
+    #[test]
+    fn test_handshake_not_upgrade() {
+        let (client, mut peer) = client();
+        peer.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        assert!(WsClient::handshake(client.stream).is_err());
+        let mut status_line = String::new();
+        BufReader::new(peer).read_line(&mut status_line).unwrap();
+        assert_eq!("HTTP/1.1 400 Bad Request\r\n", status_line);
+    }
+}